@@ -1,14 +1,13 @@
 use std::fmt::Display;
-use std::io::Write;
+use std::ops::Range;
 use std::rc::Rc;
 use std::sync::RwLock;
 use std::{path::PathBuf, sync::Mutex};
 
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use termcolor::{Buffer, BufferWriter, Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
-// To-do list:
-// * Trim code to the right of the area of interest, we don't want comments clogging it up
-//
+mod suggest;
+pub use suggest::*;
 
 static WARNING_COLOR: Color = Color::Yellow;
 static ERROR_COLOR: Color = Color::Red;
@@ -18,7 +17,8 @@ static PLAIN_WHITE: Color = Color::Rgb(255, 255, 255);
 static PROMPT_COLOR: Color = Color::Blue;
 
 pub struct DiagnosticBuilder<'a> {
-    diagnostic: Diagnostic,
+    // Boxed so that results which carry a DiagnosticBuilder as their error stay small
+    diagnostic: Box<Diagnostic>,
     handler: &'a Handler,
 }
 
@@ -35,7 +35,7 @@ impl<'a> DiagnosticBuilder<'a> {
         };
 
         Self {
-            diagnostic,
+            diagnostic: Box::new(diagnostic),
             handler,
         }
     }
@@ -71,15 +71,25 @@ impl<'a> DiagnosticBuilder<'a> {
     /// Queues this diagnostic to be emitted by the inner Handler/Emitter
     pub fn emit(&mut self) {
         if self.diagnostic.level == Level::Warning {
-            self.handler.warn(self.diagnostic.clone());
+            self.handler.warn((*self.diagnostic).clone());
+        } else if self.diagnostic.level == Level::Note {
+            self.handler.note((*self.diagnostic).clone());
         } else {
-            self.handler.error(self.diagnostic.clone());
+            self.handler.error((*self.diagnostic).clone());
         }
 
         // Mark this as cancelled so that it can be safely dropped
         self.cancel();
     }
 
+    /// Emits this note even if notes aren't being shown, because it is something that was asked
+    /// for specifically, such as a time report
+    pub fn emit_report(&mut self) {
+        self.handler.report((*self.diagnostic).clone());
+
+        self.cancel();
+    }
+
     /// Sets this DiagnosticBuilder as cancelled, meaning that it is safe to be dropped
     pub fn cancel(&mut self) {
         self.diagnostic.level = Level::Cancelled;
@@ -117,6 +127,36 @@ pub struct Diagnostic {
     pub children: Vec<SubDiagnostic>,
 }
 
+impl Diagnostic {
+    /// Returns true if the two Diagnostics would read the same, and point at the same places,
+    /// regardless of which macro expansion their spans were produced by
+    fn same_shape(&self, other: &Diagnostic) -> bool {
+        fn same_place(a: &Span, b: &Span) -> bool {
+            a.start == b.start && a.end == b.end && a.file == b.file
+        }
+
+        self.level == other.level
+            && self.message == other.message
+            && match (&self.primary, &other.primary) {
+                (Some(a), Some(b)) => same_place(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+            && self.spans.len() == other.spans.len()
+            && self
+                .spans
+                .iter()
+                .zip(other.spans.iter())
+                .all(|((a, a_label), (b, b_label))| same_place(a, b) && a_label == b_label)
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(other.children.iter())
+                .all(|(a, b)| a.level == b.level && a.message == b.message)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SubDiagnostic {
     pub level: Level,
@@ -133,6 +173,8 @@ impl SubDiagnostic {
 pub struct Emitter {
     flags: HandlerFlags,
     source_manger: Rc<RwLock<SourceManager>>,
+    /// If this is set, diagnostics are written here instead of directly to stderr
+    buffer: Option<Buffer>,
 }
 
 impl Emitter {
@@ -140,85 +182,143 @@ impl Emitter {
         Self {
             flags,
             source_manger,
+            buffer: None,
         }
     }
 
-    fn color_choice(&self) -> ColorChoice {
-        if self.flags.colored_output {
-            ColorChoice::Auto
-        } else {
-            ColorChoice::Never
+    /// Creates an Emitter that holds on to everything it emits until it is taken with
+    /// take_buffer()
+    pub fn new_buffered(flags: HandlerFlags, source_manger: Rc<RwLock<SourceManager>>) -> Self {
+        let buffer = Self::buffer_writer(flags).buffer();
+
+        Self {
+            flags,
+            source_manger,
+            buffer: Some(buffer),
+        }
+    }
+
+    fn color_choice(flags: HandlerFlags) -> ColorChoice {
+        match flags.color {
+            ColorMode::Auto if atty::is(atty::Stream::Stderr) => ColorChoice::Auto,
+            ColorMode::Auto => ColorChoice::Never,
+            ColorMode::Always => ColorChoice::Always,
+            ColorMode::Never => ColorChoice::Never,
         }
     }
 
     fn get_stderr(&self) -> StandardStream {
-        StandardStream::stderr(self.color_choice())
+        StandardStream::stderr(Self::color_choice(self.flags))
     }
 
-    pub fn emit_diagnostic(&self, diagnostic: &Diagnostic) {
-        let mut stream = self.get_stderr();
+    fn buffer_writer(flags: HandlerFlags) -> BufferWriter {
+        BufferWriter::stderr(Self::color_choice(flags))
+    }
 
-        let level_msg = diagnostic.level.as_styled_string();
+    /// Takes everything that has been emitted so far, if this Emitter is buffered
+    pub fn take_buffer(&mut self) -> Option<Buffer> {
+        let buffer = self.buffer.as_mut()?;
+        let taken = std::mem::replace(buffer, Self::buffer_writer(self.flags).buffer());
+
+        Some(taken)
+    }
 
-        if let Err(e) = self.emit_styled_string(&mut stream, &level_msg) {
-            panic!("Failed to emit error: {}", e);
+    /// Writes diagnostics that were buffered by another Emitter to stderr
+    pub fn emit_buffer(&self, buffer: &Buffer) {
+        if let Err(e) = Self::buffer_writer(self.flags).print(buffer) {
+            panic!("Failed to emit buffered diagnostics: {}", e);
         }
+    }
+
+    // If the primary span of the diagnostic came from a macro expansion, this labels each macro
+    // invocation that it was expanded from, so that it is clear which one caused it
+    fn add_expansion_backtrace(&self, diagnostic: &mut Diagnostic) {
+        let primary = match diagnostic.primary {
+            Some(primary) => primary,
+            None => return,
+        };
+
+        let source_manager = self.source_manger.read().unwrap();
+
+        for invocation in source_manager.expansion_backtrace(&primary) {
+            let name = source_manager
+                .get_by_id(invocation.file)
+                .map(|file| file.span_to_source(&invocation).to_owned())
+                .unwrap_or_default();
+
+            diagnostic.spans.push((
+                invocation,
+                format!("in expansion of macro `{}` invoked here", name),
+            ));
+        }
+    }
+
+    pub fn emit_diagnostic(&mut self, diagnostic: &Diagnostic) {
+        let result = match self.buffer.take() {
+            Some(mut buffer) => {
+                let result = self.write_diagnostic(&mut buffer, diagnostic);
+
+                self.buffer = Some(buffer);
+
+                result
+            }
+            None => self.write_diagnostic(&mut self.get_stderr(), diagnostic),
+        };
+
+        if let Err(e) = result {
+            panic!("Failed to emit diagnostic: {}", e);
+        }
+    }
+
+    fn write_diagnostic(
+        &self,
+        stream: &mut dyn WriteColor,
+        diagnostic: &Diagnostic,
+    ) -> std::io::Result<()> {
+        let level_msg = diagnostic.level.as_styled_string();
+
+        self.emit_styled_string(stream, &level_msg)?;
 
         let styled_string =
             StyledString::new(format!(": {}", diagnostic.message), Style::MainHeaderMsg);
 
-        if let Err(e) = self.emit_styled_string(&mut stream, &styled_string) {
-            panic!("Failed to emit error: {}", e);
-        }
-        eprintln!();
+        self.emit_styled_string(stream, &styled_string)?;
+
+        writeln!(stream)?;
 
         if let Some(primary) = &diagnostic.primary {
             let extra_spacer = diagnostic.spans.is_empty();
 
-            if let Err(e) = self.emit_snippet(
-                &mut stream,
-                primary,
-                diagnostic.level,
-                None,
-                extra_spacer,
-                true,
-            ) {
-                panic!("Failed to emit snippet: {}", e);
-            }
+            self.emit_snippet(stream, primary, diagnostic.level, None, extra_spacer, true)?;
         }
 
         let styled_dots = StyledString::new("...".to_string(), Style::LineAndColumn);
 
         if diagnostic.primary.is_some() && !diagnostic.spans.is_empty() {
             // We need the special dots
-            self.emit_styled_string(&mut stream, &styled_dots)
-                .expect("Failed to emit ...");
+            self.emit_styled_string(stream, &styled_dots)?;
 
-            eprintln!();
+            writeln!(stream)?;
         }
 
         for (index, (span, label)) in diagnostic.spans.iter().enumerate() {
-            let mut extra_spacer = true;
-
-            if index + 1 < diagnostic.spans.len() {
-                // Put the dots
-                self.emit_styled_string(&mut stream, &styled_dots)
-                    .expect("Failed to emit ...");
-
-                eprintln!();
-
-                extra_spacer = false;
-            }
+            let is_last = index + 1 == diagnostic.spans.len();
 
             self.emit_snippet(
-                &mut stream,
+                stream,
                 span,
                 diagnostic.level,
                 Some(label),
-                extra_spacer,
+                is_last,
                 diagnostic.primary.is_none(),
-            )
-            .expect("Failed to emit snippet");
+            )?;
+
+            if !is_last {
+                // Put the dots between this snippet and the next
+                self.emit_styled_string(stream, &styled_dots)?;
+
+                writeln!(stream)?;
+            }
         }
 
         for sub_diagnostic in diagnostic.children.iter() {
@@ -227,20 +327,21 @@ impl Emitter {
             let styled_message =
                 StyledString::new(format!(": {}", sub_diagnostic.message), Style::NoStyle);
 
-            self.emit_styled_string(&mut stream, &styled_leader)
-                .expect("Failed to emit ...");
+            self.emit_styled_string(stream, &styled_leader)?;
 
-            self.emit_styled_string(&mut stream, &styled_level)
-                .expect("Failed to emit ...");
+            self.emit_styled_string(stream, &styled_level)?;
 
-            self.emit_styled_string(&mut stream, &styled_message)
-                .expect("Failed to emit ...");
+            self.emit_styled_string(stream, &styled_message)?;
+
+            writeln!(stream)?;
         }
+
+        Ok(())
     }
 
     fn emit_snippet(
         &self,
-        stream: &mut StandardStream,
+        stream: &mut dyn WriteColor,
         span: &Span,
         level: Level,
         label: Option<&str>,
@@ -248,9 +349,15 @@ impl Emitter {
         display_file: bool,
     ) -> std::io::Result<()> {
         let (path, line_num, col) = self.get_source_location(span);
-        let snippet = self.span_to_snippet(span);
+        let lines = self.span_lines(span);
 
-        let line_num_str = format!("{}", line_num);
+        // Every line number is right-aligned to the width of the last one
+        let last_line_num = match lines.last() {
+            Some(last) => self.get_source_location(last).1,
+            None => line_num,
+        };
+
+        let line_num_str = format!("{}", last_line_num);
         let line_num_width = line_num_str.len();
 
         //   --> src/main.kasm:2:4
@@ -265,7 +372,7 @@ impl Emitter {
             self.emit_styled_string(stream, &styled_arrow)?;
 
             // src/main.kasm:2:4
-            eprintln!(" {}:{}:{}", path, line_num, col);
+            writeln!(stream, " {}:{}:{}", path, line_num, col)?;
         }
 
         let vert_bar = StyledString::new(
@@ -275,27 +382,84 @@ impl Emitter {
 
         //     |
         self.emit_styled_string(stream, &vert_bar)?;
-        eprintln!();
+        writeln!(stream)?;
+
+        // A span that crosses multiple lines is shown one line at a time, each with its own
+        // underline. The label goes on the last one
+        if lines.len() > 1 {
+            for (index, line_span) in lines.iter().enumerate() {
+                let (_, line_num, _) = self.get_source_location(line_span);
+                let snippet = self.span_to_snippet(line_span);
+                let line_label = if index + 1 == lines.len() {
+                    label
+                } else {
+                    None
+                };
+
+                self.emit_snippet_line(
+                    stream,
+                    &snippet,
+                    line_num,
+                    line_num_width,
+                    level,
+                    line_label,
+                )?;
+            }
+        } else {
+            let snippet = self.span_to_snippet(span);
+
+            self.emit_snippet_line(stream, &snippet, line_num, line_num_width, level, label)?;
+        }
+
+        if extra_spacer {
+            //     |
+            self.emit_styled_string(stream, &vert_bar)?;
+            writeln!(stream)?;
+        }
+
+        Ok(())
+    }
+
+    // Emits a single line of source code, and underlines the part of it that the snippet covers
+    fn emit_snippet_line(
+        &self,
+        stream: &mut dyn WriteColor,
+        snippet: &Snippet,
+        line_num: usize,
+        line_num_width: usize,
+        level: Level,
+        label: Option<&str>,
+    ) -> std::io::Result<()> {
+        let vert_bar = StyledString::new(
+            format!("{:spaces$} |", "", spaces = line_num_width),
+            Style::LineAndColumn,
+        );
 
         // 200 |
-        self.emit_styled_string(stream, &self.struct_line_num(line_num))?;
+        self.emit_styled_string(stream, &self.struct_line_num(line_num, line_num_width))?;
+
+        let (line, col, length) = self.trim_line(
+            &snippet.line,
+            snippet.start_col,
+            snippet.end_col - snippet.start_col,
+        );
 
         //   push NOT_ALLOWED
-        eprintln!("{}", &snippet.line);
+        writeln!(stream, "{}", line)?;
 
         //     |
         self.emit_styled_string(stream, &vert_bar)?;
 
         //    ^^^^^^^^^^^^
         // Print the spaces
-        eprint!("{:spaces$} ", "", spaces = col);
+        write!(stream, "{:spaces$} ", "", spaces = col)?;
 
         // Print the ^'s
         // If anyone reading this knows a better way, let me know. ^ is a special character in
         // formatting strings, so.
         stream.set_color(&Style::Level(level).to_spec())?;
 
-        for _ in 0..(span.end - span.start) {
+        for _ in 0..length {
             write!(stream, "^")?;
         }
 
@@ -306,15 +470,45 @@ impl Emitter {
             write!(stream, "{}", label)?;
         }
 
-        eprintln!();
+        stream.reset()?;
 
-        if extra_spacer {
-            //     |
-            self.emit_styled_string(stream, &vert_bar)?;
-            eprintln!();
+        writeln!(stream)
+    }
+
+    // Trims a line that is longer than the maximum width down to a window centered on the area of
+    // interest, marking each side that was cut off with "...". Returns the line to display, and the
+    // column and length of the underline within it
+    fn trim_line(&self, line: &str, col: usize, length: usize) -> (String, usize, usize) {
+        let max_width = self.flags.max_line_width;
+        let chars: Vec<char> = line.chars().collect();
+
+        if max_width == 0 || chars.len() <= max_width {
+            return (line.to_owned(), col, length);
         }
 
-        Ok(())
+        // If the span itself doesn't fit, then at least its beginning is shown
+        let center = col + length.min(max_width) / 2;
+        let window_start = center
+            .saturating_sub(max_width / 2)
+            .min(chars.len() - max_width);
+        let window_end = window_start + max_width;
+
+        let mut trimmed = String::new();
+        let mut window_col = col - window_start;
+        let length = length.min(max_width - window_col);
+
+        if window_start > 0 {
+            trimmed.push_str("...");
+            window_col += 3;
+        }
+
+        trimmed.extend(&chars[window_start..window_end]);
+
+        if window_end < chars.len() {
+            trimmed.push_str("...");
+        }
+
+        (trimmed, window_col, length)
     }
 
     // Constructs a StyledString that contains this line number but formatted like a diagnostic:
@@ -322,26 +516,36 @@ impl Emitter {
     // Ex:
     //
     //  243 |
-    fn struct_line_num(&self, line_num: usize) -> StyledString {
-        StyledString::new(format!("{} | ", line_num), Style::LineNumber)
+    fn struct_line_num(&self, line_num: usize, width: usize) -> StyledString {
+        StyledString::new(
+            format!("{:>width$} | ", line_num, width = width),
+            Style::LineNumber,
+        )
     }
 
     fn get_source_location(&self, span: &Span) -> (String, usize, usize) {
-        let file_id = span.file;
-
         match self
             .source_manger
             .read()
             .unwrap()
-            .get_by_id(file_id as usize)
+            .get_source_location(span, self.flags.tab_width)
         {
-            Some(source_file) => source_file.get_source_location(span),
+            Some(location) => location,
             None => {
                 panic!("Failed to get source location of span");
             }
         }
     }
 
+    fn span_lines(&self, span: &Span) -> Vec<Span> {
+        match self.source_manger.read().unwrap().get_by_id(span.file) {
+            Some(source_file) => source_file.span_lines(span),
+            None => {
+                panic!("Failed to split span into lines");
+            }
+        }
+    }
+
     fn span_to_snippet(&self, span: &Span) -> Snippet {
         let file_id = span.file;
 
@@ -351,7 +555,7 @@ impl Emitter {
             .unwrap()
             .get_by_id(file_id as usize)
         {
-            Some(source_file) => source_file.span_to_snippet(span),
+            Some(source_file) => source_file.span_to_snippet(span, self.flags.tab_width),
             None => {
                 panic!("Failed to convert span to snippet");
             }
@@ -360,7 +564,7 @@ impl Emitter {
 
     pub fn emit_styled_string(
         &self,
-        stream: &mut StandardStream,
+        stream: &mut dyn WriteColor,
         styled_string: &StyledString,
     ) -> std::io::Result<()> {
         let color_spec = styled_string.style.to_spec();
@@ -426,14 +630,30 @@ impl Style {
 
 #[derive(Debug, Copy, Clone)]
 pub struct HandlerFlags {
-    /// If the output should be colored or not. This should be false when the output is redirected
-    /// into a file, for example.
-    pub colored_output: bool,
+    /// If the output should be colored or not. By default it is only colored if stderr is a
+    /// terminal, and not when it is redirected into a file, for example.
+    pub color: ColorMode,
     /// Warnings can be disabled by command-line flags
     pub emit_warnings: bool,
+    /// Notes are progress messages, which are only shown if verbose output is requested
+    pub emit_notes: bool,
     /// This flag means if this Handler should actually print anything at all. This should probably
     /// be set when this is being used as a library
     pub quiet: bool,
+    /// The maximum number of columns of a source line to display. Longer lines are trimmed around
+    /// the span being shown. A value of 0 means lines are never trimmed
+    pub max_line_width: usize,
+    /// The number of columns that a tab is displayed as, both in snippets and source locations
+    pub tab_width: usize,
+}
+
+/// When diagnostics should be colored
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorMode {
+    /// Only if stderr is a terminal
+    Auto,
+    Always,
+    Never,
 }
 
 // This is needed so that certain parts of the Handler can be put behind a Mutex, so that they can
@@ -443,6 +663,11 @@ pub(crate) struct HandlerInner {
     /// The inner emitter that actually emits the Diagnostics
     pub emitter: Emitter,
     // pub source_manager: Rc<RwLock<SourceManager>>,
+    /// If this is set, Diagnostics are kept here instead of being given to the emitter
+    pub collected: Option<Vec<Diagnostic>>,
+    /// The last Diagnostic emitted, which is held back until a different one comes along, along
+    /// with how many identical copies of it followed it
+    pending: Option<(Diagnostic, usize)>,
 }
 
 impl HandlerInner {
@@ -450,10 +675,77 @@ impl HandlerInner {
         Self {
             emitter: Emitter::new(flags, source_manager),
             // source_manager,
+            collected: None,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn new_buffered(
+        flags: HandlerFlags,
+        source_manager: Rc<RwLock<SourceManager>>,
+    ) -> Self {
+        Self {
+            emitter: Emitter::new_buffered(flags, source_manager),
+            collected: None,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn new_collecting(
+        flags: HandlerFlags,
+        source_manager: Rc<RwLock<SourceManager>>,
+    ) -> Self {
+        Self {
+            emitter: Emitter::new(flags, source_manager),
+            collected: Some(Vec::new()),
+            pending: None,
+        }
+    }
+
+    // A macro that expands many times with the same mistake in it would otherwise report the
+    // same Diagnostic once per expansion, so a run of identical ones is coalesced into one
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        if let Some((pending, count)) = &mut self.pending {
+            if pending.same_shape(&diagnostic) {
+                *count += 1;
+                return;
+            }
+        }
+
+        self.flush();
+
+        self.pending = Some((diagnostic, 0));
+    }
+
+    /// Emits the Diagnostic that is being held back, if there is one
+    pub(crate) fn flush(&mut self) {
+        let (mut diagnostic, count) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        self.emitter.add_expansion_backtrace(&mut diagnostic);
+
+        if count > 0 {
+            diagnostic.children.push(SubDiagnostic::new(
+                Level::Note,
+                format!("(and {} more identical)", count),
+            ));
+        }
+
+        match &mut self.collected {
+            Some(collected) => collected.push(diagnostic),
+            None => self.emitter.emit_diagnostic(&diagnostic),
         }
     }
 }
 
+impl Drop for HandlerInner {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
 /// A Handler handles all Diagnostics that are to be emitted through the course of assembly.
 /// Diagnostics are things such as warnings and errors.
 pub struct Handler {
@@ -472,20 +764,90 @@ impl Handler {
         }
     }
 
+    /// Creates a new diagnostic Handler that holds on to every Diagnostic it emits, instead of
+    /// writing them out immediately. They can be retrieved with take_buffer()
+    pub fn new_buffered(flags: HandlerFlags, source_manager: Rc<RwLock<SourceManager>>) -> Self {
+        Self {
+            flags,
+            inner: Mutex::new(HandlerInner::new_buffered(flags, source_manager)),
+        }
+    }
+
+    /// Creates a new diagnostic Handler that keeps every Diagnostic it emits as-is, instead of
+    /// writing them out at all. They can be retrieved with take_collected()
+    pub fn new_collecting(flags: HandlerFlags, source_manager: Rc<RwLock<SourceManager>>) -> Self {
+        Self {
+            flags,
+            inner: Mutex::new(HandlerInner::new_collecting(flags, source_manager)),
+        }
+    }
+
+    /// Takes every Diagnostic collected so far by this Handler. This is empty if it isn't
+    /// collecting
+    pub fn take_collected(&self) -> Vec<Diagnostic> {
+        self.inner
+            .lock()
+            .ok()
+            .and_then(|mut inner| {
+                inner.flush();
+                inner.collected.as_mut().map(std::mem::take)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Takes every Diagnostic emitted so far by this Handler, if it is buffered
+    pub fn take_buffer(&self) -> Option<Buffer> {
+        self.inner.lock().ok().and_then(|mut inner| {
+            inner.flush();
+            inner.emitter.take_buffer()
+        })
+    }
+
+    /// Writes out Diagnostics that were buffered by another Handler
+    pub fn emit_buffer(&self, buffer: &Buffer) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.flush();
+            inner.emitter.emit_buffer(buffer);
+        }
+    }
+
+    /// Emits the last Diagnostic, which is held back in case identical ones follow it
+    pub fn flush(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.flush();
+        }
+    }
+
     /// This registers a warning with this error Handler
     pub fn warn(&self, warning: Diagnostic) {
         // If we can't even emit them, don't even store them
         if self.flags.emit_warnings {
-            if let Ok(inner) = self.inner.lock() {
-                inner.emitter.emit_diagnostic(&warning);
+            if let Ok(mut inner) = self.inner.lock() {
+                inner.emit(warning);
+            }
+        }
+    }
+
+    /// This registers a note with this error Handler
+    pub fn note(&self, note: Diagnostic) {
+        if self.flags.emit_notes {
+            if let Ok(mut inner) = self.inner.lock() {
+                inner.emit(note);
             }
         }
     }
 
+    /// This registers a note that is shown even if other notes aren't
+    pub fn report(&self, note: Diagnostic) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.emit(note);
+        }
+    }
+
     /// This registers an error with this error Handler
     pub fn error(&self, error: Diagnostic) {
-        if let Ok(inner) = self.inner.lock() {
-            inner.emitter.emit_diagnostic(&error);
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.emit(error);
         }
     }
 }
@@ -498,8 +860,32 @@ pub enum SourceError {
     MaxSourcesReached,
 }
 
+/// A .line directive, which changes the line number and file name that are reported for every
+/// location after it in the same source file
+#[derive(Debug, Clone)]
+struct LineDirective {
+    file_id: usize,
+    /// The index into the source that the directive takes effect at
+    start: usize,
+    /// The actual line number that the directive takes effect at
+    physical_line: usize,
+    line: usize,
+    file_name: Option<String>,
+}
+
+/// A single expansion of a macro, which every token produced by it refers back to
+#[derive(Debug, Clone, Copy)]
+struct MacroExpansion {
+    /// The span of the macro's name where it was invoked
+    invocation: Span,
+    /// The expansion that the invocation itself was part of, or 0 if there wasn't one
+    parent: usize,
+}
+
 pub struct SourceManager {
     source_files: Vec<Rc<SourceFile>>,
+    line_directives: Vec<LineDirective>,
+    expansions: Vec<MacroExpansion>,
 }
 
 impl SourceManager {
@@ -507,6 +893,8 @@ impl SourceManager {
     pub fn new() -> Self {
         Self {
             source_files: Vec::new(),
+            line_directives: Vec::new(),
+            expansions: Vec::new(),
         }
     }
 
@@ -533,6 +921,97 @@ impl SourceManager {
         // Because id == index of SourceFile as u8, we can just use it directly
         self.source_files.get(id).cloned()
     }
+
+    /// Records that locations in the given file starting at the provided index should be
+    /// reported as being on the provided line, and in the provided file if one is given
+    pub fn add_line_directive(
+        &mut self,
+        file_id: usize,
+        start: usize,
+        line: usize,
+        file_name: Option<String>,
+    ) {
+        let source_file = match self.get_by_id(file_id) {
+            Some(source_file) => source_file,
+            None => return,
+        };
+
+        let (_, physical_line, _) =
+            source_file.get_source_location(&Span::new(start, start, file_id), 0);
+
+        // A directive without a file name keeps the one from the directive before it
+        let file_name = file_name.or_else(|| {
+            self.line_directive_at(file_id, start)
+                .and_then(|directive| directive.file_name.clone())
+        });
+
+        // The same directive can be executed more than once if it is in an included file
+        self.line_directives
+            .retain(|directive| directive.file_id != file_id || directive.start != start);
+
+        self.line_directives.push(LineDirective {
+            file_id,
+            start,
+            physical_line,
+            line,
+            file_name,
+        });
+    }
+
+    /// Returns the file path, line number, and column that the Span starts at, after applying
+    /// any line directives
+    pub fn get_source_location(
+        &self,
+        span: &Span,
+        tab_width: usize,
+    ) -> Option<(String, usize, usize)> {
+        let source_file = self.get_by_id(span.file)?;
+        let (path, line_num, col) = source_file.get_source_location(span, tab_width);
+
+        Some(match self.line_directive_at(span.file, span.start) {
+            Some(directive) => (
+                directive.file_name.clone().unwrap_or(path),
+                directive.line + line_num - directive.physical_line,
+                col,
+            ),
+            None => (path, line_num, col),
+        })
+    }
+
+    /// Records an expansion of the macro invoked at the provided span, which happened inside of
+    /// the parent expansion (or 0 if none). Returns the id to give to everything it produced,
+    /// which is never 0
+    pub fn add_expansion(&mut self, invocation: Span, parent: usize) -> usize {
+        self.expansions.push(MacroExpansion { invocation, parent });
+
+        self.expansions.len()
+    }
+
+    /// Returns the span of each macro invocation that the provided Span was expanded from,
+    /// starting with the innermost one
+    pub fn expansion_backtrace(&self, span: &Span) -> Vec<Span> {
+        let mut backtrace = Vec::new();
+        let mut id = span.expansion;
+
+        while let Some(expansion) = id
+            .checked_sub(1)
+            .and_then(|index| self.expansions.get(index))
+        {
+            backtrace.push(expansion.invocation);
+
+            id = expansion.parent;
+        }
+
+        backtrace
+    }
+
+    // Finds the line directive that is in effect at the given index into a file, if any
+    fn line_directive_at(&self, file_id: usize, index: usize) -> Option<&LineDirective> {
+        self.line_directives
+            .iter()
+            .filter(|directive| directive.file_id == file_id && directive.start <= index)
+            .max_by_key(|directive| directive.start)
+    }
 }
 
 impl Default for SourceManager {
@@ -572,6 +1051,24 @@ impl SourceFile {
         }
     }
 
+    /// Returns the range of the source that lines first through last cover, including the
+    /// newline at the end of the last one. Line numbers start at 1. If the file doesn't have
+    /// that many lines, this returns the number of lines that it does have
+    pub fn line_range(&self, first: usize, last: usize) -> Result<Range<usize>, usize> {
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(self.source.match_indices('\n').map(|(index, _)| index + 1))
+            .filter(|&start| start < self.source.len())
+            .collect();
+
+        if first == 0 || last > line_starts.len() {
+            return Err(line_starts.len());
+        }
+
+        let end = line_starts.get(last).copied().unwrap_or(self.source.len());
+
+        Ok(line_starts[first - 1]..end)
+    }
+
     /// Gets the source location of a given span
     ///
     /// Note: This uses the span.start to determine the line and column
@@ -583,74 +1080,108 @@ impl SourceFile {
     /// Or if the file has no path, it just returns the name of the file. So if it is from some
     /// kind of non-file input, then it is just displayed as <input>
     ///
-    fn get_source_location(&self, span: &Span) -> (String, usize, usize) {
+    /// Tabs count as tab_width columns, to match how Snippets are displayed
+    ///
+    pub fn get_source_location(&self, span: &Span, tab_width: usize) -> (String, usize, usize) {
         let file_path = match &self.rel_path {
             Some(rel) => rel.to_str().unwrap().to_owned(),
             None => self.name.to_owned(),
         };
 
-        let mut line_num = 1;
-        let mut line_start_index = 0;
+        let before = &self.source[..span.start];
 
-        // Loop through all characters until the span.start
-        for (idx, c) in self.source.chars().take(span.start).enumerate() {
-            if c == '\n' {
-                line_num += 1;
-                line_start_index = idx + 1;
-            } else if c == '\t' {
-                line_start_index -= 3;
-            }
-        }
+        let line_num = before.matches('\n').count() + 1;
+        let line_start_index = before.rfind('\n').map_or(0, |index| index + 1);
 
-        let col = span.start - line_start_index;
+        let col = Self::columns(&before[line_start_index..], tab_width);
 
         (file_path, line_num, col)
     }
 
-    /// Converts a Span into a Snippet by getting the source code for the Span
-    pub fn span_to_snippet(&self, span: &Span) -> Snippet {
-        let mut line_begin = span.start;
-        let mut line_end = span.end;
+    // The number of columns that the text takes up when displayed, with each tab expanded to
+    // tab_width spaces
+    fn columns(text: &str, tab_width: usize) -> usize {
+        text.chars()
+            .map(|c| if c == '\t' { tab_width } else { 1 })
+            .sum()
+    }
 
-        if self.source.chars().nth(span.start).unwrap() == '\n' {
-            line_begin -= 1;
-        }
+    /// Returns the exact source code that the span covers. Unlike a Snippet, this is not altered
+    /// for display
+    pub fn span_to_source(&self, span: &Span) -> &str {
+        &self.source[span.start..span.end]
+    }
 
-        // Look for the beginning of the line this span is on
-        while line_begin > 0 {
-            if self.source.chars().nth(line_begin).unwrap() != '\n' {
-                line_begin -= 1;
-            } else {
-                // Don't take the '\n' with us
-                line_begin += 1;
-                break;
-            }
-        }
+    /// Splits a Span into one Span for each line that it covers, not including the newlines or
+    /// the indentation of any line after the first. Lines that the Span covers nothing else of
+    /// are left out
+    pub fn span_lines(&self, span: &Span) -> Vec<Span> {
+        let mut lines = Vec::new();
+        let mut line_start = span.start;
 
-        // Look for the end of the line this span is on
-        while line_end < self.source.len() {
-            if self.source.chars().nth(line_end).unwrap() != '\n' {
-                line_end += 1;
+        for (index, line) in self.source[span.start..span.end].split('\n').enumerate() {
+            let indentation = if index == 0 {
+                0
             } else {
-                break;
+                line.len() - line.trim_start_matches([' ', '\t']).len()
+            };
+
+            let start = line_start + indentation;
+            let end = line_start + line.len();
+
+            if end > start {
+                lines.push(Span::new(start, end, span.file));
             }
+
+            line_start = end + 1;
         }
 
-        let line = (&self.source[line_begin..line_end])
-            .to_owned()
-            .replace("\t", "    ")
-            .replace("\n", " ");
+        lines
+    }
 
-        let before_start_col = span.start - line_begin;
-        let mut start_col = before_start_col;
+    /// Converts a Span into a Snippet by getting the source code for the Span. Tabs are expanded
+    /// to tab_width spaces
+    pub fn span_to_snippet(&self, span: &Span, tab_width: usize) -> Snippet {
+        let source_len = self.source.len();
 
-        for (col, c) in (&self.source[line_begin..line_end]).chars().enumerate() {
-            if col < before_start_col && c == '\t' {
-                start_col += 3;
-            }
+        // A span at the very end of the file, such as one pointing at where something was expected
+        // but the file ended instead, is shown as a single column just past the last line
+        if span.start >= source_len {
+            let line_begin = self.source.rfind('\n').map_or(0, |index| index + 1);
+
+            let mut line = self.source[line_begin..].replace('\t', &" ".repeat(tab_width));
+            let start_col = Self::columns(&self.source[line_begin..], tab_width);
+
+            line.push(' ');
+
+            return Snippet {
+                line,
+                start_col,
+                end_col: start_col + 1,
+            };
         }
 
-        let end_col = start_col + (span.end - span.start);
+        let end = span.end.clamp(span.start, source_len);
+
+        // A span that starts on a newline is shown at the end of the line the newline ends
+        let line_begin = self.source[..span.start]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+
+        // The line ends at the first newline that isn't part of the span, so that a span ending
+        // with a newline doesn't take the next line along with it
+        let search_from = end.saturating_sub(1).max(span.start);
+        let line_end = self.source[search_from..]
+            .find('\n')
+            .map_or(source_len, |index| search_from + index)
+            .max(end);
+
+        let line = (&self.source[line_begin..line_end])
+            .replace('\t', &" ".repeat(tab_width))
+            .replace('\n', " ");
+
+        let start_col = Self::columns(&self.source[line_begin..span.start], tab_width);
+        let end_col = start_col + Self::columns(&self.source[span.start..end], tab_width);
 
         Snippet {
             line,
@@ -663,16 +1194,33 @@ impl SourceFile {
 /// A Span is what Diagnostics use to display pieces of code. These can be turned into Snippets
 /// which actually contain the source code that these snippets point to so that the Diagnostic can
 /// be emitted.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
     pub file: usize,
+    /// The macro expansion that this Span was produced by, or 0 if it is in the source as written
+    pub expansion: usize,
 }
 
 impl Span {
     pub fn new(start: usize, end: usize, file: usize) -> Self {
-        Self { start, end, file }
+        Self {
+            start,
+            end,
+            file,
+            expansion: 0,
+        }
+    }
+
+    /// Returns the smallest Span that covers both this Span and the other one. Both are expected
+    /// to be in the same file, and the result is from the same macro expansion as this one
+    pub fn join(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            ..self
+        }
     }
 }
 
@@ -1,11 +1,16 @@
 #![allow(clippy::result_unit_err)]
 
 mod token;
+use std::{ops::Range, rc::Rc, slice::Iter};
+
 use logos::Logos;
 use token::RawToken;
 pub use token::*;
 
-use crate::session::Session;
+use crate::{
+    errors::{SourceFile, Span},
+    session::Session,
+};
 
 pub struct Lexer<'a, 'b> {
     inner: logos::Lexer<'a, RawToken>,
@@ -27,36 +32,75 @@ impl<'a, 'b> Lexer<'a, 'b> {
         }
     }
 
+    /// Creates a new lexer for only part of the source, so that the tokens still refer to where
+    /// they are in the whole source
+    pub fn with_range(
+        source: &'a str,
+        range: Range<usize>,
+        file_id: u8,
+        session: &'b Session,
+    ) -> Lexer<'a, 'b> {
+        Lexer {
+            inner: RawToken::lexer(&source[range.clone()]),
+            done: false,
+            current_index: range.start,
+            session,
+            file_id,
+        }
+    }
+
     /// This lexes the given input using the lexer. This returns a Result that contains a tuple of
     /// a token Vec and a Session that was provided when this lexer was created. This consumes the
     /// lexer
     pub fn lex(mut self) -> Result<Vec<Token>, ()> {
         let mut tokens = Vec::new();
         let mut fail = false;
+        // Adjacent unknown tokens are reported as one error, so that a run of garbage doesn't
+        // produce a diagnostic for every character
+        let mut unknown_run: Option<(Span, usize)> = None;
 
         // Get all of the tokens, one by one
         while let Some(token) = self.next() {
             // Check if this token is an error token
             if token.kind == TokenKind::Error {
-                self.session
-                    .struct_span_error(token.as_span(), "unknown token".to_string())
-                    .emit();
+                let span = token.as_span();
+
+                unknown_run = match unknown_run {
+                    Some((run, count)) if run.end == span.start => {
+                        Some((run.join(span), count + 1))
+                    }
+                    Some(run) => {
+                        self.emit_unknown_tokens(run);
+                        Some((span, 1))
+                    }
+                    None => Some((span, 1)),
+                };
 
                 fail = true;
-            } else if token.kind == TokenKind::JunkFloatError {
-                self.session
-                    .struct_span_error(
-                        token.as_span(),
-                        "invalid floating point literal".to_string(),
-                    )
-                    .emit();
+            } else {
+                if let Some(run) = unknown_run.take() {
+                    self.emit_unknown_tokens(run);
+                }
 
-                fail = true;
+                if token.kind == TokenKind::JunkFloatError {
+                    self.session
+                        .struct_span_error(
+                            token.as_span(),
+                            "invalid floating point literal".to_string(),
+                        )
+                        .emit();
+
+                    fail = true;
+                }
             }
 
             tokens.push(token);
         }
 
+        if let Some(run) = unknown_run {
+            self.emit_unknown_tokens(run);
+        }
+
         if fail {
             Err(())
         } else {
@@ -64,6 +108,18 @@ impl<'a, 'b> Lexer<'a, 'b> {
         }
     }
 
+    fn emit_unknown_tokens(&self, (span, count): (Span, usize)) {
+        let message = if count > 1 {
+            "unknown tokens"
+        } else {
+            "unknown token"
+        };
+
+        self.session
+            .struct_span_error(span, message.to_string())
+            .emit();
+    }
+
     // Properly gets the next token
     fn next(&mut self) -> Option<Token> {
         let raw_token = self.lex_raw()?;
@@ -101,51 +157,15 @@ impl<'a, 'b> Lexer<'a, 'b> {
             RawToken::OperatorGreaterEquals => TokenKind::OperatorGreaterEquals,
             RawToken::OperatorLessEquals => TokenKind::OperatorLessEquals,
 
-            RawToken::KeywordSection => TokenKind::KeywordSection,
-            RawToken::KeywordText => TokenKind::KeywordText,
-            RawToken::KeywordData => TokenKind::KeywordData,
-
-            RawToken::TypeI8 => TokenKind::TypeI8,
-            RawToken::TypeI16 => TokenKind::TypeI16,
-            RawToken::TypeI32 => TokenKind::TypeI32,
-            RawToken::TypeI32V => TokenKind::TypeI32V,
-            RawToken::TypeF64 => TokenKind::TypeF64,
-            RawToken::TypeF64V => TokenKind::TypeF64V,
-            RawToken::TypeS => TokenKind::TypeS,
-            RawToken::TypeSV => TokenKind::TypeSV,
-            RawToken::TypeB => TokenKind::TypeB,
-            RawToken::TypeBV => TokenKind::TypeBV,
-
-            RawToken::DirectiveDefine => TokenKind::DirectiveDefine,
-            RawToken::DirectiveMacro => TokenKind::DirectiveMacro,
-            RawToken::DirectiveEndmacro => TokenKind::DirectiveEndmacro,
-            RawToken::DirectiveRepeat => TokenKind::DirectiveRepeat,
-            RawToken::DirectiveEndRepeat => TokenKind::DirectiveEndRepeat,
-            RawToken::DirectiveInclude => TokenKind::DirectiveInclude,
-            RawToken::DirectiveExtern => TokenKind::DirectiveExtern,
-            RawToken::DirectiveGlobal => TokenKind::DirectiveGlobal,
-            RawToken::DirectiveLocal => TokenKind::DirectiveLocal,
-            RawToken::DirectiveLine => TokenKind::DirectiveLine,
-            RawToken::DirectiveType => TokenKind::DirectiveType,
-            RawToken::DirectiveValue => TokenKind::DirectiveValue,
-            RawToken::DirectiveUndef => TokenKind::DirectiveUndef,
-            RawToken::DirectiveUnmacro => TokenKind::DirectiveUnmacro,
-            RawToken::DirectiveFunc => TokenKind::DirectiveFunc,
-            RawToken::DirectiveIf => TokenKind::DirectiveIf,
-            RawToken::DirectiveIfNot => TokenKind::DirectiveIfNot,
-            RawToken::DirectiveIfDef => TokenKind::DirectiveIfDef,
-            RawToken::DirectiveIfNotDef => TokenKind::DirectiveIfNotDef,
-            RawToken::DirectiveElseIf => TokenKind::DirectiveElseIf,
-            RawToken::DirectiveElseIfNot => TokenKind::DirectiveElseIfNot,
-            RawToken::DirectiveElseIfDef => TokenKind::DirectiveElseIfDef,
-            RawToken::DirectiveElseIfNotDef => TokenKind::DirectiveElseIfNotDef,
-            RawToken::DirectiveElse => TokenKind::DirectiveElse,
-            RawToken::DirectiveEndIf => TokenKind::DirectiveEndIf,
-
             RawToken::Label => TokenKind::Label,
             RawToken::InnerLabel => TokenKind::InnerLabel,
 
-            RawToken::InnerLabelReference => TokenKind::InnerLabelReference,
+            // Directives are spelled like inner label references, so they are told apart here
+            RawToken::InnerLabelReference => self
+                .inner
+                .slice()
+                .parse()
+                .unwrap_or(TokenKind::InnerLabelReference),
 
             RawToken::Identifier => TokenKind::Identifier,
 
@@ -167,6 +187,8 @@ impl<'a, 'b> Lexer<'a, 'b> {
             RawToken::SymbolHash => TokenKind::SymbolHash,
             RawToken::SymbolAt => TokenKind::SymbolAt,
             RawToken::SymbolAnd => TokenKind::SymbolAnd,
+            RawToken::SymbolLeftBrace => TokenKind::SymbolLeftBrace,
+            RawToken::SymbolRightBrace => TokenKind::SymbolRightBrace,
 
             RawToken::Comment => TokenKind::Comment,
 
@@ -183,6 +205,7 @@ impl<'a, 'b> Lexer<'a, 'b> {
             file_id: self.file_id,
             source_index,
             len: len as u16,
+            expansion: 0,
         }
     }
 }
@@ -238,3 +261,38 @@ pub fn phase0(tokens: &mut Vec<Token>, session: &Session) -> Result<(), ()> {
         Ok(())
     }
 }
+
+/// An iterator over tokens along with their text, exactly as it appears in the source
+///
+/// The text is borrowed from the source files instead of being copied out for each token, so
+/// the files that the tokens came from are passed in. They can be gotten from
+/// `Session::source_files`
+pub struct TokenTexts<'a> {
+    tokens: Iter<'a, Token>,
+    files: &'a [Rc<SourceFile>],
+}
+
+impl<'a> TokenTexts<'a> {
+    /// Creates a new iterator over the tokens, which must all come from the provided files
+    pub fn new(tokens: &'a [Token], files: &'a [Rc<SourceFile>]) -> Self {
+        Self {
+            tokens: tokens.iter(),
+            files,
+        }
+    }
+}
+
+impl<'a> Iterator for TokenTexts<'a> {
+    type Item = (Token, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = *self.tokens.next()?;
+        let span = token.as_span();
+
+        Some((token, &self.files[span.file].source[span.start..span.end]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.tokens.size_hint()
+    }
+}
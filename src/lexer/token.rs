@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
 use logos::Logos;
 
 use crate::errors::Span;
+use crate::session::Session;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -46,12 +51,18 @@ pub enum TokenKind {
     DirectiveRepeat,
     DirectiveEndRepeat,
     DirectiveInclude,
+    DirectiveIncludeDecls,
+    DirectiveIncbin,
+    DirectiveAscii,
+    DirectiveAsciz,
     DirectiveExtern,
     DirectiveGlobal,
     DirectiveLocal,
+    DirectiveWeak,
     DirectiveLine,
     DirectiveType,
     DirectiveValue,
+    DirectiveSize,
     DirectiveUndef,
     DirectiveUnmacro,
     DirectiveFunc,
@@ -63,6 +74,10 @@ pub enum TokenKind {
     DirectiveElseIfNot,
     DirectiveElseIfDef,
     DirectiveElseIfNotDef,
+    DirectiveIfSection,
+    DirectiveIfNotSection,
+    DirectiveElseIfSection,
+    DirectiveElseIfNotSection,
     DirectiveElse,
     DirectiveEndIf,
 
@@ -95,6 +110,8 @@ pub enum TokenKind {
     SymbolHash,
     SymbolAt,
     SymbolAnd,
+    SymbolLeftBrace,
+    SymbolRightBrace,
 
     Comment,
 
@@ -103,126 +120,102 @@ pub enum TokenKind {
     JunkFloatError,
 }
 
+/// The spelling of every token that starts with a `.`, which are the section keywords, data types,
+/// and directives. The lexer lexes all of these as if they were inner label references, and then
+/// looks them up here, so this is the only place that they are spelled out
+///
+/// Some kinds are listed more than once. The first spelling of a kind is the one used when
+/// printing it, and the rest are aliases
+pub const DIRECTIVE_SPELLINGS: [(&str, TokenKind); 53] = [
+    (".section", TokenKind::KeywordSection),
+    (".text", TokenKind::KeywordText),
+    (".data", TokenKind::KeywordData),
+    (".i8", TokenKind::TypeI8),
+    (".i16", TokenKind::TypeI16),
+    (".i32", TokenKind::TypeI32),
+    (".i32v", TokenKind::TypeI32V),
+    (".f64", TokenKind::TypeF64),
+    (".f64v", TokenKind::TypeF64V),
+    (".s", TokenKind::TypeS),
+    (".sv", TokenKind::TypeSV),
+    (".b", TokenKind::TypeB),
+    (".bv", TokenKind::TypeBV),
+    (".define", TokenKind::DirectiveDefine),
+    (".macro", TokenKind::DirectiveMacro),
+    (".endmacro", TokenKind::DirectiveEndmacro),
+    (".rep", TokenKind::DirectiveRepeat),
+    (".endrep", TokenKind::DirectiveEndRepeat),
+    (".include", TokenKind::DirectiveInclude),
+    (".include_decls", TokenKind::DirectiveIncludeDecls),
+    (".incbin", TokenKind::DirectiveIncbin),
+    (".ascii", TokenKind::DirectiveAscii),
+    (".asciz", TokenKind::DirectiveAsciz),
+    (".extern", TokenKind::DirectiveExtern),
+    (".global", TokenKind::DirectiveGlobal),
+    (".local", TokenKind::DirectiveLocal),
+    (".weak", TokenKind::DirectiveWeak),
+    (".line", TokenKind::DirectiveLine),
+    (".type", TokenKind::DirectiveType),
+    (".value", TokenKind::DirectiveValue),
+    (".size", TokenKind::DirectiveSize),
+    (".undef", TokenKind::DirectiveUndef),
+    (".unmacro", TokenKind::DirectiveUnmacro),
+    (".func", TokenKind::DirectiveFunc),
+    (".if", TokenKind::DirectiveIf),
+    (".ifn", TokenKind::DirectiveIfNot),
+    (".ifdef", TokenKind::DirectiveIfDef),
+    (".ifndef", TokenKind::DirectiveIfNotDef),
+    (".elif", TokenKind::DirectiveElseIf),
+    (".elifn", TokenKind::DirectiveElseIfNot),
+    (".elifdef", TokenKind::DirectiveElseIfDef),
+    (".elifndef", TokenKind::DirectiveElseIfNotDef),
+    (".ifsection", TokenKind::DirectiveIfSection),
+    (".ifnsection", TokenKind::DirectiveIfNotSection),
+    (".elifsection", TokenKind::DirectiveElseIfSection),
+    (".elifnsection", TokenKind::DirectiveElseIfNotSection),
+    (".else", TokenKind::DirectiveElse),
+    (".endif", TokenKind::DirectiveEndIf),
+    // Aliases
+    (".byte", TokenKind::TypeI8),
+    (".half", TokenKind::TypeI16),
+    (".word", TokenKind::TypeI32),
+    (".long", TokenKind::TypeI32),
+    (".globl", TokenKind::DirectiveGlobal),
+];
+
+impl TokenKind {
+    /// Returns how this kind of token is spelled, if it is a section keyword, data type, or
+    /// directive
+    pub fn directive_str(self) -> Option<&'static str> {
+        DIRECTIVE_SPELLINGS
+            .iter()
+            .find(|(_, kind)| *kind == self)
+            .map(|(spelling, _)| *spelling)
+    }
+}
+
+impl FromStr for TokenKind {
+    type Err = ();
+
+    /// Looks up the kind of a section keyword, data type, or directive by how it is spelled
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Every word starting with a `.` is looked up, so the spellings are hashed the first time
+        static KINDS: OnceLock<HashMap<&str, TokenKind>> = OnceLock::new();
+
+        KINDS
+            .get_or_init(|| DIRECTIVE_SPELLINGS.iter().copied().collect())
+            .get(s)
+            .copied()
+            .ok_or(())
+    }
+}
+
 /// These are the raw tokens produced by Logos
 #[derive(Debug, Clone, Copy, Logos, PartialEq, Eq)]
 pub enum RawToken {
     #[error]
     Error,
 
-    #[token(".section")]
-    KeywordSection,
-
-    #[token(".text")]
-    KeywordText,
-
-    #[token(".data")]
-    KeywordData,
-
-    #[token(".i8")]
-    TypeI8,
-
-    #[token(".i16")]
-    TypeI16,
-
-    #[token(".i32")]
-    TypeI32,
-
-    #[token(".i32v")]
-    TypeI32V,
-
-    #[token(".f64")]
-    TypeF64,
-
-    #[token(".f64v")]
-    TypeF64V,
-
-    #[token(".s")]
-    TypeS,
-
-    #[token(".sv")]
-    TypeSV,
-
-    #[token(".b")]
-    TypeB,
-
-    #[token(".bv")]
-    TypeBV,
-
-    #[token(".define")]
-    DirectiveDefine,
-
-    #[token(".macro")]
-    DirectiveMacro,
-
-    #[token(".endmacro")]
-    DirectiveEndmacro,
-
-    #[token(".rep")]
-    DirectiveRepeat,
-
-    #[token(".endrep")]
-    DirectiveEndRepeat,
-
-    #[token(".include")]
-    DirectiveInclude,
-
-    #[token(".extern")]
-    DirectiveExtern,
-
-    #[token(".global")]
-    DirectiveGlobal,
-
-    #[token(".local")]
-    DirectiveLocal,
-
-    #[token(".line")]
-    DirectiveLine,
-
-    #[token(".type")]
-    DirectiveType,
-
-    #[token(".value")]
-    DirectiveValue,
-
-    #[token(".undef")]
-    DirectiveUndef,
-
-    #[token(".unmacro")]
-    DirectiveUnmacro,
-
-    #[token(".func")]
-    DirectiveFunc,
-
-    #[token(".if")]
-    DirectiveIf,
-
-    #[token(".ifn")]
-    DirectiveIfNot,
-
-    #[token(".ifdef")]
-    DirectiveIfDef,
-
-    #[token(".ifndef")]
-    DirectiveIfNotDef,
-
-    #[token(".elif")]
-    DirectiveElseIf,
-
-    #[token(".elifn")]
-    DirectiveElseIfNot,
-
-    #[token(".elifdef")]
-    DirectiveElseIfDef,
-
-    #[token(".elifndef")]
-    DirectiveElseIfNotDef,
-
-    #[token(".else")]
-    DirectiveElse,
-
-    #[token(".endif")]
-    DirectiveEndIf,
-
     #[regex(r"\.[_a-zA-Z][_a-zA-Z0-9]*")]
     InnerLabelReference,
 
@@ -247,7 +240,14 @@ pub enum RawToken {
     #[regex(r"[0-9]+")]
     LiteralInteger,
 
-    #[regex(r"[0-9]+\.[0-9]+")]
+    // Separators are allowed anywhere after the first digit, but only some placements are valid.
+    // The rest are reported when the literal is parsed
+    #[regex(r"[0-9][0-9_]*\.[0-9_]+")]
+    #[regex(r"\.[0-9][0-9_]*")]
+    #[regex(
+        r"([0-9][0-9_]*\.[0-9_]+|\.[0-9][0-9_]*|[0-9][0-9_]*)[eE][+-]?[0-9_]+",
+        priority = 5
+    )]
     LiteralFloat,
 
     #[regex(r"[0-9]+\.[0-9\S]*")]
@@ -331,6 +331,12 @@ pub enum RawToken {
     #[token("&")]
     SymbolAnd,
 
+    #[token("{")]
+    SymbolLeftBrace,
+
+    #[token("}")]
+    SymbolRightBrace,
+
     #[regex(r";[^\n]*")]
     Comment,
 }
@@ -349,6 +355,10 @@ pub struct Token {
 
     /// The length of the token in the source
     pub len: u16,
+
+    /// The macro expansion that produced this token, or 0 if it was written directly in the
+    /// source
+    pub expansion: u32,
 }
 
 impl Token {
@@ -358,6 +368,15 @@ impl Token {
             start: self.source_index as usize,
             end: (self.source_index + (self.len as u32)) as usize,
             file: self.file_id as usize,
+            expansion: self.expansion as usize,
         }
     }
+
+    /// Returns the text of this token, as it appears in the source
+    pub fn resolve(&self, session: &Session) -> String {
+        session
+            .span_to_snippet(&self.as_span())
+            .as_slice()
+            .to_string()
+    }
 }
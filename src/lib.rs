@@ -1,10 +1,12 @@
 #![allow(clippy::result_unit_err)]
 
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use clap::{ArgAction, Parser};
-use errors::SourceFile;
-use kerbalobjects::ko::WritableKOFile;
+use errors::{ColorMode, Diagnostic, SourceFile};
+use kerbalobjects::ko::{KOFile, WritableKOFile};
+use kerbalobjects::BufferIterator;
 
 pub mod errors;
 pub mod session;
@@ -14,14 +16,24 @@ pub mod output;
 pub mod parser;
 pub mod preprocessor;
 
-use lexer::Token;
-use session::Session;
+use lexer::{Token, TokenKind, TokenTexts};
+use session::{OpcodeTable, Session};
 
 use crate::{
-    lexer::{phase0, Lexer, TokenKind},
-    output::{generator::Generator, Verifier},
-    parser::parse,
-    preprocessor::executor::Executor,
+    lexer::{phase0, Lexer},
+    output::{
+        disassembler::Disassembler, generator::Generator, symbol_dump::symbols_to_json,
+        VerifiedFunction, Verifier,
+    },
+    parser::{
+        parse::{self, ParseOutput, ParsedFunction},
+        peephole, LabelManager, SymbolManager,
+    },
+    preprocessor::{
+        directives::{expand_custom_directives, CustomDirectives},
+        executor::Executor,
+        past::PASTNode,
+    },
 };
 
 pub static VERSION: &'_ str = env!("CARGO_PKG_VERSION");
@@ -63,14 +75,27 @@ pub struct Config {
         conflicts_with("run_preprocessor")
     )]
     pub preprocess_only: bool,
-    /// If specified, instead of the preprocessor looking at the current working directory for
-    /// files to include, it will search the provided path
+    /// If the preprocessed source should have a .line directive wherever its lines stop following
+    /// on from each other, such as around included files and macro expansions. Assembling the
+    /// output, or reading it, then still leads back to where each line originally came from
     #[arg(
-        short = 'i',
-        long = "include-path",
-        help = "Specifies the include path for the assembler. Defaults to the current working directory"
+        long = "line-markers",
+        help = "Marks where each line of the preprocessed source came from with .line directives",
+        requires("preprocess_only")
     )]
-    pub include_path: Option<PathBuf>,
+    pub line_markers: bool,
+    /// Directories that the preprocessor searches, in order, for files to include. If a file is
+    /// not found in any of them, the directory of the including file is searched, followed by the
+    /// current working directory
+    #[arg(
+        short = 'I',
+        long = "include-dir",
+        visible_short_alias = 'i',
+        visible_alias = "include-path",
+        value_name = "DIR",
+        help = "Adds a directory to the include search path. May be given multiple times, and directories are searched in order"
+    )]
+    pub include_paths: Vec<PathBuf>,
     /// If specified, instead of the object file's "file" symbol being set to the name of the input
     /// file, it will be set to this provided value. This can be useful when creating a compiler
     /// with KASM as it allows you to use the source file's name and not the assembled file's name.
@@ -90,15 +115,214 @@ pub struct Config {
         default_value_t = format!("Compiled by KASM {}", VERSION)
     )]
     pub comment: String,
+    /// Single-line macros to define before the input file is preprocessed, in the form NAME or
+    /// NAME=VALUE. These act exactly as if the source file began with a .define for each
+    #[arg(
+        short = 'D',
+        long = "define",
+        value_name = "NAME[=VALUE]",
+        help = "Defines a macro before preprocessing, as if by .define. May be given multiple times"
+    )]
+    pub defines: Vec<String>,
+    /// If warnings should be emitted for labels that are never referenced, and macros that
+    /// are never used. This is off by default
+    #[arg(
+        long = "warn-unused",
+        help = "Warns about labels that are never referenced and macros that are never used"
+    )]
+    pub warn_unused: bool,
+    /// If warnings should be emitted for instructions that can never run, because they follow an
+    /// unconditional jump or return with no label in between. This is off by default
+    #[arg(
+        long = "warn-unreachable",
+        help = "Warns about instructions after a jmp or ret that no label makes reachable"
+    )]
+    pub warn_unreachable: bool,
+    /// If warnings should be emitted when a .rep with a count of 0, or an .if with a condition that
+    /// is always false, discards contents that aren't empty. Only counts and conditions made of
+    /// literals are checked, and not inside of macros, since ones that depend on a macro are
+    /// usually meant to be switched off
+    #[arg(
+        long = "warn-discarded",
+        help = "Warns about .rep 0 and always false .if blocks that discard their contents"
+    )]
+    pub warn_discarded: bool,
+    /// If symbols must be declared with .local, .global, or .extern before they are given a value.
+    /// By default, a data entry for an undeclared symbol implicitly declares it as local
+    #[arg(
+        long = "strict",
+        help = "Requires symbols to be declared with .local, .global, or .extern before being defined"
+    )]
+    pub strict: bool,
+    /// If instructions that obviously do nothing should be removed after parsing, such as a push
+    /// that is immediately popped. This never changes what the program does, and is off by default
+    /// so that the object file matches the source exactly
+    #[arg(
+        long = "optimize",
+        help = "Removes redundant instructions, such as a push immediately followed by a pop"
+    )]
+    pub optimize: bool,
+    /// If even warnings should be suppressed, leaving only errors
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        help = "Suppresses all output except for errors, including warnings",
+        conflicts_with("verbose")
+    )]
+    pub quiet: bool,
+    /// If notes about the progress of assembly should be emitted, such as which files were read
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        help = "Displays notes about the progress of assembly, such as files read and bytes written"
+    )]
+    pub verbose: bool,
+    /// If the time spent in each phase of assembly should be reported once it is done. This is
+    /// meant for finding out why a large file is slow to assemble
+    #[arg(
+        long = "time-report",
+        help = "Reports the time spent lexing, preprocessing, parsing, verifying, and generating"
+    )]
+    pub time_report: bool,
+    /// The maximum number of columns of a source line shown in a diagnostic. Longer lines are
+    /// trimmed around the code being pointed at. 0 disables trimming
+    #[arg(
+        long = "max-line-width",
+        value_name = "COLUMNS",
+        default_value_t = 120,
+        help = "Trims source lines in diagnostics that are longer than this many columns, 0 to disable"
+    )]
+    pub max_line_width: usize,
+    /// The number of columns that a tab is expanded to, both when showing source lines in
+    /// diagnostics and when reporting the column that something is at
+    #[arg(
+        long = "tab-width",
+        value_name = "COLUMNS",
+        default_value_t = 4,
+        help = "The number of columns a tab is displayed as in diagnostics"
+    )]
+    pub tab_width: usize,
+    /// When diagnostics should be colored. By default they are only colored if they are being
+    /// written to a terminal
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        value_enum,
+        default_value_t = ColorMode::Auto,
+        help = "Controls when diagnostics are colored"
+    )]
+    pub color: ColorMode,
+    /// The maximum number of macro expansions that can be nested inside of each other before
+    /// preprocessing is stopped. This catches macros that end up expanding to themselves
+    #[arg(
+        long = "max-expansion-depth",
+        value_name = "DEPTH",
+        default_value_t = 128,
+        help = "The maximum depth of nested macro expansions"
+    )]
+    pub max_expansion_depth: usize,
+    /// If the source code should be stored in a .source section of the object file, so that a
+    /// debugger can show it alongside the code. This makes the object file larger by about the
+    /// size of the source, so it is off by default
+    #[arg(
+        long = "embed-source",
+        value_name = "FORM",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "original",
+        help = "Stores the original or preprocessed source in a .source section of the object file"
+    )]
+    pub embed_source: Option<EmbeddedSource>,
+    /// If specified, the symbol that is the program's entry point. Its name is stored in an
+    /// .entry section of the object file, so that a compiler generating KASM can choose the entry
+    /// explicitly instead of relying on it being named _start
+    #[arg(
+        long = "entry",
+        value_name = "SYMBOL",
+        help = "Marks the given function as the entry point, recorded in an .entry section of the object file"
+    )]
+    pub entry: Option<String>,
+    /// If specified, where the symbols and labels are written out as JSON once the source has
+    /// been parsed. This is mostly useful for debugging how symbols end up being declared across
+    /// included files. A path of `-` writes them to stdout
+    #[arg(
+        long = "dump-symbols",
+        value_name = "PATH",
+        help = "Writes the parsed symbols and labels as JSON to the given path, or - for stdout"
+    )]
+    pub dump_symbols: Option<PathBuf>,
+    /// If instead of assembling, the token stream after preprocessing should be output. This is
+    /// only meant for debugging the lexer and preprocessor
+    #[arg(
+        long = "emit-tokens",
+        hide = true,
+        help = "Instead of outputting an object file, emits each token after the preprocessing step",
+        conflicts_with("preprocess_only")
+    )]
+    pub emit_tokens: bool,
+    /// Directives that are added by library users, which are replaced by the source code that
+    /// their handlers return
+    #[arg(skip)]
+    pub directives: CustomDirectives,
+}
+
+/// The same configuration that the command line interface uses when no flags are given, except
+/// that the root directory is empty if the current directory can't be found
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            emit_errors: true,
+            emit_warnings: true,
+            root_dir: std::env::current_dir().unwrap_or_default(),
+            run_preprocessor: true,
+            preprocess_only: false,
+            line_markers: false,
+            include_paths: Vec::new(),
+            file_sym_name: None,
+            comment: format!("Compiled by KASM {}", VERSION),
+            defines: Vec::new(),
+            warn_unused: false,
+            warn_unreachable: false,
+            warn_discarded: false,
+            strict: false,
+            optimize: false,
+            quiet: false,
+            verbose: false,
+            time_report: false,
+            max_line_width: 120,
+            tab_width: 4,
+            color: ColorMode::Auto,
+            max_expansion_depth: 128,
+            embed_source: None,
+            entry: None,
+            dump_symbols: None,
+            emit_tokens: false,
+            directives: CustomDirectives::default(),
+        }
+    }
+}
+
+/// Which form of the source code is embedded into the object file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum EmbeddedSource {
+    /// The primary source file exactly as it was read
+    Original,
+    /// The source after the preprocessor has run, as output by --preprocess-only
+    Preprocessed,
 }
 
 /// Configuration parameters, but for exclusive use by a command line interface
 #[derive(Debug, Clone, Parser)]
 #[command(author, version, about = "Kerbal Assembler", long_about = None)]
 pub struct CLIConfig {
-    /// The input file path to load
-    #[arg(value_name = "INPUT", help = "Sets the input file")]
-    pub input_path: PathBuf,
+    /// The input file path to load, which is only optional when listing the opcodes
+    #[arg(
+        value_name = "INPUT",
+        help = "Sets the input file",
+        required_unless_present = "list_opcodes"
+    )]
+    pub input_path: Option<PathBuf>,
     /// The output file path, which is now optional. If none is provided
     /// the file name will be the same as the input file, and the file extension
     /// is inferred by the assembler flags in Config
@@ -109,6 +333,13 @@ pub struct CLIConfig {
         help = "Sets the output path to use"
     )]
     pub output_path: Option<PathBuf>,
+    /// Prints every instruction along with its number of operands, then exits without
+    /// assembling anything
+    #[arg(
+        long = "list-opcodes",
+        help = "Prints every supported instruction and how many operands it takes, then exits"
+    )]
+    pub list_opcodes: bool,
     #[command(flatten)]
     pub base_config: Config,
 }
@@ -121,10 +352,268 @@ pub enum AssemblyOutput {
     Source(String),
 }
 
+impl AssemblyOutput {
+    /// Writes the object file or source code to the end of the provided buffer
+    pub fn write(&self, buffer: &mut Vec<u8>) {
+        match self {
+            AssemblyOutput::Object(object) => object.write(buffer),
+            AssemblyOutput::Source(source) => buffer.extend_from_slice(source.as_bytes()),
+        }
+    }
+}
+
+/// Everything that was produced while assembling a file with `assemble_full`, kept in memory
+pub struct FullArtifacts {
+    /// The assembled object file
+    pub object: WritableKOFile,
+    /// The tokens after preprocessing, which can be resolved back into source code using `session`
+    pub tokens: Vec<Token>,
+    /// The functions as they were parsed, before being verified
+    pub functions: Vec<ParsedFunction>,
+    pub label_manager: LabelManager,
+    pub symbol_manager: SymbolManager,
+    /// Any warnings or notes that were emitted, since assembly succeeded
+    pub diagnostics: Vec<Diagnostic>,
+    pub session: Session,
+}
+
+/// Lists every instruction that can be assembled, one per line, along with how many operands it
+/// takes. This comes from the same table that the assembler looks instructions up in
+pub fn opcode_listing() -> String {
+    let opcodes = OpcodeTable::new().opcodes();
+
+    let mnemonics: Vec<&str> = opcodes.iter().map(|opcode| (*opcode).into()).collect();
+    let width = mnemonics
+        .iter()
+        .map(|mnemonic| mnemonic.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut output = String::new();
+
+    for (opcode, mnemonic) in opcodes.iter().zip(mnemonics) {
+        output.push_str(&format!(
+            "{:width$} {}\n",
+            mnemonic,
+            opcode.num_operands(),
+            width = width
+        ));
+    }
+
+    output
+}
+
 /// Assemble a file given by a provided path
 pub fn assemble_path(path: &Path, config: Config) -> Result<AssemblyOutput, ()> {
+    let mut session = session_from_path(path, config)?;
+
+    assemble(&mut session)
+}
+
+/// Assemble a file given by a string
+pub fn assemble_string(source: String, config: Config) -> Result<AssemblyOutput, ()> {
+    let mut session = session_from_string(source, config);
+
+    assemble(&mut session)
+}
+
+/// Assemble a file given by a provided path, writing the output into the provided buffer instead
+/// of returning it. The buffer is cleared first, so that one buffer can be reused for many files
+/// without allocating again each time. If assembly fails, the buffer is left empty
+pub fn assemble_path_into(path: &Path, config: Config, buffer: &mut Vec<u8>) -> Result<(), ()> {
+    buffer.clear();
+
+    assemble_path(path, config)?.write(buffer);
+
+    Ok(())
+}
+
+/// Assemble a file given by a string, writing the output into the provided buffer instead of
+/// returning it. Like `assemble_path_into`, the buffer is cleared first
+pub fn assemble_string_into(
+    source: String,
+    config: Config,
+    buffer: &mut Vec<u8>,
+) -> Result<(), ()> {
+    buffer.clear();
+
+    assemble_string(source, config)?.write(buffer);
+
+    Ok(())
+}
+
+/// Checks if a file given by a string would assemble, without generating an object file. This
+/// runs everything up to and including verification. Instead of being written out, every
+/// diagnostic is returned along with whether or not the check succeeded
+pub fn check_string(source: String, config: Config) -> (bool, Vec<Diagnostic>) {
+    let mut session = Session::new_collecting(config);
+
+    let source_file = SourceFile::new("<input>".to_owned(), None, None, source, 0);
+
+    // A new Session has no files yet, so there is always room for this one
+    session.add_file(source_file).unwrap();
+
+    let success = check(&mut session).is_ok();
+
+    (success, session.take_collected_diagnostics())
+}
+
+/// Assembles a file given by a string without writing anything out, and keeps every intermediate
+/// result along with the object file. This is meant for tools such as test harnesses that want to
+/// look at more than the output. An object file is always generated, even if the Config asks for
+/// preprocessed source instead. If assembly fails, every diagnostic is returned instead
+pub fn assemble_full(source: String, config: Config) -> Result<FullArtifacts, Vec<Diagnostic>> {
+    let mut session = Session::new_collecting(config);
+
+    let source_file = SourceFile::new("<input>".to_owned(), None, None, source, 0);
+
+    // A new Session has no files yet, so there is always room for this one
+    session.add_file(source_file).unwrap();
+
+    let tokens = match preprocess(&mut session) {
+        Ok(tokens) => tokens,
+        Err(()) => return Err(session.take_collected_diagnostics()),
+    };
+
+    match assemble_artifacts(tokens.clone(), &session) {
+        Ok((object, functions, label_manager, symbol_manager)) => Ok(FullArtifacts {
+            object,
+            tokens,
+            functions,
+            label_manager,
+            symbol_manager,
+            diagnostics: session.take_collected_diagnostics(),
+            session,
+        }),
+        Err(()) => Err(session.take_collected_diagnostics()),
+    }
+}
+
+/// Assemble each file given by the provided paths, returning a result for each in the same order.
+/// Every file is assembled separately, but all diagnostics go through one handler, which also
+/// reports how many of the files were assembled successfully
+///
+/// With the `parallel` feature enabled, the files are split between several threads. Diagnostics
+/// are still written out in the same order as the files were given
+pub fn assemble_many(paths: &[PathBuf], config: Config) -> Vec<Result<AssemblyOutput, ()>> {
+    // Only the sequential path reuses the Session for each file
+    #[allow(unused_mut)]
     let mut session = Session::new(config);
 
+    #[cfg(not(feature = "parallel"))]
+    let results = assemble_each(&mut session, paths);
+
+    #[cfg(feature = "parallel")]
+    let results = assemble_each_parallel(&session, paths);
+
+    let num_assembled = results.iter().filter(|result| result.is_ok()).count();
+    let summary = format!("{} of {} files assembled", num_assembled, results.len());
+
+    if num_assembled == results.len() {
+        session.struct_note(summary).emit();
+    } else {
+        session.struct_error(summary).emit();
+    }
+
+    results
+}
+
+/// Runs only the lexer and preprocessor on a file given by a provided path, returning the
+/// macro-expanded tokens. The returned Session can be used to resolve the tokens' spans back into
+/// source code, for example with `Session::span_to_source`
+pub fn preprocess_path(path: &Path, config: Config) -> Result<(Vec<Token>, Session), ()> {
+    let mut session = session_from_path(path, config)?;
+
+    let tokens = preprocess(&mut session)?;
+
+    Ok((tokens, session))
+}
+
+/// Runs only the lexer and preprocessor on a file given by a string, returning the
+/// macro-expanded tokens along with the Session needed to resolve their spans
+pub fn preprocess_string(source: String, config: Config) -> Result<(Vec<Token>, Session), ()> {
+    let mut session = session_from_string(source, config);
+
+    let tokens = preprocess(&mut session)?;
+
+    Ok((tokens, session))
+}
+
+/// Runs the lexer and the preprocessor's parser on a file given by a string, returning the parsed
+/// PAST nodes without executing them. This is mostly useful for checking the tree that the parser
+/// builds
+pub fn parse_past_string(source: String, config: Config) -> Result<(Vec<PASTNode>, Session), ()> {
+    let session = session_from_string(source, config);
+
+    let tokens = lex(&session)?;
+
+    let nodes = parse_past(tokens, &session)?;
+
+    Ok((nodes, session))
+}
+
+// Assembles each file one after the other, reusing the provided Session
+#[cfg(not(feature = "parallel"))]
+fn assemble_each(session: &mut Session, paths: &[PathBuf]) -> Vec<Result<AssemblyOutput, ()>> {
+    paths
+        .iter()
+        .map(|path| {
+            // Each file gets its own set of source files, so that spans can't cross between them
+            session.clear_files();
+
+            read_primary_file(session, path)?;
+
+            assemble(session)
+        })
+        .collect()
+}
+
+// Assembles the files on rayon's thread pool, each file with its own Session. Each file's
+// diagnostics are buffered, and then written out through the provided Session in the same order as
+// the files were given
+#[cfg(feature = "parallel")]
+fn assemble_each_parallel(session: &Session, paths: &[PathBuf]) -> Vec<Result<AssemblyOutput, ()>> {
+    use rayon::prelude::*;
+
+    let config = session.config().clone();
+
+    let outputs: Vec<_> = paths
+        .par_iter()
+        .map(|path| {
+            let mut file_session = Session::new_buffered(config.clone());
+
+            let result = read_primary_file(&mut file_session, path)
+                .and_then(|_| assemble(&mut file_session));
+
+            (result, file_session.take_diagnostics())
+        })
+        .collect();
+
+    outputs
+        .into_iter()
+        .map(|(result, diagnostics)| {
+            if let Some(diagnostics) = diagnostics {
+                session.emit_diagnostics(&diagnostics);
+            }
+
+            result
+        })
+        .collect()
+}
+
+/// Creates a Session with the file at the provided path read in as the primary source file, ready
+/// to be given to the stages of assembly starting with `lex` or `preprocess`
+pub fn session_from_path(path: &Path, config: Config) -> Result<Session, ()> {
+    let mut session = Session::new(config);
+
+    read_primary_file(&mut session, path)?;
+
+    Ok(session)
+}
+
+// Reads the file at the provided path in as the primary source file of a Session that has no
+// files yet
+fn read_primary_file(session: &mut Session, path: &Path) -> Result<(), ()> {
     // Check if we have been given a valid file
     if !session.is_file(path) {
         session
@@ -135,8 +624,45 @@ pub fn assemble_path(path: &Path, config: Config) -> Result<AssemblyOutput, ()>
     }
 
     // Read it
-    match session.read_file(path) {
-        Ok(_) => {}
+    if let Err(e) = session.read_file(path) {
+        session.struct_read_error(path, e).emit();
+
+        return Err(());
+    }
+
+    Ok(())
+}
+
+/// Creates a Session with the provided string as the primary source file, ready to be given to
+/// the stages of assembly starting with `lex` or `preprocess`
+pub fn session_from_string(source: String, config: Config) -> Session {
+    let mut session = Session::new(config);
+
+    // Create a SourceFile but with some dummy values
+    let source_file = SourceFile::new("<input>".to_owned(), None, None, source, 0);
+
+    // A new Session has no files yet, so there is always room for this one
+    session.add_file(source_file).unwrap();
+
+    session
+}
+
+/// Disassemble a KerbalObject file given by a provided path back into KASM source code
+pub fn disassemble_path(path: &Path, config: Config) -> Result<String, ()> {
+    let session = Session::new(config);
+
+    // Check if we have been given a valid file
+    if !session.is_file(path) {
+        session
+            .struct_error(format!("input `{}` is not a file", path.to_string_lossy()))
+            .emit();
+
+        return Err(());
+    }
+
+    // Object files are binary, so they can't be read in as a SourceFile
+    let buffer = match std::fs::read(path) {
+        Ok(buffer) => buffer,
         Err(e) => {
             session
                 .struct_bug(format!(
@@ -150,160 +676,368 @@ pub fn assemble_path(path: &Path, config: Config) -> Result<AssemblyOutput, ()>
         }
     };
 
-    assemble(session)
-}
+    let mut buffer_iterator = BufferIterator::new(&buffer);
 
-/// Assemble a file given by a string
-pub fn assemble_string(source: String, config: Config) -> Result<AssemblyOutput, ()> {
-    let mut session = Session::new(config);
+    let kofile = match KOFile::parse(&mut buffer_iterator) {
+        Ok(kofile) => kofile,
+        Err(e) => {
+            session
+                .struct_error(format!(
+                    "`{}` is not a valid object file: {}",
+                    path.to_string_lossy(),
+                    e
+                ))
+                .emit();
 
-    // Create a SourceFile but with some dummy values
-    let source_file = SourceFile::new("<input>".to_owned(), None, None, source, 0);
+            return Err(());
+        }
+    };
+
+    session
+        .struct_note(format!(
+            "read object file `{}` ({} bytes)",
+            path.to_string_lossy(),
+            buffer.len()
+        ))
+        .emit();
 
-    session.add_file(source_file);
+    let disassembler = Disassembler::new(&session, &kofile);
 
-    assemble(session)
+    disassembler.disassemble()
 }
 
-// The core of the assembler. The actual function that runs everything else
-// This should be called with a session that already has the primary source file read
-fn assemble(mut session: Session) -> Result<AssemblyOutput, ()> {
+/// Lexes the primary source file of the session, and replaces its comments and line
+/// continuations. This is the first stage of assembly, and its tokens can be given to either
+/// `parse_past` or, if the preprocessor isn't needed, straight to `parse_tokens`
+pub fn lex(session: &Session) -> Result<Vec<Token>, ()> {
     let primary_file = session.get_file(0).unwrap();
 
     // Create the lexer
-    let lexer = Lexer::new(&primary_file.source, 0, &session);
+    let lexer = Lexer::new(&primary_file.source, 0, session);
 
     // Lex the tokens, if they are all valid
-    let mut tokens = lexer.lex()?;
+    let start = Instant::now();
+    let lexed = lexer.lex();
+    session.record_time("lexing", start);
+
+    let mut tokens = lexed?;
 
     // Replace comments and line continuations
-    phase0(&mut tokens, &session)?;
+    let start = Instant::now();
+    let replaced = phase0(&mut tokens, session);
+    session.record_time("phase0", start);
+
+    replaced.map(|_| tokens)
+}
+
+/// Parses lexed tokens into the preprocessor's PAST nodes, without executing any of them
+pub fn parse_past(tokens: Vec<Token>, session: &Session) -> Result<Vec<PASTNode>, ()> {
+    let preprocessor_parser = preprocessor::parser::Parser::new(tokens, session);
+
+    preprocessor_parser.parse()
+}
+
+/// Executes PAST nodes, expanding every macro and directive, and returns the resulting tokens
+pub fn execute_past(nodes: Vec<PASTNode>, session: &mut Session) -> Result<Vec<Token>, ()> {
+    let executor = Executor::new(session);
+
+    executor.execute(nodes)
+}
+
+/// Lexes the primary source file and runs the preprocessor on it if that is enabled in the
+/// session's Config. This is `lex`, `parse_past`, and `execute_past` run one after the other
+pub fn preprocess(session: &mut Session) -> Result<Vec<Token>, ()> {
+    let mut tokens = lex(session)?;
 
     // If we should run the preprocessor
     if session.config().run_preprocessor {
-        let preprocessor_parser = preprocessor::parser::Parser::new(tokens, &session);
+        let start = Instant::now();
+
+        let executed = parse_past(tokens, session).and_then(|nodes| execute_past(nodes, session));
+
+        session.record_time("preprocessing", start);
+
+        tokens = executed?;
+    }
+
+    if !session.config().directives.is_empty() {
+        tokens = expand_custom_directives(tokens, session)?;
+    }
+
+    Ok(tokens)
+}
+
+/// Parses preprocessed tokens into functions, executing the remaining assembler directives to
+/// collect the labels, symbols, and included binary files
+pub fn parse_tokens(tokens: Vec<Token>, session: &Session) -> Result<ParseOutput, ()> {
+    let start = Instant::now();
+    let parser = parse::Parser::new(tokens, session);
+
+    let output = parser.parse();
+    session.record_time("parsing", start);
+
+    output
+}
+
+/// Removes redundant instructions from parsed functions, moving labels to match. Returns the number
+/// of instructions that were removed
+pub fn optimize(functions: &mut [ParsedFunction], label_manager: &mut LabelManager) -> usize {
+    peephole::optimize(functions, label_manager)
+}
+
+/// Checks parsed functions against the labels and symbols that were parsed with them
+pub fn verify(
+    functions: Vec<ParsedFunction>,
+    label_manager: &LabelManager,
+    symbol_manager: &SymbolManager,
+    session: &Session,
+) -> Result<Vec<VerifiedFunction>, ()> {
+    let start = Instant::now();
+    let verifier = Verifier::new(functions, session, label_manager, symbol_manager);
+
+    let verified = verifier.verify();
+    session.record_time("verification", start);
+
+    verified
+}
 
-        let nodes = preprocessor_parser.parse()?;
+/// Generates an object file from verified functions, along with the contents of the binary files
+/// they include, and the source to embed in the object file, if any
+pub fn generate(
+    functions: Vec<VerifiedFunction>,
+    binaries: Vec<Vec<u8>>,
+    embedded_source: Option<String>,
+    label_manager: &LabelManager,
+    symbol_manager: &SymbolManager,
+    session: &Session,
+) -> Result<WritableKOFile, ()> {
+    let start = Instant::now();
+    let generator = Generator::new(session, symbol_manager, label_manager);
 
-        let executor = Executor::new(&mut session);
+    let kofile = generator.generate(functions, binaries, embedded_source);
+    session.record_time("generation", start);
 
-        tokens = executor.execute(nodes)?;
+    kofile
+}
+
+// The core of the assembler. The actual function that runs everything else
+// This should be called with a session that already has the primary source file read
+fn assemble(session: &mut Session) -> Result<AssemblyOutput, ()> {
+    let output = assemble_stages(session);
+
+    if session.config().time_report {
+        report_times(session);
+    }
+
+    output
+}
+
+// Runs every stage of assembly, stopping at the first one that fails
+fn assemble_stages(session: &mut Session) -> Result<AssemblyOutput, ()> {
+    let tokens = preprocess(session)?;
+
+    // If we should output a listing of the tokens themselves instead of assembling
+    if session.config().emit_tokens {
+        let output = generate_token_dump(&tokens, session);
+
+        return Ok(AssemblyOutput::Source(output));
     }
 
     // If we should output the preprocessed tokens instead of assembling
     if session.config().preprocess_only {
-        let output = generate_preprocessed(tokens, &session);
+        let output = generate_preprocessed(&tokens, session, session.config().line_markers);
 
         return Ok(AssemblyOutput::Source(output));
     }
 
-    let parser = parse::Parser::new(tokens, &session);
+    let embedded_source = embedded_source(&tokens, session);
+
+    let (mut parsed_functions, mut label_manager, symbol_manager, binaries) =
+        parse_tokens(tokens, session)?;
+
+    if session.config().optimize {
+        let start = Instant::now();
+        let removed = optimize(&mut parsed_functions, &mut label_manager);
+        session.record_time("optimization", start);
+
+        session
+            .struct_note(format!("optimized away {} instruction(s)", removed))
+            .emit();
+    }
 
-    let (parsed_functions, label_manager, symbol_manager) = parser.parse()?;
+    // This is done before verifying, so that symbols that failed verification can be looked into
+    if let Some(path) = &session.config().dump_symbols {
+        let json = symbols_to_json(&symbol_manager, &label_manager, session);
 
-    let verifier = Verifier::new(parsed_functions, &session, &label_manager, &symbol_manager);
+        write_symbol_dump(session, path, &json)?;
+    }
 
-    let verified_functions = verifier.verify()?;
+    session
+        .struct_note(format!("parsed {} function(s)", parsed_functions.len()))
+        .emit();
 
-    let generator = Generator::new(&session, &symbol_manager);
+    let verified_functions = verify(parsed_functions, &label_manager, &symbol_manager, session)?;
 
-    let kofile = generator.generate(verified_functions)?;
+    let kofile = generate(
+        verified_functions,
+        binaries,
+        embedded_source,
+        &label_manager,
+        &symbol_manager,
+        session,
+    )?;
 
     Ok(AssemblyOutput::Object(Box::new(kofile)))
 }
 
-// Generates preprocessed source output
-fn generate_preprocessed(tokens: Vec<Token>, session: &Session) -> String {
+// Reports the time spent in each phase of assembly, even if notes aren't otherwise being shown.
+// A phase that failed is still included, but the ones after it never ran
+fn report_times(session: &Session) {
+    let timings = session.take_timings();
+    let total: Duration = timings.iter().map(|(_, duration)| *duration).sum();
+
+    let mut db = session.struct_note(format!(
+        "assembly took {:.3}ms",
+        total.as_secs_f64() * 1000.0
+    ));
+
+    for (phase, duration) in timings {
+        db.note(format!(
+            "{:<13} {:>10.3}ms",
+            phase,
+            duration.as_secs_f64() * 1000.0
+        ));
+    }
+
+    db.emit_report();
+}
+
+// Runs every stage after preprocessing for assemble_full, keeping the parsed functions and the
+// managers along with the object file
+fn assemble_artifacts(
+    tokens: Vec<Token>,
+    session: &Session,
+) -> Result<
+    (
+        WritableKOFile,
+        Vec<ParsedFunction>,
+        LabelManager,
+        SymbolManager,
+    ),
+    (),
+> {
+    let embedded_source = embedded_source(&tokens, session);
+
+    let (mut functions, mut label_manager, symbol_manager, binaries) =
+        parse_tokens(tokens, session)?;
+
+    if session.config().optimize {
+        optimize(&mut functions, &mut label_manager);
+    }
+
+    let verified = verify(functions.clone(), &label_manager, &symbol_manager, session)?;
+
+    let object = generate(
+        verified,
+        binaries,
+        embedded_source,
+        &label_manager,
+        &symbol_manager,
+        session,
+    )?;
+
+    Ok((object, functions, label_manager, symbol_manager))
+}
+
+// The source code to store in the object file, in the form asked for by --embed-source
+fn embedded_source(tokens: &[Token], session: &Session) -> Option<String> {
+    match session.config().embed_source {
+        Some(EmbeddedSource::Original) => Some(session.get_file(0).unwrap().source.clone()),
+        Some(EmbeddedSource::Preprocessed) => Some(generate_preprocessed(tokens, session, false)),
+        None => None,
+    }
+}
+
+// Writes the JSON from --dump-symbols to the given path, or to stdout if the path is `-`
+fn write_symbol_dump(session: &Session, path: &Path, json: &str) -> Result<(), ()> {
+    if path == Path::new("-") {
+        print!("{}", json);
+
+        return Ok(());
+    }
+
+    if let Err(e) = std::fs::write(path, json) {
+        session
+            .struct_error(format!(
+                "unable to write symbols to `{}`: {}",
+                path.to_string_lossy(),
+                e
+            ))
+            .emit();
+
+        return Err(());
+    }
+
+    Ok(())
+}
+
+// Runs everything that assemble() does up until generating the object file
+// This should be called with a session that already has the primary source file read
+fn check(session: &mut Session) -> Result<(), ()> {
+    let tokens = preprocess(session)?;
+
+    let (parsed_functions, label_manager, symbol_manager, _) = parse_tokens(tokens, session)?;
+
+    verify(parsed_functions, &label_manager, &symbol_manager, session)?;
+
+    Ok(())
+}
+
+// Generates preprocessed source output. Every token is printed exactly as it appears in the
+// source, so apart from expanded macros the output matches the input
+//
+// With line markers, a .line directive is added before any line that doesn't directly follow the
+// one before it in the same file, which is decided by the first token on the line
+fn generate_preprocessed(tokens: &[Token], session: &Session, line_markers: bool) -> String {
     let mut output = String::new();
+    let mut at_line_start = true;
+    // The file and line that the next line of output would be on without a marker
+    let mut expected_line: Option<(String, usize)> = None;
 
     for token in tokens {
-        let str_rep = match token.kind {
-            TokenKind::Newline => "\n",
-            TokenKind::OperatorMinus => "-",
-            TokenKind::OperatorPlus => "+",
-            TokenKind::OperatorCompliment => "~",
-            TokenKind::OperatorMultiply => "*",
-            TokenKind::OperatorDivide => "/",
-            TokenKind::OperatorMod => "%",
-            TokenKind::OperatorAnd => "&&",
-            TokenKind::OperatorOr => "||",
-            TokenKind::OperatorEquals => "==",
-            TokenKind::OperatorNotEquals => "!=",
-            TokenKind::OperatorNegate => "!",
-            TokenKind::OperatorGreaterThan => ">",
-            TokenKind::OperatorLessThan => "<",
-            TokenKind::OperatorGreaterEquals => ">=",
-            TokenKind::OperatorLessEquals => "<=",
-            TokenKind::SymbolLeftParen => "(",
-            TokenKind::SymbolRightParen => ")",
-            TokenKind::SymbolComma => ",",
-            TokenKind::SymbolHash => "#",
-            TokenKind::SymbolAt => "@",
-            TokenKind::SymbolAnd => "&",
-            TokenKind::LiteralTrue => "true",
-            TokenKind::LiteralFalse => "false",
-            TokenKind::Backslash => "\\",
-            TokenKind::KeywordSection => ".section",
-            TokenKind::KeywordText => ".text",
-            TokenKind::KeywordData => ".data",
-            TokenKind::TypeI8 => ".i8",
-            TokenKind::TypeI16 => ".i16",
-            TokenKind::TypeI32 => ".i32",
-            TokenKind::TypeI32V => ".i32v",
-            TokenKind::TypeF64 => ".f64",
-            TokenKind::TypeF64V => ".f64v",
-            TokenKind::TypeS => ".s",
-            TokenKind::TypeSV => ".sv",
-            TokenKind::TypeB => ".b",
-            TokenKind::TypeBV => ".bv",
-            TokenKind::DirectiveDefine => ".define",
-            TokenKind::DirectiveMacro => ".macro",
-            TokenKind::DirectiveEndmacro => ".endmacro",
-            TokenKind::DirectiveRepeat => ".rep",
-            TokenKind::DirectiveEndRepeat => ".endrep",
-            TokenKind::DirectiveInclude => ".include",
-            TokenKind::DirectiveExtern => ".extern",
-            TokenKind::DirectiveGlobal => ".global",
-            TokenKind::DirectiveLocal => ".local",
-            TokenKind::DirectiveLine => ".line",
-            TokenKind::DirectiveType => ".type",
-            TokenKind::DirectiveValue => ".value",
-            TokenKind::DirectiveUndef => ".undef",
-            TokenKind::DirectiveUnmacro => ".unmacro",
-            TokenKind::DirectiveFunc => ".func",
-            TokenKind::DirectiveIf => ".if",
-            TokenKind::DirectiveIfNot => ".ifn",
-            TokenKind::DirectiveIfDef => ".ifdef",
-            TokenKind::DirectiveIfNotDef => ".ifndef",
-            TokenKind::DirectiveElseIf => ".elif",
-            TokenKind::DirectiveElseIfNot => ".elifn",
-            TokenKind::DirectiveElseIfDef => ".elifdef",
-            TokenKind::DirectiveElseIfNotDef => ".elifndef",
-            TokenKind::DirectiveElse => ".else",
-            TokenKind::DirectiveEndIf => ".endif",
-            TokenKind::InnerLabelReference
-            | TokenKind::InnerLabel
-            | TokenKind::Identifier
-            | TokenKind::Label
-            | TokenKind::Whitespace
-            | TokenKind::LiteralInteger
-            | TokenKind::LiteralFloat
-            | TokenKind::LiteralHex
-            | TokenKind::LiteralBinary
-            | TokenKind::LiteralString
-            | TokenKind::Comment
-            | TokenKind::Error
-            | TokenKind::JunkFloatError => "",
-        };
-
-        if !str_rep.is_empty() {
-            output.push_str(str_rep);
-        } else {
-            let snippet = session.span_to_snippet(&token.as_span());
-            let token_str = snippet.as_slice();
-
-            output.push_str(token_str);
+        let span = token.as_span();
+
+        if line_markers && at_line_start {
+            let (path, line_num, _) = session.get_source_location(&span);
+
+            if expected_line.as_ref() != Some(&(path.clone(), line_num)) {
+                output.push_str(&format!(".line {} \"{}\"\n", line_num, path));
+            }
+
+            expected_line = Some((path, line_num + 1));
         }
+
+        at_line_start = token.kind == TokenKind::Newline;
+
+        output.push_str(&session.span_to_source(&span));
+    }
+
+    output
+}
+
+// Generates a listing of each token, one per line, with its kind, location, and source code
+fn generate_token_dump(tokens: &[Token], session: &Session) -> String {
+    let mut output = String::new();
+    let files = session.source_files();
+
+    for (token, text) in TokenTexts::new(tokens, &files) {
+        let span = token.as_span();
+        let (path, line_num, col) = session.get_source_location(&span);
+
+        output.push_str(&format!(
+            "{}:{}:{} {:?} {:?}\n",
+            path, line_num, col, token.kind, text
+        ));
     }
 
     output
@@ -4,23 +4,48 @@ use std::fs::File;
 use std::path::Path;
 use std::{io::Write, process};
 
-use kasm::assemble_path;
+use kasm::session::Session;
+use kasm::{assemble_path, disassemble_path, opcode_listing};
 
 fn main() {
     let config: CLIConfig = CLIConfig::parse();
 
-    if let Ok(output) = assemble_path(&config.input_path, config.base_config) {
-        match output {
-            AssemblyOutput::Object(object) => {
+    if config.list_opcodes {
+        print!("{}", opcode_listing());
+        return;
+    }
+
+    // This can only be missing when listing the opcodes
+    let input_path = config.input_path.clone().expect("No input file");
+
+    // Object files are disassembled back into KASM source instead of being assembled
+    if input_path.extension().is_some_and(|ext| ext == "ko") {
+        disassemble(config, &input_path);
+        return;
+    }
+
+    // Used only for reporting progress once the output has been written
+    let session = Session::new(config.base_config.clone());
+
+    // Token listings aren't source code, so they shouldn't default to a source file extension
+    let source_extension = if config.base_config.emit_tokens {
+        "tokens"
+    } else {
+        ".ksm"
+    };
+
+    if let Ok(output) = assemble_path(&input_path, config.base_config) {
+        match &output {
+            AssemblyOutput::Object(_) => {
                 // 2048 is just a best guess as to the size of the file
                 let mut file_buffer = Vec::with_capacity(2048);
 
                 // Actually write to the buffer
-                object.write(&mut file_buffer);
+                output.write(&mut file_buffer);
 
                 let output_path = config
                     .output_path
-                    .unwrap_or_else(|| config.input_path.with_extension("ko"));
+                    .unwrap_or_else(|| input_path.with_extension("ko"));
 
                 let mut output_file = try_create_file(&output_path);
 
@@ -33,11 +58,13 @@ fn main() {
 
                     process::exit(4);
                 }
+
+                report_written(&session, file_buffer.len(), &output_path);
             }
             AssemblyOutput::Source(source) => {
                 let output_path = config
                     .output_path
-                    .unwrap_or_else(|| config.input_path.with_extension(".ksm"));
+                    .unwrap_or_else(|| input_path.with_extension(source_extension));
 
                 let mut output_file = try_create_file(&output_path);
 
@@ -50,6 +77,8 @@ fn main() {
 
                     process::exit(3);
                 }
+
+                report_written(&session, source.len(), &output_path);
             }
         }
     } else {
@@ -57,6 +86,42 @@ fn main() {
     }
 }
 
+fn disassemble(config: CLIConfig, input_path: &Path) {
+    let session = Session::new(config.base_config.clone());
+
+    if let Ok(source) = disassemble_path(input_path, config.base_config) {
+        let output_path = config
+            .output_path
+            .unwrap_or_else(|| input_path.with_extension("kasm"));
+
+        let mut output_file = try_create_file(&output_path);
+
+        if let Err(e) = output_file.write_all(source.as_bytes()) {
+            eprintln!(
+                "Error writing to `{}`: {}",
+                output_path.to_string_lossy(),
+                e
+            );
+
+            process::exit(3);
+        }
+
+        report_written(&session, source.len(), &output_path);
+    } else {
+        process::exit(1);
+    }
+}
+
+fn report_written(session: &Session, num_bytes: usize, path: &Path) {
+    session
+        .struct_note(format!(
+            "wrote {} bytes to `{}`",
+            num_bytes,
+            path.to_string_lossy()
+        ))
+        .emit();
+}
+
 fn try_create_file(path: &Path) -> File {
     match File::create(path) {
         Ok(file) => file,
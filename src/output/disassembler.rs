@@ -0,0 +1,472 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use kerbalobjects::ko::sections::{DataIdx, DataSection, FuncSection, StringTable, SymbolTable};
+use kerbalobjects::ko::symbols::{KOSymbol, OperandIndex, SymBind, SymType};
+use kerbalobjects::ko::{Instr, KOFile, SectionIdx};
+use kerbalobjects::{KOSValue, Opcode};
+
+use crate::lexer::TokenKind;
+use crate::preprocessor::parser::{format_float_literal, format_string_literal};
+use crate::session::Session;
+
+/// A disassembler that turns a parsed KerbalObject file back into KASM source code.
+///
+/// This is essentially the inverse of the Generator. The produced source should assemble back
+/// into an equivalent object file
+pub struct Disassembler<'a, 'b> {
+    session: &'a Session,
+    kofile: &'b KOFile,
+    data_section: Option<&'b DataSection>,
+    sym_tab: Option<&'b SymbolTable>,
+    sym_str_tab: Option<&'b StringTable>,
+    // Maps a (function section, instruction, operand) to the name of the symbol that replaces it
+    relocations: HashMap<(SectionIdx, usize, OperandIndex), &'b String>,
+}
+
+impl<'a, 'b> Disassembler<'a, 'b> {
+    pub fn new(session: &'a Session, kofile: &'b KOFile) -> Self {
+        Self {
+            session,
+            kofile,
+            data_section: kofile.data_section_by_name(".data"),
+            sym_tab: kofile.sym_tab_by_name(".symtab"),
+            sym_str_tab: kofile.str_tab_by_name(".symstrtab"),
+            relocations: HashMap::new(),
+        }
+    }
+
+    /// Runs the disassembler, producing KASM source code
+    pub fn disassemble(mut self) -> Result<String, ()> {
+        let mut output = String::new();
+
+        // Collect all of the relocations first, so that symbol operands can be resolved
+        if let Some(reld_section) = self.kofile.reld_section_by_name(".reld") {
+            for entry in reld_section.entries() {
+                let symbol = self.get_symbol(entry.symbol_index.into())?;
+                let name = self.symbol_name(symbol)?;
+
+                self.relocations.insert(
+                    (
+                        entry.section_index,
+                        entry.instr_index.into(),
+                        entry.operand_index,
+                    ),
+                    name,
+                );
+            }
+        }
+
+        let mut declarations = Vec::new();
+        // The data entries in .data, and then those in every other data section by name
+        let mut data_entries = Vec::new();
+        let mut custom_data_entries: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+        // Any symbol with a size other than the one it would be given without .size
+        let mut sizes = Vec::new();
+
+        for symbol in self.symbols() {
+            // Neither of these can be expressed in KASM, the file symbol is created by the
+            // assembler itself
+            if matches!(symbol.sym_type, SymType::File | SymType::Section) {
+                continue;
+            }
+
+            let name = self.symbol_name(symbol)?;
+
+            match symbol.sym_bind {
+                SymBind::Extern => {
+                    let sym_type = if symbol.sym_type == SymType::Func {
+                        TokenKind::DirectiveFunc
+                    } else {
+                        TokenKind::DirectiveValue
+                    };
+
+                    declarations.push(format!(
+                        "{} {} {}",
+                        directive_str(TokenKind::DirectiveExtern),
+                        directive_str(sym_type),
+                        name
+                    ));
+                }
+                SymBind::Global => {
+                    declarations.push(format!(
+                        "{} {}",
+                        directive_str(TokenKind::DirectiveGlobal),
+                        name
+                    ));
+                }
+                SymBind::Local => {}
+            }
+
+            // Without .size, only data symbols are given a size, which is that of their value
+            let mut default_size = 0;
+
+            if symbol.sym_bind != SymBind::Extern && symbol.sym_type != SymType::Func {
+                let (value, entries) = match self.custom_data_section(symbol) {
+                    Some((section_name, section)) => (
+                        self.get_data_in(Some(section), symbol.value_idx)?,
+                        custom_data_entries.entry(section_name).or_default(),
+                    ),
+                    None => (self.get_data(symbol.value_idx)?, &mut data_entries),
+                };
+
+                entries.push(format!("{} {}", name, Self::format_data_entry(value)));
+
+                default_size = value.size_bytes();
+            }
+
+            if symbol.size as usize != default_size {
+                sizes.push(format!(
+                    "{} {}, {}",
+                    directive_str(TokenKind::DirectiveSize),
+                    name,
+                    symbol.size
+                ));
+            }
+        }
+
+        // Symbol tables are not stored in any particular order, so sort these to make the output
+        // stable
+        declarations.sort();
+        data_entries.sort();
+
+        for entries in custom_data_entries.values_mut() {
+            entries.sort();
+        }
+
+        for declaration in declarations.iter() {
+            output.push_str(declaration);
+            output.push('\n');
+        }
+
+        if !data_entries.is_empty() {
+            output.push_str("\n.section .data\n");
+
+            for entry in data_entries.iter() {
+                output.push_str(entry);
+                output.push('\n');
+            }
+        }
+
+        for (section_name, entries) in custom_data_entries.iter() {
+            output.push_str(&format!("\n.section {}\n", section_name));
+
+            for entry in entries.iter() {
+                output.push_str(entry);
+                output.push('\n');
+            }
+        }
+
+        if !data_entries.is_empty() || !custom_data_entries.is_empty() {
+            output.push_str("\n.section .text\n");
+        }
+
+        // Jumps are stored as offsets relative to a global instruction index, which doesn't count
+        // lbrt instructions
+        let mut global_instruction_index = 0;
+
+        for function in self.kofile.func_sections() {
+            output.push_str(&self.disassemble_function(function, global_instruction_index)?);
+
+            global_instruction_index += function
+                .instructions()
+                .filter(|instr| instr.opcode() != Opcode::Lbrt)
+                .count();
+        }
+
+        // These come last, as every symbol has been declared or defined by then
+        if !sizes.is_empty() {
+            sizes.sort();
+
+            output.push('\n');
+
+            for size in sizes.iter() {
+                output.push_str(size);
+                output.push('\n');
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn disassemble_function(
+        &self,
+        function: &FuncSection,
+        function_start: usize,
+    ) -> Result<String, ()> {
+        let mut output = String::new();
+        let section_index = function.section_index();
+
+        let name = match self.kofile.get_section_name_by_index(section_index) {
+            Some(name) => name,
+            None => {
+                self.session
+                    .struct_error(format!(
+                        "function section {} has no name",
+                        usize::from(section_index)
+                    ))
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        // The global instruction index of each instruction in this function
+        let mut global_indices = Vec::new();
+        let mut global_instruction_index = function_start;
+
+        for instr in function.instructions() {
+            global_indices.push(global_instruction_index);
+
+            if instr.opcode() != Opcode::Lbrt {
+                global_instruction_index += 1;
+            }
+        }
+
+        // Find every instruction that is the target of a jump within this function, so that it
+        // can be given a label. The first instruction can't be, as it is already labeled by the
+        // function's label, which can only be referenced as a symbol
+        let mut targets = BTreeSet::new();
+
+        for (index, instr) in function.instructions().enumerate() {
+            if let Some(target) = self.jump_target(function, index, instr, &global_indices) {
+                targets.insert(target);
+            }
+        }
+
+        let labels: HashMap<usize, String> = targets
+            .into_iter()
+            .enumerate()
+            .map(|(label_num, target)| (target, format!(".L{}", label_num)))
+            .collect();
+
+        output.push_str(&format!("\n.func\n{}:\n", name));
+
+        for (index, instr) in function.instructions().enumerate() {
+            if let Some(label) = labels.get(&index) {
+                output.push_str(&format!("{}:\n", label));
+            }
+
+            // pushv is written to the object file as a push, and can only be told apart by the
+            // type of its operand
+            let opcode = match instr {
+                Instr::OneOp(Opcode::Push, op)
+                    if matches!(
+                        self.data_section.and_then(|data| data.get(*op)),
+                        Some(
+                            KOSValue::ScalarInt(_)
+                                | KOSValue::ScalarDouble(_)
+                                | KOSValue::BoolValue(_)
+                                | KOSValue::StringValue(_)
+                        )
+                    ) =>
+                {
+                    Opcode::Pushv
+                }
+                _ => instr.opcode(),
+            };
+
+            let opcode_str: &str = opcode.into();
+
+            let operands = match instr {
+                Instr::ZeroOp(_) => Vec::new(),
+                Instr::OneOp(_, op) => vec![(OperandIndex::One, *op)],
+                Instr::TwoOp(_, op1, op2) => {
+                    vec![(OperandIndex::One, *op1), (OperandIndex::Two, *op2)]
+                }
+            };
+
+            let jump_label = self
+                .jump_target(function, index, instr, &global_indices)
+                .and_then(|target| labels.get(&target));
+
+            let mut operand_strs = Vec::with_capacity(operands.len());
+
+            for (operand_index, data_index) in operands {
+                let relocated = self.relocations.get(&(section_index, index, operand_index));
+
+                let operand_str = if let Some(symbol_name) = relocated {
+                    symbol_name.to_string()
+                } else if let (OperandIndex::One, Some(label)) = (operand_index, jump_label) {
+                    label.to_string()
+                } else {
+                    Self::format_operand(self.get_data(data_index)?)
+                };
+
+                operand_strs.push(operand_str);
+            }
+
+            output.push_str("    ");
+            output.push_str(opcode_str);
+
+            if !operand_strs.is_empty() {
+                output.push(' ');
+                output.push_str(&operand_strs.join(", "));
+            }
+
+            output.push('\n');
+        }
+
+        Ok(output)
+    }
+
+    // If the instruction is a jump to a labelable instruction in the same function, returns the
+    // local index of the target instruction
+    fn jump_target(
+        &self,
+        function: &FuncSection,
+        index: usize,
+        instr: &Instr,
+        global_indices: &[usize],
+    ) -> Option<usize> {
+        let offset = match instr {
+            Instr::OneOp(Opcode::Jmp | Opcode::Bfa | Opcode::Btr, op) => {
+                if self.relocations.contains_key(&(
+                    function.section_index(),
+                    index,
+                    OperandIndex::One,
+                )) {
+                    return None;
+                }
+
+                match self.data_section?.get(*op)? {
+                    KOSValue::Int32(offset) => *offset,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        let target = global_indices[index] as i64 + offset as i64;
+
+        function
+            .instructions()
+            .enumerate()
+            .skip(1)
+            .find(|(i, instr)| {
+                global_indices[*i] as i64 == target && instr.opcode() != Opcode::Lbrt
+            })
+            .map(|(i, _)| i)
+    }
+
+    fn symbols(&self) -> Vec<&'b KOSymbol> {
+        match self.sym_tab {
+            Some(sym_tab) => sym_tab.symbols().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn get_symbol(&self, index: usize) -> Result<&'b KOSymbol, ()> {
+        match self.sym_tab.and_then(|sym_tab| sym_tab.get(index.into())) {
+            Some(symbol) => Ok(symbol),
+            None => {
+                self.session
+                    .struct_error(format!("relocation refers to missing symbol {}", index))
+                    .emit();
+
+                Err(())
+            }
+        }
+    }
+
+    fn symbol_name(&self, symbol: &KOSymbol) -> Result<&'b String, ()> {
+        match self
+            .sym_str_tab
+            .and_then(|sym_str_tab| sym_str_tab.get(symbol.name_idx))
+        {
+            Some(name) => Ok(name),
+            None => {
+                self.session
+                    .struct_error(format!(
+                        "symbol name {} not found in .symstrtab",
+                        usize::from(symbol.name_idx)
+                    ))
+                    .emit();
+
+                Err(())
+            }
+        }
+    }
+
+    // Returns the data section that a symbol's value is in, along with its name, if that is a
+    // section other than .data
+    fn custom_data_section(&self, symbol: &KOSymbol) -> Option<(&'b String, &'b DataSection)> {
+        let section = self
+            .kofile
+            .data_sections()
+            .find(|section| section.section_index() == symbol.sh_idx)?;
+        let name = self.kofile.get_section_name_by_index(symbol.sh_idx)?;
+
+        if name == ".data" {
+            None
+        } else {
+            Some((name, section))
+        }
+    }
+
+    fn get_data(&self, index: DataIdx) -> Result<&'b KOSValue, ()> {
+        self.get_data_in(self.data_section, index)
+    }
+
+    fn get_data_in(
+        &self,
+        data_section: Option<&'b DataSection>,
+        index: DataIdx,
+    ) -> Result<&'b KOSValue, ()> {
+        match data_section.and_then(|data| data.get(index)) {
+            Some(value) => Ok(value),
+            None => {
+                self.session
+                    .struct_error(format!(
+                        "reference to data entry {} that does not exist",
+                        u32::from(index)
+                    ))
+                    .emit();
+
+                Err(())
+            }
+        }
+    }
+
+    pub(crate) fn format_data_entry(value: &KOSValue) -> String {
+        let data_type = match value {
+            // These two don't take a type
+            KOSValue::Null | KOSValue::ArgMarker => return Self::format_operand(value),
+            KOSValue::Bool(_) => TokenKind::TypeB,
+            KOSValue::BoolValue(_) => TokenKind::TypeBV,
+            KOSValue::Byte(_) => TokenKind::TypeI8,
+            KOSValue::Int16(_) => TokenKind::TypeI16,
+            KOSValue::Int32(_) => TokenKind::TypeI32,
+            KOSValue::ScalarInt(_) => TokenKind::TypeI32V,
+            KOSValue::Float(_) | KOSValue::Double(_) => TokenKind::TypeF64,
+            KOSValue::ScalarDouble(_) => TokenKind::TypeF64V,
+            KOSValue::String(_) => TokenKind::TypeS,
+            KOSValue::StringValue(_) => TokenKind::TypeSV,
+        };
+
+        format!(
+            "{} {}",
+            directive_str(data_type),
+            Self::format_operand(value)
+        )
+    }
+
+    fn format_operand(value: &KOSValue) -> String {
+        match value {
+            KOSValue::Null => String::from("#"),
+            KOSValue::ArgMarker => String::from("@"),
+            KOSValue::Bool(b) | KOSValue::BoolValue(b) => b.to_string(),
+            KOSValue::Byte(i) => i.to_string(),
+            KOSValue::Int16(i) => i.to_string(),
+            KOSValue::Int32(i) | KOSValue::ScalarInt(i) => i.to_string(),
+            KOSValue::Float(f) => format_float_literal(*f as f64),
+            KOSValue::Double(d) | KOSValue::ScalarDouble(d) => format_float_literal(*d),
+            KOSValue::String(s) | KOSValue::StringValue(s) => format_string_literal(s),
+        }
+    }
+}
+
+// The spelling of a directive or data type that the disassembler writes out
+fn directive_str(kind: TokenKind) -> &'static str {
+    kind.directive_str()
+        .expect("the disassembler only writes out directives and data types")
+}
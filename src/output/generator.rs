@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use kerbalobjects::ko::sections::{DataIdx, InstrIdx};
 use kerbalobjects::ko::symbols::OperandIndex;
@@ -13,7 +13,7 @@ use kerbalobjects::{
 };
 
 use crate::{
-    parser::{SymbolManager, SymbolType, SymbolValue},
+    parser::{LabelManager, SymbolManager, SymbolType, SymbolValue},
     session::Session,
 };
 
@@ -22,20 +22,34 @@ use super::{VerifiedFunction, VerifiedInstruction, VerifiedOperand};
 pub struct Generator<'a, 'c> {
     session: &'a Session,
     symbol_manager: &'c SymbolManager,
+    label_manager: &'c LabelManager,
     global_instruction_index: usize,
 }
 
 impl<'a, 'c> Generator<'a, 'c> {
-    pub fn new(session: &'a Session, symbol_manager: &'c SymbolManager) -> Self {
+    pub fn new(
+        session: &'a Session,
+        symbol_manager: &'c SymbolManager,
+        label_manager: &'c LabelManager,
+    ) -> Self {
         Self {
             session,
             symbol_manager,
+            label_manager,
             global_instruction_index: 0,
         }
     }
 
-    /// Generates the final object file
-    pub fn generate(mut self, functions: Vec<VerifiedFunction>) -> Result<WritableKOFile, ()> {
+    /// Generates the final object file. Any included binaries are laid out in the data section
+    /// byte by byte, in the order they were included. If source code is provided, it is stored
+    /// as the only string in a .source string table. Likewise, the name of the entry symbol is
+    /// stored in an .entry string table if one was configured
+    pub fn generate(
+        mut self,
+        functions: Vec<VerifiedFunction>,
+        binaries: Vec<Vec<u8>>,
+        embedded_source: Option<String>,
+    ) -> Result<WritableKOFile, ()> {
         let mut function_map: HashMap<String, SectionIdx> = HashMap::new();
         let mut functions_and_sections = Vec::with_capacity(functions.len());
 
@@ -46,6 +60,16 @@ impl<'a, 'c> Generator<'a, 'c> {
         let mut comment_tab = ko.new_strtab(".comment");
         let mut sym_str_tab = ko.new_strtab(".symstrtab");
         let mut reld_section = ko.new_reld_section(".reld");
+        let source_tab = embedded_source.map(|source| {
+            let mut source_tab = ko.new_strtab(".source");
+            source_tab.add(source);
+            source_tab
+        });
+        let entry_tab = self.session.config().entry.as_ref().map(|entry| {
+            let mut entry_tab = ko.new_strtab(".entry");
+            entry_tab.add(entry);
+            entry_tab
+        });
         let mut function_sections = Vec::new();
 
         // Immediately add an initial value to the data section. This is to get around a slight
@@ -67,6 +91,31 @@ impl<'a, 'c> Generator<'a, 'c> {
             functions_and_sections.push((function_section, function));
         }
 
+        // Create a data section for each other section that data was placed in. These are
+        // created in order of their names so that the output is the same every time
+        let custom_section_names: BTreeSet<&String> = self
+            .symbol_manager
+            .symbols()
+            .into_iter()
+            .filter_map(|(_, symbol)| symbol.section.as_ref())
+            .collect();
+        let mut custom_sections = Vec::with_capacity(custom_section_names.len());
+
+        for name in custom_section_names {
+            if function_map.contains_key(name) {
+                self.session
+                    .struct_error(format!(
+                        "section `{}` has the same name as a function",
+                        name
+                    ))
+                    .emit();
+
+                return Err(());
+            }
+
+            custom_sections.push(ko.new_data_section(name));
+        }
+
         // Create the file symbol
         let file_symbol_name = self.get_file_sym_name();
         let file_symbol_name_index = sym_str_tab.add(&file_symbol_name);
@@ -100,7 +149,7 @@ impl<'a, 'c> Generator<'a, 'c> {
                 let symbol = KOSymbol::new(
                     name_index,
                     DataIdx::PLACEHOLDER,
-                    0,
+                    symbol.size.unwrap_or(0),
                     SymBind::Extern,
                     sym_type,
                     SectionIdx::NULL,
@@ -120,11 +169,11 @@ impl<'a, 'c> Generator<'a, 'c> {
                     // If it is a function
                     let function_index = *function_map.get(name).unwrap();
 
-                    // Here we set the size to be 0, but it will be updated later
+                    // Functions have no size unless one was given with .size
                     let function_symbol = KOSymbol::new(
                         name_index,
                         DataIdx::PLACEHOLDER,
-                        0,
+                        symbol.size.unwrap_or(0),
                         bind,
                         SymType::Func,
                         function_index,
@@ -133,9 +182,28 @@ impl<'a, 'c> Generator<'a, 'c> {
                     sym_tab.add(function_symbol);
                 } else if symbol.sym_type == SymbolType::Value {
                     // If it is just a value
-                    if let SymbolValue::Value(value) = &symbol.value {
-                        let size = value.size_bytes() as u16;
-                        let value_index = data_section.add_checked(value.clone());
+                    let value = match &symbol.value {
+                        SymbolValue::Value(value) => Some(value.clone()),
+                        SymbolValue::LabelAddress(label, _, scalar) => {
+                            Some(self.label_address(label, *scalar)?)
+                        }
+                        _ => None,
+                    };
+
+                    if let Some(value) = value {
+                        let section = match &symbol.section {
+                            Some(section_name) => custom_sections
+                                .iter_mut()
+                                .find(|section| {
+                                    ko.get_section_name_by_index(section.section_index())
+                                        == Some(section_name)
+                                })
+                                .unwrap(),
+                            None => &mut data_section,
+                        };
+
+                        let size = symbol.size.unwrap_or(value.size_bytes() as u16);
+                        let value_index = section.add_checked(value);
 
                         let symbol = KOSymbol::new(
                             name_index,
@@ -143,7 +211,7 @@ impl<'a, 'c> Generator<'a, 'c> {
                             size,
                             bind,
                             SymType::NoType,
-                            data_section.section_index(),
+                            section.section_index(),
                         );
 
                         sym_tab.add(symbol);
@@ -165,6 +233,13 @@ impl<'a, 'c> Generator<'a, 'c> {
             }
         }
 
+        // These are added without checking for duplicates so that each binary stays contiguous
+        for binary in binaries {
+            for byte in binary {
+                data_section.add(KOSValue::Byte(byte as i8));
+            }
+        }
+
         // Now that we are done adding all of the functions and symbols, we can actually start
         // generating code
         for (func_section, function) in functions_and_sections {
@@ -189,10 +264,22 @@ impl<'a, 'c> Generator<'a, 'c> {
         ko.add_str_tab(sym_str_tab);
         ko.add_reld_section(reld_section);
 
+        if let Some(source_tab) = source_tab {
+            ko.add_str_tab(source_tab);
+        }
+
+        if let Some(entry_tab) = entry_tab {
+            ko.add_str_tab(entry_tab);
+        }
+
         for func_section in function_sections {
             ko.add_func_section(func_section);
         }
 
+        for custom_section in custom_sections {
+            ko.add_data_section(custom_section);
+        }
+
         // Finally, we are done
         ko.validate().map_err(|(_, _)| {
             self.session
@@ -201,6 +288,27 @@ impl<'a, 'c> Generator<'a, 'c> {
         })
     }
 
+    // Labels are stored as the index of the instruction they label, counting from the start of the
+    // first function, which is how jumps refer to them as well
+    fn label_address(&self, label: &String, scalar: bool) -> Result<KOSValue, ()> {
+        let address = match self.label_manager.get(label) {
+            Some(label) => label.value as i32,
+            None => {
+                self.session
+                    .struct_bug(format!("label `{}` was not verified to exist", label))
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        Ok(if scalar {
+            KOSValue::ScalarInt(address)
+        } else {
+            KOSValue::Int32(address)
+        })
+    }
+
     fn generate_function(
         &mut self,
         mut function_section: FuncSection,
@@ -304,6 +412,8 @@ impl<'a, 'c> Generator<'a, 'c> {
         sym_str_tab: &StringTable,
     ) -> Result<DataIdx, ()> {
         Ok(match operand {
+            // Checked adds reuse an existing entry with the same value, so constants that are used
+            // by many instructions are only stored once
             VerifiedOperand::Value(value) => data_section.add_checked(value),
             VerifiedOperand::Label(location) => {
                 // Because this is an absolute location and not a relative one, we have to convert
@@ -1,4 +1,6 @@
 mod verifier;
 pub use verifier::*;
 
+pub mod disassembler;
 pub mod generator;
+pub mod symbol_dump;
@@ -0,0 +1,140 @@
+use kerbalobjects::ko::symbols::SymBind;
+
+use crate::errors::Span;
+use crate::output::disassembler::Disassembler;
+use crate::parser::{LabelManager, SymbolManager, SymbolType, SymbolValue};
+use crate::session::Session;
+
+/// Writes out every symbol and label that was parsed as JSON, for --dump-symbols
+///
+/// Symbols and labels are both ordered by where they were declared, so the same input always
+/// produces the same output. Each symbol's value is either `null` if it was never given one,
+/// `"function"`, the data entry it was given such as `{"data": ".i32 2"}`, or the label whose
+/// address it holds along with that address
+pub fn symbols_to_json(
+    symbol_manager: &SymbolManager,
+    label_manager: &LabelManager,
+    session: &Session,
+) -> String {
+    let symbols: Vec<String> = symbol_manager
+        .symbols()
+        .into_iter()
+        .map(|(name, symbol)| {
+            let binding = match symbol.binding {
+                Some(SymBind::Global) => json_string("global"),
+                Some(SymBind::Local) => json_string("local"),
+                Some(SymBind::Extern) => json_string("extern"),
+                None => String::from("null"),
+            };
+
+            let sym_type = match symbol.sym_type {
+                SymbolType::Func => json_string("func"),
+                SymbolType::Value => json_string("value"),
+                SymbolType::Default => String::from("null"),
+            };
+
+            let value = match &symbol.value {
+                SymbolValue::Undefined => String::from("null"),
+                SymbolValue::Function => json_string("function"),
+                SymbolValue::Value(value) => format!(
+                    "{{\"data\": {}}}",
+                    json_string(&Disassembler::format_data_entry(value))
+                ),
+                SymbolValue::LabelAddress(label, _, _) => {
+                    let address = match label_manager.get(label) {
+                        Some(label) => label.value.to_string(),
+                        None => String::from("null"),
+                    };
+
+                    format!(
+                        "{{\"label\": {}, \"address\": {}}}",
+                        json_string(label),
+                        address
+                    )
+                }
+            };
+
+            let section = match &symbol.section {
+                Some(section) => json_string(section),
+                None => String::from("null"),
+            };
+
+            let size = match symbol.size {
+                Some(size) => size.to_string(),
+                None => String::from("null"),
+            };
+
+            format!(
+                "{{\"name\": {}, \"binding\": {}, \"weak\": {}, \"type\": {}, \"value\": {}, \"section\": {}, \"size\": {}, \"declared\": {}}}",
+                json_string(name),
+                binding,
+                symbol.weak,
+                sym_type,
+                value,
+                section,
+                size,
+                json_location(&symbol.declared_span, session)
+            )
+        })
+        .collect();
+
+    let mut labels: Vec<_> = label_manager.labels().collect();
+    labels.sort_by_key(|(name, label)| (label.span.file, label.span.start, *name));
+
+    let labels: Vec<String> = labels
+        .into_iter()
+        .map(|(name, label)| {
+            format!(
+                "{{\"name\": {}, \"address\": {}, \"referenced\": {}, \"declared\": {}}}",
+                json_string(name),
+                label.value,
+                label_manager.is_referenced(name),
+                json_location(&label.span, session)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"symbols\": [{}],\n  \"labels\": [{}]\n}}\n",
+        json_array_items(&symbols),
+        json_array_items(&labels)
+    )
+}
+
+// Puts each item on its own line, indented within the array
+fn json_array_items(items: &[String]) -> String {
+    if items.is_empty() {
+        String::new()
+    } else {
+        format!("\n    {}\n  ", items.join(",\n    "))
+    }
+}
+
+// The location of a span as a string like "file.kasm:3:0"
+fn json_location(span: &Span, session: &Session) -> String {
+    let (path, line, col) = session.get_source_location(span);
+
+    json_string(&format!("{}:{}:{}", path, line, col))
+}
+
+fn json_string(value: &str) -> String {
+    let mut string = String::with_capacity(value.len() + 2);
+
+    string.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            '\r' => string.push_str("\\r"),
+            '\t' => string.push_str("\\t"),
+            c if c.is_control() => string.push_str(&format!("\\u{:04x}", c as u32)),
+            c => string.push(c),
+        }
+    }
+
+    string.push('"');
+
+    string
+}
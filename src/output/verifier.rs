@@ -124,6 +124,9 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
 
     /// Runs the verifier
     pub fn verify(self) -> Result<Vec<VerifiedFunction>, ()> {
+        self.verify_symbols()?;
+        self.verify_entry()?;
+
         let mut functions = Vec::new();
 
         for function in self.functions.iter() {
@@ -135,6 +138,118 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
         Ok(functions)
     }
 
+    // Verifies that the configured entry symbol, if there is one, is a function defined in this
+    // file
+    fn verify_entry(&self) -> Result<(), ()> {
+        let entry = match &self.session.config().entry {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+
+        let symbol = match self.symbol_manager.get(entry) {
+            Some(symbol)
+                if symbol.binding != Some(SymBind::Extern)
+                    && symbol.value != SymbolValue::Undefined =>
+            {
+                symbol
+            }
+            _ => {
+                self.session
+                    .struct_error(format!("entry symbol `{}` is not defined", entry))
+                    .help("the entry point must be a function defined in this file".to_string())
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        if symbol.value != SymbolValue::Function {
+            self.session
+                .struct_span_error(
+                    symbol.defined_span.unwrap_or(symbol.declared_span),
+                    format!("entry symbol `{}` is not a function", entry),
+                )
+                .emit();
+
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    // Verifies that the type of each symbol agrees with the value it was finally given
+    fn verify_symbols(&self) -> Result<(), ()> {
+        let mut failed = false;
+
+        for (name, symbol) in self.symbol_manager.symbols() {
+            let message = match (symbol.sym_type, &symbol.value) {
+                (SymbolType::Func, SymbolValue::Value(_) | SymbolValue::LabelAddress(..)) => {
+                    format!("symbol `{}` has type .func, but was given a value", name)
+                }
+                (_, SymbolValue::LabelAddress(label, span, _)) => {
+                    if self.verify_label_address(label, *span).is_err() {
+                        failed = true;
+                    }
+
+                    continue;
+                }
+                (SymbolType::Value, SymbolValue::Function) => {
+                    format!(
+                        "symbol `{}` has type .value, but was defined as a function",
+                        name
+                    )
+                }
+                // Undefined symbols have already been reported by the parser
+                _ => continue,
+            };
+
+            self.session
+                .struct_span_error(symbol.declared_span, message)
+                .emit();
+
+            failed = true;
+        }
+
+        if failed {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Verifies that a label whose address is stored in the data section exists in this file
+    fn verify_label_address(&self, label: &String, span: Span) -> Result<(), ()> {
+        if self.label_manager.contains(label) {
+            return Ok(());
+        }
+
+        match self.symbol_manager.get(label) {
+            Some(symbol) if symbol.binding == Some(SymBind::Extern) => {
+                self.session
+                    .struct_span_error(
+                        span,
+                        format!("cannot store the address of external symbol `{}`", label),
+                    )
+                    .note(
+                        "object files can only relocate instruction operands, not data".to_string(),
+                    )
+                    .emit();
+            }
+            Some(_) => {
+                self.session
+                    .struct_span_error(span, format!("symbol `{}` is not a label", label))
+                    .emit();
+            }
+            None => {
+                self.session
+                    .struct_span_error(span, format!("use of undeclared label `{}`", label))
+                    .emit();
+            }
+        }
+
+        Err(())
+    }
+
     // Verifies a single function
     fn verify_function(&self, function: &ParsedFunction) -> Result<VerifiedFunction, ()> {
         let mut instructions = Vec::new();
@@ -174,8 +289,10 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                 opcode: _,
                 span,
                 operand,
+                operand_span,
             } => {
-                let verified = self.verify_operand(operand, &accepted_operands[0], 1, *span)?;
+                let verified =
+                    self.verify_operand(operand, accepted_operands[0], 1, *span, *operand_span)?;
 
                 VerifiedInstruction::OneOp {
                     opcode,
@@ -186,10 +303,14 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                 opcode: _,
                 span,
                 operand1,
+                operand1_span,
                 operand2,
+                operand2_span,
             } => {
-                let verified1 = self.verify_operand(operand1, &accepted_operands[0], 1, *span)?;
-                let verified2 = self.verify_operand(operand2, &accepted_operands[1], 2, *span)?;
+                let verified1 =
+                    self.verify_operand(operand1, accepted_operands[0], 1, *span, *operand1_span)?;
+                let verified2 =
+                    self.verify_operand(operand2, accepted_operands[1], 2, *span, *operand2_span)?;
 
                 VerifiedInstruction::TwoOp {
                     opcode,
@@ -200,12 +321,14 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
         })
     }
 
-    // Verifies a single instruction operand
+    // Verifies a single instruction operand. The instruction span is that of the instruction's
+    // opcode, and the operand span is that of the operand being verified
     fn verify_operand(
         &self,
         operand: &InstructionOperand,
         accepted: &[OperandType],
         num: usize,
+        instr_span: Span,
         span: Span,
     ) -> Result<VerifiedOperand, ()> {
         match operand {
@@ -213,7 +336,7 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                 if accepted.contains(&OperandType::Null) {
                     Ok(VerifiedOperand::Value(KOSValue::Null))
                 } else {
-                    self.error_invalid_operand(num, span, operand, accepted)?;
+                    self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                     Err(())
                 }
@@ -222,7 +345,7 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                 if accepted.contains(&OperandType::ArgMarker) {
                     Ok(VerifiedOperand::Value(KOSValue::ArgMarker))
                 } else {
-                    self.error_invalid_operand(num, span, operand, accepted)?;
+                    self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                     Err(())
                 }
@@ -233,7 +356,7 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                 } else if accepted.contains(&OperandType::BooleanValue) {
                     Ok(VerifiedOperand::Value(KOSValue::BoolValue(*b)))
                 } else {
-                    self.error_invalid_operand(num, span, operand, accepted)?;
+                    self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                     Err(())
                 }
@@ -244,7 +367,7 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                 } else if accepted.contains(&OperandType::StringValue) {
                     Ok(VerifiedOperand::Value(KOSValue::StringValue(s.clone())))
                 } else {
-                    self.error_invalid_operand(num, span, operand, accepted)?;
+                    self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                     Err(())
                 }
@@ -255,7 +378,7 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                 } else if accepted.contains(&OperandType::ScalarDouble) {
                     Ok(VerifiedOperand::Value(KOSValue::ScalarDouble(*f)))
                 } else {
-                    self.error_invalid_operand(num, span, operand, accepted)?;
+                    self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                     Err(())
                 }
@@ -265,28 +388,32 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                     if let Some(label) = self.label_manager.get(l) {
                         Ok(VerifiedOperand::Label(label.value))
                     } else {
-                        self.session
-                            .struct_span_error(
-                                span,
-                                format!("instruction references unknown label `{}`", l),
-                            )
-                            .emit();
+                        self.error_undeclared_label(l, span);
 
                         Err(())
                     }
                 } else {
-                    self.error_invalid_operand(num, span, operand, accepted)?;
+                    self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                     Err(())
                 }
             }
+            // Labels that aren't inner labels can be branched to from any function, and are
+            // written the same way as symbols
+            InstructionOperand::Symbol(s)
+                if accepted.contains(&OperandType::Label) && self.label_manager.contains(s) =>
+            {
+                Ok(VerifiedOperand::Label(
+                    self.label_manager.get(s).unwrap().value,
+                ))
+            }
             InstructionOperand::Symbol(s) => {
                 if let Some(symbol) = self.symbol_manager.get(s) {
                     if symbol.sym_type == SymbolType::Func {
                         if accepted.contains(&OperandType::Function) {
                             Ok(VerifiedOperand::Symbol(s.clone()))
                         } else {
-                            self.error_invalid_operand(num, span, operand, accepted)?;
+                            self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                             Err(())
                         }
@@ -294,7 +421,7 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                         // We can do a little error checking, but if it is external, we can't
                         // really do much
 
-                        if symbol.binding.unwrap() != SymBind::Extern {
+                        if symbol.binding != Some(SymBind::Extern) {
                             let is_ok = match &symbol.value {
                                 SymbolValue::Value(value) => {
                                     let operand_type = match value {
@@ -315,6 +442,12 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
 
                                     accepted.contains(&operand_type)
                                 }
+                                SymbolValue::LabelAddress(_, _, false) => {
+                                    accepted.contains(&OperandType::Int32)
+                                }
+                                SymbolValue::LabelAddress(_, _, true) => {
+                                    accepted.contains(&OperandType::ScalarInt)
+                                }
                                 SymbolValue::Function => accepted.contains(&OperandType::Function),
                                 SymbolValue::Undefined => {
                                     self.session
@@ -330,7 +463,9 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                             if is_ok {
                                 Ok(VerifiedOperand::Symbol(s.clone()))
                             } else {
-                                self.error_invalid_operand(num, span, operand, accepted)?;
+                                self.error_invalid_operand(
+                                    num, instr_span, span, operand, accepted,
+                                )?;
 
                                 Err(())
                             }
@@ -346,12 +481,19 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
                         Err(())
                     }
                 } else {
-                    // This symbol doesn't exist
+                    // This symbol doesn't exist, but it may be intended to come from another file
+                    let (kind, sym_type) = if accepted.contains(&OperandType::Function) {
+                        ("label", ".func")
+                    } else {
+                        ("symbol", ".value")
+                    };
+
                     self.session
-                        .struct_span_error(
-                            span,
-                            format!("instruction references symbol `{}`, that does not exist", s),
-                        )
+                        .struct_span_error(span, format!("use of undeclared {} `{}`", kind, s))
+                        .help(format!(
+                            "if `{}` is defined in another file, declare it with `.extern {} {}`",
+                            s, sym_type, s
+                        ))
                         .emit();
 
                     Err(())
@@ -382,7 +524,7 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
 
                     Ok(VerifiedOperand::Value(value))
                 } else {
-                    self.error_invalid_operand(num, span, operand, accepted)?;
+                    self.error_invalid_operand(num, instr_span, span, operand, accepted)?;
 
                     Err(())
                 }
@@ -461,14 +603,44 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
         )
     }
 
+    // Inner labels are stored as function.label, but are written as .label
+    fn error_undeclared_label(&self, label: &str, span: Span) {
+        let (function, inner) = label.split_once('.').unwrap_or(("", label));
+
+        let mut db = self
+            .session
+            .struct_span_error(span, format!("use of undeclared label `.{}`", inner));
+
+        // If it was declared, just in another function. Labels are stored in a HashMap, so the
+        // first function in the source is picked to keep this the same from run to run
+        let declared_elsewhere = self
+            .label_manager
+            .labels()
+            .filter_map(|(name, label)| Some((name.split_once('.')?, label.value)))
+            .filter(|((other_function, other_inner), _)| {
+                *other_inner == inner && *other_function != function
+            })
+            .min_by_key(|&((other_function, _), value)| (value, other_function));
+
+        if let Some(((other_function, _), _)) = declared_elsewhere {
+            db.note(format!(
+                "a label `.{}` is declared in function `{}`, but inner labels can only be used within their own function",
+                inner, other_function
+            ));
+        }
+
+        db.emit();
+    }
+
     fn error_invalid_operand(
         &self,
         num: usize,
+        instr_span: Span,
         span: Span,
         provided: &InstructionOperand,
         accepted: &[OperandType],
     ) -> Result<(), ()> {
-        let instr_snippet = self.session.span_to_snippet(&span);
+        let instr_snippet = self.session.span_to_snippet(&instr_span);
         let instr_str = instr_snippet.as_slice();
 
         let accepted_types_s = self.accepted_types_to_string(accepted);
@@ -488,19 +660,31 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
     fn accepted_types_to_string(&self, accepted: &[OperandType]) -> String {
         let mut s = String::new();
 
+        // Several operand types share a name, such as all of the integer sizes, so only list each
+        // name once
+        let mut accepted_strs: Vec<&str> = Vec::new();
+
+        for op_type in accepted.iter() {
+            if !accepted_strs.contains(&op_type.to_str()) {
+                accepted_strs.push(op_type.to_str());
+            }
+        }
+
+        let accepted = accepted_strs;
+
         if accepted.len() == 1 {
-            s = accepted.first().unwrap().to_str().to_string();
+            s = accepted.first().unwrap().to_string();
         } else if accepted.len() == 2 {
-            let first = accepted.first().unwrap().to_str();
-            let second = accepted.last().unwrap().to_str();
+            let first = accepted.first().unwrap();
+            let second = accepted.last().unwrap();
 
             s = format!("{} or {}", first, second);
         } else {
-            let second_to_last = accepted.iter().nth(accepted.len() - 2).unwrap().to_str();
-            let last = accepted.last().unwrap().to_str();
+            let second_to_last = accepted[accepted.len() - 2];
+            let last = accepted.last().unwrap();
 
             for op_type in accepted.iter().take(accepted.len() - 2) {
-                s.push_str(&format!("{}, ", op_type.to_str()));
+                s.push_str(&format!("{}, ", op_type));
             }
 
             s.push_str(&format!("{} or {}", second_to_last, last));
@@ -578,7 +762,10 @@ impl<'a, 'b, 'c> Verifier<'a, 'b, 'c> {
             Opcode::Bscp => &[&[OperandType::Int16], &[OperandType::Int16]],
             Opcode::Escp => &[&[OperandType::Int16]],
             Opcode::Stoe => &[&[OperandType::String]],
-            Opcode::Phdl => &[&[OperandType::Byte, OperandType::Int16, OperandType::Int32]],
+            Opcode::Phdl => &[
+                &[OperandType::Byte, OperandType::Int16, OperandType::Int32],
+                &[OperandType::Bool],
+            ],
             Opcode::Btr => &[&[OperandType::String, OperandType::Int32, OperandType::Label]],
             Opcode::Exst => &[&[]],
             Opcode::Argb => &[&[]],
@@ -1,4 +1,7 @@
-use std::collections::{hash_map::Iter, HashMap};
+use std::collections::{
+    hash_map::{Iter, IterMut},
+    HashMap, HashSet,
+};
 
 use kerbalobjects::{ko::symbols::SymBind, KOSValue};
 
@@ -14,6 +17,9 @@ pub enum SymbolType {
 #[derive(Debug, PartialEq, Clone)]
 pub enum SymbolValue {
     Value(KOSValue),
+    /// The address of the named label, which is only known once every instruction has been laid
+    /// out. The bool is if it is stored as a ScalarInt instead of an Int32
+    LabelAddress(String, Span, bool),
     Function,
     Undefined,
 }
@@ -22,8 +28,21 @@ pub enum SymbolValue {
 pub struct DeclaredSymbol {
     pub declared_span: Span,
     pub binding: Option<SymBind>,
+    /// Where the symbol was given its binding, if it has one
+    pub binding_span: Option<Span>,
+    /// If the symbol was only declared with .weak. Object files have no weak binding, so these
+    /// are given a global binding, but a .global declaration of the same symbol isn't redundant
+    pub weak: bool,
     pub sym_type: SymbolType,
     pub value: SymbolValue,
+    /// Where the symbol was given its value, if it has one
+    pub defined_span: Option<Span>,
+    /// The name of the section that the symbol's value was given in, if it isn't .data
+    pub section: Option<String>,
+    /// The size given to the symbol with .size, if any. Otherwise the size of its value is used
+    pub size: Option<u16>,
+    /// Where the symbol was given its size, if it has one
+    pub size_span: Option<Span>,
 }
 
 impl DeclaredSymbol {
@@ -35,9 +54,15 @@ impl DeclaredSymbol {
     ) -> Self {
         Self {
             declared_span: span,
+            binding_span: binding.map(|_| span),
             binding,
+            weak: false,
             sym_type,
+            defined_span: (value != SymbolValue::Undefined).then_some(span),
             value,
+            section: None,
+            size: None,
+            size_span: None,
         }
     }
 }
@@ -69,8 +94,20 @@ impl SymbolManager {
         self.map.insert(identifier, declared);
     }
 
-    pub fn symbols(&self) -> Iter<String, DeclaredSymbol> {
-        self.map.iter()
+    /// Returns all of the symbols, ordered by where they were declared. This order is used when
+    /// writing the symbol table, so that assembling the same input always produces the same output
+    pub fn symbols(&self) -> Vec<(&String, &DeclaredSymbol)> {
+        let mut symbols: Vec<_> = self.map.iter().collect();
+
+        symbols.sort_by(|(a_name, a), (b_name, b)| {
+            (a.declared_span.file, a.declared_span.start, a_name).cmp(&(
+                b.declared_span.file,
+                b.declared_span.start,
+                b_name,
+            ))
+        });
+
+        symbols
     }
 }
 
@@ -93,15 +130,27 @@ impl Label {
 
 pub struct LabelManager {
     map: HashMap<String, Label>,
+    // Labels can be referenced before they are declared, so this is kept separately
+    referenced: HashSet<String>,
 }
 
 impl LabelManager {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            referenced: HashSet::new(),
         }
     }
 
+    /// Records that an instruction operand referenced the label with the given name
+    pub fn reference(&mut self, name: String) {
+        self.referenced.insert(name);
+    }
+
+    pub fn is_referenced(&self, name: &String) -> bool {
+        self.referenced.contains(name)
+    }
+
     pub fn contains(&self, name: &String) -> bool {
         self.map.contains_key(name)
     }
@@ -121,6 +170,10 @@ impl LabelManager {
     pub fn labels(&self) -> Iter<String, Label> {
         self.map.iter()
     }
+
+    pub fn labels_mut(&mut self) -> IterMut<'_, String, Label> {
+        self.map.iter_mut()
+    }
 }
 
 impl Default for LabelManager {
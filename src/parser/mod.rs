@@ -1,3 +1,4 @@
 mod managers;
 pub mod parse;
+pub mod peephole;
 pub use managers::*;
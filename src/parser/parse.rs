@@ -1,21 +1,30 @@
-use std::convert::TryFrom;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fmt::{Display, Formatter},
+};
 
 use kerbalobjects::{ko::symbols::SymBind, KOSValue, Opcode};
 
 use crate::{
-    errors::Span,
-    lexer::{Token, TokenKind},
+    errors::{closest_match, DiagnosticBuilder, Span},
+    lexer::{Token, TokenKind, DIRECTIVE_SPELLINGS},
     parser::{DeclaredSymbol, SymbolType},
     preprocessor::{
-        evaluator::ExpressionEvaluator,
-        expressions::{ExpressionParser, Value},
+        evaluator::{EvalErrorKind, ExpressionEvaluator},
+        expressions::{is_i32_min_magnitude, ExpressionParser, Value},
+        parser::{
+            format_float_literal, format_string_literal, parse_binary_literal,
+            parse_hexadecimal_literal, parse_integer_literal, parse_string_literal,
+            struct_escape_error,
+        },
     },
     session::Session,
 };
 
 use super::{Label, LabelManager, SymbolManager, SymbolValue};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParsedFunction {
     pub name: String,
     pub instructions: Vec<ParsedInstruction>,
@@ -37,30 +46,47 @@ pub enum ParsedInstruction {
         opcode: Opcode,
         span: Span,
         operand: InstructionOperand,
+        operand_span: Span,
     },
     TwoOp {
         opcode: Opcode,
         span: Span,
         operand1: InstructionOperand,
+        operand1_span: Span,
         operand2: InstructionOperand,
+        operand2_span: Span,
     },
 }
 
 impl ParsedInstruction {
     pub fn opcode(&self) -> Opcode {
         *match self {
-            ParsedInstruction::ZeroOp { opcode, span: _ } => opcode,
-            ParsedInstruction::OneOp {
-                opcode,
-                span: _,
-                operand: _,
-            } => opcode,
+            ParsedInstruction::ZeroOp { opcode, .. } => opcode,
+            ParsedInstruction::OneOp { opcode, .. } => opcode,
+            ParsedInstruction::TwoOp { opcode, .. } => opcode,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        *match self {
+            ParsedInstruction::ZeroOp { span, .. } => span,
+            ParsedInstruction::OneOp { span, .. } => span,
+            ParsedInstruction::TwoOp { span, .. } => span,
+        }
+    }
+}
+
+/// Renders the instruction as KASM source code, its mnemonic followed by its operands
+impl Display for ParsedInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mnemonic: &str = self.opcode().into();
+
+        match self {
+            ParsedInstruction::ZeroOp { .. } => write!(f, "{}", mnemonic),
+            ParsedInstruction::OneOp { operand, .. } => write!(f, "{} {}", mnemonic, operand),
             ParsedInstruction::TwoOp {
-                opcode,
-                span: _,
-                operand1: _,
-                operand2: _,
-            } => opcode,
+                operand1, operand2, ..
+            } => write!(f, "{} {}, {}", mnemonic, operand1, operand2),
         }
     }
 }
@@ -92,6 +118,27 @@ impl InstructionOperand {
     }
 }
 
+/// Renders the operand as it would be written in KASM source code
+impl Display for InstructionOperand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::String(s) => write!(f, "{}", format_string_literal(s)),
+            Self::Float(d) => write!(f, "{}", format_float_literal(*d)),
+            // Labels are stored along with the function label they are under, but are written
+            // as inner labels
+            Self::Label(label) => match label.split_once('.') {
+                Some((_, inner)) => write!(f, ".{}", inner),
+                None => write!(f, "{}", label),
+            },
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Symbol(symbol) => write!(f, "{}", symbol),
+            Self::ArgMarker => write!(f, "@"),
+            Self::Null => write!(f, "#"),
+        }
+    }
+}
+
 pub type PResult = Result<(), ()>;
 
 /// The parser for KASM instructions, symbols, and labels
@@ -104,13 +151,66 @@ pub struct Parser<'a> {
     label_manager: LabelManager,
     latest_label: String,
     instruction_count: usize,
-    mode: Mode,
+    section: Section,
+    binaries: Vec<Vec<u8>>,
+    operand_cache: OperandCache,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum Mode {
+/// The functions, labels, and symbols that were parsed, along with the contents of every binary
+/// file that was included using .incbin
+pub type ParseOutput = (
+    Vec<ParsedFunction>,
+    LabelManager,
+    SymbolManager,
+    Vec<Vec<u8>>,
+);
+
+// The section that everything is currently being placed in
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Section {
     Text,
     Data,
+    // Any other section, which like .data can only contain data entries
+    Custom(String),
+}
+
+// The sections that every object file has
+const STANDARD_SECTION_NAMES: [&str; 2] = [".text", ".data"];
+
+// Section names that are already used by the sections the assembler creates itself
+const RESERVED_SECTION_NAMES: [&str; 6] = [
+    ".symtab",
+    ".symstrtab",
+    ".comment",
+    ".reld",
+    ".source",
+    ".entry",
+];
+
+// Labels can't start with this, so that they never clash with names the assembler generates
+const RESERVED_LABEL_PREFIX: &str = "__";
+
+// The binding that a binding directive gives a symbol, and whether or not the symbol is weak.
+// There is no weak binding in KerbalObject files, so weak symbols are global
+// The smallest and largest values that an integer data type can hold, or None if the type
+// isn't an integer
+fn integer_type_range(kind: TokenKind) -> Option<(i64, i64)> {
+    match kind {
+        TokenKind::TypeI8 => Some((i8::MIN.into(), i8::MAX.into())),
+        TokenKind::TypeI16 => Some((i16::MIN.into(), i16::MAX.into())),
+        TokenKind::TypeI32 | TokenKind::TypeI32V => Some((i32::MIN.into(), i32::MAX.into())),
+        _ => None,
+    }
+}
+
+fn symbol_binding(kind: TokenKind) -> Option<(SymBind, bool)> {
+    match kind {
+        TokenKind::DirectiveExtern => Some((SymBind::Extern, false)),
+        TokenKind::DirectiveGlobal => Some((SymBind::Global, false)),
+        TokenKind::DirectiveWeak => Some((SymBind::Global, true)),
+        TokenKind::DirectiveLocal => Some((SymBind::Local, false)),
+        _ => None,
+    }
 }
 
 impl<'a> Parser<'a> {
@@ -124,15 +224,17 @@ impl<'a> Parser<'a> {
             label_manager: LabelManager::new(),
             latest_label: String::new(),
             instruction_count: 0,
-            mode: Mode::Text,
+            section: Section::Text,
+            binaries: Vec::new(),
+            operand_cache: OperandCache::new(),
         }
     }
 
     /// Parses the provided tokens as functions and instructions.
     /// This also happens to execute all remaining assembler directives such as declaring symbols
     /// and their bindings. It produces a list of functions, as well as the symbols and labels that
-    /// were encountered
-    pub fn parse(mut self) -> Result<(Vec<ParsedFunction>, LabelManager, SymbolManager), ()> {
+    /// were encountered, and the contents of every binary file included by .incbin
+    pub fn parse(mut self) -> Result<ParseOutput, ()> {
         let mut functions = Vec::new();
 
         // Skip until we get to a non-whitespace token
@@ -149,15 +251,11 @@ impl<'a> Parser<'a> {
                 }
                 TokenKind::DirectiveExtern
                 | TokenKind::DirectiveGlobal
-                | TokenKind::DirectiveLocal => {
-                    let binding = match next.kind {
-                        TokenKind::DirectiveExtern => SymBind::Extern,
-                        TokenKind::DirectiveGlobal => SymBind::Global,
-                        TokenKind::DirectiveLocal => SymBind::Local,
-                        _ => unreachable!(),
-                    };
+                | TokenKind::DirectiveLocal
+                | TokenKind::DirectiveWeak => {
+                    let (binding, weak) = symbol_binding(next.kind).unwrap();
 
-                    self.parse_binding(next.as_span(), binding)?;
+                    self.parse_binding(next.as_span(), binding, weak)?;
 
                     self.assert_nothing_before_newline()?;
                 }
@@ -166,15 +264,42 @@ impl<'a> Parser<'a> {
 
                     self.assert_nothing_before_newline()?;
                 }
+                TokenKind::DirectiveSize => self.parse_size(next.as_span())?,
                 TokenKind::DirectiveValue => {}
+                TokenKind::DirectiveIncbin
+                | TokenKind::DirectiveAscii
+                | TokenKind::DirectiveAsciz => {
+                    if self.section != Section::Data {
+                        let directive_str = next.resolve(self.session);
+
+                        let mut db = self.session.struct_span_error(
+                            next.as_span(),
+                            format!("{} must be in a .data section", directive_str),
+                        );
+
+                        self.note_current_section(&mut db);
+
+                        db.emit();
+
+                        return Err(());
+                    } else if next.kind == TokenKind::DirectiveIncbin {
+                        self.parse_incbin(next.as_span())?;
+                    } else {
+                        let null_terminated = next.kind == TokenKind::DirectiveAsciz;
+
+                        self.parse_ascii(next.as_span(), null_terminated)?;
+                    }
+                }
                 TokenKind::DirectiveFunc => {
-                    if self.mode == Mode::Data {
-                        self.session
-                            .struct_span_error(
-                                next.as_span(),
-                                "functions must be in a .text section".to_string(),
-                            )
-                            .emit();
+                    if self.section != Section::Text {
+                        let mut db = self.session.struct_span_error(
+                            next.as_span(),
+                            "functions must be in a .text section".to_string(),
+                        );
+
+                        self.note_current_section(&mut db);
+
+                        db.emit();
 
                         return Err(());
                     } else {
@@ -184,7 +309,7 @@ impl<'a> Parser<'a> {
                     }
                 }
                 TokenKind::Identifier => {
-                    if self.mode == Mode::Text {
+                    if self.section == Section::Text {
                         self.session
                             .struct_span_error(
                                 next.as_span(),
@@ -198,7 +323,20 @@ impl<'a> Parser<'a> {
                         self.parse_data_entry(next.as_span())?;
                     }
                 }
+                // Directives are spelled the same way as references to inner labels, which
+                // can't start a line, so this is most likely a misspelled directive
+                TokenKind::InnerLabelReference => {
+                    self.struct_err_unknown_directive(next).emit();
+
+                    return Err(());
+                }
                 _ => {
+                    if let Some(mut db) = self.struct_err_invalid_label() {
+                        db.emit();
+
+                        return Err(());
+                    }
+
                     self.session
                         .struct_span_error(
                             next.as_span(),
@@ -214,35 +352,252 @@ impl<'a> Parser<'a> {
             self.skip_empty_lines();
         }
 
-        for (_, symbol) in self.symbol_manager.symbols() {
-            if let Some(SymBind::Extern) = symbol.binding {
+        self.validate_symbols()?;
+
+        if self.session.config().warn_unused {
+            self.warn_unreferenced_labels();
+        }
+
+        if self.session.config().warn_unreachable {
+            self.warn_unreachable_instructions(&functions);
+        }
+
+        Ok((
+            functions,
+            self.label_manager,
+            self.symbol_manager,
+            self.binaries,
+        ))
+    }
+
+    // Checks that every symbol was declared and defined consistently with its binding, once all of
+    // them are known. Every symbol that isn't is reported, not just the first
+    //
+    // External symbols must have their type specified, and can't be given a value, as that is
+    // provided by another file. Every other symbol must be given a value
+    fn validate_symbols(&self) -> PResult {
+        let mut valid = true;
+
+        for (name, symbol) in self.symbol_manager.symbols() {
+            let binding_span = symbol.binding_span.unwrap_or(symbol.declared_span);
+
+            if symbol.binding == Some(SymBind::Extern) {
                 if symbol.sym_type == SymbolType::Default {
                     self.session
                         .struct_span_error(
-                            symbol.declared_span,
-                            "external symbols must have the type specified".to_string(),
+                            binding_span,
+                            format!("external symbol `{}` must have its type specified", name),
                         )
+                        .help(format!(
+                            "declare it with `.extern .func {}` or `.extern .value {}`",
+                            name, name
+                        ))
                         .emit();
 
-                    return Err(());
+                    valid = false;
                 }
-            }
 
-            if symbol.value == SymbolValue::Undefined
-                && (symbol.binding.is_none() || symbol.binding.unwrap() != SymBind::Extern)
-            {
+                if symbol.value != SymbolValue::Undefined {
+                    self.session
+                        .struct_span_error(
+                            symbol.defined_span.unwrap_or(symbol.declared_span),
+                            format!("external symbol `{}` cannot be given a value", name),
+                        )
+                        .span_label(binding_span, "declared external here".to_string())
+                        .emit();
+
+                    valid = false;
+                }
+            } else if symbol.value == SymbolValue::Undefined {
                 self.session
                     .struct_span_error(
                         symbol.declared_span,
-                        "symbol declared but never given a value".to_string(),
+                        format!("symbol `{}` declared but never given a value", name),
+                    )
+                    .emit();
+
+                valid = false;
+            }
+        }
+
+        if valid {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    // Warns about every label that is never referenced by an instruction. Function labels are
+    // exempt, as they are symbols that may be used from other files
+    fn warn_unreferenced_labels(&self) {
+        let mut unreferenced: Vec<&Label> = self
+            .label_manager
+            .labels()
+            .filter(|(name, _)| {
+                !self.label_manager.is_referenced(name)
+                    && !matches!(self.symbol_manager.get(name), Some(symbol) if symbol.sym_type == SymbolType::Func)
+            })
+            .map(|(_, label)| label)
+            .collect();
+
+        unreferenced.sort_by_key(|label| (label.span.file, label.span.start));
+
+        for label in unreferenced {
+            let label_snippet = self.session.span_to_snippet(&label.span);
+            let label_str = label_snippet.as_slice();
+
+            self.session
+                .struct_span_warn(
+                    label.span,
+                    format!(
+                        "label `{}` is never referenced",
+                        &label_str[..label_str.len() - 1]
+                    ),
+                )
+                .emit();
+        }
+    }
+
+    // Warns about the first instruction after each jmp or ret that execution can never reach.
+    // Any label makes the code after it reachable again, because it could be jumped to
+    fn warn_unreachable_instructions(&self, functions: &[ParsedFunction]) {
+        let label_positions: HashSet<usize> = self
+            .label_manager
+            .labels()
+            .map(|(_, label)| label.value)
+            .collect();
+
+        // Label positions count instructions across every function
+        let mut index = 0;
+
+        for function in functions {
+            // The span of the jmp or ret that makes the current instruction unreachable, if any
+            let mut transfer_span = None;
+            // Only the first instruction of each unreachable run is warned about
+            let mut warned = false;
+
+            for instruction in function.instructions.iter() {
+                if label_positions.contains(&index) {
+                    transfer_span = None;
+                }
+
+                match transfer_span {
+                    Some(span) if !warned => {
+                        self.session
+                            .struct_span_warn(
+                                instruction.span(),
+                                "unreachable instruction".to_string(),
+                            )
+                            .span_label(span, "any code following this is unreachable".to_string())
+                            .emit();
+
+                        warned = true;
+                    }
+                    Some(_) => {}
+                    None => {
+                        if matches!(instruction.opcode(), Opcode::Jmp | Opcode::Ret) {
+                            transfer_span = Some(instruction.span());
+                            warned = false;
+                        }
+                    }
+                }
+
+                index += 1;
+            }
+        }
+    }
+
+    // Reads the raw bytes of the file referenced by an .incbin directive, so that they can be
+    // placed into the data section
+    fn parse_incbin(&mut self, incbin_span: Span) -> PResult {
+        self.skip_whitespace();
+
+        let path_token = self.expect_consume_token(incbin_span, ".incbin requires path")?;
+        let path_span = path_token.as_span();
+
+        if path_token.kind != TokenKind::LiteralString {
+            self.session
+                .struct_span_error(path_span, "expected path".to_string())
+                .emit();
+
+            return Err(());
+        }
+
+        let path_snippet = self.session.span_to_snippet(&path_span);
+        let path_str = path_snippet.as_slice().trim_matches('\"');
+
+        let path_buf = self.session.resolve_include(path_span, path_str)?;
+
+        let bytes = match std::fs::read(&path_buf) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.session
+                    .struct_span_error(
+                        path_span,
+                        format!(
+                            "unable to read file `{}`: {}",
+                            path_buf.to_string_lossy(),
+                            e
+                        ),
                     )
                     .emit();
 
                 return Err(());
             }
+        };
+
+        self.session
+            .struct_note(format!(
+                "included binary file `{}` ({} bytes)",
+                path_buf.to_string_lossy(),
+                bytes.len()
+            ))
+            .emit();
+
+        if !bytes.is_empty() {
+            self.binaries.push(bytes);
+        }
+
+        self.assert_nothing_before_newline()
+    }
+
+    // Lays out the bytes of the string given to an .ascii or .asciz directive in the data section
+    // the same way as .incbin, with a null byte after them for .asciz
+    fn parse_ascii(&mut self, ascii_span: Span, null_terminated: bool) -> PResult {
+        self.skip_whitespace();
+
+        let string_token = self.expect_consume_token(ascii_span, "expected string")?;
+        let string_span = string_token.as_span();
+
+        if string_token.kind != TokenKind::LiteralString {
+            self.session
+                .struct_span_error(string_span, "expected string".to_string())
+                .emit();
+
+            return Err(());
+        }
+
+        let mut bytes = self.parse_string_literal(string_span)?.into_bytes();
+
+        if null_terminated {
+            bytes.push(0);
+        }
+
+        if !bytes.is_empty() {
+            self.binaries.push(bytes);
         }
 
-        Ok((functions, self.label_manager, self.symbol_manager))
+        self.assert_nothing_before_newline()
+    }
+
+    // Converts a string literal token into the string it represents, by removing the quotes and
+    // replacing any escape sequences
+    fn parse_string_literal(&self, span: Span) -> Result<String, ()> {
+        let snippet = self.session.span_to_snippet(&span);
+
+        parse_string_literal(snippet.as_slice()).map_err(|escape| {
+            struct_escape_error(self.session, span, escape).emit();
+        })
     }
 
     fn parse_data_entry(&mut self, ident_span: Span) -> PResult {
@@ -251,6 +606,10 @@ impl<'a> Parser<'a> {
 
         self.skip_whitespace();
 
+        if let Some(address) = self.parse_label_address()? {
+            return self.define_data_symbol(ident_span, ident_str, address);
+        }
+
         // Now we try to parse the data type and value
         let value = if let Some(&type_token) = self.consume_next() {
             let type_span = type_token.as_span();
@@ -286,7 +645,8 @@ impl<'a> Parser<'a> {
                             | TokenKind::TypeBV
                     ) {
                         // Parse a value as an expression
-                        let value = self.parse_symbol_expression(type_span)?;
+                        let (value, value_span) =
+                            self.parse_symbol_expression(type_span, other, type_str)?;
 
                         // If it is supposed to be a boolean
                         if matches!(other, TokenKind::TypeB | TokenKind::TypeBV) {
@@ -327,28 +687,29 @@ impl<'a> Parser<'a> {
                         } else {
                             // If it is a supposed to be an integer of some kind
                             if let Value::Int(i) = value {
-                                if other == TokenKind::TypeI8 {
-                                    if let Ok(i) = i8::try_from(i) {
-                                        KOSValue::Byte(i)
-                                    } else {
-                                        self.session.struct_span_error(type_span, format!("value provided {} is too large to fit into a byte", i)).emit();
-                                        return Err(());
+                                let fits = match other {
+                                    TokenKind::TypeI8 => i8::try_from(i).map(KOSValue::Byte).ok(),
+                                    TokenKind::TypeI16 => {
+                                        i16::try_from(i).map(KOSValue::Int16).ok()
                                     }
-                                } else if other == TokenKind::TypeI16 {
-                                    if let Ok(i) = i16::try_from(i) {
-                                        KOSValue::Int16(i)
-                                    } else {
-                                        self.session.struct_span_error(type_span, format!("value provided {} is too large to fit into a 16-bit integer", i)).emit();
+                                    TokenKind::TypeI32 => Some(KOSValue::Int32(i)),
+                                    _ => Some(KOSValue::ScalarInt(i)),
+                                };
+
+                                match fits {
+                                    Some(value) => value,
+                                    None => {
+                                        self.struct_err_out_of_range(
+                                            value_span,
+                                            &i.to_string(),
+                                            other,
+                                            type_str,
+                                        )
+                                        .emit();
+
                                         return Err(());
                                     }
                                 }
-                                // These values have already been checked to fit because they
-                                // needed to be parsed
-                                else if other == TokenKind::TypeI32 {
-                                    KOSValue::Int32(i)
-                                } else {
-                                    KOSValue::ScalarInt(i)
-                                }
                             } else {
                                 self.session
                                     .struct_span_error(
@@ -364,9 +725,7 @@ impl<'a> Parser<'a> {
                         // If it is supposed to be a string
                         let value = if let Some(&s) = self.consume_next() {
                             if s.kind == TokenKind::LiteralString {
-                                let value_snippet = self.session.span_to_snippet(&s.as_span());
-                                let value_str = value_snippet.as_slice();
-                                let value_str = value_str[1..value_str.len() - 1].to_string();
+                                let value_str = self.parse_string_literal(s.as_span())?;
 
                                 if other == TokenKind::TypeS {
                                     KOSValue::String(value_str)
@@ -417,27 +776,118 @@ impl<'a> Parser<'a> {
             return Err(());
         };
 
+        self.define_data_symbol(ident_span, ident_str, SymbolValue::Value(value))
+    }
+
+    // Parses an integer data entry whose value is the name of a label, such as:
+    //
+    // entry .i32 my_func
+    //
+    // The address of the label isn't known until every instruction has been laid out, so it is
+    // filled in by the generator. Returns None without consuming anything if the entry isn't one
+    fn parse_label_address(&mut self) -> Result<Option<SymbolValue>, ()> {
+        let mut cursor = self.token_cursor;
+
+        let scalar = match self.tokens.get(cursor).map(|token| token.kind) {
+            Some(TokenKind::TypeI32) => false,
+            Some(TokenKind::TypeI32V) => true,
+            _ => return Ok(None),
+        };
+
+        cursor += 1;
+
+        while self
+            .tokens
+            .get(cursor)
+            .is_some_and(|token| token.kind == TokenKind::Whitespace)
+        {
+            cursor += 1;
+        }
+
+        let label = match self.tokens.get(cursor) {
+            Some(&token) if token.kind == TokenKind::Identifier => token,
+            _ => return Ok(None),
+        };
+
+        // Anything after the name makes this an expression, which can use other data symbols
+        let ends_line = self.tokens[cursor + 1..]
+            .iter()
+            .find(|token| token.kind != TokenKind::Whitespace)
+            .is_none_or(|token| token.kind == TokenKind::Newline);
+
+        if !ends_line {
+            return Ok(None);
+        }
+
+        let label_span = label.as_span();
+        let label_snippet = self.session.span_to_snippet(&label_span);
+        let label_str = label_snippet.as_slice().to_string();
+
+        // A lone data symbol is also an expression, unless a label shares its name
+        let is_data_symbol = self
+            .symbol_manager
+            .get(&label_str)
+            .is_some_and(|symbol| matches!(symbol.value, SymbolValue::Value(_)));
+
+        if is_data_symbol && !self.label_manager.contains(&label_str) {
+            return Ok(None);
+        }
+
+        self.token_cursor = cursor + 1;
+
+        self.assert_nothing_before_newline()?;
+
+        self.label_manager.reference(label_str.clone());
+
+        Ok(Some(SymbolValue::LabelAddress(
+            label_str, label_span, scalar,
+        )))
+    }
+
+    // Gives the symbol that a data entry is for its value, declaring it if it hasn't been already
+    fn define_data_symbol(
+        &mut self,
+        ident_span: Span,
+        ident_str: String,
+        value: SymbolValue,
+    ) -> PResult {
+        // In strict mode, a data entry can't be what declares a symbol's binding
+        if self.session.config().strict
+            && self
+                .symbol_manager
+                .get(&ident_str)
+                .is_none_or(|symbol| symbol.binding.is_none())
+        {
+            self.session
+                .struct_span_error(
+                    ident_span,
+                    format!("symbol `{}` defined without being declared", ident_str),
+                )
+                .help(format!(
+                    "declare it first with `.local {}` or `.global {}`",
+                    ident_str, ident_str
+                ))
+                .emit();
+
+            return Err(());
+        }
+
+        let section = match &self.section {
+            Section::Custom(name) => Some(name.clone()),
+            _ => None,
+        };
+
         if let Some(existing_symbol) = self.symbol_manager.get_mut(&ident_str) {
             if existing_symbol.value == SymbolValue::Undefined {
-                if existing_symbol.binding.is_none()
-                    || existing_symbol.binding.unwrap() != SymBind::Extern
-                {
+                // A conflicting type given by .type is reported by the verifier
+                if existing_symbol.sym_type == SymbolType::Default {
                     existing_symbol.sym_type = SymbolType::Value;
-                    existing_symbol.value = SymbolValue::Value(value);
-                } else {
-                    self.session
-                        .struct_span_error(
-                            ident_span,
-                            "symbol declared to be external but provided value".to_string(),
-                        )
-                        .span_label(
-                            existing_symbol.declared_span,
-                            "first declared as external".to_string(),
-                        )
-                        .emit();
-
-                    return Err(());
                 }
+
+                // Giving an external symbol a value is reported once parsing is done
+                existing_symbol.value = value;
+                existing_symbol.defined_span = Some(ident_span);
+                existing_symbol.section = section;
             } else {
                 self.session
                     .struct_span_error(ident_span, format!("symbol `{}` declared twice", ident_str))
@@ -450,12 +900,8 @@ impl<'a> Parser<'a> {
                 return Err(());
             }
         } else {
-            let new_symbol = DeclaredSymbol::new(
-                ident_span,
-                None,
-                SymbolType::Value,
-                SymbolValue::Value(value),
-            );
+            let mut new_symbol = DeclaredSymbol::new(ident_span, None, SymbolType::Value, value);
+            new_symbol.section = section;
 
             self.symbol_manager.insert(ident_str, new_symbol);
         }
@@ -463,7 +909,16 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn parse_symbol_expression(&mut self, type_span: Span) -> Result<Value, ()> {
+    // Parses and evaluates the value of a data entry, along with the span of the expression
+    //
+    // Integer types get their range checked even for values that don't fit into an i32 at all,
+    // which the expression itself can't hold
+    fn parse_symbol_expression(
+        &mut self,
+        type_span: Span,
+        type_kind: TokenKind,
+        type_str: &str,
+    ) -> Result<(Value, Span), ()> {
         let mut expression_tokens = Vec::new();
 
         while let Some(&expression_token) = self.consume_next() {
@@ -479,56 +934,202 @@ impl<'a> Parser<'a> {
                 .struct_span_error(type_span, "expected symbol value".to_string())
                 .emit();
 
-            Err(())
-        } else {
-            let mut exp_tokens = expression_tokens.iter().peekable();
-            let parsed_exp =
-                ExpressionParser::parse_expression(&mut exp_tokens, self.session, false)
-                    .map_err(|mut db| db.emit())?;
-
-            if let Some(exp) = parsed_exp {
-                let evaluated = match ExpressionEvaluator::evaluate(&exp) {
-                    Ok(exp) => exp,
-                    Err(e) => {
-                        let message = match e {
-                            crate::preprocessor::evaluator::EvalError::NegateBool => {
-                                "tried to apply operator - to boolean value"
-                            }
-                            crate::preprocessor::evaluator::EvalError::FlipDouble => {
-                                "tried to apply operator ~ to double value"
-                            }
-                            crate::preprocessor::evaluator::EvalError::ZeroDivide => {
-                                "tried to divide by zero"
-                            }
-                        };
+            return Err(());
+        }
 
-                        self.session
-                            .struct_span_error(
-                                type_span,
-                                format!("expression following this {}", message),
-                            )
-                            .emit();
+        let is_integer_type = integer_type_range(type_kind).is_some();
 
-                        return Err(());
-                    }
+        if is_integer_type {
+            for (index, token) in expression_tokens.iter().enumerate() {
+                let negated = expression_tokens[..index]
+                    .iter()
+                    .rfind(|token| token.kind != TokenKind::Whitespace)
+                    .is_some_and(|token| token.kind == TokenKind::OperatorMinus);
+
+                if negated && is_i32_min_magnitude(token, self.session) {
+                    continue;
+                }
+
+                let literal = token.resolve(self.session);
+
+                let too_large = match token.kind {
+                    TokenKind::LiteralInteger => parse_integer_literal(&literal).is_err(),
+                    TokenKind::LiteralHex => parse_hexadecimal_literal(&literal).is_err(),
+                    TokenKind::LiteralBinary => parse_binary_literal(&literal).is_err(),
+                    _ => false,
                 };
 
-                Ok(evaluated)
-            } else {
+                if too_large {
+                    self.struct_err_out_of_range(token.as_span(), &literal, type_kind, type_str)
+                        .emit();
+
+                    return Err(());
+                }
+            }
+        }
+
+        let mut exp_tokens = expression_tokens.iter().peekable();
+        let parsed_exp = ExpressionParser::parse_data_expression(&mut exp_tokens, self.session)
+            .map_err(|mut db| db.emit())?;
+
+        if let Some(mut exp) = parsed_exp {
+            let exp_span = exp.span();
+
+            exp.resolve_symbols(&mut |span| self.symbol_integer_value(span))?;
+
+            let evaluated = match ExpressionEvaluator::evaluate(&exp) {
+                Ok(exp) => exp,
+                Err(e) if is_integer_type && e.kind == EvalErrorKind::Overflow => {
+                    let exp_snippet = self.session.span_to_snippet(&exp_span);
+
+                    self.struct_err_out_of_range(
+                        exp_span,
+                        exp_snippet.as_slice(),
+                        type_kind,
+                        type_str,
+                    )
+                    .emit();
+
+                    return Err(());
+                }
+                Err(e) => {
+                    let mut db = self
+                        .session
+                        .struct_span_error(e.span, e.kind.message().to_string());
+
+                    if e.kind == EvalErrorKind::NegateBool {
+                        db.help("use `!` to invert a boolean".to_string());
+                    }
+
+                    db.emit();
+
+                    return Err(());
+                }
+            };
+
+            Ok((evaluated, exp_span))
+        } else {
+            self.session
+                .struct_bug("parsed expression is None despite having a first value".to_string())
+                .emit();
+
+            Err(())
+        }
+    }
+
+    // Finds the value of a data symbol that is used in a data expression. The value is needed
+    // right away, so only integers that were given earlier in the file can be used
+    fn symbol_integer_value(&self, span: Span) -> Result<Value, ()> {
+        let name = self.session.span_to_snippet(&span).as_slice().to_string();
+
+        let symbol = match self.symbol_manager.get(&name) {
+            Some(symbol) if symbol.value != SymbolValue::Undefined => symbol,
+            _ => {
                 self.session
-                    .struct_bug(
-                        "parsed expression is None despite having a first value".to_string(),
+                    .struct_span_error(
+                        span,
+                        format!("symbol `{}` is used before it is given a value", name),
+                    )
+                    .help(
+                        "only data symbols defined earlier in the file can be used in expressions"
+                            .to_string(),
                     )
                     .emit();
 
+                return Err(());
+            }
+        };
+
+        let value = match &symbol.value {
+            SymbolValue::Value(KOSValue::Byte(i)) => Some(*i as i32),
+            SymbolValue::Value(KOSValue::Int16(i)) => Some(*i as i32),
+            SymbolValue::Value(KOSValue::Int32(i) | KOSValue::ScalarInt(i)) => Some(*i),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => Ok(Value::Int(value)),
+            None => {
+                let mut db = self.session.struct_span_error(
+                    span,
+                    format!("symbol `{}` does not have an integer value", name),
+                );
+
+                if let Some(defined_span) = symbol.defined_span {
+                    db.span_label(defined_span, "defined here".to_string());
+                }
+
+                db.emit();
+
                 Err(())
             }
         }
     }
 
+    // A value that doesn't fit into the integer data type it was given, which shows the range
+    // that would have
+    fn struct_err_out_of_range(
+        &self,
+        span: Span,
+        value: &str,
+        type_kind: TokenKind,
+        type_str: &str,
+    ) -> DiagnosticBuilder<'_> {
+        let (min, max) = integer_type_range(type_kind).unwrap_or((i64::MIN, i64::MAX));
+
+        let mut db = self.session.struct_error(format!(
+            "value `{}` does not fit into `{}`",
+            value, type_str
+        ));
+
+        db.span_label(span, format!("expected {}..={}", min, max));
+
+        db
+    }
+
     fn parse_type(&mut self, type_span: Span) -> PResult {
         self.skip_whitespace();
 
+        // A binding can be given before the type, which declares both in one directive
+        if let Some(&binding_token) = self.peek_next() {
+            if let Some((binding, weak)) = symbol_binding(binding_token.kind) {
+                self.consume_next();
+                self.skip_whitespace();
+
+                // The type is left for parse_binding() to consume along with the identifier
+                let type_token = match self.peek_next() {
+                    Some(&token) => token,
+                    None => {
+                        self.session
+                            .struct_span_error(
+                                binding_token.as_span(),
+                                "expected symbol type".to_string(),
+                            )
+                            .emit();
+
+                        return Err(());
+                    }
+                };
+
+                if type_token.kind != TokenKind::DirectiveFunc
+                    && type_token.kind != TokenKind::DirectiveValue
+                {
+                    let type_str = type_token.resolve(self.session);
+
+                    self.session
+                        .struct_span_error(
+                            type_token.as_span(),
+                            format!("expected symbol type, found {}", type_str),
+                        )
+                        .emit();
+
+                    return Err(());
+                }
+
+                return self.parse_binding(binding_token.as_span(), binding, weak);
+            }
+        }
+
         let type_token = self.expect_consume_token(type_span, "expected symbol type")?;
 
         let sym_type = if type_token.kind == TokenKind::DirectiveFunc {
@@ -536,8 +1137,7 @@ impl<'a> Parser<'a> {
         } else if type_token.kind == TokenKind::DirectiveValue {
             SymbolType::Value
         } else {
-            let type_snippet = self.session.span_to_snippet(&type_token.as_span());
-            let type_str = type_snippet.as_slice();
+            let type_str = type_token.resolve(self.session);
 
             self.session
                 .struct_span_error(
@@ -554,8 +1154,7 @@ impl<'a> Parser<'a> {
         let ident_token = self.expect_consume_token(type_token.as_span(), "expected identifier")?;
 
         if ident_token.kind == TokenKind::Identifier {
-            let ident_snippet = self.session.span_to_snippet(&ident_token.as_span());
-            let ident_str = ident_snippet.as_slice().to_string();
+            let ident_str = ident_token.resolve(self.session);
 
             if let Some(symbol) = self.symbol_manager.get_mut(&ident_str) {
                 if symbol.sym_type == SymbolType::Default {
@@ -595,8 +1194,7 @@ impl<'a> Parser<'a> {
 
             Ok(())
         } else {
-            let token_snippet = self.session.span_to_snippet(&ident_token.as_span());
-            let token_str = token_snippet.as_slice();
+            let token_str = ident_token.resolve(self.session);
 
             self.session
                 .struct_span_error(
@@ -605,66 +1203,275 @@ impl<'a> Parser<'a> {
                 )
                 .emit();
 
-            Err(())
-        }
-    }
+            Err(())
+        }
+    }
+
+    // Parses a .size directive, such as:
+    //
+    // .size table, 16
+    //
+    // The symbol has to have been declared already, and the size is a constant expression
+    fn parse_size(&mut self, size_span: Span) -> PResult {
+        self.skip_whitespace();
+
+        let ident_token = self.expect_consume_token(size_span, "expected identifier")?;
+
+        if ident_token.kind != TokenKind::Identifier {
+            let token_str = ident_token.resolve(self.session);
+
+            self.session
+                .struct_span_error(
+                    ident_token.as_span(),
+                    format!("expected identifier, found {}", token_str),
+                )
+                .emit();
+
+            return Err(());
+        }
+
+        self.skip_whitespace();
+
+        match self.consume_next() {
+            Some(&comma) if comma.kind == TokenKind::SymbolComma => {}
+            _ => {
+                self.session
+                    .struct_span_error(
+                        ident_token.as_span(),
+                        "expected `,` and a size after the symbol".to_string(),
+                    )
+                    .emit();
+
+                return Err(());
+            }
+        }
+
+        self.skip_whitespace();
+
+        let ident_str = ident_token.resolve(self.session);
+        let ident_span = ident_token.as_span();
+
+        let (value, value_span) =
+            self.parse_symbol_expression(size_span, TokenKind::DirectiveSize, ".size")?;
+
+        let size = match value {
+            Value::Int(i) => match u16::try_from(i) {
+                Ok(size) => size,
+                Err(_) => {
+                    self.session
+                        .struct_error(format!("size `{}` is out of range", i))
+                        .span_label(value_span, format!("expected 0..={}", u16::MAX))
+                        .emit();
+
+                    return Err(());
+                }
+            },
+            _ => {
+                self.session
+                    .struct_span_error(value_span, "expected integer size".to_string())
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        let symbol = match self.symbol_manager.get_mut(&ident_str) {
+            Some(symbol) => symbol,
+            None => {
+                self.session
+                    .struct_span_error(
+                        ident_span,
+                        format!("cannot give a size to undeclared symbol `{}`", ident_str),
+                    )
+                    .help("a symbol must be declared or defined before its .size".to_string())
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        match (symbol.size, symbol.size_span) {
+            (Some(previous), Some(previous_span)) if previous != size => {
+                self.session
+                    .struct_span_error(ident_span, "conflicting symbol sizes".to_string())
+                    .span_label(
+                        previous_span,
+                        format!("size first given as {} here", previous),
+                    )
+                    .emit();
+
+                Err(())
+            }
+            (Some(_), Some(previous_span)) => {
+                self.session
+                    .struct_span_warn(ident_span, "redundant .size declaration".to_string())
+                    .span_label(previous_span, "size first given here".to_string())
+                    .emit();
+
+                Ok(())
+            }
+            _ => {
+                symbol.size = Some(size);
+                symbol.size_span = Some(ident_span);
+
+                Ok(())
+            }
+        }
+    }
+
+    fn assert_nothing_before_newline(&mut self) -> PResult {
+        while let Some(&token) = self.consume_next() {
+            if token.kind == TokenKind::Newline {
+                return Ok(());
+            } else if token.kind != TokenKind::Whitespace {
+                self.session
+                    .struct_span_error(token.as_span(), "newline expected".to_string())
+                    .emit();
+
+                return Err(());
+            }
+        }
+
+        // An EOF is actually okay
+        Ok(())
+    }
+
+    fn expect_consume_token(&mut self, before_span: Span, message: &str) -> Result<Token, ()> {
+        if let Some(&token) = self.consume_next() {
+            Ok(token)
+        } else {
+            self.session
+                .struct_span_error(before_span, message.to_string())
+                .emit();
+
+            Err(())
+        }
+    }
+
+    fn parse_section(&mut self, section_span: Span) -> PResult {
+        self.skip_whitespace();
+
+        let mode_token = self.expect_consume_token(section_span, "expected section type")?;
+
+        let mode_token_str = mode_token.resolve(self.session);
+
+        self.section = match mode_token.kind {
+            TokenKind::KeywordText => Section::Text,
+            TokenKind::KeywordData => Section::Data,
+            TokenKind::Identifier | TokenKind::InnerLabelReference => {
+                if RESERVED_SECTION_NAMES.contains(&mode_token_str.as_str()) {
+                    self.session
+                        .struct_span_error(
+                            mode_token.as_span(),
+                            format!("section name `{}` is reserved", mode_token_str),
+                        )
+                        .emit();
+
+                    return Err(());
+                }
+
+                Section::Custom(mode_token_str.to_string())
+            }
+            _ => {
+                let mut db = self.session.struct_span_error(
+                    mode_token.as_span(),
+                    format!("expected section type, found `{}`", mode_token_str),
+                );
+
+                if let Some(suggestion) = closest_match(&mode_token_str, &STANDARD_SECTION_NAMES) {
+                    db.help(format!("did you mean `{}`?", suggestion));
+                }
+
+                db.help(
+                    "sections are `.text`, `.data`, or the name of a custom data section"
+                        .to_string(),
+                );
 
-    fn assert_nothing_before_newline(&mut self) -> PResult {
-        while let Some(&token) = self.consume_next() {
-            if token.kind == TokenKind::Newline {
-                return Ok(());
-            } else if token.kind != TokenKind::Whitespace {
-                self.session
-                    .struct_span_error(token.as_span(), "newline expected".to_string())
-                    .emit();
+                db.emit();
 
                 return Err(());
             }
-        }
+        };
 
-        // An EOF is actually okay
         Ok(())
     }
 
-    fn expect_consume_token(&mut self, before_span: Span, message: &str) -> Result<Token, ()> {
-        if let Some(&token) = self.consume_next() {
-            Ok(token)
-        } else {
-            self.session
-                .struct_span_error(before_span, message.to_string())
-                .emit();
+    // Checks if the token that was just consumed starts a label that was split into several tokens
+    // by a character that can't be in a label, such as `my-label:` or `1abc:`
+    fn struct_err_invalid_label(&self) -> Option<DiagnosticBuilder<'_>> {
+        let start = self.token_cursor.checked_sub(1)?;
+        let mut end = start;
 
-            Err(())
+        while let Some(next) = self.tokens.get(end + 1) {
+            let previous = self.tokens[end];
+            let touching = next.file_id == previous.file_id
+                && next.source_index == previous.source_index + previous.len as u32;
+
+            if !touching || matches!(next.kind, TokenKind::Whitespace | TokenKind::Newline) {
+                break;
+            }
+
+            end += 1;
+        }
+
+        if end == start || self.tokens[end].kind != TokenKind::Label {
+            return None;
         }
+
+        let span = self.tokens[start]
+            .as_span()
+            .join(self.tokens[end].as_span());
+        let label_snippet = self.session.span_to_snippet(&span);
+        let label_str = label_snippet.as_slice();
+
+        let mut db = self.session.struct_span_error(
+            span,
+            format!("invalid label name `{}`", &label_str[..label_str.len() - 1]),
+        );
+
+        db.note(
+            "labels can only contain letters, digits, and `_`, and can't start with a digit"
+                .to_string(),
+        );
+
+        Some(db)
     }
 
-    fn parse_section(&mut self, section_span: Span) -> PResult {
-        self.skip_whitespace();
+    // Suggests the directive that was most likely meant, or lists all of them if none are close
+    fn struct_err_unknown_directive(&self, token: Token) -> DiagnosticBuilder<'_> {
+        let directive = token.resolve(self.session);
 
-        let mode_token = self.expect_consume_token(section_span, "expected section type")?;
+        let mut db = self.session.struct_span_error(
+            token.as_span(),
+            format!("unknown directive `{}`", directive),
+        );
 
-        if mode_token.kind == TokenKind::KeywordText {
-            self.mode = Mode::Text;
-        } else if mode_token.kind == TokenKind::KeywordData {
-            self.mode = Mode::Data;
-        } else {
-            let mode_token_snippet = self.session.span_to_snippet(&mode_token.as_span());
-            let mode_token_str = mode_token_snippet.as_slice();
+        let spellings: Vec<&str> = DIRECTIVE_SPELLINGS
+            .iter()
+            .map(|(spelling, _)| *spelling)
+            .collect();
 
-            self.session
-                .struct_span_error(
-                    mode_token.as_span(),
-                    format!("expected section type, found `{}`", mode_token_str),
-                )
-                .emit();
+        match closest_match(&directive, &spellings) {
+            Some(suggestion) => db.help(format!("did you mean `{}`?", suggestion)),
+            None => db.note(format!("directives are {}", spellings.join(", "))),
+        };
 
-            return Err(());
-        }
+        db
+    }
 
-        Ok(())
+    // Explains what section a directive was found in, when that is a custom section. The name
+    // might have been meant to be a standard section, but was misspelled
+    fn note_current_section(&self, db: &mut DiagnosticBuilder) {
+        if let Section::Custom(name) = &self.section {
+            db.note(format!("the current section is `{}`", name));
+
+            if let Some(suggestion) = closest_match(name, &STANDARD_SECTION_NAMES) {
+                db.help(format!("did you mean `.section {}`?", suggestion));
+            }
+        }
     }
 
-    fn parse_binding(&mut self, span: Span, binding: SymBind) -> PResult {
+    fn parse_binding(&mut self, span: Span, binding: SymBind, weak: bool) -> PResult {
         self.skip_whitespace();
 
         // The next token must be either a type, or an identifier
@@ -719,8 +1526,59 @@ impl<'a> Parser<'a> {
             }
         }
 
-        let ident_snippet = self.session.span_to_snippet(&next.as_span());
-        let ident_string = ident_snippet.as_slice().to_string();
+        // Any number of symbols can be declared at once, separated by commas, and they all share
+        // the type if one was given
+        loop {
+            self.declare_binding(next, sym_type, binding, weak)?;
+
+            self.skip_whitespace();
+
+            match self.peek_next() {
+                Some(&comma) if comma.kind == TokenKind::SymbolComma => {
+                    self.consume_next();
+                    self.skip_whitespace();
+
+                    next = match self.consume_next() {
+                        Some(&token) if token.kind == TokenKind::Identifier => token,
+                        Some(&token) if token.kind != TokenKind::Newline => {
+                            self.session
+                                .struct_span_error(
+                                    token.as_span(),
+                                    "expected identifier".to_string(),
+                                )
+                                .emit();
+
+                            return Err(());
+                        }
+                        _ => {
+                            self.session
+                                .struct_span_error(
+                                    comma.as_span(),
+                                    "expected identifier after `,`".to_string(),
+                                )
+                                .emit();
+
+                            return Err(());
+                        }
+                    };
+                }
+                _ => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Declares a single symbol named by a .global, .local, .weak, or .extern, checking it against
+    // any declaration that came before it
+    fn declare_binding(
+        &mut self,
+        next: Token,
+        sym_type: SymbolType,
+        binding: SymBind,
+        weak: bool,
+    ) -> PResult {
+        let ident_string = next.resolve(self.session);
 
         // Because this is a declaration of a symbol we should check if this symbol was
         // previously declared
@@ -747,44 +1605,44 @@ impl<'a> Parser<'a> {
                     }
                 }
 
-                if declared_symbol.value != SymbolValue::Undefined && binding == SymBind::Extern {
-                    self.session
-                        .struct_span_error(
-                            next.as_span(),
-                            "symbol declared to be external".to_string(),
-                        )
-                        .span_label(
-                            declared_symbol.declared_span,
-                            "found to be declared locally".to_string(),
-                        )
-                        .emit();
-
-                    return Err(());
-                }
-
                 declared_symbol.binding = Some(binding);
+                declared_symbol.binding_span = Some(next.as_span());
+                declared_symbol.weak = weak;
+            } else if declared_symbol.binding == Some(binding) && declared_symbol.weak != weak {
+                // A weak and a strong declaration of the same symbol, so the strong one wins
+                declared_symbol.weak = false;
             } else if declared_symbol.binding.unwrap() == binding {
                 self.session
                     .struct_span_warn(
                         next.as_span(),
                         "redundant declaration of symbol binding".to_string(),
                     )
+                    .span_label(
+                        declared_symbol.declared_span,
+                        "first declared here".to_string(),
+                    )
                     .emit();
             } else {
                 self.session
                     .struct_span_error(next.as_span(), "conflicting symbol bindings".to_string())
+                    .span_label(
+                        declared_symbol.declared_span,
+                        "first declared here".to_string(),
+                    )
                     .emit();
 
                 return Err(());
             }
         } else {
-            let declared_symbol = DeclaredSymbol::new(
+            let mut declared_symbol = DeclaredSymbol::new(
                 next.as_span(),
                 Some(binding),
                 sym_type,
                 SymbolValue::Undefined,
             );
 
+            declared_symbol.weak = weak;
+
             self.symbol_manager.insert(ident_string, declared_symbol);
         }
 
@@ -794,13 +1652,8 @@ impl<'a> Parser<'a> {
     fn parse_function(&mut self, span: Span) -> Result<ParsedFunction, ()> {
         let mut instructions = Vec::new();
 
-        self.skip_whitespace();
-
-        self.struct_expected("newline", TokenKind::Newline, Some(span))?;
-
-        let label = self.struct_expected("function label", TokenKind::Label, Some(span))?;
-        let label_snippet = self.session.span_to_snippet(&label.as_span());
-        let label_str = label_snippet.as_slice();
+        let label = self.parse_function_label(span)?;
+        let label_str = label.resolve(self.session);
         let label_str = label_str[..label_str.len() - 1].to_string();
 
         self.declare_label(label.as_span(), false)?;
@@ -822,24 +1675,9 @@ impl<'a> Parser<'a> {
                     return Err(());
                 }
 
-                // If this was declared to have a binding of "extern"
-                if let Some(SymBind::Extern) = existing_symbol.binding {
-                    self.session
-                        .struct_span_error(
-                            label.as_span(),
-                            "declared to be external, but found defined here".to_string(),
-                        )
-                        .span_label(
-                            existing_symbol.declared_span,
-                            "first declared here".to_string(),
-                        )
-                        .emit();
-
-                    return Err(());
-                }
-
                 existing_symbol.sym_type = SymbolType::Func;
                 existing_symbol.value = SymbolValue::Function;
+                existing_symbol.defined_span = Some(label.as_span());
             }
             // If it does have a previously defined value
             else {
@@ -899,6 +1737,27 @@ impl<'a> Parser<'a> {
         let snippet = self.session.span_to_snippet(&span);
         let label_str = snippet.as_slice();
 
+        // Inner labels are always stored after the name of the label they belong to, so they
+        // can't clash with a generated name
+        if !inner && label_str.starts_with(RESERVED_LABEL_PREFIX) {
+            self.session
+                .struct_span_error(
+                    span,
+                    format!(
+                        "label `{}` starts with the reserved prefix `{}`",
+                        &label_str[..label_str.len() - 1],
+                        RESERVED_LABEL_PREFIX
+                    ),
+                )
+                .note(format!(
+                    "names starting with `{}` are reserved for labels that the assembler generates",
+                    RESERVED_LABEL_PREFIX
+                ))
+                .emit();
+
+            return Err(());
+        }
+
         let label_str = if inner {
             format!(
                 "{}.{}",
@@ -960,8 +1819,8 @@ impl<'a> Parser<'a> {
                 .struct_span_error(
                     opcode_span,
                     format!(
-                        "{:?} requires {} argument{}, {} provided",
-                        opcode,
+                        "`{}` requires {} operand{}, {} provided",
+                        <&str>::from(opcode),
                         wanted_num,
                         if wanted_num == 1 { "" } else { "s" },
                         provided_num
@@ -979,23 +1838,36 @@ impl<'a> Parser<'a> {
                 opcode,
                 span: opcode_span,
             },
-            1 => ParsedInstruction::OneOp {
-                opcode,
-                span: opcode_span,
-                operand: operands.next().unwrap(),
-            },
-            _ => ParsedInstruction::TwoOp {
-                opcode,
-                span: opcode_span,
-                operand1: operands.next().unwrap(),
-                operand2: operands.next().unwrap(),
-            },
+            1 => {
+                let (operand, operand_span) = operands.next().unwrap();
+
+                ParsedInstruction::OneOp {
+                    opcode,
+                    span: opcode_span,
+                    operand,
+                    operand_span,
+                }
+            }
+            _ => {
+                let (operand1, operand1_span) = operands.next().unwrap();
+                let (operand2, operand2_span) = operands.next().unwrap();
+
+                ParsedInstruction::TwoOp {
+                    opcode,
+                    span: opcode_span,
+                    operand1,
+                    operand1_span,
+                    operand2,
+                    operand2_span,
+                }
+            }
         })
     }
 
-    fn parse_operands(&mut self) -> Result<Vec<InstructionOperand>, ()> {
+    // Parses the operands of an instruction, along with the span that each operand covers
+    fn parse_operands(&mut self) -> Result<Vec<(InstructionOperand, Span)>, ()> {
         let mut raw_operands = Vec::new();
-        let mut operand = Vec::new();
+        let mut operand: Vec<Token> = Vec::new();
 
         while let Some(&next) = self.consume_next() {
             if next.kind == TokenKind::Newline {
@@ -1015,6 +1887,34 @@ impl<'a> Parser<'a> {
                     operand = Vec::new();
                 }
             } else {
+                // Two values next to each other are almost always two operands missing the comma
+                // between them, which would otherwise be reported as a confusing expression error
+                if let Some(&previous) = operand.last() {
+                    if Self::ends_value(previous.kind) && Self::starts_value(next.kind) {
+                        let previous_span = previous.as_span();
+                        let next_span = next.as_span();
+                        let gap = Span {
+                            start: previous_span.end,
+                            end: next_span.start,
+                            ..next_span
+                        };
+                        let span = if previous_span.file == next_span.file
+                            && previous_span.expansion == next_span.expansion
+                            && gap.start < gap.end
+                        {
+                            gap
+                        } else {
+                            next_span
+                        };
+
+                        self.session
+                            .struct_span_error(span, "expected `,` between operands".to_string())
+                            .emit();
+
+                        return Err(());
+                    }
+                }
+
                 operand.push(next);
             }
 
@@ -1028,20 +1928,78 @@ impl<'a> Parser<'a> {
         let mut converted_operands = Vec::new();
 
         for raw in raw_operands {
-            converted_operands.push(self.convert_operand(raw)?);
+            let first_span = raw.first().unwrap().as_span();
+            let last_span = raw.last().unwrap().as_span();
+
+            // Tokens from a macro can be in another file, or earlier in this one, so they can only
+            // be joined when they were written next to each other
+            let span = if first_span.file == last_span.file
+                && first_span.expansion == last_span.expansion
+                && first_span.start <= last_span.end
+            {
+                first_span.join(last_span)
+            } else {
+                first_span
+            };
+
+            converted_operands.push((self.convert_operand(raw)?, span));
         }
 
         Ok(converted_operands)
     }
 
-    fn convert_operand(&self, raw: Vec<Token>) -> Result<InstructionOperand, ()> {
+    // If a token of this kind can be the last token of an operand's value
+    fn ends_value(kind: TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::Identifier
+                | TokenKind::InnerLabelReference
+                | TokenKind::LiteralInteger
+                | TokenKind::LiteralFloat
+                | TokenKind::LiteralHex
+                | TokenKind::LiteralBinary
+                | TokenKind::LiteralTrue
+                | TokenKind::LiteralFalse
+                | TokenKind::LiteralString
+                | TokenKind::SymbolRightParen
+                | TokenKind::SymbolHash
+                | TokenKind::SymbolAt
+        )
+    }
+
+    // If a token of this kind can be the first token of an operand's value, but can't come after
+    // another value. `-` is left out, because it can also be a binary operator
+    fn starts_value(kind: TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::Identifier
+                | TokenKind::InnerLabelReference
+                | TokenKind::LiteralInteger
+                | TokenKind::LiteralFloat
+                | TokenKind::LiteralHex
+                | TokenKind::LiteralBinary
+                | TokenKind::LiteralTrue
+                | TokenKind::LiteralFalse
+                | TokenKind::LiteralString
+                | TokenKind::SymbolLeftParen
+                | TokenKind::SymbolHash
+                | TokenKind::SymbolAt
+                | TokenKind::OperatorNegate
+                | TokenKind::OperatorCompliment
+        )
+    }
+
+    fn convert_operand(&mut self, raw: Vec<Token>) -> Result<InstructionOperand, ()> {
         let first_token = raw.first().unwrap();
         let mut one_token = true;
 
         let operand = match first_token.kind {
             TokenKind::Identifier => {
-                let snippet = self.session.span_to_snippet(&first_token.as_span());
-                let identifier_str = snippet.as_slice().to_string();
+                let identifier_str = first_token.resolve(self.session);
+
+                // This could also be a label in another function, which is only known for sure
+                // once every function has been parsed
+                self.label_manager.reference(identifier_str.clone());
 
                 InstructionOperand::Symbol(identifier_str)
             }
@@ -1050,82 +2008,32 @@ impl<'a> Parser<'a> {
             | TokenKind::LiteralBinary
             | TokenKind::LiteralTrue
             | TokenKind::LiteralFalse
-            | TokenKind::LiteralFloat => {
-                let mut exp_tokens = raw.iter().peekable();
-                let parsed_exp = match ExpressionParser::parse_expression(
-                    &mut exp_tokens,
-                    self.session,
-                    false,
-                ) {
-                    Ok(exp) => exp,
-                    Err(mut db) => {
-                        db.emit();
-
-                        return Err(());
-                    }
+            | TokenKind::LiteralFloat
+            | TokenKind::OperatorMinus => {
+                let operand = match self.operand_cache.evaluate(&raw, self.session)? {
+                    Value::Int(i) => InstructionOperand::Integer(i),
+                    Value::Bool(b) => InstructionOperand::Bool(b),
+                    Value::Double(d) => InstructionOperand::Float(d),
+                    Value::String(s) => InstructionOperand::String(s),
                 };
 
-                if let Some(exp) = parsed_exp {
-                    let evaluated = match ExpressionEvaluator::evaluate(&exp) {
-                        Ok(exp) => exp,
-                        Err(e) => {
-                            let message = match e {
-                                crate::preprocessor::evaluator::EvalError::NegateBool => {
-                                    "tried to apply operator - to boolean value"
-                                }
-                                crate::preprocessor::evaluator::EvalError::FlipDouble => {
-                                    "tried to apply operator ~ to double value"
-                                }
-                                crate::preprocessor::evaluator::EvalError::ZeroDivide => {
-                                    "tried to divide by zero"
-                                }
-                            };
-
-                            self.session
-                                .struct_span_error(
-                                    first_token.as_span(),
-                                    format!("expression following this {}", message),
-                                )
-                                .emit();
-
-                            return Err(());
-                        }
-                    };
-
-                    let operand = match evaluated {
-                        Value::Int(i) => InstructionOperand::Integer(i),
-                        Value::Bool(b) => InstructionOperand::Bool(b),
-                        Value::Double(d) => InstructionOperand::Float(d),
-                    };
-
-                    one_token = false;
-
-                    operand
-                } else {
-                    self.session
-                        .struct_bug(
-                            "parsed expression is None despite having a first value".to_string(),
-                        )
-                        .emit();
+                one_token = false;
 
-                    return Err(());
-                }
+                operand
             }
             TokenKind::SymbolAt => InstructionOperand::ArgMarker,
             TokenKind::SymbolHash => InstructionOperand::Null,
             TokenKind::InnerLabelReference => {
-                let snippet = self.session.span_to_snippet(&first_token.as_span());
-                let label = &snippet.as_slice()[1..];
+                let label_str = first_token.resolve(self.session);
+                let label = &label_str[1..];
                 let combined_label = format!("{}.{}", self.latest_label, label);
 
+                self.label_manager.reference(combined_label.clone());
+
                 InstructionOperand::Label(combined_label)
             }
             TokenKind::LiteralString => {
-                let snippet = self.session.span_to_snippet(&first_token.as_span());
-                let inner = snippet.as_slice();
-                let inner = &inner[1..inner.len() - 1];
-
-                InstructionOperand::String(inner.to_string())
+                InstructionOperand::String(self.parse_string_literal(first_token.as_span())?)
             }
             _ => {
                 self.session
@@ -1166,12 +2074,17 @@ impl<'a> Parser<'a> {
             self.struct_expected("identifier", TokenKind::Identifier, before)?
         };
 
-        let snippet = self.session.span_to_snippet(&identifier_token.as_span());
-        let identifier_str = snippet.as_slice();
+        let identifier_str = identifier_token.resolve(self.session);
 
-        let opcode = Opcode::from(identifier_str);
+        let opcode = self.session.opcode(&identifier_str);
 
         if opcode == Opcode::Bogus {
+            if let Some(mut db) = self.struct_err_invalid_label() {
+                db.emit();
+
+                return Err(());
+            }
+
             self.session
                 .struct_span_error(
                     identifier_token.as_span(),
@@ -1185,6 +2098,54 @@ impl<'a> Parser<'a> {
         Ok((opcode, identifier_token.as_span()))
     }
 
+    // Consumes the newline and label that must follow a .func directive
+    fn parse_function_label(&mut self, func_span: Span) -> Result<Token, ()> {
+        self.skip_whitespace();
+
+        let found = match self.consume_next().copied() {
+            Some(next) if next.kind == TokenKind::Newline => self.consume_next().copied(),
+            next => next,
+        };
+
+        if let Some(label) = found.filter(|token| token.kind == TokenKind::Label) {
+            return Ok(label);
+        }
+
+        if found.is_some() {
+            if let Some(mut db) = self.struct_err_invalid_label() {
+                db.emit();
+
+                return Err(());
+            }
+        }
+
+        let mut error = self.session.struct_span_error(
+            func_span,
+            "`.func` must be followed by a function label".to_string(),
+        );
+
+        match found {
+            Some(token) if token.kind == TokenKind::Newline => {
+                error.note("found an empty line".to_string());
+            }
+            Some(token) => {
+                error.span_label(
+                    token.as_span(),
+                    format!("found `{}`", token.resolve(self.session)),
+                );
+            }
+            None => {
+                error.note("found end of file".to_string());
+            }
+        }
+
+        error
+            .help("functions are declared as `.func` followed by a label such as `myfunc:` on the next line".to_string())
+            .emit();
+
+        Err(())
+    }
+
     fn struct_expected(
         &mut self,
         expected: &str,
@@ -1198,8 +2159,7 @@ impl<'a> Parser<'a> {
                 let found = if next.kind == TokenKind::Newline {
                     String::from("newline")
                 } else {
-                    let snippet = self.session.span_to_snippet(&next.as_span());
-                    snippet.as_slice().to_string()
+                    next.resolve(self.session)
                 };
 
                 self.session
@@ -1278,3 +2238,103 @@ impl<'a> Parser<'a> {
         was_whitespace
     }
 }
+
+/// Evaluates operands that are constant expressions. Generated code tends to repeat the same
+/// constants many times, so the results are cached by the expression's source text. Only
+/// expressions made of nothing but literals and operators always evaluate to the same value
+pub struct OperandCache {
+    values: HashMap<String, Value>,
+    evaluated: usize,
+    reused: usize,
+}
+
+impl OperandCache {
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+            evaluated: 0,
+            reused: 0,
+        }
+    }
+
+    /// Evaluates the tokens of an operand, or returns the cached value if the same constant
+    /// expression has already been evaluated
+    pub fn evaluate(&mut self, raw: &[Token], session: &Session) -> Result<Value, ()> {
+        let cacheable = raw.iter().all(|token| token.kind != TokenKind::Identifier);
+
+        let key = if cacheable {
+            let key: String = raw
+                .iter()
+                .map(|token| session.span_to_source(&token.as_span()))
+                .collect();
+
+            if let Some(value) = self.values.get(&key) {
+                self.reused += 1;
+
+                return Ok(value.clone());
+            }
+
+            Some(key)
+        } else {
+            None
+        };
+
+        let mut exp_tokens = raw.iter().peekable();
+        let parsed_exp = match ExpressionParser::parse_expression(&mut exp_tokens, session, false) {
+            Ok(exp) => exp,
+            Err(mut db) => {
+                db.emit();
+
+                return Err(());
+            }
+        };
+
+        let exp = match parsed_exp {
+            Some(exp) => exp,
+            None => {
+                session
+                    .struct_bug(
+                        "parsed expression is None despite having a first value".to_string(),
+                    )
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        let value = match ExpressionEvaluator::evaluate(&exp) {
+            Ok(value) => value,
+            Err(e) => {
+                session
+                    .struct_span_error(e.span, e.kind.message().to_string())
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        self.evaluated += 1;
+
+        if let Some(key) = key {
+            self.values.insert(key, value.clone());
+        }
+
+        Ok(value)
+    }
+
+    /// The number of operands that had to be evaluated
+    pub fn evaluated(&self) -> usize {
+        self.evaluated
+    }
+
+    /// The number of operands whose value was reused from the cache
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+}
+
+impl Default for OperandCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::{BTreeSet, HashSet};
+
+use kerbalobjects::Opcode;
+
+use super::{
+    parse::{InstructionOperand, ParsedFunction, ParsedInstruction},
+    LabelManager,
+};
+
+/// Removes instructions that obviously have no effect, for --optimize
+///
+/// The rules are kept conservative so that they never change what the program does:
+///
+/// * A `push` immediately followed by a `pop` is removed, unless a label points at the `pop`
+/// * A `jmp` to the instruction right after it is removed, if that instruction is in the same
+///   function
+///
+/// Labels count instructions across every function, so each one after a removed instruction is
+/// moved back to keep pointing at the same code. Nothing is removed at all if any branch uses a
+/// relative integer offset, because those can't be adjusted. Returns the number of instructions
+/// that were removed
+pub fn optimize(functions: &mut [ParsedFunction], label_manager: &mut LabelManager) -> usize {
+    if functions
+        .iter()
+        .flat_map(|function| function.instructions.iter())
+        .any(is_relative_branch)
+    {
+        return 0;
+    }
+
+    let mut removed_total = 0;
+
+    // Each removal can bring two more instructions next to each other, such as the push and pop
+    // around a pair that was just removed, so this is repeated until there is nothing left to do
+    loop {
+        let removed = find_redundant(functions, label_manager);
+
+        if removed.is_empty() {
+            break;
+        }
+
+        remove_instructions(functions, &removed);
+
+        for (_, label) in label_manager.labels_mut() {
+            label.value -= removed.range(..label.value).count();
+        }
+
+        removed_total += removed.len();
+    }
+
+    removed_total
+}
+
+// Finds the first instructions that can be removed, by their index counting across every function
+fn find_redundant(functions: &[ParsedFunction], label_manager: &LabelManager) -> BTreeSet<usize> {
+    let label_positions: HashSet<usize> = label_manager
+        .labels()
+        .map(|(_, label)| label.value)
+        .collect();
+
+    let mut start = 0;
+
+    for function in functions {
+        let instructions = &function.instructions;
+
+        for (offset, pair) in instructions.windows(2).enumerate() {
+            let index = start + offset;
+
+            let redundant: &[usize] = match (&pair[0], &pair[1]) {
+                // Jumping to the pop would pop a value that the push never pushed
+                (
+                    ParsedInstruction::OneOp {
+                        opcode: Opcode::Push,
+                        ..
+                    },
+                    ParsedInstruction::ZeroOp {
+                        opcode: Opcode::Pop,
+                        ..
+                    },
+                ) if !label_positions.contains(&(index + 1)) => &[index, index + 1],
+                (
+                    ParsedInstruction::OneOp {
+                        opcode: Opcode::Jmp,
+                        operand: InstructionOperand::Label(label),
+                        ..
+                    },
+                    _,
+                ) if label_manager
+                    .get(label)
+                    .is_some_and(|label| label.value == index + 1) =>
+                {
+                    &[index]
+                }
+                _ => &[],
+            };
+
+            // A function is never left without any instructions
+            if !redundant.is_empty() && redundant.len() < instructions.len() {
+                return redundant.iter().copied().collect();
+            }
+        }
+
+        start += instructions.len();
+    }
+
+    BTreeSet::new()
+}
+
+// Removes the instructions at each of the indexes, counting across every function
+fn remove_instructions(functions: &mut [ParsedFunction], removed: &BTreeSet<usize>) {
+    let mut start = 0;
+
+    for function in functions.iter_mut() {
+        let len = function.instructions.len();
+        let mut offset = 0;
+
+        function.instructions.retain(|_| {
+            let keep = !removed.contains(&(start + offset));
+            offset += 1;
+            keep
+        });
+
+        start += len;
+    }
+}
+
+// A branch whose operand is a number of instructions to move by, rather than a label
+fn is_relative_branch(instruction: &ParsedInstruction) -> bool {
+    matches!(
+        instruction,
+        ParsedInstruction::OneOp {
+            opcode: Opcode::Jmp | Opcode::Bfa | Opcode::Btr,
+            operand: InstructionOperand::Integer(_),
+            ..
+        }
+    )
+}
@@ -0,0 +1,145 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use crate::{
+    errors::{SourceFile, Span},
+    lexer::{phase0, Lexer, Token, TokenKind},
+    session::Session,
+};
+
+/// A function that handles a custom directive. It is given the directive as it was found, and
+/// returns the KASM source code that should replace it, or Err(()) if it emitted an error
+pub type DirectiveHandler = dyn Fn(&DirectiveCall, &Session) -> Result<String, ()> + Send + Sync;
+
+/// A use of a custom directive that is passed to its handler
+pub struct DirectiveCall<'a> {
+    /// The directive's name, including the leading `.`
+    pub name: &'a str,
+    /// The span of the directive's name, for pointing diagnostics at
+    pub span: Span,
+    /// Every token after the name up to the end of the line, which the directive consumes
+    pub arguments: &'a [Token],
+}
+
+/// The custom directives that library users have registered, by name
+///
+/// Directives that KASM already has can't be replaced, because they are lexed differently from
+/// any other dot-prefixed word. Custom directives are only recognized at the start of a line, and
+/// are replaced after the preprocessor runs, so their arguments have any macros already expanded
+/// but the source code they are replaced with is not preprocessed
+#[derive(Clone, Default)]
+pub struct CustomDirectives {
+    handlers: HashMap<String, Arc<DirectiveHandler>>,
+}
+
+impl CustomDirectives {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler for the directive with the given name, such as `.twice`, replacing any
+    /// handler that was already registered for it
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        handler: impl Fn(&DirectiveCall, &Session) -> Result<String, ()> + Send + Sync + 'static,
+    ) {
+        self.handlers.insert(name.into(), Arc::new(handler));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<DirectiveHandler>> {
+        self.handlers.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
+
+impl Debug for CustomDirectives {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+/// Replaces each use of a custom directive with the tokens of the source code that its handler
+/// returns. Each replacement is lexed as its own file, named after the directive
+pub fn expand_custom_directives(
+    tokens: Vec<Token>,
+    session: &mut Session,
+) -> Result<Vec<Token>, ()> {
+    let directives = session.config().directives.clone();
+
+    let mut expanded = Vec::with_capacity(tokens.len());
+    let mut line_start = true;
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let token = tokens[index];
+        index += 1;
+
+        if token.kind == TokenKind::InnerLabelReference && line_start {
+            let name = token.resolve(session);
+
+            if let Some(handler) = directives.get(&name) {
+                let arguments_end = tokens[index..]
+                    .iter()
+                    .position(|token| token.kind == TokenKind::Newline)
+                    .map_or(tokens.len(), |position| index + position);
+
+                let call = DirectiveCall {
+                    name: &name,
+                    span: token.as_span(),
+                    arguments: &tokens[index..arguments_end],
+                };
+
+                let source = handler(&call, session)?;
+
+                expanded.append(&mut lex_replacement(
+                    token.as_span(),
+                    &name,
+                    source,
+                    session,
+                )?);
+
+                index = arguments_end;
+
+                continue;
+            }
+        }
+
+        if token.kind == TokenKind::Newline {
+            line_start = true;
+        } else if token.kind != TokenKind::Whitespace {
+            line_start = false;
+        }
+
+        expanded.push(token);
+    }
+
+    Ok(expanded)
+}
+
+fn lex_replacement(
+    span: Span,
+    name: &str,
+    source: String,
+    session: &mut Session,
+) -> Result<Vec<Token>, ()> {
+    let source_file = SourceFile::new(format!("<{}>", name), None, None, source, 0);
+    let file_id = session.add_file(source_file).map_err(|_| {
+        session
+            .struct_span_error(span, "too many source files".to_string())
+            .note(format!(
+                "at most {} files can be used, including one for each use of a custom directive",
+                u8::MAX
+            ))
+            .emit();
+    })?;
+    let file = session.get_file(file_id as usize).unwrap();
+
+    let mut tokens = Lexer::new(&file.source, file_id, session).lex()?;
+
+    phase0(&mut tokens, session)?;
+
+    Ok(tokens)
+}
@@ -1,15 +1,54 @@
+use crate::errors::Span;
+
 use super::expressions::{BinOp, ExpNode, UnOp, Value};
 
 pub type EvalResult = Result<Value, EvalError>;
-pub type OpResult<T> = Result<T, EvalError>;
+pub type OpResult<T> = Result<T, EvalErrorKind>;
 
-pub enum EvalError {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalErrorKind {
     /// A scenario such as trying to evaluate -false
     NegateBool,
     /// A scenario such as trying to evaluate ~2.0
     FlipDouble,
     /// A scenario such as trying to evaluate 2 / 0
     ZeroDivide,
+    /// A scenario such as trying to evaluate 2147483647 + 1. Integer arithmetic never wraps
+    Overflow,
+    /// A scenario such as trying to evaluate "a" + "b". Strings can only be compared for equality
+    StringOperator,
+    /// A scenario such as trying to evaluate "a" == 1
+    StringComparison,
+    /// A symbol's name that was never replaced by its value
+    Symbol,
+}
+
+impl EvalErrorKind {
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::NegateBool => "`-` operator invalid for booleans",
+            Self::FlipDouble => "`~` operator invalid for floats",
+            Self::ZeroDivide => "expression tried to divide by 0",
+            Self::Overflow => "constant expression overflows i32",
+            Self::StringOperator => "operator invalid for strings",
+            Self::StringComparison => "strings can only be compared with other strings",
+            Self::Symbol => "symbols can only be used in data expressions",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    /// For invalid operands, this is the span of the operand. For division by zero and overflow,
+    /// this is the span of the operator
+    pub span: Span,
+}
+
+impl EvalError {
+    pub fn new(kind: EvalErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
 }
 
 pub struct ExpressionEvaluator {}
@@ -19,44 +58,122 @@ impl ExpressionEvaluator {
     /// Returns Err() when expression evaluation fails
     pub fn evaluate(expression: &ExpNode) -> EvalResult {
         match expression {
-            ExpNode::Constant(constant) => Ok(*constant),
-            ExpNode::UnOp(op, node) => Self::evaluate_unop(*op, node),
-            ExpNode::BinOp(lhs, op, rhs) => Self::evaluate_binop(lhs, *op, rhs),
+            ExpNode::Constant(constant, _) => Ok(constant.clone()),
+            ExpNode::UnOp(op, op_span, node) => Self::evaluate_unop(*op, *op_span, node),
+            ExpNode::BinOp(lhs, op, op_span, rhs) => Self::evaluate_binop(lhs, *op, *op_span, rhs),
+            ExpNode::Symbol(span) => Err(EvalError::new(EvalErrorKind::Symbol, *span)),
         }
     }
 
-    fn evaluate_unop(op: UnOp, node: &ExpNode) -> EvalResult {
+    fn evaluate_unop(op: UnOp, op_span: Span, node: &ExpNode) -> EvalResult {
         let value = Self::evaluate(node)?;
 
-        Ok(match op {
-            UnOp::Not => value.not()?,
-            UnOp::Flip => value.flip()?,
-            UnOp::Negate => value.negate()?,
-        })
+        Number::try_from(value)
+            .and_then(|number| match op {
+                UnOp::Not => number.not(),
+                UnOp::Flip => number.flip(),
+                UnOp::Negate => number.negate(),
+            })
+            .map(Value::from)
+            .map_err(|kind| {
+                let span = if kind == EvalErrorKind::Overflow {
+                    op_span
+                } else {
+                    node.span()
+                };
+
+                EvalError::new(kind, span)
+            })
     }
 
-    fn evaluate_binop(lhs: &ExpNode, op: BinOp, rhs: &ExpNode) -> EvalResult {
+    fn evaluate_binop(lhs: &ExpNode, op: BinOp, op_span: Span, rhs: &ExpNode) -> EvalResult {
         let lhs_value = Self::evaluate(lhs)?;
         let rhs_value = Self::evaluate(rhs)?;
 
+        if matches!(lhs_value, Value::String(_)) || matches!(rhs_value, Value::String(_)) {
+            return Self::evaluate_string_binop(lhs_value, op, rhs_value)
+                .map_err(|kind| EvalError::new(kind, op_span));
+        }
+
+        let lhs_number =
+            Number::try_from(lhs_value).map_err(|kind| EvalError::new(kind, op_span))?;
+        let rhs_number =
+            Number::try_from(rhs_value).map_err(|kind| EvalError::new(kind, op_span))?;
+
+        match op {
+            BinOp::Add => lhs_number.add(rhs_number),
+            BinOp::Sub => lhs_number.sub(rhs_number),
+            BinOp::Mult => lhs_number.mult(rhs_number),
+            BinOp::Div => lhs_number.div(rhs_number),
+            BinOp::Mod => lhs_number.modulus(rhs_number),
+            BinOp::Eq => lhs_number.equal(rhs_number),
+            BinOp::Ne => lhs_number.equal(rhs_number).and_then(|value| value.not()),
+            BinOp::Gt => lhs_number.greater(rhs_number),
+            BinOp::Lte => lhs_number.greater(rhs_number).and_then(|value| value.not()),
+            BinOp::Lt => lhs_number.less(rhs_number),
+            BinOp::Gte => lhs_number.less(rhs_number).and_then(|value| value.not()),
+            BinOp::Or => lhs_number.or(rhs_number),
+            BinOp::And => lhs_number.and(rhs_number),
+        }
+        .map(Value::from)
+        .map_err(|kind| EvalError::new(kind, op_span))
+    }
+
+    // Strings only support being checked for equality with other strings
+    fn evaluate_string_binop(lhs: Value, op: BinOp, rhs: Value) -> OpResult<Value> {
+        let equal = match (lhs, rhs) {
+            (Value::String(s1), Value::String(s2)) => s1 == s2,
+            _ if matches!(op, BinOp::Eq | BinOp::Ne) => {
+                return Err(EvalErrorKind::StringComparison)
+            }
+            _ => return Err(EvalErrorKind::StringOperator),
+        };
+
         match op {
-            BinOp::Add => lhs_value.add(rhs_value),
-            BinOp::Sub => lhs_value.sub(rhs_value),
-            BinOp::Mult => lhs_value.mult(rhs_value),
-            BinOp::Div => lhs_value.div(rhs_value),
-            BinOp::Mod => lhs_value.modulus(rhs_value),
-            BinOp::Eq => lhs_value.equal(rhs_value),
-            BinOp::Ne => lhs_value.equal(rhs_value)?.not(),
-            BinOp::Gt => lhs_value.greater(rhs_value),
-            BinOp::Lte => lhs_value.greater(rhs_value)?.not(),
-            BinOp::Lt => lhs_value.less(rhs_value),
-            BinOp::Gte => lhs_value.less(rhs_value)?.not(),
-            BinOp::Or => lhs_value.or(rhs_value),
-            BinOp::And => lhs_value.and(rhs_value),
+            BinOp::Eq => Ok(Value::Bool(equal)),
+            BinOp::Ne => Ok(Value::Bool(!equal)),
+            _ => Err(EvalErrorKind::StringOperator),
         }
     }
 }
 
+// A value that any operator can be applied to. Strings can only be compared for equality, so they
+// are turned away when a Value is converted into a Number, before any operator sees them
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Number {
+    Int(i32),
+    Bool(bool),
+    Double(f64),
+}
+
+impl TryFrom<Value> for Number {
+    type Error = EvalErrorKind;
+
+    fn try_from(value: Value) -> OpResult<Self> {
+        match value {
+            Value::Int(i) => Ok(Number::Int(i)),
+            Value::Bool(b) => Ok(Number::Bool(b)),
+            Value::Double(d) => Ok(Number::Double(d)),
+            Value::String(_) => Err(EvalErrorKind::StringOperator),
+        }
+    }
+}
+
+impl From<Number> for Value {
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(i) => Value::Int(i),
+            Number::Bool(b) => Value::Bool(b),
+            Number::Double(d) => Value::Double(d),
+        }
+    }
+}
+
+// Converts the result of checked integer arithmetic into an error if it overflowed
+fn checked(result: Option<i32>) -> OpResult<i32> {
+    result.ok_or(EvalErrorKind::Overflow)
+}
+
 trait Not: Sized {
     fn not(self) -> OpResult<Self>;
 }
@@ -113,295 +230,303 @@ trait Or: Sized {
     fn or(self, other: Self) -> OpResult<Self>;
 }
 
-impl Not for Value {
+impl Not for Number {
     fn not(self) -> OpResult<Self> {
-        Ok(Value::Bool(match self {
-            Value::Int(i) => i != 0,
-            Value::Bool(b) => !b,
-            Value::Double(d) => d != 0.0,
+        Ok(Number::Bool(match self {
+            Number::Int(i) => i != 0,
+            Number::Bool(b) => !b,
+            Number::Double(d) => d != 0.0,
         }))
     }
 }
 
-impl Negate for Value {
+impl Negate for Number {
     fn negate(self) -> OpResult<Self> {
         match self {
-            Value::Int(i) => Ok(Value::Int(-i)),
-            Value::Bool(_) => Err(EvalError::NegateBool),
-            Value::Double(d) => Ok(Value::Double(-d)),
+            Number::Int(i) => Ok(Number::Int(checked(i.checked_neg())?)),
+            Number::Bool(_) => Err(EvalErrorKind::NegateBool),
+            Number::Double(d) => Ok(Number::Double(-d)),
         }
     }
 }
 
-impl Flip for Value {
+impl Flip for Number {
     fn flip(self) -> OpResult<Self> {
         match self {
-            Value::Int(i) => Ok(Value::Int(!i)),
-            Value::Bool(b) => Ok(Value::Bool(!b)),
-            Value::Double(_) => Err(EvalError::FlipDouble),
+            Number::Int(i) => Ok(Number::Int(!i)),
+            Number::Bool(b) => Ok(Number::Bool(!b)),
+            Number::Double(_) => Err(EvalErrorKind::FlipDouble),
         }
     }
 }
 
-impl Add for Value {
+impl Add for Number {
     fn add(self, other: Self) -> OpResult<Self> {
         Ok(match self {
-            Value::Int(i) => match other {
-                Value::Int(i2) => Value::Int(i + i2),
-                Value::Bool(b) => Value::Int(i + if b { 1 } else { 0 }),
-                Value::Double(d) => Value::Double(i as f64 + d),
+            Number::Int(i) => match other {
+                Number::Int(i2) => Number::Int(checked(i.checked_add(i2))?),
+                Number::Bool(b) => Number::Int(checked(i.checked_add(b as i32))?),
+                Number::Double(d) => Number::Double(i as f64 + d),
             },
-            Value::Bool(b) => match other {
-                Value::Int(i) => Value::Int(i + if b { 1 } else { 0 }),
-                Value::Bool(b1) => Value::Int(if b { 1 } else { 0 } + if b1 { 1 } else { 0 }),
-                Value::Double(d) => Value::Double(d + if b { 1.0 } else { 0.0 }),
+            Number::Bool(b) => match other {
+                Number::Int(i) => Number::Int(checked(i.checked_add(b as i32))?),
+                Number::Bool(b1) => Number::Int(if b { 1 } else { 0 } + if b1 { 1 } else { 0 }),
+                Number::Double(d) => Number::Double(d + if b { 1.0 } else { 0.0 }),
             },
-            Value::Double(d) => match other {
-                Value::Int(i) => Value::Double(i as f64 + d),
-                Value::Bool(b) => Value::Double(d + if b { 1.0 } else { 0.0 }),
-                Value::Double(d1) => Value::Double(d + d1),
+            Number::Double(d) => match other {
+                Number::Int(i) => Number::Double(i as f64 + d),
+                Number::Bool(b) => Number::Double(d + if b { 1.0 } else { 0.0 }),
+                Number::Double(d1) => Number::Double(d + d1),
             },
         })
     }
 }
 
-impl Sub for Value {
+impl Sub for Number {
     fn sub(self, other: Self) -> OpResult<Self> {
         Ok(match self {
-            Value::Int(i) => match other {
-                Value::Int(i2) => Value::Int(i - i2),
-                Value::Bool(b) => Value::Int(i - if b { 1 } else { 0 }),
-                Value::Double(d) => Value::Double(i as f64 - d),
+            Number::Int(i) => match other {
+                Number::Int(i2) => Number::Int(checked(i.checked_sub(i2))?),
+                Number::Bool(b) => Number::Int(checked(i.checked_sub(b as i32))?),
+                Number::Double(d) => Number::Double(i as f64 - d),
             },
-            Value::Bool(b) => match other {
-                Value::Int(i) => Value::Int(i - if b { 1 } else { 0 }),
-                Value::Bool(b1) => Value::Int(if b { 1 } else { 0 } - if b1 { 1 } else { 0 }),
-                Value::Double(d) => Value::Double(d - if b { 1.0 } else { 0.0 }),
+            Number::Bool(b) => match other {
+                Number::Int(i) => Number::Int(checked((b as i32).checked_sub(i))?),
+                Number::Bool(b1) => Number::Int(if b { 1 } else { 0 } - if b1 { 1 } else { 0 }),
+                Number::Double(d) => Number::Double(if b { 1.0 } else { 0.0 } - d),
             },
-            Value::Double(d) => match other {
-                Value::Int(i) => Value::Double(i as f64 - d),
-                Value::Bool(b) => Value::Double(d - if b { 1.0 } else { 0.0 }),
-                Value::Double(d1) => Value::Double(d - d1),
+            Number::Double(d) => match other {
+                Number::Int(i) => Number::Double(d - i as f64),
+                Number::Bool(b) => Number::Double(d - if b { 1.0 } else { 0.0 }),
+                Number::Double(d1) => Number::Double(d - d1),
             },
         })
     }
 }
 
-impl Mult for Value {
+impl Mult for Number {
     fn mult(self, other: Self) -> OpResult<Self> {
         Ok(match self {
-            Value::Int(i) => match other {
-                Value::Int(i2) => Value::Int(i * i2),
-                Value::Bool(b) => Value::Int(i * if b { 1 } else { 0 }),
-                Value::Double(d) => Value::Double(i as f64 * d),
+            Number::Int(i) => match other {
+                Number::Int(i2) => Number::Int(checked(i.checked_mul(i2))?),
+                Number::Bool(b) => Number::Int(checked(i.checked_mul(b as i32))?),
+                Number::Double(d) => Number::Double(i as f64 * d),
             },
-            Value::Bool(b) => match other {
-                Value::Int(i) => Value::Int(i * if b { 1 } else { 0 }),
-                Value::Bool(b1) => Value::Int(if b { 1 } else { 0 } * if b1 { 1 } else { 0 }),
-                Value::Double(d) => Value::Double(d * if b { 1.0 } else { 0.0 }),
+            Number::Bool(b) => match other {
+                Number::Int(i) => Number::Int(checked(i.checked_mul(b as i32))?),
+                Number::Bool(b1) => Number::Int(if b { 1 } else { 0 } * if b1 { 1 } else { 0 }),
+                Number::Double(d) => Number::Double(d * if b { 1.0 } else { 0.0 }),
             },
-            Value::Double(d) => match other {
-                Value::Int(i) => Value::Double(i as f64 * d),
-                Value::Bool(b) => Value::Double(d * if b { 1.0 } else { 0.0 }),
-                Value::Double(d1) => Value::Double(d * d1),
+            Number::Double(d) => match other {
+                Number::Int(i) => Number::Double(i as f64 * d),
+                Number::Bool(b) => Number::Double(d * if b { 1.0 } else { 0.0 }),
+                Number::Double(d1) => Number::Double(d * d1),
             },
         })
     }
 }
 
-impl Div for Value {
+impl Div for Number {
     fn div(self, other: Self) -> OpResult<Self> {
         match self {
-            Value::Int(i) => {
+            Number::Int(i) => {
                 let other_int = match other {
-                    Value::Int(i2) => i2,
-                    Value::Bool(b) => {
+                    Number::Int(i2) => i2,
+                    Number::Bool(b) => {
                         if b {
                             1
                         } else {
                             0
                         }
                     }
-                    Value::Double(d) => {
+                    Number::Double(d) => {
                         return if d != 0.0 {
-                            Ok(Value::Double(i as f64 / d))
+                            Ok(Number::Double(i as f64 / d))
                         } else {
-                            Err(EvalError::ZeroDivide)
+                            Err(EvalErrorKind::ZeroDivide)
                         };
                     }
                 };
 
                 if other_int != 0 {
-                    Ok(Value::Int(i / other_int))
+                    Ok(Number::Int(checked(i.checked_div(other_int))?))
                 } else {
-                    Err(EvalError::ZeroDivide)
+                    Err(EvalErrorKind::ZeroDivide)
                 }
             }
-            Value::Bool(b) => {
+            Number::Bool(b) => {
                 let other_int = match other {
-                    Value::Int(i) => i,
-                    Value::Bool(b) => {
+                    Number::Int(i) => i,
+                    Number::Bool(b) => {
                         if b {
                             1
                         } else {
                             0
                         }
                     }
-                    Value::Double(d) => {
+                    Number::Double(d) => {
                         return if d != 0.0 {
-                            Ok(Value::Double(if b { 1.0 } else { 0.0 } / d))
+                            Ok(Number::Double(if b { 1.0 } else { 0.0 } / d))
                         } else {
-                            Err(EvalError::ZeroDivide)
+                            Err(EvalErrorKind::ZeroDivide)
                         };
                     }
                 };
 
                 if other_int != 0 {
-                    Ok(Value::Int(if b { 1 } else { 0 } / other_int))
+                    Ok(Number::Int(if b { 1 } else { 0 } / other_int))
                 } else {
-                    Err(EvalError::ZeroDivide)
+                    Err(EvalErrorKind::ZeroDivide)
                 }
             }
-            Value::Double(d) => {
+            Number::Double(d) => {
                 let other_double = match other {
-                    Value::Int(i) => i as f64,
-                    Value::Bool(b) => {
+                    Number::Int(i) => i as f64,
+                    Number::Bool(b) => {
                         if b {
                             1.0
                         } else {
                             0.0
                         }
                     }
-                    Value::Double(d1) => d1,
+                    Number::Double(d1) => d1,
                 };
 
                 if other_double != 0.0 {
-                    Ok(Value::Double(d / other_double))
+                    Ok(Number::Double(d / other_double))
                 } else {
-                    Err(EvalError::ZeroDivide)
+                    Err(EvalErrorKind::ZeroDivide)
                 }
             }
         }
     }
 }
 
-impl Mod for Value {
+impl Mod for Number {
     fn modulus(self, other: Self) -> OpResult<Self> {
         Ok(match self {
-            Value::Int(i) => {
+            Number::Int(i) => {
                 let other_int = match other {
-                    Value::Int(i2) => i2,
-                    Value::Bool(b) => {
+                    Number::Int(i2) => i2,
+                    Number::Bool(b) => {
                         if b {
                             1
                         } else {
                             0
                         }
                     }
-                    Value::Double(d) => {
-                        return Ok(Value::Double(i as f64 % d));
+                    Number::Double(d) => {
+                        return Ok(Number::Double(i as f64 % d));
                     }
                 };
 
-                Value::Int(i % other_int)
+                if other_int == 0 {
+                    return Err(EvalErrorKind::ZeroDivide);
+                }
+
+                Number::Int(checked(i.checked_rem(other_int))?)
             }
-            Value::Bool(b) => {
+            Number::Bool(b) => {
                 let other_int = match other {
-                    Value::Int(i) => i,
-                    Value::Bool(b) => {
+                    Number::Int(i) => i,
+                    Number::Bool(b) => {
                         if b {
                             1
                         } else {
                             0
                         }
                     }
-                    Value::Double(d) => {
-                        return Ok(Value::Double(if b { 1.0 } else { 0.0 } % d));
+                    Number::Double(d) => {
+                        return Ok(Number::Double(if b { 1.0 } else { 0.0 } % d));
                     }
                 };
 
-                Value::Int(if b { 1 } else { 0 } % other_int)
+                if other_int == 0 {
+                    return Err(EvalErrorKind::ZeroDivide);
+                }
+
+                Number::Int(if b { 1 } else { 0 } % other_int)
             }
-            Value::Double(d) => {
+            Number::Double(d) => {
                 let other_double = match other {
-                    Value::Int(i) => i as f64,
-                    Value::Bool(b) => {
+                    Number::Int(i) => i as f64,
+                    Number::Bool(b) => {
                         if b {
                             1.0
                         } else {
                             0.0
                         }
                     }
-                    Value::Double(d1) => d1,
+                    Number::Double(d1) => d1,
                 };
 
-                Value::Double(d % other_double)
+                Number::Double(d % other_double)
             }
         })
     }
 }
 
-impl Equal for Value {
+impl Equal for Number {
     fn equal(self, other: Self) -> OpResult<Self> {
-        Ok(Value::Bool(match self {
-            Value::Int(i) => match other {
-                Value::Int(i2) => i == i2,
-                Value::Bool(b) => i == if b { 1 } else { 0 },
-                Value::Double(d) => (i as f64 - d).abs() < f64::EPSILON,
+        Ok(Number::Bool(match self {
+            Number::Int(i) => match other {
+                Number::Int(i2) => i == i2,
+                Number::Bool(b) => i == if b { 1 } else { 0 },
+                Number::Double(d) => (i as f64 - d).abs() < f64::EPSILON,
             },
-            Value::Bool(b) => match other {
-                Value::Int(i) => i == if b { 1 } else { 0 },
-                Value::Bool(b1) => b == b1,
-                Value::Double(d) => (d - if b { 1.0 } else { 0.0 }).abs() < f64::EPSILON,
+            Number::Bool(b) => match other {
+                Number::Int(i) => i == if b { 1 } else { 0 },
+                Number::Bool(b1) => b == b1,
+                Number::Double(d) => (d - if b { 1.0 } else { 0.0 }).abs() < f64::EPSILON,
             },
-            Value::Double(d) => match other {
-                Value::Int(i) => (i as f64 - d).abs() < f64::EPSILON,
-                Value::Bool(b) => (d - if b { 1.0 } else { 0.0 }).abs() < f64::EPSILON,
-                Value::Double(d1) => (d - d1).abs() < f64::EPSILON,
+            Number::Double(d) => match other {
+                Number::Int(i) => (i as f64 - d).abs() < f64::EPSILON,
+                Number::Bool(b) => (d - if b { 1.0 } else { 0.0 }).abs() < f64::EPSILON,
+                Number::Double(d1) => (d - d1).abs() < f64::EPSILON,
             },
         }))
     }
 }
 
-impl Greater for Value {
+impl Greater for Number {
     fn greater(self, other: Self) -> OpResult<Self> {
-        Ok(Value::Bool(match self {
-            Value::Int(i) => match other {
-                Value::Int(i2) => i > i2,
-                Value::Bool(b) => i > if b { 1 } else { 0 },
-                Value::Double(d) => i as f64 > d,
+        Ok(Number::Bool(match self {
+            Number::Int(i) => match other {
+                Number::Int(i2) => i > i2,
+                Number::Bool(b) => i > if b { 1 } else { 0 },
+                Number::Double(d) => i as f64 > d,
             },
-            Value::Bool(b) => match other {
-                Value::Int(i) => i > if b { 1 } else { 0 },
-                Value::Bool(b1) => b && !b1,
-                Value::Double(d) => d > if b { 1.0 } else { 0.0 },
+            Number::Bool(b) => match other {
+                Number::Int(i) => (if b { 1 } else { 0 }) > i,
+                Number::Bool(b1) => b && !b1,
+                Number::Double(d) => (if b { 1.0 } else { 0.0 }) > d,
             },
-            Value::Double(d) => match other {
-                Value::Int(i) => i as f64 > d,
-                Value::Bool(b) => d > if b { 1.0 } else { 0.0 },
-                Value::Double(d1) => d > d1,
+            Number::Double(d) => match other {
+                Number::Int(i) => d > i as f64,
+                Number::Bool(b) => d > if b { 1.0 } else { 0.0 },
+                Number::Double(d1) => d > d1,
             },
         }))
     }
 }
 
-impl Less for Value {
+impl Less for Number {
     fn less(self, other: Self) -> OpResult<Self> {
-        Ok(Value::Bool(match self {
-            Value::Int(i) => match other {
-                Value::Int(i2) => i < i2,
-                Value::Bool(b) => i < if b { 1 } else { 0 },
-                Value::Double(d) => (i as f64) < d,
+        Ok(Number::Bool(match self {
+            Number::Int(i) => match other {
+                Number::Int(i2) => i < i2,
+                Number::Bool(b) => i < if b { 1 } else { 0 },
+                Number::Double(d) => (i as f64) < d,
             },
-            Value::Bool(b) => match other {
-                Value::Int(i) => i < if b { 1 } else { 0 },
-                Value::Bool(b1) => !b && b1,
-                Value::Double(d) => d < if b { 1.0 } else { 0.0 },
+            Number::Bool(b) => match other {
+                Number::Int(i) => (if b { 1 } else { 0 }) < i,
+                Number::Bool(b1) => !b && b1,
+                Number::Double(d) => (if b { 1.0 } else { 0.0 }) < d,
             },
-            Value::Double(d) => match other {
-                Value::Int(i) => (i as f64) < d,
-                Value::Bool(b) => d < if b { 1.0 } else { 0.0 },
-                Value::Double(d1) => d < d1,
+            Number::Double(d) => match other {
+                Number::Int(i) => d < i as f64,
+                Number::Bool(b) => d < if b { 1.0 } else { 0.0 },
+                Number::Double(d1) => d < d1,
             },
         }))
     }
@@ -413,24 +538,35 @@ impl ToBool for Value {
             Value::Int(i) => i != 0,
             Value::Bool(b) => b,
             Value::Double(d) => d != 0.0,
+            Value::String(s) => !s.is_empty(),
+        }
+    }
+}
+
+impl ToBool for Number {
+    fn to_bool(self) -> bool {
+        match self {
+            Number::Int(i) => i != 0,
+            Number::Bool(b) => b,
+            Number::Double(d) => d != 0.0,
         }
     }
 }
 
-impl And for Value {
+impl And for Number {
     fn and(self, other: Self) -> OpResult<Self> {
         let b1 = self.to_bool();
         let b2 = other.to_bool();
 
-        Ok(Value::Bool(b1 && b2))
+        Ok(Number::Bool(b1 && b2))
     }
 }
 
-impl Or for Value {
+impl Or for Number {
     fn or(self, other: Self) -> OpResult<Self> {
         let b1 = self.to_bool();
         let b2 = other.to_bool();
 
-        Ok(Value::Bool(b1 || b2))
+        Ok(Number::Bool(b1 || b2))
     }
 }
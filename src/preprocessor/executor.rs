@@ -1,10 +1,10 @@
-use std::{collections::hash_map::DefaultHasher, hash::Hasher, path::PathBuf};
+use std::{collections::hash_map::DefaultHasher, hash::Hasher};
 
 use crate::{
-    errors::Span,
+    errors::{SourceFile, Span},
     lexer::{phase0, Lexer, Token, TokenKind},
     preprocessor::{
-        evaluator::{EvalError, ExpressionEvaluator, ToBool},
+        evaluator::{ExpressionEvaluator, ToBool},
         expressions::{ExpressionParser, Value},
         parser::parse_integer_literal,
         past::{BenignTokens, Ident},
@@ -16,8 +16,8 @@ use super::{
     maps::{MLMacroMap, SLMacroMap},
     parser::Parser,
     past::{
-        IfClause, IfCondition, IfStatement, Include, MLMacroDef, MLMacroUndef, MacroInvok,
-        PASTNode, Repeat, SLMacroDef, SLMacroUndef,
+        Defined, IfClause, IfCondition, IfDefCondition, IfStatement, Include, IncludeLines, Line,
+        MLMacroDef, MLMacroUndef, MacroInvok, PASTNode, Repeat, SLMacroDef, SLMacroUndef,
     },
 };
 
@@ -28,6 +28,19 @@ pub struct Executor<'a> {
     session: &'a mut Session,
     sl_macros: SLMacroMap,
     ml_macros: MLMacroMap,
+    // The identifier hash and current index token of each .rep being executed that binds one
+    rep_indices: Vec<(u64, Token)>,
+    // The index tokens that every .rep shares, from 0 up to the most that any .rep has needed
+    rep_index_cache: Vec<Token>,
+    // The .extern token that a header's .global and .weak declarations are replaced with
+    extern_token: Option<Token>,
+    // The identifier span and expansion id of each macro invokation that is currently being
+    // expanded
+    expansion_stack: Vec<(Span, usize)>,
+    // The section that the tokens produced so far are in, for .ifsection
+    section: String,
+    // If the last token produced was a .section, and the section's name is still to come
+    awaiting_section: bool,
 }
 
 impl<'a> Executor<'a> {
@@ -36,16 +49,88 @@ impl<'a> Executor<'a> {
             session,
             sl_macros: SLMacroMap::new(),
             ml_macros: MLMacroMap::new(),
+            rep_indices: Vec::new(),
+            rep_index_cache: Vec::new(),
+            extern_token: None,
+            expansion_stack: Vec::new(),
+            section: String::from(".text"),
+            awaiting_section: false,
         }
     }
 
     /// Run the executor
     pub fn execute(mut self, nodes: Vec<PASTNode>) -> EResult<Vec<Token>> {
+        self.execute_predefines()?;
+
         let new_tokens = self.execute_nodes(nodes)?;
 
+        if self.session.config().warn_unused {
+            self.warn_unused_macros();
+        }
+
         Ok(new_tokens)
     }
 
+    // Warns about every macro that was defined but never expanded or checked
+    fn warn_unused_macros(&self) {
+        let mut unused = self.sl_macros.unused();
+
+        unused.extend(self.ml_macros.unused());
+        unused.sort_by_key(|span| (span.file, span.start));
+
+        for span in unused {
+            let name_snippet = self.session.span_to_snippet(&span);
+
+            self.session
+                .struct_span_warn(
+                    span,
+                    format!(
+                        "macro `{}` is defined but never used",
+                        name_snippet.as_slice()
+                    ),
+                )
+                .emit();
+        }
+    }
+
+    // Defines all of the macros that were provided in the Config, as if the input file started
+    // with a .define for each of them
+    fn execute_predefines(&mut self) -> EResult<()> {
+        if self.session.config().defines.is_empty() {
+            return Ok(());
+        }
+
+        let mut source = String::new();
+
+        for define in self.session.config().defines.iter() {
+            let (name, value) = define.split_once('=').unwrap_or((define, ""));
+
+            source.push_str(&format!(".define {} {}\n", name, value));
+        }
+
+        let source_file = SourceFile::new("<command line>".to_owned(), None, None, source, 0);
+        let file_id = self.add_file(source_file, None)?;
+        let file = self.session.get_file(file_id as usize).unwrap();
+
+        let lexer = Lexer::new(&file.source, file_id, self.session);
+
+        let mut tokens = lexer.lex()?;
+
+        phase0(&mut tokens, self.session)?;
+
+        let preprocessor_parser = Parser::new(tokens, self.session);
+
+        let nodes = preprocessor_parser.parse()?;
+
+        // These are only definitions, so there are no tokens to keep
+        self.execute_nodes(nodes)?;
+
+        // Definitions from the command line are meant to be optional to use
+        self.sl_macros.mark_all_used();
+
+        Ok(())
+    }
+
     fn execute_nodes(&mut self, nodes: Vec<PASTNode>) -> EResult<Vec<Token>> {
         let mut new_tokens = Vec::new();
 
@@ -54,9 +139,14 @@ impl<'a> Executor<'a> {
                 PASTNode::IfStatement(statement) => self.execute_if_statement(statement)?,
                 PASTNode::SLMacroDef(sl_macro) => self.execute_sl_macro_def(sl_macro)?,
                 PASTNode::MLMacroDef(ml_macro) => self.execute_ml_macro_def(ml_macro)?,
-                PASTNode::BenignTokens(tokens) => Some(tokens.tokens),
+                PASTNode::BenignTokens(tokens) => {
+                    self.track_section(&tokens.tokens);
+
+                    Some(tokens.tokens)
+                }
                 PASTNode::Repeat(repeat) => self.execute_rep(repeat)?,
                 PASTNode::Include(include) => self.execute_include(include)?,
+                PASTNode::Line(line) => self.execute_line(line)?,
                 PASTNode::SLMacroUndef(sl_macro_undef) => {
                     self.execute_sl_macro_undef(sl_macro_undef)?
                 }
@@ -64,6 +154,7 @@ impl<'a> Executor<'a> {
                     self.execute_ml_macro_undef(ml_macro_undef)?
                 }
                 PASTNode::MacroInvok(macro_invok) => self.execute_macro_invokation(macro_invok)?,
+                PASTNode::Defined(defined) => Some(self.execute_defined(defined)),
             } {
                 new_tokens.append(&mut tokens);
             }
@@ -72,6 +163,29 @@ impl<'a> Executor<'a> {
         Ok(new_tokens)
     }
 
+    // Follows the .section directives in tokens as they are produced, the same way that the parser
+    // will once preprocessing is done. Custom section names that aren't instructions are parsed as
+    // macro invokations, so the name can come separately from the .section before it
+    fn track_section(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            if token.kind == TokenKind::KeywordSection {
+                self.awaiting_section = true;
+            } else if self.awaiting_section && token.kind != TokenKind::Whitespace {
+                self.awaiting_section = false;
+
+                match token.kind {
+                    TokenKind::KeywordText => self.section = String::from(".text"),
+                    TokenKind::KeywordData => self.section = String::from(".data"),
+                    TokenKind::Identifier | TokenKind::InnerLabelReference => {
+                        self.section = token.resolve(self.session);
+                    }
+                    // This is an error that the parser reports
+                    _ => {}
+                }
+            }
+        }
+    }
+
     fn expand_sl_macro(
         &self,
         sl_macro: &SLMacroDef,
@@ -89,8 +203,7 @@ impl<'a> Executor<'a> {
 
                         for token in &benign_tokens.tokens {
                             if token.kind == TokenKind::Identifier {
-                                let ident_snippet = self.session.span_to_snippet(&token.as_span());
-                                let ident_str = ident_snippet.as_slice();
+                                let ident_str = token.resolve(self.session);
 
                                 let mut hasher = DefaultHasher::new();
                                 hasher.write(ident_str.as_bytes());
@@ -139,7 +252,7 @@ impl<'a> Executor<'a> {
     ) -> EResult<Option<Vec<PASTNode>>> {
         if let Some(ml_args) = &ml_macro.args {
             // If there are defaults that we might fill in
-            let mut default_replacements = if let Some(arg_defaults) = &ml_macro.defaults {
+            if let Some(arg_defaults) = &ml_macro.defaults {
                 let num_needed_defaults =
                     ml_args.maximum.map(|val| val.get() as usize).unwrap_or(0) - num_args_provided;
 
@@ -150,107 +263,151 @@ impl<'a> Executor<'a> {
                     .take(num_needed_defaults)
                     .rev();
 
-                let mut replacement_tokens = Vec::new();
-
+                // Defaults can refer to earlier arguments, including ones that were themselves
+                // filled in by a default, so these are bound one at a time
                 for replacement_default in replacement_defaults {
-                    let mut tokens = Vec::new();
-
-                    for token in &replacement_default.tokens {
-                        tokens.push(*token);
-                    }
+                    let tokens =
+                        self.replace_ml_arg_refs(&replacement_default.tokens, &arg_replacements)?;
 
-                    replacement_tokens.push(tokens);
+                    arg_replacements.push(tokens);
                 }
-
-                replacement_tokens
             }
-            // If there aren't
-            else {
-                Vec::new()
-            };
 
-            // Append the defaults to the replacements
-            arg_replacements.append(&mut default_replacements);
+            let cleaner_contents =
+                self.replace_ml_arg_refs_in_nodes(&ml_macro.contents, &arg_replacements)?;
+
+            Ok(Some(cleaner_contents))
+        } else {
+            Ok(Some(ml_macro.contents.clone()))
+        }
+    }
+
+    // Replaces every multi-line macro argument reference in the nodes, including the ones in the
+    // conditions and clauses of .if statements
+    fn replace_ml_arg_refs_in_nodes(
+        &self,
+        nodes: &[PASTNode],
+        arg_replacements: &[Vec<Token>],
+    ) -> EResult<Vec<PASTNode>> {
+        let mut new_nodes = Vec::with_capacity(nodes.len());
 
-            let mut cleaner_contents = Vec::new();
+        for node in nodes {
+            new_nodes.push(match node {
+                PASTNode::BenignTokens(benign_tokens) => {
+                    let new_benign_tokens =
+                        self.replace_ml_arg_refs(&benign_tokens.tokens, arg_replacements)?;
 
-            for node in &ml_macro.contents {
-                if let PASTNode::BenignTokens(benign_tokens) = node {
-                    let mut new_benign_tokens = Vec::new();
-                    let mut was_arg_ref = false;
+                    PASTNode::BenignTokens(BenignTokens::from_vec(new_benign_tokens))
+                }
+                PASTNode::IfStatement(statement) => {
+                    let mut statement = statement.clone();
+
+                    for clause in statement.clauses.iter_mut() {
+                        if let IfCondition::Exp(condition) = &mut clause.condition {
+                            condition.expression = self.replace_ml_arg_refs_in_nodes(
+                                &condition.expression,
+                                arg_replacements,
+                            )?;
+                        }
 
-                    for token in &benign_tokens.tokens {
-                        if token.kind == TokenKind::SymbolAnd {
-                            was_arg_ref = true;
-                        } else if was_arg_ref {
-                            was_arg_ref = false;
+                        clause.contents =
+                            self.replace_ml_arg_refs_in_nodes(&clause.contents, arg_replacements)?;
+                    }
 
-                            if token.kind != TokenKind::LiteralInteger {
-                                self.session.struct_bug("didn't properly check for multi-line macro argument references".to_string()).emit();
-                                return Err(());
-                            }
+                    PASTNode::IfStatement(statement)
+                }
+                _ => node.clone(),
+            });
+        }
 
-                            let arg_ref_snippet = self.session.span_to_snippet(&token.as_span());
-                            let arg_ref_str = arg_ref_snippet.as_slice();
-                            let arg_ref = match parse_integer_literal(arg_ref_str) {
-                                Ok(num) => num,
-                                Err(_) => {
-                                    self.session
-                                        .struct_span_error(
-                                            token.as_span(),
-                                            "integer value out of bounds for signed 32 bit"
-                                                .to_string(),
-                                        )
-                                        .emit();
-                                    return Err(());
-                                }
-                            };
-
-                            if arg_ref == 0 {
-                                self.session
-                                    .struct_span_error(
-                                        token.as_span(),
-                                        "macro argument indexes start at 1".to_string(),
-                                    )
-                                    .emit();
-                                return Err(());
-                            }
+        Ok(new_nodes)
+    }
 
-                            // We offset by 1 here, because macro arguments are 1-indexed
-                            if let Some(replacement) = arg_replacements.get((arg_ref as usize) - 1)
-                            {
-                                for token in replacement {
-                                    new_benign_tokens.push(*token);
-                                }
-                            } else {
-                                self.session
-                                    .struct_span_error(
-                                        token.as_span(),
-                                        "argument index out of bounds".to_string(),
-                                    )
-                                    .emit();
-                                return Err(());
-                            }
-                        } else {
-                            new_benign_tokens.push(*token);
-                        }
+    // Replaces every multi-line macro argument reference (&1, &2, ...) in the tokens with the
+    // tokens of the argument that it refers to
+    fn replace_ml_arg_refs(
+        &self,
+        tokens: &[Token],
+        arg_replacements: &[Vec<Token>],
+    ) -> EResult<Vec<Token>> {
+        let mut new_tokens = Vec::new();
+        let mut was_arg_ref = false;
+
+        for token in tokens {
+            if token.kind == TokenKind::SymbolAnd {
+                was_arg_ref = true;
+            } else if was_arg_ref {
+                was_arg_ref = false;
+
+                if token.kind != TokenKind::LiteralInteger {
+                    self.session
+                        .struct_bug(
+                            "didn't properly check for multi-line macro argument references"
+                                .to_string(),
+                        )
+                        .emit();
+                    return Err(());
+                }
+
+                let arg_ref_str = token.resolve(self.session);
+                let arg_ref = match parse_integer_literal(&arg_ref_str) {
+                    Ok(num) => num,
+                    Err(_) => {
+                        self.session
+                            .struct_span_error(
+                                token.as_span(),
+                                "integer value out of bounds for signed 32 bit".to_string(),
+                            )
+                            .emit();
+                        return Err(());
                     }
+                };
+
+                if arg_ref == 0 {
+                    self.session
+                        .struct_span_error(
+                            token.as_span(),
+                            "macro argument indexes start at 1".to_string(),
+                        )
+                        .emit();
+                    return Err(());
+                }
 
-                    cleaner_contents.push(PASTNode::BenignTokens(BenignTokens::from_vec(
-                        new_benign_tokens,
-                    )));
+                // We offset by 1 here, because macro arguments are 1-indexed
+                if let Some(replacement) = arg_replacements.get((arg_ref as usize) - 1) {
+                    for token in replacement {
+                        new_tokens.push(*token);
+                    }
                 } else {
-                    cleaner_contents.push(node.clone());
+                    self.session
+                        .struct_span_error(
+                            token.as_span(),
+                            "argument index out of bounds".to_string(),
+                        )
+                        .emit();
+                    return Err(());
                 }
+            } else {
+                new_tokens.push(*token);
             }
-
-            Ok(Some(cleaner_contents))
-        } else {
-            Ok(Some(ml_macro.contents.clone()))
         }
+
+        Ok(new_tokens)
     }
 
     fn execute_macro_invokation(&mut self, macro_invok: MacroInvok) -> EMaybe {
+        // The innermost .rep that binds this identifier as its index takes precedence
+        if macro_invok.args.is_none() {
+            if let Some((_, index_token)) = self
+                .rep_indices
+                .iter()
+                .rev()
+                .find(|(hash, _)| *hash == macro_invok.identifier.hash)
+            {
+                return Ok(Some(vec![*index_token]));
+            }
+        }
+
         let invok_args = if let Some(args) = &macro_invok.args {
             args.args.clone()
         } else {
@@ -268,11 +425,16 @@ impl<'a> Executor<'a> {
             arg_replacements.push(tokens);
         }
 
+        self.sl_macros
+            .mark_used(macro_invok.identifier.hash, num_args_provided as u8);
+        self.ml_macros.mark_used(&macro_invok);
+
         if let Some(sl_macro) = self.sl_macros.get(&macro_invok) {
             let new_contents = self.expand_sl_macro(sl_macro, arg_replacements)?;
 
             if let Some(new_contents) = new_contents {
-                self.execute_nodes(new_contents).map(Some)
+                self.execute_expansion(macro_invok.identifier.span, new_contents)
+                    .map(Some)
             } else {
                 Ok(None)
             }
@@ -281,7 +443,8 @@ impl<'a> Executor<'a> {
                 self.expand_ml_macro(ml_macro, arg_replacements, num_args_provided)?;
 
             if let Some(new_contents) = new_contents {
-                self.execute_nodes(new_contents).map(Some)
+                self.execute_expansion(macro_invok.identifier.span, new_contents)
+                    .map(Some)
             } else {
                 Ok(None)
             }
@@ -341,17 +504,100 @@ impl<'a> Executor<'a> {
 
                     // Just assume that it is a name of a label or data
                     // So we just turn it back into a token.
-                    Ok(Some(vec![Token {
+                    let tokens = vec![Token {
                         kind: TokenKind::Identifier,
                         file_id,
                         source_index,
                         len,
-                    }]))
+                        expansion: macro_invok.identifier.span.expansion as u32,
+                    }];
+
+                    // This could be the name of a custom section
+                    self.track_section(&tokens);
+
+                    Ok(Some(tokens))
                 }
             }
         }
     }
 
+    // Executes the contents of a macro that was invoked at the provided span, making sure that
+    // macros don't end up expanding each other forever
+    fn execute_expansion(
+        &mut self,
+        invok_span: Span,
+        contents: Vec<PASTNode>,
+    ) -> EResult<Vec<Token>> {
+        let max_depth = self.session.config().max_expansion_depth;
+
+        if self.expansion_stack.len() >= max_depth {
+            self.emit_expansion_depth_error(invok_span, max_depth);
+
+            return Err(());
+        }
+
+        let parent = self.expansion_stack.last().map_or(0, |(_, id)| *id);
+        let id = self.session.add_expansion(invok_span, parent);
+
+        self.expansion_stack.push((invok_span, id));
+
+        let result = self.execute_nodes(contents);
+
+        self.expansion_stack.pop();
+
+        // Tokens that already refer to an expansion came from a macro expanded inside of this one,
+        // or from one in an argument, and they keep that more specific one
+        result.map(|mut tokens| {
+            for token in tokens.iter_mut().filter(|token| token.expansion == 0) {
+                token.expansion = id as u32;
+            }
+
+            tokens
+        })
+    }
+
+    fn emit_expansion_depth_error(&self, invok_span: Span, max_depth: usize) {
+        // Only the most recent expansions are listed, which should be enough to spot a cycle
+        const MAX_FRAMES_SHOWN: usize = 8;
+
+        let macro_name_snippet = self.session.span_to_snippet(&invok_span);
+
+        let mut db = self.session.struct_span_error(
+            invok_span,
+            format!(
+                "expansion of macro `{}` exceeded the maximum depth of {}",
+                macro_name_snippet.as_slice(),
+                max_depth
+            ),
+        );
+
+        for (frame, _) in self.expansion_stack.iter().rev().take(MAX_FRAMES_SHOWN) {
+            let frame_name_snippet = self.session.span_to_snippet(frame);
+            let (path, line, col) = self.session.get_source_location(frame);
+
+            db.note(format!(
+                "expanded from `{}` at {}:{}:{}",
+                frame_name_snippet.as_slice(),
+                path,
+                line,
+                col
+            ));
+        }
+
+        if self.expansion_stack.len() > MAX_FRAMES_SHOWN {
+            db.note(format!(
+                "and {} more expansions",
+                self.expansion_stack.len() - MAX_FRAMES_SHOWN
+            ));
+        }
+
+        db.help(String::from(
+            "check for macros that expand to each other, or raise the limit with --max-expansion-depth",
+        ));
+
+        db.emit();
+    }
+
     fn execute_ml_macro_undef(&mut self, ml_macro_undef: MLMacroUndef) -> EMaybe {
         self.ml_macros.undefine(ml_macro_undef);
 
@@ -364,35 +610,21 @@ impl<'a> Executor<'a> {
         Ok(None)
     }
 
-    fn include_path(&mut self, span: &Span, path: &str) -> EResult<Vec<Token>> {
-        let mut path_buf = PathBuf::from(path);
-
-        if let Some(include_path) = &self.session.config().include_path {
-            let include_path_buf = PathBuf::from(include_path);
-            path_buf = include_path_buf.join(path_buf);
-        }
-
-        let path_buf_str = path_buf.as_os_str().to_str().unwrap();
-
-        // Check if we have been given a valid file
-        if !path_buf.is_file() {
-            self.session
-                .struct_span_error(
-                    *span,
-                    format!("path provided `{}` is not a file", path_buf_str),
-                )
-                .help("maybe check your include paths".to_string())
-                .emit();
-
-            return Err(());
-        }
+    fn include_path(
+        &mut self,
+        span: &Span,
+        path: &str,
+        lines: Option<IncludeLines>,
+    ) -> EResult<Vec<Token>> {
+        let path_buf = self.session.resolve_include(*span, path)?;
 
         // Read it
         let file_id = match self.session.read_file(&path_buf) {
             Ok(file_id) => file_id,
             Err(e) => {
                 self.session
-                    .struct_bug(format!("unable to read file `{}`: {}", path_buf_str, e))
+                    .struct_read_error(&path_buf, e)
+                    .span_label(*span, "included here".to_string())
                     .emit();
 
                 return Err(());
@@ -401,8 +633,31 @@ impl<'a> Executor<'a> {
 
         let file = self.session.get_file(file_id as usize).unwrap();
 
-        // Create the lexer
-        let lexer = Lexer::new(&file.source, file_id, self.session);
+        // Create the lexer, which only lexes the requested lines if there are any. The tokens
+        // still point into the whole file, so diagnostics report the right line numbers
+        let lexer = match lines {
+            Some(lines) => match file.line_range(lines.first, lines.last) {
+                Ok(range) => Lexer::with_range(&file.source, range, file_id, self.session),
+                Err(num_lines) => {
+                    self.session
+                        .struct_span_error(
+                            lines.span,
+                            format!(
+                                "lines {}-{} are out of range for `{}`, which has {} line{}",
+                                lines.first,
+                                lines.last,
+                                path,
+                                num_lines,
+                                if num_lines == 1 { "" } else { "s" }
+                            ),
+                        )
+                        .emit();
+
+                    return Err(());
+                }
+            },
+            None => Lexer::new(&file.source, file_id, self.session),
+        };
 
         // Lex the tokens, if they are all valid
         let mut tokens = lexer.lex()?;
@@ -419,6 +674,16 @@ impl<'a> Executor<'a> {
         Ok(tokens)
     }
 
+    fn execute_line(&mut self, line: Line) -> EMaybe {
+        // If the directive is the last line in the file, there is nothing for it to apply to
+        if let Some(next_line_start) = line.next_line_start {
+            self.session
+                .add_line_directive(line.span.file, next_line_start, line.line, line.file);
+        }
+
+        Ok(None)
+    }
+
     fn execute_include(&mut self, include: Include) -> EMaybe {
         let path = self.execute_nodes(include.path.expression)?;
 
@@ -432,9 +697,25 @@ impl<'a> Executor<'a> {
 
                 let path_str = path_snippet.as_slice().trim_matches('\"');
 
-                let included_tokens = self.include_path(&include.path.span, path_str)?;
+                // A header's .section directives are dropped, so they can't change the section
+                let section = (self.section.clone(), self.awaiting_section);
+
+                let included_tokens =
+                    self.include_path(&include.path.span, path_str, include.lines)?;
+
+                if include.declarations_only {
+                    (self.section, self.awaiting_section) = section;
+
+                    let extern_token = self.extern_token(include.path.span)?;
 
-                Ok(Some(included_tokens))
+                    Ok(Some(declarations(
+                        included_tokens,
+                        extern_token,
+                        self.session,
+                    )))
+                } else {
+                    Ok(Some(included_tokens))
+                }
             } else {
                 self.session
                     .struct_span_error(include.path.span, "expected path".to_string())
@@ -453,6 +734,8 @@ impl<'a> Executor<'a> {
     }
 
     fn execute_rep(&mut self, repeat: Repeat) -> EMaybe {
+        let constant = is_constant(&repeat.number.expression);
+
         let evaluation = self.evaluate_expression(&repeat.number.span, repeat.number.expression)?;
 
         let num = match evaluation {
@@ -468,28 +751,143 @@ impl<'a> Executor<'a> {
 
                 return Err(());
             }
-            Value::Double(d) => d as i32,
+            Value::Double(_) => {
+                self.session
+                    .struct_span_error(
+                        repeat.number.span,
+                        "expression resulted in double value".to_string(),
+                    )
+                    .help(".rep requires an integer value".to_string())
+                    .emit();
+
+                return Err(());
+            }
+            Value::String(_) => {
+                self.session
+                    .struct_span_error(
+                        repeat.number.span,
+                        "expression resulted in string value".to_string(),
+                    )
+                    .help(".rep requires an integer value".to_string())
+                    .emit();
+
+                return Err(());
+            }
         };
 
+        if num == 0 && constant {
+            self.warn_discarded(repeat.number.span, &repeat.contents);
+        }
+
         if num < 0 {
             self.session
                 .struct_span_error(
                     repeat.number.span,
-                    "expression resulted in negative number".to_string(),
+                    "repeat count cannot be negative".to_string(),
                 )
-                .help(".rep number must be positive".to_string())
+                .note(format!("the count evaluated to {}", num))
                 .emit();
 
             return Err(());
         }
 
-        let mut repeat_tokens = self.execute_nodes(repeat.contents)?;
+        let index = match repeat.index {
+            Some(index) => index,
+            None => {
+                let mut repeat_tokens = self.execute_nodes(repeat.contents)?;
+
+                repeat_tokens = repeat_tokens.repeat(num as usize);
+
+                return Ok(Some(repeat_tokens));
+            }
+        };
+
+        // The contents are different each time, so they have to be executed for each iteration
+        let mut repeat_tokens = Vec::new();
+
+        for index_token in self.rep_index_tokens(repeat.number.span, num)? {
+            self.rep_indices.push((index.hash, index_token));
+
+            let result = self.execute_nodes(repeat.contents.clone());
+
+            self.rep_indices.pop();
 
-        repeat_tokens = repeat_tokens.repeat(num as usize);
+            repeat_tokens.append(&mut result?);
+        }
 
         Ok(Some(repeat_tokens))
     }
 
+    // Returns integer literal tokens for each index of a .rep with a bound index identifier. They
+    // all come from one source file, which is only replaced when a .rep needs more indices than it
+    // has, so that nested .reps don't use up every file ID
+    fn rep_index_tokens(&mut self, span: Span, num: i32) -> EResult<Vec<Token>> {
+        let num = num as usize;
+
+        if self.rep_index_cache.len() < num {
+            let count = num.max(self.rep_index_cache.len() * 2);
+            let source = (0..count).map(|i| format!("{}\n", i)).collect();
+
+            let source_file = SourceFile::new("<rep index>".to_owned(), None, None, source, 0);
+            let file_id = self.add_file(source_file, Some(span))?;
+            let file = self.session.get_file(file_id as usize).unwrap();
+
+            let lexer = Lexer::new(&file.source, file_id, self.session);
+
+            self.rep_index_cache = lexer
+                .lex()?
+                .into_iter()
+                .filter(|token| token.kind == TokenKind::LiteralInteger)
+                .collect();
+        }
+
+        Ok(self.rep_index_cache[..num].to_vec())
+    }
+
+    // Returns a .extern directive token, which is lexed from a generated file the first time it
+    // is needed
+    fn extern_token(&mut self, span: Span) -> EResult<Token> {
+        if let Some(token) = self.extern_token {
+            return Ok(token);
+        }
+
+        let source_file = SourceFile::new(
+            "<declarations>".to_owned(),
+            None,
+            None,
+            ".extern\n".to_owned(),
+            0,
+        );
+        let file_id = self.add_file(source_file, Some(span))?;
+        let file = self.session.get_file(file_id as usize).unwrap();
+
+        let lexer = Lexer::new(&file.source, file_id, self.session);
+
+        let token = lexer.lex()?[0];
+        self.extern_token = Some(token);
+
+        Ok(token)
+    }
+
+    // Adds a source file that the preprocessor generated, or emits an error pointing at the span
+    // that needed it, if any, when there are already too many files
+    fn add_file(&mut self, source_file: SourceFile, span: Option<Span>) -> EResult<u8> {
+        self.session.add_file(source_file).map_err(|_| {
+            let message = "too many source files".to_string();
+
+            let mut db = match span {
+                Some(span) => self.session.struct_span_error(span, message),
+                None => self.session.struct_error(message),
+            };
+
+            db.note(format!(
+                "at most {} files can be used, including ones generated for .rep indices",
+                u8::MAX
+            ))
+            .emit();
+        })
+    }
+
     fn execute_sl_macro_def(&mut self, sl_macro: SLMacroDef) -> EMaybe {
         if let Some(ml_macro) = self.ml_macros.find_by_hash(sl_macro.identifier.hash) {
             self.session
@@ -527,7 +925,22 @@ impl<'a> Executor<'a> {
             return Err(());
         }
 
-        self.ml_macros.define(ml_macro);
+        let identifier_span = ml_macro.identifier.span;
+
+        if let Some(replaced) = self.ml_macros.define(ml_macro) {
+            let name_snippet = self.session.span_to_snippet(&identifier_span);
+
+            self.session
+                .struct_span_warn(
+                    identifier_span,
+                    format!("macro `{}` redefined", name_snippet.as_slice()),
+                )
+                .span_label(
+                    replaced.identifier.span,
+                    "previously defined here".to_string(),
+                )
+                .emit();
+        }
 
         Ok(None)
     }
@@ -546,6 +959,13 @@ impl<'a> Executor<'a> {
     fn execute_if_clause(&mut self, clause: IfClause) -> EMaybe {
         let inverse = clause.begin.inverse;
 
+        let constant_span = match &clause.condition {
+            IfCondition::Exp(expression) if is_constant(&expression.expression) => {
+                Some(expression.span)
+            }
+            _ => None,
+        };
+
         let condition = self.evaluate_if_condition(clause.condition)? ^ inverse;
 
         Ok(if condition {
@@ -555,12 +975,39 @@ impl<'a> Executor<'a> {
 
             Some(tokens)
         } else {
+            if let Some(span) = constant_span {
+                self.warn_discarded(span, &clause.contents);
+            }
+
             None
         })
     }
 
     fn evaluate_expression(&mut self, span: &Span, expression: Vec<PASTNode>) -> EResult<Value> {
+        // Definitions are expanded here so that their values can be used in the expression
         let expanded_tokens = self.execute_nodes(expression)?;
+
+        // Any identifier that is left after expansion was not a defined macro
+        if let Some(token) = expanded_tokens
+            .iter()
+            .find(|token| token.kind == TokenKind::Identifier)
+        {
+            let ident_str = token.resolve(self.session);
+
+            self.session
+                .struct_span_error(
+                    token.as_span(),
+                    format!("`{}` is not a defined macro", ident_str),
+                )
+                .help(
+                    "macros can be defined with .define, or with -D on the command line"
+                        .to_string(),
+                )
+                .emit();
+
+            return Err(());
+        }
+
         let mut token_iter = expanded_tokens.iter().peekable();
 
         let root_node =
@@ -578,21 +1025,17 @@ impl<'a> Executor<'a> {
                 }
                 Err(mut db) => {
                     db.emit();
-                    todo!()
+
+                    return Err(());
                 }
             };
 
         let evaluation = match ExpressionEvaluator::evaluate(&root_node) {
             Ok(evaluation) => evaluation,
             Err(e) => {
-                let error_message = match e {
-                    EvalError::NegateBool => "`-` operator invalid for booleans",
-                    EvalError::FlipDouble => "`~` operator invalid for doubles",
-                    EvalError::ZeroDivide => "expression tried to divide by 0",
-                }
-                .to_string();
-
-                self.session.struct_span_error(*span, error_message).emit();
+                self.session
+                    .struct_span_error(e.span, e.kind.message().to_string())
+                    .emit();
 
                 return Err(());
             }
@@ -601,31 +1044,211 @@ impl<'a> Executor<'a> {
         Ok(evaluation)
     }
 
+    // Checks if a macro matching the .ifdef condition is defined, and counts it as used if so
+    fn is_defined(&mut self, definition: &IfDefCondition) -> bool {
+        let hash = definition.identifier.hash;
+
+        // A single number of arguments only matches a macro that can take exactly that
+        // many, and a range matches one that can take any number within it
+        let (required, maximum) = match &definition.args {
+            Some(args) => (
+                args.required,
+                args.maximum.map_or(args.required, |maximum| maximum.get()),
+            ),
+            None => (0, 0),
+        };
+
+        let mut defined = false;
+
+        for num_args in required..=maximum {
+            self.sl_macros.mark_used(hash, num_args);
+
+            defined |= self.sl_macros.contains(hash, num_args);
+        }
+
+        self.ml_macros.mark_used_by_args(hash, &definition.args);
+
+        defined || self.ml_macros.contains(hash, &definition.args)
+    }
+
+    // Replaces a defined(NAME) check in an .if expression with the true or false it results in
+    fn execute_defined(&mut self, defined: Defined) -> Vec<Token> {
+        let kind = if self.is_defined(&defined.condition) {
+            TokenKind::LiteralTrue
+        } else {
+            TokenKind::LiteralFalse
+        };
+
+        vec![Token {
+            kind,
+            file_id: defined.span.file as u8,
+            source_index: defined.span.start as u32,
+            len: (defined.span.end - defined.span.start) as u16,
+            expansion: defined.span.expansion as u32,
+        }]
+    }
+
+    // Warns that the contents of a .rep or .if are never used because of the count or condition at
+    // the provided span, if that warning is enabled and there is anything in them. Ones that are
+    // inside of a macro expansion are left alone, because its arguments can switch them off
+    fn warn_discarded(&self, span: Span, contents: &[PASTNode]) {
+        if !self.session.config().warn_discarded
+            || !self.expansion_stack.is_empty()
+            || is_empty(contents)
+        {
+            return;
+        }
+
+        self.session
+            .struct_span_warn(span, "contents are always discarded".to_string())
+            .help(
+                "if this is meant to be switched off, use a macro in the count or condition"
+                    .to_string(),
+            )
+            .emit();
+    }
+
     fn evaluate_if_condition(&mut self, condition: IfCondition) -> EResult<bool> {
         match condition {
             IfCondition::Exp(expression) => {
                 let evaluation =
                     self.evaluate_expression(&expression.span, expression.expression)?;
 
+                if let Value::String(_) = evaluation {
+                    self.session
+                        .struct_span_error(
+                            expression.span,
+                            "expression resulted in string value".to_string(),
+                        )
+                        .help("strings can only be compared with == or !=".to_string())
+                        .emit();
+
+                    return Err(());
+                }
+
                 Ok(evaluation.to_bool())
             }
-            IfCondition::Def(definition) => {
-                let hash = definition.identifier.hash;
+            IfCondition::Def(definition) => Ok(self.is_defined(&definition)),
+            IfCondition::Section(section) => Ok(section.section == self.section),
+            IfCondition::Else => Ok(true),
+        }
+    }
+}
 
-                let args = match &definition.args {
-                    Some(args) => (args.required, args.maximum),
-                    None => (0, None),
-                };
+// Keeps only the lines that declare symbols, for .include_decls. Macros have already been
+// defined by the time the tokens are produced, so they don't need to be kept.
+//
+// The header defines its .global and .weak symbols itself, so to the file including it they are
+// .extern instead. Its .local symbols can't be used outside of it at all, so they are dropped
+// along with their .type declarations
+fn declarations(tokens: Vec<Token>, extern_token: Token, session: &Session) -> Vec<Token> {
+    let lines: Vec<&[Token]> = tokens
+        .split_inclusive(|token| token.kind == TokenKind::Newline)
+        .collect();
+
+    let directive = |line: &[Token]| {
+        line.iter()
+            .position(|token| token.kind != TokenKind::Whitespace)
+            .filter(|&index| {
+                matches!(
+                    line[index].kind,
+                    TokenKind::DirectiveExtern
+                        | TokenKind::DirectiveGlobal
+                        | TokenKind::DirectiveLocal
+                        | TokenKind::DirectiveWeak
+                        | TokenKind::DirectiveType
+                )
+            })
+    };
+
+    let symbol = |line: &[Token]| {
+        line.iter()
+            .rev()
+            .find(|token| token.kind == TokenKind::Identifier)
+            .map(|token| token.resolve(session))
+    };
+
+    let local_symbols: Vec<String> = lines
+        .iter()
+        .filter(|line| {
+            directive(line).is_some_and(|index| line[index].kind == TokenKind::DirectiveLocal)
+        })
+        .filter_map(|line| symbol(line))
+        .collect();
 
-                match args {
-                    (_, Some(_)) => Ok(self.ml_macros.contains(hash, &definition.args)),
-                    (num_args, None) => Ok({
-                        self.sl_macros.contains(hash, num_args)
-                            || self.ml_macros.contains(hash, &definition.args)
-                    }),
-                }
+    let mut declarations = Vec::new();
+
+    for line in lines {
+        let Some(index) = directive(line) else {
+            continue;
+        };
+
+        match line[index].kind {
+            TokenKind::DirectiveLocal => continue,
+            TokenKind::DirectiveType
+                if symbol(line).is_some_and(|name| local_symbols.contains(&name)) =>
+            {
+                continue
             }
-            IfCondition::Else => Ok(true),
+            TokenKind::DirectiveGlobal | TokenKind::DirectiveWeak => {
+                declarations.extend_from_slice(&line[..index]);
+                declarations.push(extern_token);
+                declarations.extend_from_slice(&line[index + 1..]);
+            }
+            _ => declarations.extend_from_slice(line),
         }
     }
+
+    declarations
+}
+
+// Checks if a .rep count or .if condition is made up of only literals and operators, so that it
+// doesn't depend on any macro or .rep index
+fn is_constant(expression: &[PASTNode]) -> bool {
+    expression.iter().all(|node| match node {
+        PASTNode::BenignTokens(benign_tokens) => benign_tokens.tokens.iter().all(|token| {
+            matches!(
+                token.kind,
+                TokenKind::LiteralInteger
+                    | TokenKind::LiteralFloat
+                    | TokenKind::LiteralHex
+                    | TokenKind::LiteralBinary
+                    | TokenKind::LiteralTrue
+                    | TokenKind::LiteralFalse
+                    | TokenKind::LiteralString
+                    | TokenKind::OperatorMinus
+                    | TokenKind::OperatorPlus
+                    | TokenKind::OperatorCompliment
+                    | TokenKind::OperatorMultiply
+                    | TokenKind::OperatorDivide
+                    | TokenKind::OperatorMod
+                    | TokenKind::OperatorAnd
+                    | TokenKind::OperatorOr
+                    | TokenKind::OperatorEquals
+                    | TokenKind::OperatorNotEquals
+                    | TokenKind::OperatorNegate
+                    | TokenKind::OperatorGreaterThan
+                    | TokenKind::OperatorLessThan
+                    | TokenKind::OperatorGreaterEquals
+                    | TokenKind::OperatorLessEquals
+                    | TokenKind::SymbolLeftParen
+                    | TokenKind::SymbolRightParen
+                    | TokenKind::Whitespace
+            )
+        }),
+        _ => false,
+    })
+}
+
+// Checks if the contents of a .rep or .if have nothing in them but blank lines
+fn is_empty(contents: &[PASTNode]) -> bool {
+    contents.iter().all(|node| match node {
+        PASTNode::BenignTokens(benign_tokens) => benign_tokens.tokens.iter().all(|token| {
+            matches!(
+                token.kind,
+                TokenKind::Newline | TokenKind::Whitespace | TokenKind::Comment
+            )
+        }),
+        _ => false,
+    })
 }
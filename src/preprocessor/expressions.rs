@@ -1,23 +1,45 @@
 use std::{iter::Peekable, slice::Iter};
 
 use crate::{
-    errors::DiagnosticBuilder,
+    errors::{DiagnosticBuilder, Span},
     lexer::{Token, TokenKind},
     session::Session,
 };
 
 use super::parser::{
     parse_binary_literal, parse_float_literal, parse_hexadecimal_literal, parse_integer_literal,
+    parse_string_literal, struct_escape_error,
 };
 
+/// Returns true if the token is an integer literal of 2147483648, which is too large for an i32
+/// on its own but can still be negated to get the smallest i32
+pub fn is_i32_min_magnitude(token: &Token, session: &Session) -> bool {
+    let literal: String = token
+        .resolve(session)
+        .chars()
+        .filter(|c| *c != '_')
+        .collect();
+
+    let magnitude = match token.kind {
+        TokenKind::LiteralInteger => literal.parse().ok(),
+        TokenKind::LiteralHex => u64::from_str_radix(&literal[2..], 16).ok(),
+        TokenKind::LiteralBinary => u64::from_str_radix(&literal[2..], 2).ok(),
+        _ => None,
+    };
+
+    magnitude == Some(1 << 31)
+}
+
 pub type ExpResult<'a> = Result<Option<ExpNode>, DiagnosticBuilder<'a>>;
 pub type TokenIter<'a> = Peekable<Iter<'a, Token>>;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i32),
     Double(f64),
     Bool(bool),
+    /// Strings can only be compared for equality
+    String(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -49,9 +71,73 @@ pub enum BinOp {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ExpNode {
-    BinOp(Box<ExpNode>, BinOp, Box<ExpNode>),
-    UnOp(UnOp, Box<ExpNode>),
-    Constant(Value),
+    /// A binary operation, along with the span of the operator
+    BinOp(Box<ExpNode>, BinOp, Span, Box<ExpNode>),
+    /// A unary operation, along with the span of the operator
+    UnOp(UnOp, Span, Box<ExpNode>),
+    /// A constant, along with the span of the literal it was parsed from
+    Constant(Value, Span),
+    /// The name of a symbol, which has to be replaced with its value by resolve_symbols() before
+    /// the expression can be evaluated
+    Symbol(Span),
+}
+
+impl ExpNode {
+    /// Returns the span of the source code this node was parsed from, not including any
+    /// parenthesis around it
+    ///
+    /// If the parts of the node came from different places, as can happen with macro expansion,
+    /// this is just the span of the operator
+    pub fn span(&self) -> Span {
+        match self {
+            ExpNode::BinOp(lhs, _, op_span, rhs) => {
+                let (lhs_span, rhs_span) = (lhs.span(), rhs.span());
+
+                if lhs_span.file == rhs_span.file && lhs_span.start <= rhs_span.end {
+                    Span {
+                        end: rhs_span.end,
+                        ..lhs_span
+                    }
+                } else {
+                    *op_span
+                }
+            }
+            ExpNode::UnOp(_, op_span, node) => {
+                let node_span = node.span();
+
+                if op_span.file == node_span.file && op_span.start <= node_span.end {
+                    Span {
+                        end: node_span.end,
+                        ..*op_span
+                    }
+                } else {
+                    *op_span
+                }
+            }
+            ExpNode::Constant(_, span) | ExpNode::Symbol(span) => *span,
+        }
+    }
+
+    /// Replaces each symbol in this expression with the value that resolve returns for it,
+    /// stopping at the first one that it fails for
+    pub fn resolve_symbols<E>(
+        &mut self,
+        resolve: &mut impl FnMut(Span) -> Result<Value, E>,
+    ) -> Result<(), E> {
+        match self {
+            ExpNode::BinOp(lhs, _, _, rhs) => {
+                lhs.resolve_symbols(resolve)?;
+                rhs.resolve_symbols(resolve)
+            }
+            ExpNode::UnOp(_, _, node) => node.resolve_symbols(resolve),
+            ExpNode::Constant(..) => Ok(()),
+            ExpNode::Symbol(span) => {
+                *self = ExpNode::Constant(resolve(*span)?, *span);
+
+                Ok(())
+            }
+        }
+    }
 }
 
 // Generates binary operator parsing code, only suitable for extremely simple binary operators
@@ -67,7 +153,8 @@ macro_rules! gen_binop {
                     $tokens.next();
 
                     if let Some(rhs) = Self::$func_name($tokens, $session)? {
-                        lhs = ExpNode::BinOp(Box::new(lhs), $op_kind, Box::new(rhs));
+                        lhs =
+                            ExpNode::BinOp(Box::new(lhs), $op_kind, token.as_span(), Box::new(rhs));
                     } else {
                         let db = $session
                             .struct_span_error(token.as_span(), "trailing operator".to_string());
@@ -90,10 +177,34 @@ macro_rules! gen_binop {
 pub struct ExpressionParser {}
 
 impl ExpressionParser {
+    /// Parses an expression made of only constants and operators
     pub fn parse_expression<'a>(
         tokens: &mut TokenIter,
         session: &'a Session,
         nested: bool,
+    ) -> ExpResult<'a> {
+        let mut parsed = Self::parse_with_symbols(tokens, session, nested)?;
+
+        if let Some(exp) = &mut parsed {
+            exp.resolve_symbols(&mut |span| Err(Self::struct_invalid_token(session, span)))?;
+        }
+
+        Ok(parsed)
+    }
+
+    /// Parses an expression in a data entry, which can also contain the names of symbols. These
+    /// have to be replaced using ExpNode::resolve_symbols() before it can be evaluated
+    pub fn parse_data_expression<'a>(
+        tokens: &mut TokenIter,
+        session: &'a Session,
+    ) -> ExpResult<'a> {
+        Self::parse_with_symbols(tokens, session, false)
+    }
+
+    fn parse_with_symbols<'a>(
+        tokens: &mut TokenIter,
+        session: &'a Session,
+        nested: bool,
     ) -> ExpResult<'a> {
         let parsed = Self::parse_logical_or(tokens, session)?;
 
@@ -150,6 +261,10 @@ impl ExpressionParser {
         Self::skip_whitespace(tokens);
         if let Some(mut lhs) = Self::parse_relational_exp(tokens, session)? {
             Self::skip_whitespace(tokens);
+
+            // The operator of the comparison that lhs is, if it was parsed by this loop
+            let mut previous_op: Option<Span> = None;
+
             while let Some(&&token) = tokens.peek() {
                 // Check if it is an equality operator: ==, !=
                 let op = match token.kind {
@@ -160,10 +275,20 @@ impl ExpressionParser {
                     }
                 };
 
+                if let Some(previous_span) = previous_op {
+                    return Err(Self::struct_chained_comparison(
+                        session,
+                        previous_span,
+                        token.as_span(),
+                    ));
+                }
+
                 tokens.next();
 
                 if let Some(rhs) = Self::parse_relational_exp(tokens, session)? {
-                    lhs = ExpNode::BinOp(Box::new(lhs), op, Box::new(rhs));
+                    lhs = ExpNode::BinOp(Box::new(lhs), op, token.as_span(), Box::new(rhs));
+
+                    previous_op = Some(token.as_span());
                 } else {
                     let db =
                         session.struct_span_error(token.as_span(), "trailing operator".to_string());
@@ -182,6 +307,10 @@ impl ExpressionParser {
         Self::skip_whitespace(tokens);
         if let Some(mut lhs) = Self::parse_additive_exp(tokens, session)? {
             Self::skip_whitespace(tokens);
+
+            // The operator of the comparison that lhs is, if it was parsed by this loop
+            let mut previous_op: Option<Span> = None;
+
             while let Some(&&token) = tokens.peek() {
                 // Check if it is a relational operator: >, <, >=, or <=
                 let op = match token.kind {
@@ -194,10 +323,20 @@ impl ExpressionParser {
                     }
                 };
 
+                if let Some(previous_span) = previous_op {
+                    return Err(Self::struct_chained_comparison(
+                        session,
+                        previous_span,
+                        token.as_span(),
+                    ));
+                }
+
                 tokens.next();
 
                 if let Some(rhs) = Self::parse_additive_exp(tokens, session)? {
-                    lhs = ExpNode::BinOp(Box::new(lhs), op, Box::new(rhs));
+                    lhs = ExpNode::BinOp(Box::new(lhs), op, token.as_span(), Box::new(rhs));
+
+                    previous_op = Some(token.as_span());
                 } else {
                     let db =
                         session.struct_span_error(token.as_span(), "trailing operator".to_string());
@@ -211,6 +350,26 @@ impl ExpressionParser {
         }
     }
 
+    // Creates the error for a comparison like 1 < x < 3, which would otherwise compare the boolean
+    // result of the first comparison with the last operand
+    fn struct_chained_comparison(
+        session: &Session,
+        previous_span: Span,
+        span: Span,
+    ) -> DiagnosticBuilder<'_> {
+        let mut db = session.struct_span_error(
+            span,
+            "chained comparisons are not allowed; use `&&`".to_string(),
+        );
+
+        db.span_label(
+            previous_span,
+            "the result of this comparison would be compared again".to_string(),
+        );
+
+        db
+    }
+
     // Parses an additive expression, or if none exists, parses the next lowest precidence
     fn parse_additive_exp<'a>(tokens: &mut TokenIter, session: &'a Session) -> ExpResult<'a> {
         Self::skip_whitespace(tokens);
@@ -229,7 +388,7 @@ impl ExpressionParser {
                 tokens.next();
 
                 if let Some(rhs) = Self::parse_term(tokens, session)? {
-                    lhs = ExpNode::BinOp(Box::new(lhs), op, Box::new(rhs));
+                    lhs = ExpNode::BinOp(Box::new(lhs), op, token.as_span(), Box::new(rhs));
                 } else {
                     let db =
                         session.struct_span_error(token.as_span(), "trailing operator".to_string());
@@ -261,7 +420,7 @@ impl ExpressionParser {
                 tokens.next();
 
                 if let Some(rhs) = Self::parse_factor(tokens, session)? {
-                    lhs = ExpNode::BinOp(Box::new(lhs), op, Box::new(rhs));
+                    lhs = ExpNode::BinOp(Box::new(lhs), op, token.as_span(), Box::new(rhs));
                 } else {
                     let db =
                         session.struct_span_error(token.as_span(), "trailing operator".to_string());
@@ -283,12 +442,11 @@ impl ExpressionParser {
             match token.kind {
                 // (
                 TokenKind::SymbolLeftParen => {
-                    let inner_expression = Self::parse_expression(tokens, session, true)?;
+                    let inner_expression = Self::parse_with_symbols(tokens, session, true)?;
 
                     Self::skip_whitespace(tokens);
                     if let Some(next) = tokens.next() {
                         if next.kind != TokenKind::SymbolRightParen {
-                            println!("Token was: {:?}", next);
                             // Error
                             let db = session.struct_span_error(
                                 next.as_span(),
@@ -318,8 +476,23 @@ impl ExpressionParser {
                         _ => unreachable!(),
                     };
 
+                    if op == UnOp::Negate {
+                        Self::skip_whitespace(tokens);
+
+                        if let Some(&&literal) = tokens
+                            .peek()
+                            .filter(|next| is_i32_min_magnitude(next, session))
+                        {
+                            tokens.next();
+
+                            let span = token.as_span().join(literal.as_span());
+
+                            return Ok(Some(ExpNode::Constant(Value::Int(i32::MIN), span)));
+                        }
+                    }
+
                     if let Some(factor) = Self::parse_factor(tokens, session)? {
-                        Ok(Some(ExpNode::UnOp(op, Box::new(factor))))
+                        Ok(Some(ExpNode::UnOp(op, token.as_span(), Box::new(factor))))
                     } else {
                         let db = session.struct_span_error(
                             token.as_span(),
@@ -330,16 +503,15 @@ impl ExpressionParser {
                     }
                 }
                 TokenKind::LiteralInteger | TokenKind::LiteralHex | TokenKind::LiteralBinary => {
-                    let value_snippet = session.span_to_snippet(&token.as_span());
-                    let value_str = value_snippet.as_slice();
+                    let value_str = token.resolve(session);
 
                     if let Ok(value) = match token.kind {
-                        TokenKind::LiteralInteger => parse_integer_literal(value_str),
-                        TokenKind::LiteralHex => parse_hexadecimal_literal(value_str),
-                        TokenKind::LiteralBinary => parse_binary_literal(value_str),
+                        TokenKind::LiteralInteger => parse_integer_literal(&value_str),
+                        TokenKind::LiteralHex => parse_hexadecimal_literal(&value_str),
+                        TokenKind::LiteralBinary => parse_binary_literal(&value_str),
                         _ => unreachable!(),
                     } {
-                        Ok(Some(ExpNode::Constant(Value::Int(value))))
+                        Ok(Some(ExpNode::Constant(Value::Int(value), token.as_span())))
                     } else {
                         let db = session.struct_span_error(
                             token.as_span(),
@@ -350,31 +522,47 @@ impl ExpressionParser {
                     }
                 }
                 TokenKind::LiteralFloat => {
-                    let value_snippet = session.span_to_snippet(&token.as_span());
-                    let value_str = value_snippet.as_slice();
+                    let value_str = token.resolve(session);
 
-                    if let Ok(value) = parse_float_literal(value_str) {
-                        Ok(Some(ExpNode::Constant(Value::Double(value))))
+                    if let Ok(value) = parse_float_literal(&value_str) {
+                        Ok(Some(ExpNode::Constant(
+                            Value::Double(value),
+                            token.as_span(),
+                        )))
                     } else {
-                        let db = session.struct_bug(format!("error parsing float {}", value_str));
+                        let db = session.struct_span_error(
+                            token.as_span(),
+                            "invalid floating point literal".to_string(),
+                        );
 
                         Err(db)
                     }
                 }
                 TokenKind::LiteralTrue | TokenKind::LiteralFalse => Ok(Some(ExpNode::Constant(
                     Value::Bool(token.kind == TokenKind::LiteralTrue),
+                    token.as_span(),
                 ))),
-                _ => {
-                    let mut db = session
-                        .struct_error("expected parenthesis, constant, or operator".to_string());
-
-                    db.span_label(token.as_span(), "found invalid token".to_string());
-
-                    Err(db)
-                }
+                TokenKind::Identifier => Ok(Some(ExpNode::Symbol(token.as_span()))),
+                TokenKind::LiteralString => match parse_string_literal(&token.resolve(session)) {
+                    Ok(value) => Ok(Some(ExpNode::Constant(
+                        Value::String(value),
+                        token.as_span(),
+                    ))),
+                    Err(escape) => Err(struct_escape_error(session, token.as_span(), escape)),
+                },
+                _ => Err(Self::struct_invalid_token(session, token.as_span())),
             }
         } else {
             Ok(None)
         }
     }
+
+    fn struct_invalid_token(session: &Session, span: Span) -> DiagnosticBuilder<'_> {
+        let mut db =
+            session.struct_error("expected parenthesis, constant, or operator".to_string());
+
+        db.span_label(span, "found invalid token".to_string());
+
+        db
+    }
 }
@@ -1,9 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::Span;
 
 use super::past::{MLMacroArgs, MLMacroDef, MLMacroUndef, MacroInvok, SLMacroDef, SLMacroUndef};
 
 pub struct SLMacroMap {
     map: HashMap<(u64, u8), SLMacroDef>,
+    used: HashSet<(u64, u8)>,
+    // The identifier spans of definitions that were replaced or undefined without being used
+    discarded_unused: Vec<Span>,
 }
 
 impl Default for SLMacroMap {
@@ -16,6 +21,8 @@ impl SLMacroMap {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            used: HashSet::new(),
+            discarded_unused: Vec::new(),
         }
     }
 
@@ -26,14 +33,55 @@ impl SLMacroMap {
             None => 0,
         };
 
-        self.map.insert((hash, args), sl_macro);
+        if let Some(replaced) = self.map.insert((hash, args), sl_macro) {
+            self.discard((hash, args), replaced);
+        }
     }
 
     pub fn undefine(&mut self, sl_macro_undef: SLMacroUndef) {
         let hash = sl_macro_undef.identifier.hash;
         let args = sl_macro_undef.args.num;
 
-        self.map.remove(&(hash, args));
+        if let Some(removed) = self.map.remove(&(hash, args)) {
+            self.discard((hash, args), removed);
+        }
+    }
+
+    // Keeps track of a definition that is no longer in the map, if it was never used
+    fn discard(&mut self, key: (u64, u8), sl_macro: SLMacroDef) {
+        if !self.used.remove(&key) {
+            self.discarded_unused.push(sl_macro.identifier.span);
+        }
+    }
+
+    /// Records that the single-line macro with the identifier hash and number of arguments was
+    /// used, if it is defined
+    pub fn mark_used(&mut self, hash: u64, num_args: u8) {
+        if self.map.contains_key(&(hash, num_args)) {
+            self.used.insert((hash, num_args));
+        }
+    }
+
+    /// Records that every currently defined single-line macro was used
+    pub fn mark_all_used(&mut self) {
+        self.used.extend(self.map.keys());
+    }
+
+    /// Returns the identifier spans of every single-line macro definition that was never used,
+    /// in the order they appear in the source
+    pub fn unused(&self) -> Vec<Span> {
+        let mut unused = self.discarded_unused.clone();
+
+        unused.extend(
+            self.map
+                .iter()
+                .filter(|(key, _)| !self.used.contains(key))
+                .map(|(_, sl_macro)| sl_macro.identifier.span),
+        );
+
+        unused.sort_by_key(|span| (span.file, span.start));
+
+        unused
     }
 
     pub fn get(&self, invokation: &MacroInvok) -> Option<&SLMacroDef> {
@@ -115,7 +163,10 @@ impl SLMacroMap {
 }
 
 pub struct MLMacroMap {
-    macros: Vec<(u64, MLMacroDef)>,
+    // The identifier hash of each macro, the macro, and if it has been used
+    macros: Vec<(u64, MLMacroDef, bool)>,
+    // The identifier spans of definitions that were replaced or undefined without being used
+    discarded_unused: Vec<Span>,
 }
 
 impl Default for MLMacroMap {
@@ -127,26 +178,30 @@ impl Default for MLMacroMap {
 impl MLMacroMap {
     /// Creates a new empty MLMacroMap
     pub fn new() -> Self {
-        Self { macros: Vec::new() }
+        Self {
+            macros: Vec::new(),
+            discarded_unused: Vec::new(),
+        }
     }
 
-    /// Defines a new multi-line macro. This function returns true if this macro was redefined, and
-    /// false otherwise.
-    pub fn define(&mut self, ml_macro: MLMacroDef) -> bool {
+    /// Defines a new multi-line macro. If this macro replaces one with an overlapping number of
+    /// arguments, the previous definition is returned
+    pub fn define(&mut self, ml_macro: MLMacroDef) -> Option<MLMacroDef> {
         let hash = ml_macro.identifier.hash;
 
         let replace_index = self.find(hash, &ml_macro.args);
 
-        if let Some(replace_index) = replace_index {
-            self.macros.swap_remove(replace_index);
-            self.macros.push((hash, ml_macro));
+        let replaced = replace_index.map(|replace_index| {
+            let (_, replaced, used) = self.macros.swap_remove(replace_index);
 
-            true
-        } else {
-            self.macros.push((hash, ml_macro));
+            self.discard(&replaced, used);
 
-            false
-        }
+            replaced
+        });
+
+        self.macros.push((hash, ml_macro, false));
+
+        replaced
     }
 
     /// Undefines a multi-line macro if it exists
@@ -156,10 +211,51 @@ impl MLMacroMap {
         let index = self.find(hash, &Some(ml_macro_undef.args));
 
         if let Some(index) = index {
-            self.macros.swap_remove(index);
+            let (_, removed, used) = self.macros.swap_remove(index);
+
+            self.discard(&removed, used);
+        }
+    }
+
+    // Keeps track of a definition that is no longer in the map, if it was never used
+    fn discard(&mut self, ml_macro: &MLMacroDef, used: bool) {
+        if !used {
+            self.discarded_unused.push(ml_macro.identifier.span);
         }
     }
 
+    /// Records that the multi-line macro that matches the invokation was used, if there is one
+    pub fn mark_used(&mut self, invokation: &MacroInvok) {
+        if let Some(index) = self.find_invoked(invokation) {
+            self.macros[index].2 = true;
+        }
+    }
+
+    /// Records that the multi-line macro with the identifier hash and argument range was used, if
+    /// it is defined
+    pub fn mark_used_by_args(&mut self, hash: u64, ml_args: &Option<MLMacroArgs>) {
+        if let Some(index) = self.find(hash, ml_args) {
+            self.macros[index].2 = true;
+        }
+    }
+
+    /// Returns the identifier spans of every multi-line macro definition that was never used, in
+    /// the order they appear in the source
+    pub fn unused(&self) -> Vec<Span> {
+        let mut unused = self.discarded_unused.clone();
+
+        unused.extend(
+            self.macros
+                .iter()
+                .filter(|(_, _, used)| !used)
+                .map(|(_, ml_macro, _)| ml_macro.identifier.span),
+        );
+
+        unused.sort_by_key(|span| (span.file, span.start));
+
+        unused
+    }
+
     /// Returns true if a multi-line macro with the identifier hash and argument range is defined
     /// in the map
     pub fn contains(&self, hash: u64, ml_args: &Option<MLMacroArgs>) -> bool {
@@ -183,6 +279,12 @@ impl MLMacroMap {
     /// Gets a corresponding macro definition to a macro invokation, if it does match any in the
     /// map
     pub fn get(&self, invokation: &MacroInvok) -> Option<&MLMacroDef> {
+        self.find_invoked(invokation)
+            .map(|index| &self.macros[index].1)
+    }
+
+    // Returns the index of the macro that an invokation would expand, or None if there isn't one
+    fn find_invoked(&self, invokation: &MacroInvok) -> Option<usize> {
         let hash = invokation.identifier.hash;
 
         let args = match &invokation.args {
@@ -193,15 +295,9 @@ impl MLMacroMap {
             None => (0, 0),
         };
 
-        for (macro_hash, ml_macro) in self.macros.iter() {
-            let macro_range = Self::get_arg_range(&ml_macro.args);
-
-            if hash == *macro_hash && Self::overlaps(args, macro_range) {
-                return Some(ml_macro);
-            }
-        }
-
-        None
+        self.macros.iter().position(|(macro_hash, ml_macro, _)| {
+            hash == *macro_hash && Self::overlaps(args, Self::get_arg_range(&ml_macro.args))
+        })
     }
 
     // Returns a "range" with the None case being replaced with (0, 0), and the case where there is
@@ -221,7 +317,7 @@ impl MLMacroMap {
         let range = Self::get_arg_range(ml_args);
         let mut replace_index = None;
 
-        for (index, (other_hash, other_macro)) in self.macros.iter().enumerate() {
+        for (index, (other_hash, other_macro, _)) in self.macros.iter().enumerate() {
             let other_range = Self::get_arg_range(&other_macro.args);
 
             if hash == *other_hash && Self::overlaps(range, other_range) {
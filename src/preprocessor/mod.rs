@@ -1,3 +1,4 @@
+pub mod directives;
 pub mod evaluator;
 pub mod executor;
 pub mod expressions;
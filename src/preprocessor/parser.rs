@@ -17,10 +17,10 @@ use crate::{
 };
 
 use super::past::{
-    Ident, IfClause, IfClauseBegin, IfCondition, IfDefCondition, IfExpCondition, Include,
-    IncludePath, MLMacroArgs, MLMacroDefDefaults, MLMacroUndef, MacroInvok, MacroInvokArg,
-    MacroInvokArgs, PASTNode, Repeat, RepeatNumber, SLMacroDefArgs, SLMacroDefContents,
-    SLMacroUndef, SLMacroUndefArgs,
+    Defined, Ident, IfClause, IfClauseBegin, IfCondition, IfDefCondition, IfExpCondition,
+    IfSectionCondition, Include, IncludeLines, IncludePath, Line, MLMacroArgs, MLMacroDefDefaults,
+    MLMacroUndef, MacroInvok, MacroInvokArg, MacroInvokArgs, PASTNode, Repeat, RepeatNumber,
+    SLMacroDefArgs, SLMacroDefContents, SLMacroUndef, SLMacroUndefArgs,
 };
 
 /// The parser for the preprocessor, which turns tokenized source code into preprocessable PASTNodes
@@ -69,16 +69,21 @@ impl<'a> Parser<'a> {
             TokenKind::DirectiveUndef => self.parse_sl_macro_undef(),
             TokenKind::DirectiveUnmacro => self.parse_ml_macro_undef(),
             TokenKind::DirectiveRepeat => self.parse_repeat(),
-            TokenKind::DirectiveInclude => self.parse_include(),
+            TokenKind::DirectiveInclude | TokenKind::DirectiveIncludeDecls => self.parse_include(),
+            TokenKind::DirectiveLine => self.parse_line(),
             TokenKind::DirectiveIf
             | TokenKind::DirectiveIfNot
             | TokenKind::DirectiveIfDef
-            | TokenKind::DirectiveIfNotDef => self.parse_if_statement(next, true, true),
+            | TokenKind::DirectiveIfNotDef
+            | TokenKind::DirectiveIfSection
+            | TokenKind::DirectiveIfNotSection => self.parse_if_statement(next, true, true),
             TokenKind::DirectiveElseIf
             | TokenKind::DirectiveElse
             | TokenKind::DirectiveElseIfDef
             | TokenKind::DirectiveElseIfNot
             | TokenKind::DirectiveElseIfNotDef
+            | TokenKind::DirectiveElseIfSection
+            | TokenKind::DirectiveElseIfNotSection
             | TokenKind::DirectiveEndIf => {
                 self.session
                     .struct_span_error(
@@ -90,17 +95,16 @@ impl<'a> Parser<'a> {
                 Err(())
             }
             TokenKind::Identifier => {
-                let snippet = self.session.span_to_snippet(&next.as_span());
-                let ident_str = snippet.as_slice();
+                let ident_str = next.resolve(self.session);
                 self.consume_next();
 
                 // Tests if this is an instruction or not
-                if Opcode::from(ident_str) != Opcode::Bogus {
+                if self.session.is_instruction(&ident_str) {
                     // If it is, we parse it as such
                     self.parse_benign_tokens(next)
                 } else {
                     // If it isn't, it is going to be parsed as a macro invokation
-                    let macro_invok = self.parse_macro_invok(next.as_span(), ident_str)?;
+                    let macro_invok = self.parse_macro_invok(next.as_span(), &ident_str)?;
 
                     // If we have captured any tokens before this
                     // Update this just in case it is the last part of the contents
@@ -137,44 +141,34 @@ impl<'a> Parser<'a> {
         let mut clauses = Vec::new();
         let mut else_encountered = false;
 
-        let (first_clause, end_kind) = self.parse_if_clause(token, allow_preprocessor)?;
-        clauses.push(first_clause);
-
-        if end_kind != TokenKind::DirectiveEndIf {
-            if end_kind == TokenKind::DirectiveElse {
-                else_encountered = true;
-            }
-
-            token = *self.consume_next().unwrap();
-
-            loop {
-                // Parse the clause
-                let (if_clause, end_kind) = self.parse_if_clause(token, allow_preprocessor)?;
+        loop {
+            // Parse the clause
+            let (if_clause, end_kind) = self.parse_if_clause(token, allow_preprocessor)?;
 
-                if else_encountered && !matches!(if_clause.condition, IfCondition::Else) {
-                    self.session
-                        .struct_span_error(
-                            if_clause.begin.span,
-                            ".endif expected after .else clause".to_string(),
-                        )
-                        .emit();
+            // Nothing can come after an .else, including another .else
+            if else_encountered {
+                self.session
+                    .struct_span_error(
+                        if_clause.begin.span,
+                        ".endif expected after .else clause".to_string(),
+                    )
+                    .emit();
 
-                    return Err(());
-                }
+                return Err(());
+            }
 
-                // Add it
-                clauses.push(if_clause);
+            if matches!(if_clause.condition, IfCondition::Else) {
+                else_encountered = true;
+            }
 
-                // If it isn't the end, set the next token
-                if end_kind != TokenKind::DirectiveEndIf {
-                    if end_kind == TokenKind::DirectiveElse {
-                        else_encountered = true;
-                    }
+            // Add it
+            clauses.push(if_clause);
 
-                    token = *self.consume_next().unwrap();
-                } else {
-                    break;
-                }
+            // If it isn't the end, set the next token
+            if end_kind != TokenKind::DirectiveEndIf {
+                token = *self.consume_next().unwrap();
+            } else {
+                break;
             }
         }
 
@@ -202,24 +196,29 @@ impl<'a> Parser<'a> {
                 | TokenKind::DirectiveRepeat
                 | TokenKind::DirectiveEndRepeat
                 | TokenKind::DirectiveInclude
+                | TokenKind::DirectiveIncludeDecls
+                | TokenKind::DirectiveLine
                 | TokenKind::DirectiveIf
                 | TokenKind::DirectiveIfDef
                 | TokenKind::DirectiveIfNot
                 | TokenKind::DirectiveIfNotDef
+                | TokenKind::DirectiveIfSection
+                | TokenKind::DirectiveIfNotSection
                 | TokenKind::DirectiveElse
                 | TokenKind::DirectiveElseIf
                 | TokenKind::DirectiveElseIfDef
                 | TokenKind::DirectiveElseIfNot
                 | TokenKind::DirectiveElseIfNotDef
+                | TokenKind::DirectiveElseIfSection
+                | TokenKind::DirectiveElseIfNotSection
                 | TokenKind::DirectiveEndIf => {
                     break;
                 }
                 TokenKind::Identifier => {
-                    let snippet = self.session.span_to_snippet(&next.as_span());
-                    let ident_str = snippet.as_slice();
+                    let ident_str = next.resolve(self.session);
 
                     // Tests if this is an instruction or not
-                    if Opcode::from(ident_str) != Opcode::Bogus {
+                    if self.session.is_instruction(&ident_str) {
                         // It is, which is "benign"
                         tokens.push(next);
 
@@ -251,15 +250,12 @@ impl<'a> Parser<'a> {
         if_token: Token,
         allow_preprocessor: bool,
     ) -> PResult<(IfClause, TokenKind)> {
-        let mut span = Span::new(0, 0, 0);
         let begin = self.parse_if_clause_begin(if_token)?;
+        let mut span = begin.span;
         let condition = self.parse_if_condition(if_token)?;
         let mut contents = Vec::new();
         let mut end_kind = TokenKind::Error;
 
-        span.start = begin.span.start;
-        span.file = begin.span.file;
-
         // Two different loops is pretty bad, but it avoids checking the allow_preprocessor flag
         // every loop
         if allow_preprocessor {
@@ -270,32 +266,38 @@ impl<'a> Parser<'a> {
                     TokenKind::DirectiveUndef => self.parse_sl_macro_undef(),
                     TokenKind::DirectiveUnmacro => self.parse_ml_macro_undef(),
                     TokenKind::DirectiveRepeat => self.parse_repeat(),
-                    TokenKind::DirectiveInclude => self.parse_include(),
+                    TokenKind::DirectiveInclude | TokenKind::DirectiveIncludeDecls => {
+                        self.parse_include()
+                    }
+                    TokenKind::DirectiveLine => self.parse_line(),
                     TokenKind::DirectiveIf
                     | TokenKind::DirectiveIfNot
                     | TokenKind::DirectiveIfDef
-                    | TokenKind::DirectiveIfNotDef => self.parse_if_statement(next, true, true),
+                    | TokenKind::DirectiveIfNotDef
+                    | TokenKind::DirectiveIfSection
+                    | TokenKind::DirectiveIfNotSection => self.parse_if_statement(next, true, true),
                     TokenKind::DirectiveEndIf
                     | TokenKind::DirectiveElse
                     | TokenKind::DirectiveElseIf
                     | TokenKind::DirectiveElseIfDef
                     | TokenKind::DirectiveElseIfNot
-                    | TokenKind::DirectiveElseIfNotDef => {
+                    | TokenKind::DirectiveElseIfNotDef
+                    | TokenKind::DirectiveElseIfSection
+                    | TokenKind::DirectiveElseIfNotSection => {
                         end_kind = next.kind;
                         break;
                     }
                     TokenKind::Identifier => {
-                        let snippet = self.session.span_to_snippet(&next.as_span());
-                        let ident_str = snippet.as_slice();
+                        let ident_str = next.resolve(self.session);
                         self.consume_next();
 
                         // Tests if this is an instruction or not
-                        if Opcode::from(ident_str) != Opcode::Bogus {
+                        if self.session.is_instruction(&ident_str) {
                             // If it is, we parse it as such
                             self.parse_benign_tokens(next)
                         } else {
                             // If it isn't, it is going to be parsed as a macro invokation
-                            let macro_invok = self.parse_macro_invok(next.as_span(), ident_str)?;
+                            let macro_invok = self.parse_macro_invok(next.as_span(), &ident_str)?;
 
                             // If we have captured any tokens before this
                             // Update this just in case it is the last part of the contents
@@ -308,7 +310,7 @@ impl<'a> Parser<'a> {
                     }
                 }?;
 
-                span.end = node.span_end();
+                span = span.join(node.span());
 
                 contents.push(node);
             }
@@ -322,7 +324,9 @@ impl<'a> Parser<'a> {
                     | TokenKind::DirectiveUnmacro
                     | TokenKind::DirectiveRepeat
                     | TokenKind::DirectiveEndRepeat
-                    | TokenKind::DirectiveInclude => {
+                    | TokenKind::DirectiveInclude
+                    | TokenKind::DirectiveIncludeDecls
+                    | TokenKind::DirectiveLine => {
                         self.session
                             .struct_span_error(
                                 next.as_span(),
@@ -332,32 +336,38 @@ impl<'a> Parser<'a> {
 
                         return Err(());
                     }
+                    // Nested conditionals are under the same restrictions as this one
                     TokenKind::DirectiveIf
                     | TokenKind::DirectiveIfNot
                     | TokenKind::DirectiveIfDef
-                    | TokenKind::DirectiveIfNotDef => self.parse_if_statement(next, true, true),
+                    | TokenKind::DirectiveIfNotDef
+                    | TokenKind::DirectiveIfSection
+                    | TokenKind::DirectiveIfNotSection => {
+                        self.parse_if_statement(next, true, false)
+                    }
                     TokenKind::DirectiveEndIf
                     | TokenKind::DirectiveElse
                     | TokenKind::DirectiveElseIf
                     | TokenKind::DirectiveElseIfDef
                     | TokenKind::DirectiveElseIfNot
-                    | TokenKind::DirectiveElseIfNotDef => {
+                    | TokenKind::DirectiveElseIfNotDef
+                    | TokenKind::DirectiveElseIfSection
+                    | TokenKind::DirectiveElseIfNotSection => {
                         end_kind = next.kind;
                         break;
                     }
                     TokenKind::Identifier => {
-                        let snippet = self.session.span_to_snippet(&next.as_span());
-                        let ident_str = snippet.as_slice();
+                        let ident_str = next.resolve(self.session);
 
                         self.consume_next();
 
                         // Tests if this is an instruction or not
-                        if Opcode::from(ident_str) != Opcode::Bogus {
+                        if self.session.is_instruction(&ident_str) {
                             // If it is, we parse it as such
                             self.parse_benign_tokens(next)
                         } else {
                             // If it isn't, it is going to be parsed as a macro invokation
-                            let macro_invok = self.parse_macro_invok(next.as_span(), ident_str)?;
+                            let macro_invok = self.parse_macro_invok(next.as_span(), &ident_str)?;
 
                             // If we have captured any tokens before this
                             // Update this just in case it is the last part of the contents
@@ -370,7 +380,7 @@ impl<'a> Parser<'a> {
                     }
                 }?;
 
-                span.end = node.span_end();
+                span = span.join(node.span());
 
                 contents.push(node);
             }
@@ -395,13 +405,16 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_if_clause_begin(&mut self, if_token: Token) -> PResult<IfClauseBegin> {
-        let inverse = !matches!(
+        // Only the negated spellings are listed, so that a new kind of clause isn't inverted by
+        // default
+        let inverse = matches!(
             if_token.kind,
-            TokenKind::DirectiveIf
-                | TokenKind::DirectiveIfDef
-                | TokenKind::DirectiveElseIf
-                | TokenKind::DirectiveElseIfDef
-                | TokenKind::DirectiveElse
+            TokenKind::DirectiveIfNot
+                | TokenKind::DirectiveIfNotDef
+                | TokenKind::DirectiveIfNotSection
+                | TokenKind::DirectiveElseIfNot
+                | TokenKind::DirectiveElseIfNotDef
+                | TokenKind::DirectiveElseIfNotSection
         );
 
         let span = if_token.as_span();
@@ -415,6 +428,12 @@ impl<'a> Parser<'a> {
             | TokenKind::DirectiveIfNotDef
             | TokenKind::DirectiveElseIfDef
             | TokenKind::DirectiveElseIfNotDef => IfCondition::Def(self.parse_if_def_condition()?),
+            TokenKind::DirectiveIfSection
+            | TokenKind::DirectiveIfNotSection
+            | TokenKind::DirectiveElseIfSection
+            | TokenKind::DirectiveElseIfNotSection => {
+                IfCondition::Section(self.parse_if_section_condition(if_token.as_span())?)
+            }
             TokenKind::DirectiveElse => {
                 self.parse_if_else_condition()?;
                 IfCondition::Else
@@ -440,8 +459,6 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_if_def_condition(&mut self) -> PResult<IfDefCondition> {
-        let mut span = Span::new(0, 0, 0);
-
         self.skip_whitespace();
 
         // We need an identifier, that is the entire idea of an .ifdef
@@ -450,21 +467,88 @@ impl<'a> Parser<'a> {
         // A number of arguments is optional though
         let args = self.parse_ml_macro_args()?;
 
-        span.start = identifier.span.start;
-        span.file = identifier.span.file;
-
-        if let Some(args) = &args {
-            span.end = args.span.end;
-        } else {
-            span.end = identifier.span.end;
-        }
+        let span = match &args {
+            Some(args) => identifier.span.join(args.span),
+            None => identifier.span,
+        };
 
         Ok(IfDefCondition::new(span, identifier, args))
     }
 
-    fn parse_if_exp_condition(&mut self, if_token: Token) -> PResult<IfExpCondition> {
-        let mut span = Span::new(0, 0, 0);
+    // Parses the section after an .ifsection, which is named the same way as it would be in a
+    // .section directive. The standard sections can also be written as just `text` or `data`
+    fn parse_if_section_condition(&mut self, if_span: Span) -> PResult<IfSectionCondition> {
+        self.skip_whitespace();
+
+        let token = match self.consume_next() {
+            Some(&token) if token.kind != TokenKind::Newline => token,
+            _ => {
+                self.session
+                    .struct_span_error(if_span, "expected section name".to_string())
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        let section = match token.kind {
+            TokenKind::KeywordText => String::from(".text"),
+            TokenKind::KeywordData => String::from(".data"),
+            TokenKind::Identifier | TokenKind::InnerLabelReference => {
+                let name = token.resolve(self.session);
+
+                match name.as_str() {
+                    "text" => String::from(".text"),
+                    "data" => String::from(".data"),
+                    _ => name,
+                }
+            }
+            _ => {
+                self.struct_err_expected_found(token.as_span(), "section name")
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        Ok(IfSectionCondition::new(token.as_span(), section))
+    }
+
+    // Parses the `(NAME)` after `defined` in an .if expression
+    fn parse_defined(&mut self, defined_span: Span) -> PResult<Defined> {
+        self.assert_next(TokenKind::SymbolLeftParen)?;
+        self.skip_whitespace();
+
+        let identifier = self.parse_ident()?;
+
+        self.skip_whitespace();
+
+        match self.consume_next() {
+            Some(&token) if token.kind == TokenKind::SymbolRightParen => {
+                let condition = IfDefCondition::new(identifier.span, identifier, None);
+
+                Ok(Defined::new(defined_span.join(token.as_span()), condition))
+            }
+            Some(&token) if token.kind != TokenKind::Newline => {
+                self.struct_err_expected_found(token.as_span(), "`)` after macro name")
+                    .emit();
 
+                Err(())
+            }
+            _ => {
+                self.session
+                    .struct_span_error(
+                        defined_span.join(identifier.span),
+                        "expected `)` after macro name".to_string(),
+                    )
+                    .emit();
+
+                Err(())
+            }
+        }
+    }
+
+    fn parse_if_exp_condition(&mut self, if_token: Token) -> PResult<IfExpCondition> {
         let mut expression = Vec::new();
 
         let mut benign_tokens = Vec::new();
@@ -488,15 +572,21 @@ impl<'a> Parser<'a> {
                 | TokenKind::DirectiveRepeat
                 | TokenKind::DirectiveEndRepeat
                 | TokenKind::DirectiveInclude
+                | TokenKind::DirectiveIncludeDecls
+                | TokenKind::DirectiveLine
                 | TokenKind::DirectiveIf
                 | TokenKind::DirectiveIfDef
                 | TokenKind::DirectiveIfNot
                 | TokenKind::DirectiveIfNotDef
+                | TokenKind::DirectiveIfSection
+                | TokenKind::DirectiveIfNotSection
                 | TokenKind::DirectiveElse
                 | TokenKind::DirectiveElseIf
                 | TokenKind::DirectiveElseIfDef
                 | TokenKind::DirectiveElseIfNot
                 | TokenKind::DirectiveElseIfNotDef
+                | TokenKind::DirectiveElseIfSection
+                | TokenKind::DirectiveElseIfNotSection
                 | TokenKind::DirectiveEndIf => {
                     self.session
                         .struct_span_error(token.as_span(), "Expected condition".to_string())
@@ -505,19 +595,34 @@ impl<'a> Parser<'a> {
                     return Err(());
                 }
                 TokenKind::Identifier => {
-                    let snippet = self.session.span_to_snippet(&token.as_span());
-                    let ident_str = snippet.as_slice();
+                    let ident_str = token.resolve(self.session);
+
+                    let is_defined_check = ident_str == "defined"
+                        && self
+                            .peek_next()
+                            .is_some_and(|next| next.kind == TokenKind::SymbolLeftParen);
+
+                    if is_defined_check {
+                        let defined = self.parse_defined(token.as_span())?;
+
+                        // If we have captured any tokens before this
+                        if !benign_tokens.is_empty() {
+                            let benign_tokens_node = BenignTokens::from_vec(benign_tokens);
+                            expression.push(PASTNode::BenignTokens(benign_tokens_node));
 
+                            benign_tokens = Vec::new();
+                        }
+
+                        expression.push(PASTNode::Defined(defined));
+                    }
                     // Tests if this is an instruction or not
-                    if Opcode::from(ident_str) != Opcode::Bogus {
+                    else if self.session.is_instruction(&ident_str) {
                         // If it is
                         // Just push it
                         benign_tokens.push(token);
-
-                        span.end = token.as_span().end;
                     } else {
                         // If it isn't, it is going to be parsed as a macro invokation
-                        let macro_invok = self.parse_macro_invok(token.as_span(), ident_str)?;
+                        let macro_invok = self.parse_macro_invok(token.as_span(), &ident_str)?;
 
                         // If we have captured any tokens before this
                         if !benign_tokens.is_empty() {
@@ -527,18 +632,12 @@ impl<'a> Parser<'a> {
                             benign_tokens = Vec::new();
                         }
 
-                        // Update this just in case it is the last part of the contents
-                        span.end = macro_invok.span.end;
-
                         expression.push(PASTNode::MacroInvok(macro_invok));
                     }
                 }
                 _ => {
                     // Just push this, it is allowed and not special
                     benign_tokens.push(token);
-
-                    // Just in case this is the last one
-                    span.end = token.as_span().end;
                 }
             }
         }
@@ -560,6 +659,9 @@ impl<'a> Parser<'a> {
             return Err(());
         }
 
+        let first_span = expression.first().unwrap().span();
+        let span = first_span.join(expression.last().unwrap().span());
+
         // We didn't end with a newline, we ended with an EOF
         if !ended {
             // At this point due to the upper check, we are guaranteed to have a valid span
@@ -578,33 +680,29 @@ impl<'a> Parser<'a> {
     // See the MLMacroDef grammar
     //
     fn parse_ml_macro_def(&mut self) -> PResult<PASTNode> {
-        let mut span = Span::new(0, 0, 0);
-
         // Consume the .macro
         let macro_span = self.assert_next(TokenKind::DirectiveMacro)?;
 
-        // Copy the span values
-        span.start = macro_span.start;
-        span.file = macro_span.file;
-
         // Skip whitespace
         self.skip_whitespace();
 
         let identifier = self.parse_ident()?;
+        let mut span = macro_span.join(identifier.span);
 
         // Capture the macro arguments
         let args = self.parse_ml_macro_args()?;
 
         let defaults = if let Some(args) = &args {
             // Update this to be passed in
-            span.end = args.span.end;
+            span = span.join(args.span);
 
             if let Some(maximum) = args.maximum {
                 let num_required_defaults = maximum.get() - args.required;
 
-                let defaults = self.parse_ml_macro_defaults(span, num_required_defaults)?;
+                let defaults =
+                    self.parse_ml_macro_defaults(span, args.required, num_required_defaults)?;
 
-                span.end = defaults.span.end;
+                span = span.join(defaults.span);
 
                 Some(defaults)
             } else {
@@ -617,6 +715,27 @@ impl<'a> Parser<'a> {
         // Now we parse the actual contents
         let contents = self.parse_ml_macro_contents(macro_span)?;
 
+        // A body with nothing but whitespace in it expands to nothing, which is almost always a
+        // mistake
+        let is_empty = contents.iter().all(|node| match node {
+            PASTNode::BenignTokens(benign_tokens) => benign_tokens.tokens.iter().all(|token| {
+                token.kind == TokenKind::Whitespace || token.kind == TokenKind::Newline
+            }),
+            _ => false,
+        });
+
+        if is_empty {
+            let ident_snippet = self.session.span_to_snippet(&identifier.span);
+
+            self.session
+                .struct_span_warn(
+                    macro_span.join(identifier.span),
+                    format!("macro `{}` has an empty body", ident_snippet),
+                )
+                .note("invoking it expands to nothing".to_string())
+                .emit();
+        }
+
         Ok(PASTNode::MLMacroDef(MLMacroDef::new(
             span, identifier, args, defaults, contents,
         )))
@@ -626,20 +745,12 @@ impl<'a> Parser<'a> {
     fn parse_ml_macro_contents(&mut self, macro_span: Span) -> PResult<Vec<PASTNode>> {
         let mut contents = Vec::new();
         let mut benign_tokens = Vec::new();
-        let mut span = Span::new(0, 0, 0);
         let mut found_end = false;
 
-        // Parse the first token. We will allow this to immediately be an .endmacro
-        if let Some(&token) = self.consume_next() {
-            if token.kind == TokenKind::DirectiveEndmacro {
-                found_end = true;
-            } else {
-                span.start = token.as_span().start;
-                span.file = token.as_span().file;
-
-                benign_tokens.push(token);
-            }
-        } else {
+        // There has to be something after the arguments, even if it is just the .endmacro. The
+        // first token is left for the loop below, because it can be a directive like .if when
+        // the arguments end the line
+        if self.peek_next().is_none() {
             self.session
                 .struct_span_error(macro_span, "missing accompanying `.endmacro`".to_string())
                 .emit();
@@ -647,40 +758,71 @@ impl<'a> Parser<'a> {
             return Err(());
         }
 
-        if !found_end {
-            while let Some(&token) = self.consume_next() {
-                match token.kind {
-                    TokenKind::DirectiveDefine
-                    | TokenKind::DirectiveMacro
-                    | TokenKind::DirectiveRepeat
-                    | TokenKind::DirectiveEndRepeat
-                    | TokenKind::DirectiveInclude
-                    | TokenKind::DirectiveUndef
-                    | TokenKind::DirectiveElseIf
-                    | TokenKind::DirectiveElseIfNot
-                    | TokenKind::DirectiveElseIfDef
-                    | TokenKind::DirectiveElseIfNotDef
-                    | TokenKind::DirectiveElse
-                    | TokenKind::DirectiveEndIf
-                    | TokenKind::DirectiveUnmacro => {
-                        self.session
-                            .struct_span_error(
-                                token.as_span(),
-                                "not allowed within .macro block".to_string(),
-                            )
-                            .span_label(macro_span, "in macro".to_string())
-                            .emit();
+        while let Some(&token) = self.consume_next() {
+            match token.kind {
+                TokenKind::DirectiveDefine
+                | TokenKind::DirectiveMacro
+                | TokenKind::DirectiveRepeat
+                | TokenKind::DirectiveEndRepeat
+                | TokenKind::DirectiveInclude
+                | TokenKind::DirectiveIncludeDecls
+                | TokenKind::DirectiveLine
+                | TokenKind::DirectiveUndef
+                | TokenKind::DirectiveElseIf
+                | TokenKind::DirectiveElseIfNot
+                | TokenKind::DirectiveElseIfDef
+                | TokenKind::DirectiveElseIfNotDef
+                | TokenKind::DirectiveElseIfSection
+                | TokenKind::DirectiveElseIfNotSection
+                | TokenKind::DirectiveElse
+                | TokenKind::DirectiveEndIf
+                | TokenKind::DirectiveUnmacro => {
+                    self.session
+                        .struct_span_error(
+                            token.as_span(),
+                            "not allowed within .macro block".to_string(),
+                        )
+                        .span_label(macro_span, "in macro".to_string())
+                        .emit();
 
-                        return Err(());
+                    return Err(());
+                }
+                TokenKind::DirectiveIf
+                | TokenKind::DirectiveIfNot
+                | TokenKind::DirectiveIfDef
+                | TokenKind::DirectiveIfNotDef
+                | TokenKind::DirectiveIfSection
+                | TokenKind::DirectiveIfNotSection => {
+                    let if_statement = match self.parse_if_statement(token, false, false)? {
+                        PASTNode::IfStatement(statement) => statement,
+                        _ => unreachable!(),
+                    };
+
+                    // If we have captured any tokens before this
+                    if !benign_tokens.is_empty() {
+                        let benign_tokens_node = BenignTokens::from_vec(benign_tokens);
+                        contents.push(PASTNode::BenignTokens(benign_tokens_node));
+
+                        benign_tokens = Vec::new();
                     }
-                    TokenKind::DirectiveIf
-                    | TokenKind::DirectiveIfNot
-                    | TokenKind::DirectiveIfDef
-                    | TokenKind::DirectiveIfNotDef => {
-                        let if_statement = match self.parse_if_statement(token, false, false)? {
-                            PASTNode::IfStatement(statement) => statement,
-                            _ => unreachable!(),
-                        };
+
+                    contents.push(PASTNode::IfStatement(if_statement));
+                }
+                TokenKind::DirectiveEndmacro => {
+                    found_end = true;
+                    break;
+                }
+                TokenKind::Identifier => {
+                    let ident_str = token.resolve(self.session);
+
+                    // Tests if this is an instruction or not
+                    if self.session.is_instruction(&ident_str) {
+                        // If it is
+                        // Just push it
+                        benign_tokens.push(token);
+                    } else {
+                        // If it isn't, it is going to be parsed as a macro invokation
+                        let macro_invok = self.parse_macro_invok(token.as_span(), &ident_str)?;
 
                         // If we have captured any tokens before this
                         if !benign_tokens.is_empty() {
@@ -690,81 +832,42 @@ impl<'a> Parser<'a> {
                             benign_tokens = Vec::new();
                         }
 
-                        // Update this just in case it is the last part of the contents
-                        span.end = if_statement.span.end;
-
-                        contents.push(PASTNode::IfStatement(if_statement));
-                    }
-                    TokenKind::DirectiveEndmacro => {
-                        found_end = true;
-                        break;
-                    }
-                    TokenKind::Identifier => {
-                        let snippet = self.session.span_to_snippet(&token.as_span());
-                        let ident_str = snippet.as_slice();
-
-                        // Tests if this is an instruction or not
-                        if Opcode::from(ident_str) != Opcode::Bogus {
-                            // If it is
-                            // Just push it
-                            benign_tokens.push(token);
-
-                            span.end = token.as_span().end;
-                        } else {
-                            // If it isn't, it is going to be parsed as a macro invokation
-                            let macro_invok = self.parse_macro_invok(token.as_span(), ident_str)?;
-
-                            // If we have captured any tokens before this
-                            if !benign_tokens.is_empty() {
-                                let benign_tokens_node = BenignTokens::from_vec(benign_tokens);
-                                contents.push(PASTNode::BenignTokens(benign_tokens_node));
-
-                                benign_tokens = Vec::new();
-                            }
-
-                            // Update this just in case it is the last part of the contents
-                            span.end = macro_invok.span.end;
-
-                            contents.push(PASTNode::MacroInvok(macro_invok));
-                        }
+                        contents.push(PASTNode::MacroInvok(macro_invok));
                     }
-                    TokenKind::SymbolAnd => {
-                        benign_tokens.push(token);
-
-                        // We expect an integer literal after this
-                        if let Some(&hopefully_num) = self.consume_next() {
-                            // If there is anything at all
-                            if hopefully_num.kind != TokenKind::LiteralInteger {
-                                if hopefully_num.kind != TokenKind::Newline {
-                                    self.session
-                                        .struct_span_error(
-                                            hopefully_num.as_span(),
-                                            "expected argument number".to_string(),
-                                        )
-                                        .emit();
-                                } else {
-                                    self.session
-                                        .struct_span_error(
-                                            token.as_span(),
-                                            "expected argument number after `&`".to_string(),
-                                        )
-                                        .emit();
-                                }
+                }
+                TokenKind::SymbolAnd => {
+                    benign_tokens.push(token);
 
-                                return Err(());
+                    // We expect an integer literal after this
+                    if let Some(&hopefully_num) = self.consume_next() {
+                        // If there is anything at all
+                        if hopefully_num.kind != TokenKind::LiteralInteger {
+                            if hopefully_num.kind != TokenKind::Newline {
+                                self.session
+                                    .struct_span_error(
+                                        hopefully_num.as_span(),
+                                        "expected argument number".to_string(),
+                                    )
+                                    .emit();
                             } else {
-                                benign_tokens.push(hopefully_num);
+                                self.session
+                                    .struct_span_error(
+                                        token.as_span(),
+                                        "expected argument number after `&`".to_string(),
+                                    )
+                                    .emit();
                             }
-                        }
-                    }
-                    _ => {
-                        // Just push this, it is allowed and not special
-                        benign_tokens.push(token);
 
-                        // Just in case this is the last one
-                        span.end = token.as_span().end;
+                            return Err(());
+                        } else {
+                            benign_tokens.push(hopefully_num);
+                        }
                     }
                 }
+                _ => {
+                    // Just push this, it is allowed and not special
+                    benign_tokens.push(token);
+                }
             }
         }
 
@@ -791,12 +894,15 @@ impl<'a> Parser<'a> {
     fn parse_ml_macro_defaults(
         &mut self,
         err_span: Span,
+        required: u8,
         number: u8,
     ) -> PResult<MLMacroDefDefaults> {
-        let mut defaults = Vec::new();
+        let mut defaults: Vec<BenignTokens> = Vec::new();
 
         // Collect as many as we can
-        while let (Some(default), end) = self.parse_ml_macro_default()? {
+        while let (Some(default), end) =
+            self.parse_ml_macro_default(required as usize + defaults.len() + 1)?
+        {
             defaults.push(default);
 
             if end {
@@ -826,13 +932,14 @@ impl<'a> Parser<'a> {
         }
     }
 
-    // Parse a single multi line macro argument default
+    // Parse a single multi line macro argument default, for the argument at the given position
     //
-    // Only "benign" tokens are allowed. No macro invokations or preprocessor directives
+    // Only "benign" tokens are allowed. No macro invokations or preprocessor directives. Arguments
+    // before this one can be referenced, as they will already be bound when this is expanded
     //
     // Returns a tuple of an Option<BenignTokens> that represents if there was a default to parse
     //
-    fn parse_ml_macro_default(&mut self) -> PResult<(Option<BenignTokens>, bool)> {
+    fn parse_ml_macro_default(&mut self, position: usize) -> PResult<(Option<BenignTokens>, bool)> {
         // Skip whitespace
         self.skip_whitespace();
 
@@ -848,11 +955,10 @@ impl<'a> Parser<'a> {
                 end = true;
                 break;
             } else if token.kind == TokenKind::Identifier {
-                let ident_snippet = self.session.span_to_snippet(&token.as_span());
-                let ident_str = ident_snippet.as_slice();
+                let ident_str = token.resolve(self.session);
 
                 // If this isn't an instruction
-                if Opcode::from(ident_str) == Opcode::Bogus {
+                if !self.session.is_instruction(&ident_str) {
                     self.session
                         .struct_span_error(
                             token.as_span(),
@@ -863,6 +969,40 @@ impl<'a> Parser<'a> {
 
                     return Err(());
                 }
+            } else if token.kind == TokenKind::SymbolAnd {
+                tokens.push(token);
+
+                let arg_ref = match self.consume_next() {
+                    Some(&num) if num.kind == TokenKind::LiteralInteger => num,
+                    _ => {
+                        self.session
+                            .struct_span_error(
+                                token.as_span(),
+                                "expected argument number after `&`".to_string(),
+                            )
+                            .emit();
+
+                        return Err(());
+                    }
+                };
+
+                match parse_integer_literal(&arg_ref.resolve(self.session)) {
+                    Ok(num) if num >= 1 && (num as usize) < position => {}
+                    _ => {
+                        self.session
+                            .struct_span_error(
+                                arg_ref.as_span(),
+                                "argument defaults can only refer to earlier arguments".to_string(),
+                            )
+                            .emit();
+
+                        return Err(());
+                    }
+                }
+
+                tokens.push(arg_ref);
+
+                continue;
             }
 
             tokens.push(token);
@@ -877,48 +1017,209 @@ impl<'a> Parser<'a> {
                     )
                     .emit();
 
-                Err(())
-            } else {
-                Ok((None, end))
-            }
-        } else {
-            Ok((Some(BenignTokens::from_vec(tokens)), end))
+                Err(())
+            } else {
+                Ok((None, end))
+            }
+        } else {
+            Ok((Some(BenignTokens::from_vec(tokens)), end))
+        }
+    }
+
+    // Parse an include directive
+    //
+    // See the Include grammar
+    //
+    fn parse_include(&mut self) -> PResult<PASTNode> {
+        // Consume the .include or .include_decls
+        let include_token = *self.consume_next().unwrap();
+        let include_span = include_token.as_span();
+        let declarations_only = include_token.kind == TokenKind::DirectiveIncludeDecls;
+
+        // Skip any whitespace
+        self.skip_whitespace();
+
+        // This has to be taken out first, because otherwise it would be parsed as part of the path
+        let lines = self.take_include_lines()?;
+
+        // We now require the actual include path
+        if let Some((path_span, expression)) = self.parse_non_preprocessor(&[])? {
+            let span = match &lines {
+                Some(lines) => include_span.join(lines.span),
+                None => include_span.join(path_span),
+            };
+
+            // We got one
+            let path = IncludePath::new(path_span, expression);
+
+            Ok(PASTNode::Include(Include::new(
+                span,
+                path,
+                lines,
+                declarations_only,
+            )))
+        } else {
+            // This is required
+            self.session
+                .struct_span_error(include_span, ".include with no path".to_string())
+                .emit();
+
+            Err(())
+        }
+    }
+
+    // If the current line ends with `lines <first>-<last>`, removes those tokens and returns the
+    // range of lines
+    fn take_include_lines(&mut self) -> PResult<Option<IncludeLines>> {
+        // The positions of the tokens on this line that aren't whitespace
+        let line: Vec<usize> = self.tokens[self.token_cursor..]
+            .iter()
+            .take_while(|token| token.kind != TokenKind::Newline)
+            .enumerate()
+            .filter(|(_, token)| token.kind != TokenKind::Whitespace)
+            .map(|(offset, _)| self.token_cursor + offset)
+            .collect();
+
+        // The path always comes first, so anything named `lines` after it starts the range
+        let lines_position = match line.iter().skip(1).position(|&position| {
+            let token = self.tokens[position];
+
+            token.kind == TokenKind::Identifier && token.resolve(self.session) == "lines"
+        }) {
+            Some(index) => index + 1,
+            None => return Ok(None),
+        };
+
+        let range: Vec<Token> = line[lines_position..]
+            .iter()
+            .map(|&position| self.tokens[position])
+            .collect();
+        let span = range[0].as_span().join(range[range.len() - 1].as_span());
+
+        let (first, last) = match range.as_slice() {
+            [_, first, minus, last]
+                if first.kind == TokenKind::LiteralInteger
+                    && minus.kind == TokenKind::OperatorMinus
+                    && last.kind == TokenKind::LiteralInteger =>
+            {
+                match (
+                    parse_integer_literal(&first.resolve(self.session)),
+                    parse_integer_literal(&last.resolve(self.session)),
+                ) {
+                    (Ok(first), Ok(last)) => (first as usize, last as usize),
+                    _ => {
+                        self.session
+                            .struct_span_error(
+                                span,
+                                "integer value out of bounds for signed 32 bit".to_string(),
+                            )
+                            .emit();
+
+                        return Err(());
+                    }
+                }
+            }
+            _ => {
+                self.session
+                    .struct_span_error(span, "expected a range of lines".to_string())
+                    .help("a range of lines is of the form `lines <first>-<last>`".to_string())
+                    .emit();
+
+                return Err(());
+            }
+        };
+
+        if first < 1 {
+            self.session
+                .struct_span_error(span, "line numbers start at 1".to_string())
+                .emit();
+
+            return Err(());
+        }
+
+        if first > last {
+            self.session
+                .struct_span_error(
+                    span,
+                    format!("line range ends at {}, before it starts at {}", last, first),
+                )
+                .emit();
+
+            return Err(());
         }
+
+        self.tokens
+            .drain(line[lines_position]..=line[line.len() - 1]);
+
+        Ok(Some(IncludeLines::new(span, first, last)))
     }
 
-    // Parse an include directive
+    // Parse a line directive
     //
-    // See the Include grammar
+    // See the Line grammar
     //
-    fn parse_include(&mut self) -> PResult<PASTNode> {
-        let mut span = Span::new(0, 0, 0);
-
-        // Consume the .include
-        let include_span = self.assert_next(TokenKind::DirectiveInclude)?;
-
-        // Copy the span values
-        span.start = include_span.start;
-        span.file = include_span.file;
+    fn parse_line(&mut self) -> PResult<PASTNode> {
+        // Consume the .line
+        let line_span = self.assert_next(TokenKind::DirectiveLine)?;
 
         // Skip any whitespace
         self.skip_whitespace();
 
-        // We now require the actual include path
-        if let Some((path_span, expression)) = self.parse_non_preprocessor(&[])? {
-            span.end = path_span.end;
+        // The line number is required, and can't come from a macro
+        let (number_span, number) = match self.parse_number() {
+            Ok(data) => data,
+            Err((mut db, _)) => {
+                db.help("line directives are of the form .line <number> \"<file>\"".to_string());
+                db.emit();
 
-            // We got one
-            let path = IncludePath::new(path_span, expression);
+                return Err(());
+            }
+        };
 
-            Ok(PASTNode::Include(Include::new(span, path)))
-        } else {
-            // This is required
+        if number < 1 {
             self.session
-                .struct_span_error(include_span, ".include with no path".to_string())
+                .struct_span_error(number_span, "line numbers start at 1".to_string())
                 .emit();
 
-            Err(())
+            return Err(());
+        }
+
+        let mut span = line_span.join(number_span);
+
+        self.skip_whitespace();
+
+        // The file name is optional
+        let mut file = None;
+
+        if let Some(&token) = self.peek_next() {
+            if token.kind == TokenKind::LiteralString {
+                self.consume_next();
+
+                file = Some(token.resolve(self.session).trim_matches('\"').to_string());
+                span = span.join(token.as_span());
+
+                self.skip_whitespace();
+            }
         }
+
+        // The directive must be the only thing on its line
+        let next_line_start = match self.consume_next() {
+            Some(&token) if token.kind == TokenKind::Newline => Some(token.as_span().end),
+            Some(&token) => {
+                self.struct_err_expected_found(token.as_span(), "newline")
+                    .emit();
+
+                return Err(());
+            }
+            None => None,
+        };
+
+        Ok(PASTNode::Line(Line::new(
+            span,
+            number as usize,
+            file,
+            next_line_start,
+        )))
     }
 
     // Parse a repeat directive
@@ -926,24 +1227,67 @@ impl<'a> Parser<'a> {
     // See the Repeat grammar
     //
     fn parse_repeat(&mut self) -> PResult<PASTNode> {
-        let mut span = Span::new(0, 0, 0);
-
         // Consume the .rep
         let rep_span = self.assert_next(TokenKind::DirectiveRepeat)?;
 
-        // Copy the span values
-        span.start = rep_span.start;
-        span.file = rep_span.file;
+        self.parse_repeat_after(rep_span)
+    }
 
+    // Parses the rest of a repeat directive, after the .rep has been consumed
+    fn parse_repeat_after(&mut self, rep_span: Span) -> PResult<PASTNode> {
         // Skip any whitespace
         self.skip_whitespace();
 
+        // This has to be taken out first, because otherwise it would be parsed as part of the number
+        let index = self.take_repeat_index();
+
         // As per the grammar, the next tokens must not contain preprocessor directives
         let number = self.parse_repeat_number(rep_span)?;
+        let span = rep_span.join(number.span);
 
         let contents = self.parse_repeat_contents(rep_span)?;
 
-        Ok(PASTNode::Repeat(Repeat::new(span, number, contents)))
+        Ok(PASTNode::Repeat(Repeat::new(span, number, index, contents)))
+    }
+
+    // If the current line ends with `as <identifier>`, removes those tokens and returns the
+    // identifier
+    fn take_repeat_index(&mut self) -> Option<Ident> {
+        // The positions of the tokens on this line that aren't whitespace
+        let line: Vec<usize> = self.tokens[self.token_cursor..]
+            .iter()
+            .take_while(|token| token.kind != TokenKind::Newline)
+            .enumerate()
+            .filter(|(_, token)| token.kind != TokenKind::Whitespace)
+            .map(|(offset, _)| self.token_cursor + offset)
+            .collect();
+
+        // There has to be at least a number before the `as`
+        let (as_position, index_position) = match line.as_slice() {
+            [_, .., as_position, index_position] => (*as_position, *index_position),
+            _ => return None,
+        };
+
+        let as_token = self.tokens[as_position];
+        let index_token = self.tokens[index_position];
+
+        if as_token.kind != TokenKind::Identifier
+            || index_token.kind != TokenKind::Identifier
+            || as_token.resolve(self.session) != "as"
+        {
+            return None;
+        }
+
+        let span = index_token.as_span();
+        let snippet = self.session.span_to_snippet(&span);
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write(snippet.as_slice().as_bytes());
+        let hash = hasher.finish();
+
+        self.tokens.drain(as_position..=index_position);
+
+        Some(Ident::new(span, hash))
     }
 
     // Parses a repeat preprocessor directive's contents.
@@ -953,18 +1297,14 @@ impl<'a> Parser<'a> {
     fn parse_repeat_contents(&mut self, rep_span: Span) -> PResult<Vec<PASTNode>> {
         let mut contents = Vec::new();
         let mut benign_tokens = Vec::new();
-        let mut span = Span::new(0, 0, 0);
         let mut found_end = false;
 
-        // Parse the first token. We will allow this to immediately be an .endrep
-        if let Some(&token) = self.consume_next() {
+        // Check the first token. We will allow this to immediately be an .endrep
+        if let Some(&token) = self.peek_next() {
             if token.kind == TokenKind::DirectiveEndRepeat {
-                found_end = true;
-            } else {
-                span.start = token.as_span().start;
-                span.file = token.as_span().file;
+                self.consume_next();
 
-                benign_tokens.push(token);
+                found_end = true;
             }
         } else {
             self.session
@@ -980,18 +1320,23 @@ impl<'a> Parser<'a> {
                     TokenKind::DirectiveDefine
                     | TokenKind::DirectiveMacro
                     | TokenKind::DirectiveEndmacro
-                    | TokenKind::DirectiveRepeat
                     | TokenKind::DirectiveInclude
+                    | TokenKind::DirectiveIncludeDecls
+                    | TokenKind::DirectiveLine
                     | TokenKind::DirectiveUndef
                     | TokenKind::DirectiveUnmacro
                     | TokenKind::DirectiveIf
                     | TokenKind::DirectiveIfNot
                     | TokenKind::DirectiveIfDef
                     | TokenKind::DirectiveIfNotDef
+                    | TokenKind::DirectiveIfSection
+                    | TokenKind::DirectiveIfNotSection
                     | TokenKind::DirectiveElseIf
                     | TokenKind::DirectiveElseIfNot
                     | TokenKind::DirectiveElseIfDef
                     | TokenKind::DirectiveElseIfNotDef
+                    | TokenKind::DirectiveElseIfSection
+                    | TokenKind::DirectiveElseIfNotSection
                     | TokenKind::DirectiveElse
                     | TokenKind::DirectiveEndIf => {
                         self.session
@@ -1007,20 +1352,31 @@ impl<'a> Parser<'a> {
                         found_end = true;
                         break;
                     }
+                    TokenKind::DirectiveRepeat => {
+                        let repeat = self.parse_repeat_after(token.as_span())?;
+
+                        // If we have captured any tokens before this
+                        if !benign_tokens.is_empty() {
+                            let benign_tokens_node = BenignTokens::from_vec(benign_tokens);
+                            contents.push(PASTNode::BenignTokens(benign_tokens_node));
+
+                            benign_tokens = Vec::new();
+                        }
+
+                        contents.push(repeat);
+                    }
                     TokenKind::Identifier => {
-                        let snippet = self.session.span_to_snippet(&token.as_span());
-                        let ident_str = snippet.as_slice();
+                        let ident_str = token.resolve(self.session);
 
                         // Tests if this is an instruction or not
-                        if Opcode::from(ident_str) != Opcode::Bogus {
+                        if self.session.is_instruction(&ident_str) {
                             // If it is
                             // Just push it
                             benign_tokens.push(token);
-
-                            span.end = token.as_span().end;
                         } else {
                             // If it isn't, it is going to be parsed as a macro invokation
-                            let macro_invok = self.parse_macro_invok(token.as_span(), ident_str)?;
+                            let macro_invok =
+                                self.parse_macro_invok(token.as_span(), &ident_str)?;
 
                             // If we have captured any tokens before this
                             if !benign_tokens.is_empty() {
@@ -1030,18 +1386,12 @@ impl<'a> Parser<'a> {
                                 benign_tokens = Vec::new();
                             }
 
-                            // Update this just in case it is the last part of the contents
-                            span.end = macro_invok.span.end;
-
                             contents.push(PASTNode::MacroInvok(macro_invok));
                         }
                     }
                     _ => {
                         // Just push this, it is allowed and not special
                         benign_tokens.push(token);
-
-                        // Just in case this is the last one
-                        span.end = token.as_span().end;
                     }
                 }
             }
@@ -1086,15 +1436,9 @@ impl<'a> Parser<'a> {
     // See the MLMacroUndef grammar
     //
     fn parse_ml_macro_undef(&mut self) -> PResult<PASTNode> {
-        let mut span = Span::new(0, 0, 0);
-
         // Consume the .unmacro
         let unmacro_span = self.assert_next(TokenKind::DirectiveUnmacro)?;
 
-        // Copy the span values
-        span.start = unmacro_span.start;
-        span.file = unmacro_span.file;
-
         // Skip any whitespace
         self.skip_whitespace();
 
@@ -1107,8 +1451,7 @@ impl<'a> Parser<'a> {
             None => MLMacroArgs::new(identifier.span, 0, None),
         };
 
-        // Adjust the span
-        span.end = args.span.end;
+        let span = unmacro_span.join(args.span);
 
         Ok(PASTNode::MLMacroUndef(MLMacroUndef::new(
             span, identifier, args,
@@ -1183,14 +1526,12 @@ impl<'a> Parser<'a> {
                 None
             };
 
-            let mut span = Span::new(required_span.start, 0, required_span.file);
-
             if let Some((max_span, max_num)) = maximum {
-                span.end = max_span.end;
+                let span = required_span.join(max_span);
 
                 Ok(Some(MLMacroArgs::new(span, required_num, Some(max_num))))
             } else {
-                Ok(Some(MLMacroArgs::new(span, required_num, None)))
+                Ok(Some(MLMacroArgs::new(required_span, required_num, None)))
             }
         }
         // If we didn't get a number of arguments at all, give the default of 0
@@ -1208,15 +1549,9 @@ impl<'a> Parser<'a> {
     // See the SLMacroUndef grammar
     //
     fn parse_sl_macro_undef(&mut self) -> PResult<PASTNode> {
-        let mut span = Span::new(0, 0, 0);
-
         // Consume the .undef
         let undef_span = self.assert_next(TokenKind::DirectiveUndef)?;
 
-        // Copy the span values
-        span.start = undef_span.start;
-        span.file = undef_span.file;
-
         // Skip any whitespace
         self.skip_whitespace();
 
@@ -1229,8 +1564,7 @@ impl<'a> Parser<'a> {
             None => SLMacroUndefArgs::new(identifier.span, 0),
         };
 
-        // Adjust the span
-        span.end = args.span.end;
+        let span = undef_span.join(args.span);
 
         Ok(PASTNode::SLMacroUndef(SLMacroUndef::new(
             span, identifier, args,
@@ -1271,7 +1605,8 @@ impl<'a> Parser<'a> {
                 if let Some((string, token)) = data {
                     // If we actually got an identifier
                     if token.kind == TokenKind::Identifier {
-                        // If it isn't an instruction
+                        // If it isn't an instruction. The diagnostic still borrows the session, so
+                        // this can't use its opcode table, but this is only reached on an error
                         if Opcode::from(string.as_str()) == Opcode::Bogus {
                             db.help("macros expansions are not allowed here".to_string());
                         }
@@ -1367,14 +1702,9 @@ impl<'a> Parser<'a> {
     // See the SLMacroDef grammar
     //
     fn parse_sl_macro_def(&mut self) -> PResult<PASTNode> {
-        let mut span = Span::new(0, 0, 0);
         // Consume the .define
         let define_span = self.assert_next(TokenKind::DirectiveDefine)?;
 
-        // Copy the span values
-        span.start = define_span.start;
-        span.file = define_span.file;
-
         // Skip any whitespace
         self.skip_whitespace();
 
@@ -1393,8 +1723,8 @@ impl<'a> Parser<'a> {
         let contents = self.parse_sl_macro_def_contents(not_macros)?;
 
         // Adjust this SLMacroDef's span
-        if let Some(contents) = &contents {
-            span.end = contents.span.end;
+        let span = if let Some(contents) = &contents {
+            define_span.join(contents.span)
         } else if let Some(args) = &args {
             // This means we have arguments, but no tokens to expand
             // This is valid, but we should emit a warning
@@ -1403,10 +1733,10 @@ impl<'a> Parser<'a> {
                 .struct_span_warn(args.span, "macro arguments but no expansion".to_string())
                 .emit();
 
-            span.end = args.span.end;
+            define_span.join(args.span)
         } else {
-            span.end = identifier.span.end;
-        }
+            define_span.join(identifier.span)
+        };
 
         Ok(PASTNode::SLMacroDef(SLMacroDef::new(
             span, identifier, args, contents,
@@ -1422,25 +1752,23 @@ impl<'a> Parser<'a> {
                 Ok(None)
             } else {
                 let mut arguments = Vec::new();
-                let mut span = Span::new(0, 0, 0);
 
                 // Consume the (
                 let paren_span = self.assert_next(TokenKind::SymbolLeftParen)?;
 
-                span.start = paren_span.start;
-                span.file = paren_span.file;
-
                 // We have (
                 // Now we parse until we reach a )
-                while let Some(&token) = self.peek_next() {
-                    // Check if it is a )
-                    if token.kind == TokenKind::SymbolRightParen {
-                        break;
-                    }
-
+                loop {
                     // We could have whitespace before this which shouldn't matter
                     self.skip_whitespace();
 
+                    // Check if it is a ), which can also come right after a trailing comma
+                    match self.peek_next() {
+                        Some(token) if token.kind == TokenKind::SymbolRightParen => break,
+                        None => break,
+                        _ => {}
+                    }
+
                     // It should now be an identifier
                     let ident = self.parse_ident()?;
                     arguments.push(ident);
@@ -1475,8 +1803,7 @@ impl<'a> Parser<'a> {
                 } else {
                     // Consume the ) that caused us to stop
                     let right_span = self.assert_next(TokenKind::SymbolRightParen)?;
-
-                    span.end = right_span.end;
+                    let span = paren_span.join(right_span);
 
                     Ok(Some(SLMacroDefArgs::new(span, arguments)))
                 }
@@ -1523,10 +1850,7 @@ impl<'a> Parser<'a> {
                 return Ok(None);
             }
 
-            let mut span = Span::new(0, 0, 0);
-            let first_span = next.as_span();
-            span.file = first_span.file;
-            span.start = first_span.start;
+            let mut span = next.as_span();
 
             let mut nodes = Vec::new();
             let mut benign_tokens = Vec::new();
@@ -1538,12 +1862,16 @@ impl<'a> Parser<'a> {
                     | TokenKind::DirectiveIfNot
                     | TokenKind::DirectiveIfDef
                     | TokenKind::DirectiveIfNotDef
+                    | TokenKind::DirectiveIfSection
+                    | TokenKind::DirectiveIfNotSection
                     | TokenKind::DirectiveEndIf
                     | TokenKind::DirectiveElse
                     | TokenKind::DirectiveElseIf
                     | TokenKind::DirectiveElseIfNot
                     | TokenKind::DirectiveElseIfDef
                     | TokenKind::DirectiveElseIfNotDef
+                    | TokenKind::DirectiveElseIfSection
+                    | TokenKind::DirectiveElseIfNotSection
                     | TokenKind::DirectiveMacro
                     | TokenKind::DirectiveEndmacro
                     | TokenKind::DirectiveRepeat
@@ -1551,7 +1879,9 @@ impl<'a> Parser<'a> {
                     | TokenKind::DirectiveDefine
                     | TokenKind::DirectiveUndef
                     | TokenKind::DirectiveUnmacro
-                    | TokenKind::DirectiveInclude => {
+                    | TokenKind::DirectiveInclude
+                    | TokenKind::DirectiveIncludeDecls
+                    | TokenKind::DirectiveLine => {
                         self.session
                             .struct_span_error(
                                 next.as_span(),
@@ -1563,16 +1893,15 @@ impl<'a> Parser<'a> {
                     }
                     TokenKind::Newline => break,
                     TokenKind::Identifier => {
-                        let snippet = self.session.span_to_snippet(&next.as_span());
-                        let ident_str = snippet.as_slice();
+                        let ident_str = next.resolve(self.session);
 
                         // Tests if this is an instruction or not
-                        if Opcode::from(ident_str) != Opcode::Bogus {
+                        if self.session.is_instruction(&ident_str) {
                             // If it is
                             // Just push it
                             benign_tokens.push(next);
 
-                            span.end = next.as_span().end;
+                            span = span.join(next.as_span());
                         } else {
                             let mut hasher = DefaultHasher::new();
                             hasher.write(ident_str.as_bytes());
@@ -1585,11 +1914,11 @@ impl<'a> Parser<'a> {
                                 benign_tokens.push(next);
 
                                 // Just in case this is the last one
-                                span.end = next.as_span().end;
+                                span = span.join(next.as_span());
                             } else {
                                 // If it isn't, it is going to be parsed as a macro invokation
                                 let macro_invok =
-                                    self.parse_macro_invok(next.as_span(), ident_str)?;
+                                    self.parse_macro_invok(next.as_span(), &ident_str)?;
 
                                 // If we have captured any tokens before this
                                 if !benign_tokens.is_empty() {
@@ -1600,7 +1929,7 @@ impl<'a> Parser<'a> {
                                 }
 
                                 // Update this just in case it is the last part of the contents
-                                span.end = macro_invok.span.end;
+                                span = span.join(macro_invok.span);
 
                                 nodes.push(PASTNode::MacroInvok(macro_invok));
                             }
@@ -1611,7 +1940,7 @@ impl<'a> Parser<'a> {
                         benign_tokens.push(next);
 
                         // Just in case this is the last one
-                        span.end = next.as_span().end;
+                        span = span.join(next.as_span());
                     }
                 }
             }
@@ -1633,8 +1962,6 @@ impl<'a> Parser<'a> {
         let mut hasher = DefaultHasher::new();
         hasher.write(ident_str.as_bytes());
         let hash = hasher.finish();
-        let mut span = Span::new(ident_span.start, 0, ident_span.file);
-
         let identifier = Ident::new(ident_span, hash);
 
         // After the identifier, there could be arguments, or not
@@ -1643,19 +1970,14 @@ impl<'a> Parser<'a> {
                 self.assert_next(TokenKind::SymbolLeftParen)?;
 
                 let args = self.parse_macro_invok_args(token.as_span())?;
-
-                span.end = args.span.end;
+                let span = ident_span.join(args.span);
 
                 Ok(MacroInvok::new(span, identifier, Some(args)))
             } else {
-                span.end = identifier.span.end;
-
-                Ok(MacroInvok::new(span, identifier, None))
+                Ok(MacroInvok::new(ident_span, identifier, None))
             }
         } else {
-            span.end = identifier.span.end;
-
-            Ok(MacroInvok::new(span, identifier, None))
+            Ok(MacroInvok::new(ident_span, identifier, None))
         }
     }
 
@@ -1676,23 +1998,51 @@ impl<'a> Parser<'a> {
         loop {
             let (arg, is_last) = self.parse_macro_invok_arg(paren_span)?;
 
-            args.push(arg);
+            match arg {
+                Some(arg) => args.push(arg),
+                // A trailing comma is allowed, but there has to be at least one argument
+                None if args.is_empty() => {
+                    let close_paren_span = self.last_token.unwrap().as_span();
+
+                    self.session
+                        .struct_span_error(
+                            close_paren_span,
+                            "expected argument before `)`".to_string(),
+                        )
+                        .emit();
+
+                    return Err(());
+                }
+                None => {}
+            }
 
             if is_last {
                 break;
             }
         }
 
-        Ok(MacroInvokArgs::from_vec(args))
+        let mut args = MacroInvokArgs::from_vec(args);
+
+        // Include the `)` in the span, even if it came after a trailing comma
+        args.span = args.span.join(self.last_token.unwrap().as_span());
+
+        Ok(args)
     }
 
     // Parses a single macro invokation argument, ended by a comma, or a `)`
     //
     // No preprocessor directives are allowed as argument parts, but other macro invokations are
-    // allowed.
+    // allowed. Anything between `{` and `}` belongs to the argument, even commas and parentheses,
+    // and the outermost pair of braces is left out of it
     //
-    fn parse_macro_invok_arg(&mut self, paren_span: Span) -> PResult<(MacroInvokArg, bool)> {
-        let mut span = Span::new(0, 0, 0);
+    // Returns None if the argument was empty and ended by a `)`, which happens after a trailing
+    // comma
+    //
+    fn parse_macro_invok_arg(
+        &mut self,
+        paren_span: Span,
+    ) -> PResult<(Option<MacroInvokArg>, bool)> {
+        let mut first_span = None;
 
         let mut contents = Vec::new();
         let mut benign_tokens = Vec::new();
@@ -1700,116 +2050,50 @@ impl<'a> Parser<'a> {
         let mut close_paren_span = None;
         let mut is_last = false;
 
-        if let Some(&token) = self.consume_next() {
-            let token_span = token.as_span();
+        // The spans of the `{` that haven't been closed yet
+        let mut open_braces = Vec::new();
+
+        while let Some(&token) = self.consume_next() {
+            first_span.get_or_insert(token.as_span());
 
             match token.kind {
+                TokenKind::SymbolComma | TokenKind::SymbolRightParen if !open_braces.is_empty() => {
+                    benign_tokens.push(token);
+                }
                 TokenKind::SymbolComma => {
-                    comma_span = Some(token_span);
-
-                    span.start = token_span.start;
-                    span.file = token_span.file;
+                    comma_span = Some(token.as_span());
+                    break;
                 }
                 TokenKind::SymbolRightParen => {
                     close_paren_span = Some(token.as_span());
                     is_last = true;
-
-                    span.start = token_span.start;
-                    span.file = token_span.file;
-                }
-                TokenKind::Newline => {
-                    self.session
-                        .struct_span_error(
-                            paren_span,
-                            "Macro invokation requires closing `)`".to_string(),
-                        )
-                        .emit();
-
-                    return Err(());
-                }
-                TokenKind::DirectiveDefine
-                | TokenKind::DirectiveMacro
-                | TokenKind::DirectiveEndmacro
-                | TokenKind::DirectiveUndef
-                | TokenKind::DirectiveUnmacro
-                | TokenKind::DirectiveRepeat
-                | TokenKind::DirectiveEndRepeat
-                | TokenKind::DirectiveInclude
-                | TokenKind::DirectiveIf
-                | TokenKind::DirectiveIfNot
-                | TokenKind::DirectiveIfDef
-                | TokenKind::DirectiveIfNotDef
-                | TokenKind::DirectiveElseIf
-                | TokenKind::DirectiveElseIfNot
-                | TokenKind::DirectiveElseIfDef
-                | TokenKind::DirectiveElseIfNotDef
-                | TokenKind::DirectiveElse
-                | TokenKind::DirectiveEndIf => {
-                    self.session
-                        .struct_error("Not allowed in macro arguments".to_string())
-                        .span_label(token.as_span(), "found preprocessor directive".to_string())
-                        .emit();
-
-                    return Err(());
+                    break;
                 }
-                TokenKind::Identifier => {
-                    let snippet = self.session.span_to_snippet(&token_span);
-                    let ident_str = snippet.as_slice();
-
-                    // Tests if this is an instruction or not
-                    if Opcode::from(ident_str) != Opcode::Bogus {
-                        // If it is
-                        // Just push it
+                TokenKind::SymbolLeftBrace => {
+                    if !open_braces.is_empty() {
                         benign_tokens.push(token);
-
-                        span.end = token.as_span().end;
-                    } else {
-                        // If it isn't, it is going to be parsed as a macro invokation
-                        let macro_invok = self.parse_macro_invok(token.as_span(), ident_str)?;
-
-                        // If we have captured any tokens before this
-                        if !benign_tokens.is_empty() {
-                            let benign_tokens_node = BenignTokens::from_vec(benign_tokens);
-                            contents.push(PASTNode::BenignTokens(benign_tokens_node));
-
-                            benign_tokens = Vec::new();
-                        }
-
-                        // Update this just in case it is the last part of the contents
-                        span.end = macro_invok.span.end;
-
-                        contents.push(PASTNode::MacroInvok(macro_invok));
                     }
 
-                    span.start = token_span.start;
-                    span.file = token_span.file;
+                    open_braces.push(token.as_span());
                 }
-                _ => {
-                    benign_tokens.push(token);
+                TokenKind::SymbolRightBrace => {
+                    if open_braces.pop().is_none() {
+                        self.session
+                            .struct_span_error(
+                                token.as_span(),
+                                "unmatched `}` in macro argument".to_string(),
+                            )
+                            .emit();
 
-                    span.start = token_span.start;
-                    span.file = token_span.file;
-                }
-            }
-        }
+                        return Err(());
+                    }
 
-        while let Some(&token) = self.consume_next() {
-            match token.kind {
-                TokenKind::SymbolComma => {
-                    comma_span = Some(token.as_span());
-                    break;
-                }
-                TokenKind::SymbolRightParen => {
-                    close_paren_span = Some(token.as_span());
-                    is_last = true;
-                    break;
+                    if !open_braces.is_empty() {
+                        benign_tokens.push(token);
+                    }
                 }
                 TokenKind::Newline => {
-                    self.session
-                        .struct_span_error(
-                            paren_span,
-                            "Macro invokation requires closing `)`".to_string(),
-                        )
+                    self.struct_err_unclosed_argument(paren_span, &open_braces)
                         .emit();
 
                     return Err(());
@@ -1822,14 +2106,20 @@ impl<'a> Parser<'a> {
                 | TokenKind::DirectiveRepeat
                 | TokenKind::DirectiveEndRepeat
                 | TokenKind::DirectiveInclude
+                | TokenKind::DirectiveIncludeDecls
+                | TokenKind::DirectiveLine
                 | TokenKind::DirectiveIf
                 | TokenKind::DirectiveIfNot
                 | TokenKind::DirectiveIfDef
                 | TokenKind::DirectiveIfNotDef
+                | TokenKind::DirectiveIfSection
+                | TokenKind::DirectiveIfNotSection
                 | TokenKind::DirectiveElseIf
                 | TokenKind::DirectiveElseIfNot
                 | TokenKind::DirectiveElseIfDef
                 | TokenKind::DirectiveElseIfNotDef
+                | TokenKind::DirectiveElseIfSection
+                | TokenKind::DirectiveElseIfNotSection
                 | TokenKind::DirectiveElse
                 | TokenKind::DirectiveEndIf => {
                     self.session
@@ -1840,19 +2130,16 @@ impl<'a> Parser<'a> {
                     return Err(());
                 }
                 TokenKind::Identifier => {
-                    let snippet = self.session.span_to_snippet(&token.as_span());
-                    let ident_str = snippet.as_slice();
+                    let ident_str = token.resolve(self.session);
 
                     // Tests if this is an instruction or not
-                    if Opcode::from(ident_str) != Opcode::Bogus {
+                    if self.session.is_instruction(&ident_str) {
                         // If it is
                         // Just push it
                         benign_tokens.push(token);
-
-                        span.end = token.as_span().end;
                     } else {
                         // If it isn't, it is going to be parsed as a macro invokation
-                        let macro_invok = self.parse_macro_invok(token.as_span(), ident_str)?;
+                        let macro_invok = self.parse_macro_invok(token.as_span(), &ident_str)?;
 
                         // If we have captured any tokens before this
                         if !benign_tokens.is_empty() {
@@ -1862,9 +2149,6 @@ impl<'a> Parser<'a> {
                             benign_tokens = Vec::new();
                         }
 
-                        // Update this just in case it is the last part of the contents
-                        span.end = macro_invok.span.end;
-
                         contents.push(PASTNode::MacroInvok(macro_invok));
                     }
                 }
@@ -1874,6 +2158,20 @@ impl<'a> Parser<'a> {
             }
         }
 
+        // The tokens ran out inside of braces
+        if !open_braces.is_empty() {
+            self.struct_err_unclosed_argument(paren_span, &open_braces)
+                .emit();
+
+            return Err(());
+        }
+
+        // An argument that is only whitespace counts as empty
+        let is_empty = contents.is_empty()
+            && benign_tokens
+                .iter()
+                .all(|token| token.kind == TokenKind::Whitespace);
+
         // Check if benign_tokens didn't end empty
         if !benign_tokens.is_empty() {
             contents.push(PASTNode::BenignTokens(BenignTokens::from_vec(
@@ -1881,25 +2179,23 @@ impl<'a> Parser<'a> {
             )));
         }
 
-        // If we just have nothing
-        if contents.is_empty() {
-            // This is always an error
+        if is_empty {
             if let Some(comma_span) = comma_span {
+                // Empty arguments between two others are always an error
                 self.session
-                    .struct_span_error(comma_span, "no arguments after comma".to_string())
-                    .emit();
-            } else if let Some(close_paren_span) = close_paren_span {
-                self.session
-                    .struct_span_error(close_paren_span, "expected argument before `)`".to_string())
+                    .struct_span_error(comma_span, "expected argument before `,`".to_string())
                     .emit();
+            } else if close_paren_span.is_some() {
+                return Ok((None, true));
             }
 
             return Err(());
-        } else {
-            span.end = self.last_token.unwrap().as_span().end;
         }
 
-        Ok((MacroInvokArg::new(span, contents), is_last))
+        // The argument's span includes the `,` or `)` that ended it
+        let span = first_span.unwrap().join(self.last_token.unwrap().as_span());
+
+        Ok((Some(MacroInvokArg::new(span, contents)), is_last))
     }
 
     // Peeks the next token from the Parser's tokens
@@ -1989,6 +2285,23 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // A macro argument that is missing either a `}` or the invokation's closing `)`
+    fn struct_err_unclosed_argument(
+        &self,
+        paren_span: Span,
+        open_braces: &[Span],
+    ) -> DiagnosticBuilder<'_> {
+        match open_braces.first() {
+            Some(&brace_span) => self
+                .session
+                .struct_span_error(brace_span, "unclosed `{` in macro argument".to_string()),
+            None => self.session.struct_span_error(
+                paren_span,
+                "Macro invokation requires closing `)`".to_string(),
+            ),
+        }
+    }
+
     fn struct_err_expected_eof(&self, last: Span, expected: &str) -> DiagnosticBuilder<'_> {
         let message = format!("expected {}", expected);
         let mut db = self.session.struct_error(message);
@@ -2028,12 +2341,12 @@ pub fn parse_integer_literal(string: &str) -> Result<i32, ()> {
         }
     }
 
-    Ok(no_separators.parse().unwrap())
+    no_separators.parse().map_err(|_| ())
 }
 
 /// Parses a hexadecimal literal from the given &str
 pub fn parse_hexadecimal_literal(string: &str) -> Result<i32, ()> {
-    let string = &string[2..];
+    let string = string.strip_prefix("0x").ok_or(())?;
     let mut no_separators = String::with_capacity(string.len());
 
     for c in string.chars() {
@@ -2044,12 +2357,12 @@ pub fn parse_hexadecimal_literal(string: &str) -> Result<i32, ()> {
         }
     }
 
-    Ok(i32::from_str_radix(&no_separators, 16).unwrap())
+    i32::from_str_radix(&no_separators, 16).map_err(|_| ())
 }
 
 /// Parses a binary literal from the given &str
 pub fn parse_binary_literal(string: &str) -> Result<i32, ()> {
-    let string = &string[2..];
+    let string = string.strip_prefix("0b").ok_or(())?;
     let mut no_separators = String::with_capacity(string.len());
 
     for c in string.chars() {
@@ -2060,10 +2373,131 @@ pub fn parse_binary_literal(string: &str) -> Result<i32, ()> {
         }
     }
 
-    Ok(i32::from_str_radix(&no_separators, 2).unwrap())
+    i32::from_str_radix(&no_separators, 2).map_err(|_| ())
 }
 
-/// Parses a float literal from the given &str
+/// Parses a float literal from the given &str, which may use scientific notation
+///
+/// Like integer literals, `_` separators are allowed, but only between two digits. This keeps them
+/// away from the decimal point and exponent, where they would be confusing. Values too large to
+/// be stored are errors instead of becoming infinity
 pub fn parse_float_literal(string: &str) -> Result<f64, ()> {
-    Ok(string.parse().unwrap())
+    let chars: Vec<char> = string.chars().collect();
+    let mut no_separators = String::with_capacity(string.len());
+
+    for (index, &c) in chars.iter().enumerate() {
+        if c != '_' {
+            no_separators.push(c);
+            continue;
+        }
+
+        let before = index.checked_sub(1).and_then(|before| chars.get(before));
+        let after = chars.get(index + 1);
+
+        if !before.is_some_and(char::is_ascii_digit) || !after.is_some_and(char::is_ascii_digit) {
+            return Err(());
+        }
+    }
+
+    let value: f64 = no_separators.parse().map_err(|_| ())?;
+
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(())
+    }
+}
+
+/// Parses a quoted string literal from the given &str, replacing any escape sequences.
+///
+/// If an unknown escape sequence is found, returns the byte offset of its backslash within the
+/// literal, along with the character that was escaped
+pub fn parse_string_literal(string: &str) -> Result<String, (usize, char)> {
+    let inner = &string[1..string.len() - 1];
+
+    let mut value = String::with_capacity(inner.len());
+    let mut chars = inner.char_indices();
+
+    while let Some((_, c)) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+
+        // The lexer guarantees that a backslash is always followed by another character
+        let (index, escaped) = chars.next().unwrap();
+
+        value.push(match escaped {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            // The backslash comes right before the escaped character, but the opening quote also
+            // has to be accounted for
+            _ => return Err((index, escaped)),
+        });
+    }
+
+    Ok(value)
+}
+
+/// Formats a string as a quoted string literal, the inverse of parse_string_literal
+pub fn format_string_literal(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+
+    literal.push('"');
+
+    for c in value.chars() {
+        match c {
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            '\0' => literal.push_str("\\0"),
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            _ => literal.push(c),
+        }
+    }
+
+    literal.push('"');
+
+    literal
+}
+
+/// Formats a float as a float literal. Float literals always require a decimal point, even if
+/// the value is a whole number
+pub fn format_float_literal(value: f64) -> String {
+    let literal = value.to_string();
+
+    if literal.contains('.') {
+        literal
+    } else {
+        format!("{}.0", literal)
+    }
+}
+
+/// Creates the error for an unknown escape sequence returned by parse_string_literal, pointing at
+/// just the escape sequence within the literal's span
+pub fn struct_escape_error(
+    session: &Session,
+    literal_span: Span,
+    (offset, escaped): (usize, char),
+) -> DiagnosticBuilder<'_> {
+    let start = literal_span.start + offset;
+    let escape_span = Span {
+        start,
+        end: start + 1 + escaped.len_utf8(),
+        ..literal_span
+    };
+
+    let mut db = session.struct_span_error(
+        escape_span,
+        format!("unknown escape sequence `\\{}`", escaped),
+    );
+
+    db.help("valid escape sequences are \\n, \\t, \\r, \\0, \\\\, and \\\"".to_string());
+
+    db
 }
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::num::NonZeroU8;
 
 use crate::errors::Span;
@@ -9,8 +10,11 @@ use crate::lexer::Token;
 /// parsed, then "generated" which means that it generates the rest of the code that will be used
 /// in KASM's subsequent operation.
 ///
+/// Every PAST type compares and prints only its structure: spans are left out of both `PartialEq`
+/// and `Debug`, so that a tree parsed from source can be checked against one written out by hand.
+///
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PASTNode {
     BenignTokens(BenignTokens),
     SLMacroDef(SLMacroDef),
@@ -21,25 +25,49 @@ pub enum PASTNode {
     Repeat(Repeat),
     IfStatement(IfStatement),
     Include(Include),
+    Line(Line),
+    Defined(Defined),
 }
 
 impl PASTNode {
-    pub fn span_end(&self) -> usize {
+    pub fn span(&self) -> Span {
         match self {
-            PASTNode::BenignTokens(benign_tokens) => benign_tokens.span.end,
-            PASTNode::SLMacroDef(sl_macro_def) => sl_macro_def.span.end,
-            PASTNode::MacroInvok(macro_invok) => macro_invok.span.end,
-            PASTNode::MLMacroDef(ml_macro_def) => ml_macro_def.span.end,
-            PASTNode::SLMacroUndef(sl_macro_undef) => sl_macro_undef.span.end,
-            PASTNode::MLMacroUndef(ml_macro_undef) => ml_macro_undef.span.end,
-            PASTNode::Repeat(repeat) => repeat.span.end,
-            PASTNode::IfStatement(if_statement) => if_statement.span.end,
-            PASTNode::Include(include) => include.span.end,
+            PASTNode::BenignTokens(benign_tokens) => benign_tokens.span,
+            PASTNode::SLMacroDef(sl_macro_def) => sl_macro_def.span,
+            PASTNode::MacroInvok(macro_invok) => macro_invok.span,
+            PASTNode::MLMacroDef(ml_macro_def) => ml_macro_def.span,
+            PASTNode::SLMacroUndef(sl_macro_undef) => sl_macro_undef.span,
+            PASTNode::MLMacroUndef(ml_macro_undef) => ml_macro_undef.span,
+            PASTNode::Repeat(repeat) => repeat.span,
+            PASTNode::IfStatement(if_statement) => if_statement.span,
+            PASTNode::Include(include) => include.span,
+            PASTNode::Line(line) => line.span,
+            PASTNode::Defined(defined) => defined.span,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+// Implements PartialEq and Debug for a PAST type using only the listed fields, which leaves out
+// the span
+macro_rules! impl_structural {
+    ($name:ident { $($field:ident),+ }) => {
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                $(self.$field == other.$field)&&+
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    $(.field(stringify!($field), &self.$field))+
+                    .finish()
+            }
+        }
+    };
+}
+
+#[derive(Copy, Clone)]
 pub struct Ident {
     pub span: Span,
     pub hash: u64,
@@ -57,7 +85,13 @@ impl PartialEq for Ident {
     }
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Debug for Ident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Ident").field(&self.hash).finish()
+    }
+}
+
+#[derive(Clone)]
 pub struct BenignTokens {
     pub span: Span,
     pub tokens: Vec<Token>,
@@ -69,19 +103,41 @@ impl BenignTokens {
     /// The vector MUST NOT BE EMPTY. If it is, this function will panic
     ///
     pub fn from_vec(tokens: Vec<Token>) -> Self {
-        let mut span = Span::new(0, 0, 0);
-
         let first_span = tokens.first().unwrap().as_span();
         let last_span = tokens.last().unwrap().as_span();
 
-        span.file = first_span.file;
-        span.start = first_span.start;
-        span.end = last_span.end;
+        let span = first_span.join(last_span);
 
         Self { span, tokens }
     }
 }
 
+// Tokens don't store their text, so only the kinds of the tokens are compared
+impl PartialEq for BenignTokens {
+    fn eq(&self, other: &Self) -> bool {
+        self.tokens.len() == other.tokens.len()
+            && self
+                .tokens
+                .iter()
+                .zip(other.tokens.iter())
+                .all(|(a, b)| a.kind == b.kind)
+    }
+}
+
+impl fmt::Debug for BenignTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BenignTokens")
+            .field(
+                &self
+                    .tokens
+                    .iter()
+                    .map(|token| token.kind)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
 /// A PAST Node representing a single line macro definition
 ///
 /// Grammar:
@@ -93,7 +149,7 @@ impl BenignTokens {
 ///              |   .define <identifier> <SLMacroDefArgs> <SLMacroDefContents>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SLMacroDef {
     pub span: Span,
     pub identifier: Ident,
@@ -117,6 +173,12 @@ impl SLMacroDef {
     }
 }
 
+impl_structural!(SLMacroDef {
+    identifier,
+    args,
+    contents
+});
+
 /// A PAST Node representing a single line macro definition's arguments
 ///
 /// Grammar:
@@ -128,7 +190,7 @@ impl SLMacroDef {
 /// <arguments> ::= <identifier> | <identifier>, <arguments>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SLMacroDefArgs {
     pub span: Span,
     pub args: Vec<Ident>,
@@ -140,6 +202,8 @@ impl SLMacroDefArgs {
     }
 }
 
+impl_structural!(SLMacroDefArgs { args });
+
 /// A PAST Node representing a single line macro definition's contents
 ///
 /// This grammar may be incomplete, however it is meant to convey that this can contain anything
@@ -156,7 +220,7 @@ impl SLMacroDefArgs {
 ///                      |   <keyword> <SLMacroDefContents>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SLMacroDefContents {
     pub span: Span,
     pub contents: Vec<PASTNode>,
@@ -168,7 +232,9 @@ impl SLMacroDefContents {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(SLMacroDefContents { contents });
+
+#[derive(Clone)]
 pub struct MacroInvok {
     pub span: Span,
     pub identifier: Ident,
@@ -185,7 +251,9 @@ impl MacroInvok {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(MacroInvok { identifier, args });
+
+#[derive(Clone)]
 pub struct MacroInvokArgs {
     pub span: Span,
     pub args: Vec<MacroInvokArg>,
@@ -197,20 +265,18 @@ impl MacroInvokArgs {
     }
 
     pub fn from_vec(args: Vec<MacroInvokArg>) -> Self {
-        let mut span = Span::new(0, 0, 0);
-
         let first_span = args.first().unwrap().span;
         let last_span = args.last().unwrap().span;
 
-        span.start = first_span.start;
-        span.file = first_span.file;
-        span.end = last_span.end;
+        let span = first_span.join(last_span);
 
         MacroInvokArgs { span, args }
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(MacroInvokArgs { args });
+
+#[derive(Clone)]
 pub struct MacroInvokArg {
     pub span: Span,
     pub contents: Vec<PASTNode>,
@@ -222,7 +288,9 @@ impl MacroInvokArg {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(MacroInvokArg { contents });
+
+#[derive(Clone)]
 pub struct MLMacroDef {
     pub span: Span,
     pub identifier: Ident,
@@ -249,7 +317,14 @@ impl MLMacroDef {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(MLMacroDef {
+    identifier,
+    args,
+    defaults,
+    contents
+});
+
+#[derive(Clone)]
 pub struct MLMacroArgs {
     pub span: Span,
     pub required: u8,
@@ -266,7 +341,9 @@ impl MLMacroArgs {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(MLMacroArgs { required, maximum });
+
+#[derive(Clone)]
 pub struct MLMacroDefDefaults {
     pub span: Span,
     pub values: Vec<BenignTokens>,
@@ -278,19 +355,17 @@ impl MLMacroDefDefaults {
     }
 
     pub fn from_vec(values: Vec<BenignTokens>) -> Self {
-        let mut span = Span::new(0, 0, 0);
-
         let first_span = values.first().unwrap().span;
         let last_span = values.last().unwrap().span;
 
-        span.start = first_span.start;
-        span.file = first_span.file;
-        span.end = last_span.end;
+        let span = first_span.join(last_span);
 
         MLMacroDefDefaults { span, values }
     }
 }
 
+impl_structural!(MLMacroDefDefaults { values });
+
 /// A PAST Node that represents a single line macro undefinition
 ///
 /// Grammar:
@@ -300,7 +375,7 @@ impl MLMacroDefDefaults {
 ///                |   .undef <ident> <SLMacroUndefArgs>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SLMacroUndef {
     pub span: Span,
     pub identifier: Ident,
@@ -317,13 +392,15 @@ impl SLMacroUndef {
     }
 }
 
+impl_structural!(SLMacroUndef { identifier, args });
+
 /// Represents a single line macro's number of arguments
 ///
 /// ```sh,ignore,no_run
 /// <SLMacroUndefArgs> ::= <number>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SLMacroUndefArgs {
     pub span: Span,
     pub num: u8,
@@ -335,6 +412,8 @@ impl SLMacroUndefArgs {
     }
 }
 
+impl_structural!(SLMacroUndefArgs { num });
+
 /// A PAST Node that represents a multi line macro undefinition
 ///
 /// Grammar:
@@ -344,7 +423,7 @@ impl SLMacroUndefArgs {
 ///                |   .unmacro <ident> <MLMacroArgs>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct MLMacroUndef {
     pub span: Span,
     pub identifier: Ident,
@@ -361,31 +440,48 @@ impl MLMacroUndef {
     }
 }
 
+impl_structural!(MLMacroUndef { identifier, args });
+
 /// A PAST node that represents a repeat directive
 ///
 /// Grammar:
 ///
 /// ```sh,ignore,no_run
 /// <Repeat> ::= .rep <RepeatNumber>
+///           |   .rep <RepeatNumber> as <identifier>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Repeat {
     pub span: Span,
     pub number: RepeatNumber,
+    /// The identifier that is replaced by the current iteration's index within the contents
+    pub index: Option<Ident>,
     pub contents: Vec<PASTNode>,
 }
 
 impl Repeat {
-    pub fn new(span: Span, number: RepeatNumber, contents: Vec<PASTNode>) -> Self {
+    pub fn new(
+        span: Span,
+        number: RepeatNumber,
+        index: Option<Ident>,
+        contents: Vec<PASTNode>,
+    ) -> Self {
         Self {
             span,
             number,
+            index,
             contents,
         }
     }
 }
 
+impl_structural!(Repeat {
+    number,
+    index,
+    contents
+});
+
 /// A PAST node that represents a repeat directive's number of repetitions
 ///
 /// Grammar:
@@ -394,7 +490,7 @@ impl Repeat {
 /// <RepeatNumber> ::= <BenignTokens> | <MacroInvok>
 /// ```
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct RepeatNumber {
     pub span: Span,
     pub expression: Vec<PASTNode>,
@@ -406,7 +502,9 @@ impl RepeatNumber {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(RepeatNumber { expression });
+
+#[derive(Clone)]
 pub struct IfStatement {
     pub span: Span,
     pub clauses: Vec<IfClause>,
@@ -418,20 +516,18 @@ impl IfStatement {
     }
 
     pub fn from_vec(clauses: Vec<IfClause>) -> Self {
-        let mut span = Span::new(0, 0, 0);
-
         let first_span = clauses.first().unwrap().span;
         let last_span = clauses.last().unwrap().span;
 
-        span.start = first_span.start;
-        span.file = first_span.file;
-        span.end = last_span.end;
+        let span = first_span.join(last_span);
 
         Self { span, clauses }
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(IfStatement { clauses });
+
+#[derive(Clone)]
 pub struct IfClause {
     pub span: Span,
     pub begin: IfClauseBegin,
@@ -455,8 +551,14 @@ impl IfClause {
     }
 }
 
+impl_structural!(IfClause {
+    begin,
+    condition,
+    contents
+});
+
 /// This represents a single part like .if or .ifn
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct IfClauseBegin {
     pub span: Span,
     pub inverse: bool,
@@ -468,14 +570,17 @@ impl IfClauseBegin {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(IfClauseBegin { inverse });
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum IfCondition {
     Exp(IfExpCondition),
     Def(IfDefCondition),
+    Section(IfSectionCondition),
     Else,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct IfDefCondition {
     pub span: Span,
     pub identifier: Ident,
@@ -492,7 +597,40 @@ impl IfDefCondition {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(IfDefCondition { identifier, args });
+
+/// The section that an .ifsection checks is the current one, either `.text`, `.data`, or the name
+/// of a custom section
+#[derive(Clone)]
+pub struct IfSectionCondition {
+    pub span: Span,
+    pub section: String,
+}
+
+impl IfSectionCondition {
+    pub fn new(span: Span, section: String) -> Self {
+        Self { span, section }
+    }
+}
+
+impl_structural!(IfSectionCondition { section });
+
+/// A `defined(NAME)` check inside of an .if expression, which becomes either true or false
+#[derive(Clone)]
+pub struct Defined {
+    pub span: Span,
+    pub condition: IfDefCondition,
+}
+
+impl Defined {
+    pub fn new(span: Span, condition: IfDefCondition) -> Self {
+        Self { span, condition }
+    }
+}
+
+impl_structural!(Defined { condition });
+
+#[derive(Clone)]
 pub struct IfExpCondition {
     pub span: Span,
     pub expression: Vec<PASTNode>,
@@ -504,19 +642,58 @@ impl IfExpCondition {
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(IfExpCondition { expression });
+
+#[derive(Clone)]
 pub struct Include {
     pub span: Span,
     pub path: IncludePath,
+    /// If only part of the file should be included, the lines to include
+    pub lines: Option<IncludeLines>,
+    /// If this is an .include_decls, which only keeps the file's macros and symbol declarations
+    pub declarations_only: bool,
 }
 
 impl Include {
-    pub fn new(span: Span, path: IncludePath) -> Self {
-        Self { span, path }
+    pub fn new(
+        span: Span,
+        path: IncludePath,
+        lines: Option<IncludeLines>,
+        declarations_only: bool,
+    ) -> Self {
+        Self {
+            span,
+            path,
+            lines,
+            declarations_only,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+impl_structural!(Include {
+    path,
+    lines,
+    declarations_only
+});
+
+/// The range of lines in `.include "file" lines <first>-<last>`. Both lines are included, and
+/// line numbers start at 1
+#[derive(Clone)]
+pub struct IncludeLines {
+    pub span: Span,
+    pub first: usize,
+    pub last: usize,
+}
+
+impl IncludeLines {
+    pub fn new(span: Span, first: usize, last: usize) -> Self {
+        Self { span, first, last }
+    }
+}
+
+impl_structural!(IncludeLines { first, last });
+
+#[derive(Clone)]
 pub struct IncludePath {
     pub span: Span,
     pub expression: Vec<PASTNode>,
@@ -527,3 +704,42 @@ impl IncludePath {
         Self { span, expression }
     }
 }
+
+impl_structural!(IncludePath { expression });
+
+/// A PAST Node that represents a line directive, which changes the line number and optionally the
+/// file name that are reported for the lines that follow it
+///
+/// Grammar:
+///
+/// ```sh,ignore,no_run
+/// <Line> ::= .line <number>
+///        |   .line <number> <string>
+/// ```
+///
+#[derive(Clone)]
+pub struct Line {
+    pub span: Span,
+    pub line: usize,
+    pub file: Option<String>,
+    /// The index into the source that the following line starts at, if there is one
+    pub next_line_start: Option<usize>,
+}
+
+impl Line {
+    pub fn new(
+        span: Span,
+        line: usize,
+        file: Option<String>,
+        next_line_start: Option<usize>,
+    ) -> Self {
+        Self {
+            span,
+            line,
+            file,
+            next_line_start,
+        }
+    }
+}
+
+impl_structural!(Line { line, file });
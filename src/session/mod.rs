@@ -1,28 +1,50 @@
+use std::cell::RefCell;
 use std::path::Path;
+use std::time::{Duration, Instant};
 use std::{path::PathBuf, rc::Rc, sync::RwLock};
 
+use kerbalobjects::Opcode;
+use termcolor::Buffer;
+
 use crate::{
     errors::{
-        DiagnosticBuilder, Handler, HandlerFlags, Level, Snippet, SourceFile, SourceManager, Span,
+        Diagnostic, DiagnosticBuilder, Handler, HandlerFlags, Level, Snippet, SourceError,
+        SourceFile, SourceManager, Span,
     },
     Config,
 };
 
+mod opcodes;
+pub use opcodes::OpcodeTable;
+
+/// The reason that a source file couldn't be read by Session::read_file()
+#[derive(Debug)]
+pub enum ReadError {
+    Io(std::io::Error),
+    /// The file was read, but it isn't UTF-8 text. This holds the line that the first invalid
+    /// byte is on
+    NotUtf8(usize),
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
 pub struct Session {
     source_manager: Rc<RwLock<SourceManager>>,
     config: Config,
     handler: Handler,
     num_files: usize,
+    opcodes: OpcodeTable,
+    /// The time taken by each phase of assembly so far, if a time report was requested
+    timings: RefCell<Vec<(&'static str, Duration)>>,
 }
 
 impl Session {
     pub fn new(config: Config) -> Self {
-        let flags = HandlerFlags {
-            colored_output: Self::colored_output(),
-            emit_warnings: config.emit_warnings,
-            quiet: !config.emit_errors,
-        };
-
+        let flags = Self::handler_flags(&config);
         let source_manager = Rc::new(RwLock::new(SourceManager::new()));
 
         Self {
@@ -30,22 +52,167 @@ impl Session {
             config,
             handler: Handler::new(flags, source_manager),
             num_files: 0,
+            opcodes: OpcodeTable::new(),
+            timings: RefCell::new(Vec::new()),
         }
     }
 
+    /// Creates a Session that holds on to every diagnostic emitted instead of writing them out
+    /// immediately. They can be retrieved with take_diagnostics()
+    pub fn new_buffered(config: Config) -> Self {
+        let flags = Self::handler_flags(&config);
+        let source_manager = Rc::new(RwLock::new(SourceManager::new()));
+
+        Self {
+            source_manager: source_manager.clone(),
+            config,
+            handler: Handler::new_buffered(flags, source_manager),
+            num_files: 0,
+            opcodes: OpcodeTable::new(),
+            timings: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Creates a Session that keeps every diagnostic emitted instead of writing them out. They
+    /// can be retrieved with take_collected_diagnostics()
+    pub fn new_collecting(config: Config) -> Self {
+        let flags = Self::handler_flags(&config);
+        let source_manager = Rc::new(RwLock::new(SourceManager::new()));
+
+        Self {
+            source_manager: source_manager.clone(),
+            config,
+            handler: Handler::new_collecting(flags, source_manager),
+            num_files: 0,
+            opcodes: OpcodeTable::new(),
+            timings: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn handler_flags(config: &Config) -> HandlerFlags {
+        HandlerFlags {
+            color: config.color,
+            emit_warnings: config.emit_warnings && !config.quiet,
+            emit_notes: config.verbose,
+            quiet: !config.emit_errors,
+            max_line_width: config.max_line_width,
+            tab_width: config.tab_width,
+        }
+    }
+
+    /// Records the time that a phase of assembly took, which started at the provided instant. This
+    /// does nothing unless a time report was requested
+    pub fn record_time(&self, phase: &'static str, start: Instant) {
+        if self.config.time_report {
+            self.timings.borrow_mut().push((phase, start.elapsed()));
+        }
+    }
+
+    /// Takes the time taken by each phase of assembly that has been recorded so far, in order
+    pub fn take_timings(&self) -> Vec<(&'static str, Duration)> {
+        self.timings.take()
+    }
+
+    /// Takes every diagnostic emitted so far, if this Session was created with new_buffered()
+    pub fn take_diagnostics(&self) -> Option<Buffer> {
+        self.handler.take_buffer()
+    }
+
+    /// Takes every diagnostic emitted so far, if this Session was created with new_collecting()
+    pub fn take_collected_diagnostics(&self) -> Vec<Diagnostic> {
+        self.handler.take_collected()
+    }
+
+    /// Writes out diagnostics that were taken from another Session
+    pub fn emit_diagnostics(&self, diagnostics: &Buffer) {
+        self.handler.emit_buffer(diagnostics);
+    }
+
+    /// Returns the Opcode with the provided mnemonic, or Opcode::Bogus if there isn't one
+    pub fn opcode(&self, mnemonic: &str) -> Opcode {
+        self.opcodes.get(mnemonic)
+    }
+
+    /// Returns true if the identifier is the mnemonic of an instruction, instead of possibly being
+    /// the name of a macro, label, or symbol
+    pub fn is_instruction(&self, identifier: &str) -> bool {
+        self.opcodes.contains(identifier)
+    }
+
     pub fn span_to_snippet(&self, span: &Span) -> Snippet {
         self.source_manager
             .read()
             .unwrap()
             .get_by_id(span.file)
             .unwrap()
-            .span_to_snippet(span)
+            .span_to_snippet(span, self.config.tab_width)
+    }
+
+    /// Returns the exact source code that the span covers, including any tabs and newlines
+    pub fn span_to_source(&self, span: &Span) -> String {
+        self.source_manager
+            .read()
+            .unwrap()
+            .get_by_id(span.file)
+            .unwrap()
+            .span_to_source(span)
+            .to_owned()
+    }
+
+    /// Returns the file path, line number, and column that the span starts at
+    pub fn get_source_location(&self, span: &Span) -> (String, usize, usize) {
+        self.source_manager
+            .read()
+            .unwrap()
+            .get_source_location(span, self.config.tab_width)
+            .unwrap()
+    }
+
+    /// Makes every location after the given index in a file be reported as being on the given
+    /// line, and in the given file if one is provided
+    pub fn add_line_directive(
+        &self,
+        file_id: usize,
+        start: usize,
+        line: usize,
+        file_name: Option<String>,
+    ) {
+        self.source_manager
+            .write()
+            .unwrap()
+            .add_line_directive(file_id, start, line, file_name);
+    }
+
+    /// Records an expansion of the macro invoked at the provided span, inside of the parent
+    /// expansion (or 0 if none). Returns the id that tokens produced by it should be given
+    pub fn add_expansion(&self, invocation: Span, parent: usize) -> usize {
+        self.source_manager
+            .write()
+            .unwrap()
+            .add_expansion(invocation, parent)
+    }
+
+    /// Discards every source file that has been read, so that this Session can be reused to
+    /// assemble another primary file. The handler, and so any diagnostics already emitted, are kept
+    pub fn clear_files(&mut self) {
+        // A held back diagnostic can only be displayed while its source file is still around
+        self.handler.flush();
+
+        *self.source_manager.write().unwrap() = SourceManager::new();
+
+        self.num_files = 0;
     }
 
     pub fn get_file(&self, file_id: usize) -> Option<Rc<SourceFile>> {
         self.source_manager.read().unwrap().get_by_id(file_id)
     }
 
+    /// Returns every source file that has been read so far, indexed by file ID. Text can be
+    /// borrowed from these without holding onto the Session, for example by `TokenTexts`
+    pub fn source_files(&self) -> Vec<Rc<SourceFile>> {
+        (0..).map_while(|file_id| self.get_file(file_id)).collect()
+    }
+
     pub fn is_file(&self, path: &Path) -> bool {
         path.is_file()
     }
@@ -54,7 +221,7 @@ impl Session {
         self.num_files > u8::MAX as usize
     }
 
-    pub fn read_file(&mut self, path: &Path) -> std::io::Result<u8> {
+    pub fn read_file(&mut self, path: &Path) -> Result<u8, ReadError> {
         let path_buf = PathBuf::from(&path);
 
         // This should be fine, given that we _should have_ already checked that this is a file
@@ -65,9 +232,19 @@ impl Session {
 
         let abs_path = std::fs::canonicalize(&path_buf)?;
 
-        let rel_path = pathdiff::diff_paths(&abs_path, &self.config.root_dir).unwrap();
+        // There is no relative path between different drives on Windows, so the absolute path is
+        // used instead
+        let rel_path = pathdiff::diff_paths(&abs_path, &self.config.root_dir)
+            .unwrap_or_else(|| abs_path.clone());
+
+        let source = String::from_utf8(std::fs::read(path)?).map_err(|e| {
+            let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
 
-        let source = std::fs::read_to_string(&path)?;
+            ReadError::NotUtf8(valid.iter().filter(|&&byte| byte == b'\n').count() + 1)
+        })?;
+
+        self.struct_note(format!("read file `{}`", rel_path.to_string_lossy()))
+            .emit();
 
         // The file id will be replaced by the source manager anyway
         let source_file = SourceFile::new(file_name, Some(abs_path), Some(rel_path), source, 0);
@@ -82,12 +259,78 @@ impl Session {
             .unwrap())
     }
 
-    pub fn add_file(&mut self, source_file: SourceFile) {
-        self.source_manager
-            .write()
-            .unwrap()
-            .add(source_file)
-            .unwrap();
+    /// Creates the diagnostic for a file that read_file() couldn't read
+    pub fn struct_read_error(&self, path: &Path, error: ReadError) -> DiagnosticBuilder<'_> {
+        match error {
+            ReadError::Io(e) => self.struct_bug(format!(
+                "unable to read file `{}`: {}",
+                path.to_string_lossy(),
+                e
+            )),
+            ReadError::NotUtf8(line) => {
+                let mut db = self.struct_error(format!(
+                    "file `{}` is not valid UTF-8 text",
+                    path.to_string_lossy()
+                ));
+
+                db.note(format!("the first invalid byte is on line {}", line))
+                    .help("source files must be saved with the UTF-8 encoding".to_string());
+
+                db
+            }
+        }
+    }
+
+    /// Finds a file referenced by `.include` or `.incbin` by searching each include directory in
+    /// order, then the directory of the file that referenced it, then the current working
+    /// directory. The first file found wins.
+    ///
+    /// If no file is found, an error is emitted at the provided span
+    pub fn resolve_include(&self, span: Span, path: &str) -> Result<PathBuf, ()> {
+        let mut search_dirs = self.config.include_paths.clone();
+
+        if let Some(including_dir) = self
+            .get_file(span.file)
+            .and_then(|file| file.abs_path.clone())
+            .and_then(|abs_path| abs_path.parent().map(PathBuf::from))
+        {
+            search_dirs.push(including_dir);
+        }
+
+        search_dirs.push(PathBuf::new());
+
+        for dir in search_dirs.iter() {
+            let path_buf = dir.join(path);
+
+            if path_buf.is_file() {
+                return Ok(path_buf);
+            }
+        }
+
+        let searched = search_dirs
+            .iter()
+            .map(|dir| {
+                if dir.as_os_str().is_empty() {
+                    String::from("`.`")
+                } else {
+                    format!("`{}`", dir.to_string_lossy())
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        self.struct_span_error(span, format!("unable to find included file `{}`", path))
+            .note(format!("searched directories: {}", searched))
+            .help("maybe check your include paths".to_string())
+            .emit();
+
+        Err(())
+    }
+
+    /// Adds a source file that wasn't read from the file system, returning its file ID, or an
+    /// error if the maximum number of files has been reached
+    pub fn add_file(&mut self, source_file: SourceFile) -> Result<u8, SourceError> {
+        self.source_manager.write().unwrap().add(source_file)
     }
 
     pub fn get_input_file_name(&self) -> String {
@@ -128,9 +371,8 @@ impl Session {
         DiagnosticBuilder::new(&self.handler, Level::Warning, message)
     }
 
-    // Returns true if error output should be colored, false if not
-    fn colored_output() -> bool {
-        atty::is(atty::Stream::Stderr)
+    pub fn struct_note(&self, message: String) -> DiagnosticBuilder<'_> {
+        DiagnosticBuilder::new(&self.handler, Level::Note, message)
     }
 
     pub fn config(&self) -> &Config {
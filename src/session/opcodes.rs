@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use kerbalobjects::Opcode;
+
+/// A table of every instruction mnemonic, for quickly looking up the Opcode that an identifier
+/// refers to. Nearly every identifier is checked against this, to tell instructions apart from
+/// macros, labels, and symbols
+pub struct OpcodeTable {
+    opcodes: HashMap<&'static str, Opcode>,
+    // No identifier longer than this can be an instruction, which rules out most macro names
+    // without hashing them
+    max_len: usize,
+}
+
+impl OpcodeTable {
+    pub fn new() -> Self {
+        let opcodes: HashMap<&'static str, Opcode> = (0..=u8::MAX)
+            .map(Opcode::from)
+            .filter(|opcode| *opcode != Opcode::Bogus)
+            .map(|opcode| (opcode.into(), opcode))
+            .collect();
+
+        let max_len = opcodes
+            .keys()
+            .map(|mnemonic| mnemonic.len())
+            .max()
+            .unwrap_or(0);
+
+        Self { opcodes, max_len }
+    }
+
+    /// Returns the Opcode with the provided mnemonic, or Opcode::Bogus if there isn't one. This
+    /// gives the same result as Opcode::from()
+    pub fn get(&self, mnemonic: &str) -> Opcode {
+        if mnemonic.len() > self.max_len {
+            return Opcode::Bogus;
+        }
+
+        self.opcodes.get(mnemonic).copied().unwrap_or(Opcode::Bogus)
+    }
+
+    /// Returns every Opcode in the table, in the order of their byte values
+    pub fn opcodes(&self) -> Vec<Opcode> {
+        let mut opcodes: Vec<Opcode> = self.opcodes.values().copied().collect();
+
+        opcodes.sort_by_key(|opcode| u8::from(*opcode));
+
+        opcodes
+    }
+
+    /// Returns true if the identifier is the mnemonic of an instruction
+    pub fn contains(&self, identifier: &str) -> bool {
+        self.get(identifier) != Opcode::Bogus
+    }
+}
+
+impl Default for OpcodeTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,8 +1,12 @@
+use std::path::PathBuf;
+
+use kasm::{assemble_many, assemble_path, AssemblyOutput, Config, EmbeddedSource};
 use kerbalobjects::ko::symbols::{SymBind, SymType};
-use kerbalobjects::Opcode;
+use kerbalobjects::ko::KOFile;
+use kerbalobjects::{BufferIterator, KOSValue, Opcode};
 
 mod common;
-use common::{run_assembly_test, AssemblyTestInput};
+use common::{assemble_to_bytes, run_assembly_test, test_config, AssemblyTestInput};
 
 #[test]
 fn externs() {
@@ -22,6 +26,23 @@ fn externs() {
     });
 }
 
+#[test]
+fn type_with_binding() {
+    run_assembly_test(AssemblyTestInput {
+        file_name_base: String::from("type_binding"),
+        expected_symbols: vec![
+            (String::from("other"), SymBind::Extern, SymType::Func),
+            (
+                String::from("global_value"),
+                SymBind::Global,
+                SymType::NoType,
+            ),
+            (String::from("_start"), SymBind::Global, SymType::Func),
+        ],
+        expected_code: vec![(String::from("_start"), vec![Opcode::Push, Opcode::Call])],
+    });
+}
+
 #[test]
 fn add_numbers() {
     run_assembly_test(AssemblyTestInput {
@@ -52,3 +73,253 @@ fn single_instruction() {
         expected_code: vec![(String::from("_start"), vec![Opcode::Eop])],
     });
 }
+
+#[test]
+fn global_function() {
+    run_assembly_test(AssemblyTestInput {
+        file_name_base: String::from("global_function"),
+        expected_symbols: vec![
+            (String::from("helper"), SymBind::Global, SymType::Func),
+            (String::from("_start"), SymBind::Global, SymType::Func),
+        ],
+        expected_code: vec![
+            (String::from("helper"), vec![Opcode::Push, Opcode::Ret]),
+            (String::from("_start"), vec![Opcode::Call, Opcode::Pop]),
+        ],
+    });
+}
+
+#[test]
+fn globl_alias() {
+    run_assembly_test(AssemblyTestInput {
+        file_name_base: String::from("globl_function"),
+        expected_symbols: vec![
+            (String::from("helper"), SymBind::Global, SymType::Func),
+            (String::from("_start"), SymBind::Global, SymType::Func),
+        ],
+        expected_code: vec![
+            (String::from("helper"), vec![Opcode::Push, Opcode::Ret]),
+            (String::from("_start"), vec![Opcode::Call, Opcode::Pop]),
+        ],
+    });
+}
+
+#[test]
+fn global_list() {
+    run_assembly_test(AssemblyTestInput {
+        file_name_base: String::from("global_list"),
+        expected_symbols: vec![
+            (String::from("helper"), SymBind::Global, SymType::Func),
+            (String::from("_start"), SymBind::Global, SymType::Func),
+            (String::from("limit"), SymBind::Global, SymType::NoType),
+            (String::from("total"), SymBind::Global, SymType::NoType),
+        ],
+        expected_code: vec![
+            (String::from("helper"), vec![Opcode::Push, Opcode::Ret]),
+            (String::from("_start"), vec![Opcode::Call, Opcode::Push]),
+        ],
+    });
+}
+
+#[test]
+fn weak() {
+    run_assembly_test(AssemblyTestInput {
+        file_name_base: String::from("weak"),
+        expected_symbols: vec![
+            (String::from("handler"), SymBind::Global, SymType::Func),
+            (String::from("limit"), SymBind::Global, SymType::NoType),
+            (String::from("_start"), SymBind::Global, SymType::Func),
+        ],
+        expected_code: vec![(String::from("_start"), vec![Opcode::Push, Opcode::Call])],
+    });
+}
+
+#[test]
+fn reproducible_output() {
+    // This file has enough symbols that a hash-ordered symbol table would be unlikely to come out
+    // the same way twice
+    let first = assemble_to_bytes("disassembly");
+    let second = assemble_to_bytes("disassembly");
+
+    assert_eq!(first, second);
+}
+
+#[test]
+fn assemble_many_files() {
+    let paths = vec![
+        PathBuf::from("./tests/sources/add_numbers.kasm"),
+        PathBuf::from("./tests/sources/undeclared.kasm"),
+        PathBuf::from("./tests/sources/single_instruction.kasm"),
+    ];
+
+    let results = assemble_many(&paths, test_config());
+
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[0], Ok(AssemblyOutput::Object(_))));
+    assert!(results[1].is_err());
+    assert!(matches!(results[2], Ok(AssemblyOutput::Object(_))));
+}
+
+// assemble_many() writes its diagnostics straight to stderr, so this test runs itself again in a
+// child process to assemble the files, and checks what the child printed
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_diagnostics_in_order() {
+    let paths: Vec<PathBuf> = (0..16)
+        .map(|i| std::env::temp_dir().join(format!("kasm_parallel_{}.kasm", i)))
+        .collect();
+
+    if std::env::var_os("KASM_PARALLEL_CHILD").is_some() {
+        assemble_many(&paths, test_config());
+
+        return;
+    }
+
+    for (i, path) in paths.iter().enumerate() {
+        let source = format!(".func\n_start:\n    push missing_{}\n", i);
+
+        std::fs::write(path, source).expect("Failed to write source");
+    }
+
+    let output = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["parallel_diagnostics_in_order", "--exact", "--nocapture"])
+        .env("KASM_PARALLEL_CHILD", "1")
+        .output()
+        .expect("Failed to run test");
+
+    for path in &paths {
+        std::fs::remove_file(path).ok();
+    }
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    let reported: Vec<usize> = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("error: use of undeclared symbol `missing_"))
+        .map(|rest| rest.trim_end_matches('`').parse().unwrap())
+        .collect();
+
+    assert_eq!(reported, (0..16).collect::<Vec<_>>(), "{}", stderr);
+}
+
+// Assembles the file, then reads the written object file back and returns the contents of the
+// string table with the given name, if it has one
+fn assembled_strtab(path: &str, config: Config, name: &str) -> Option<String> {
+    let mut buffer = Vec::new();
+
+    match assemble_path(&PathBuf::from(path), config).unwrap() {
+        AssemblyOutput::Object(ko) => ko.write(&mut buffer),
+        AssemblyOutput::Source(_) => panic!("Expected object file"),
+    }
+
+    let ko = KOFile::parse(&mut BufferIterator::new(&buffer)).unwrap();
+
+    ko.str_tab_by_name(name)
+        .map(|str_tab| str_tab.strings().cloned().collect())
+}
+
+// Returns the contents of the .source section when the file is assembled with the given form of
+// its source embedded
+fn embedded_source(path: &str, embed_source: Option<EmbeddedSource>) -> Option<String> {
+    let mut config = test_config();
+    config.embed_source = embed_source;
+
+    assembled_strtab(path, config, ".source")
+}
+
+#[test]
+fn embed_source() {
+    let path = "./tests/sources/single_macro.kasm";
+    let original = std::fs::read_to_string(path).unwrap();
+
+    assert_eq!(embedded_source(path, None), None);
+    assert_eq!(
+        embedded_source(path, Some(EmbeddedSource::Original)),
+        Some(original)
+    );
+
+    let preprocessed = embedded_source(path, Some(EmbeddedSource::Preprocessed)).unwrap();
+
+    assert!(!preprocessed.contains(".macro"));
+    assert!(preprocessed.contains("push 3"));
+}
+
+// Returns the contents of the .entry section when the file is assembled with the given entry
+// symbol
+fn recorded_entry(path: &str, entry: Option<&str>) -> Option<String> {
+    let mut config = test_config();
+    config.entry = entry.map(String::from);
+
+    assembled_strtab(path, config, ".entry")
+}
+
+#[test]
+fn entry_recorded() {
+    let path = "./tests/sources/global_function.kasm";
+
+    assert_eq!(recorded_entry(path, None), None);
+    assert_eq!(
+        recorded_entry(path, Some("helper")),
+        Some(String::from("helper"))
+    );
+}
+
+#[test]
+fn entry_not_defined() {
+    let path = PathBuf::from("./tests/sources/externs.kasm");
+
+    // Neither a symbol that doesn't exist, nor one that is defined in another file, can be the
+    // entry point. Neither can a symbol that isn't a function
+    for entry in ["main", "other", "global_value"] {
+        let mut config = test_config();
+        config.emit_errors = false;
+        config.entry = Some(String::from(entry));
+
+        assert!(assemble_path(&path, config).is_err(), "{}", entry);
+    }
+}
+
+#[test]
+fn dump_symbols() {
+    let path = PathBuf::from("./tests/sources/externs.kasm");
+    let dump_path = std::env::temp_dir().join("kasm_dump_symbols.json");
+
+    let mut config = test_config();
+    config.dump_symbols = Some(dump_path.clone());
+
+    assemble_path(&path, config).expect("Assembly failed");
+
+    let json = std::fs::read_to_string(&dump_path).expect("Symbols were not written");
+    std::fs::remove_file(&dump_path).ok();
+
+    assert!(json.contains(
+        "{\"name\": \"other\", \"binding\": \"extern\", \"weak\": false, \"type\": \"func\", \"value\": null,"
+    ));
+    assert!(json.contains("\"name\": \"global_value\", \"binding\": \"global\""));
+    assert!(json.contains("\"labels\": ["));
+}
+
+#[test]
+fn cross_function_branch() {
+    let buffer = assemble_to_bytes("cross_function_branch");
+    let ko = KOFile::parse(&mut BufferIterator::new(&buffer)).unwrap();
+
+    let data = ko.data_section_by_name(".data").unwrap();
+
+    // Finds the offset that the function's jmp instruction branches by
+    let jump_offset = |name: &str| {
+        let func = ko.func_section_by_name(name).unwrap();
+
+        func.instructions()
+            .find_map(|instr| match instr {
+                kerbalobjects::ko::Instr::OneOp(Opcode::Jmp, op) => data.get(*op).cloned(),
+                _ => None,
+            })
+            .unwrap()
+    };
+
+    // The forward branch goes from instruction 1 to shared_end at instruction 4, before helper
+    // has been generated, and the branch back goes from instruction 5 to instruction 2
+    assert_eq!(jump_offset("_start"), KOSValue::Int32(3));
+    assert_eq!(jump_offset("helper"), KOSValue::Int32(-3));
+}
@@ -0,0 +1,121 @@
+use std::process::Command;
+
+// Runs the kasm binary with the given arguments, expecting it to fail, and returns everything it
+// printed to stderr
+fn run_kasm_failing(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_kasm"))
+        .args(args)
+        .output()
+        .expect("Failed to run kasm");
+
+    assert!(!output.status.success());
+
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn conflicting_bindings_show_both_spans() {
+    let stderr = run_kasm_failing(&["tests/sources/bindings.kasm", "-o", "tests/bindings.ko"]);
+
+    assert!(stderr.contains("conflicting symbol bindings"));
+
+    // The new binding is the primary span, and the original is labeled
+    assert!(stderr.contains("bindings.kasm:2:8"));
+    assert!(stderr.contains("2 | .extern counter"));
+    assert!(stderr.contains("1 | .global counter"));
+    assert!(stderr.contains("first declared here"));
+}
+
+#[test]
+fn binding_list_conflict_on_second_name() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/binding_list_conflict.kasm",
+        "-o",
+        "tests/binding_list_conflict.ko",
+    ]);
+
+    assert!(stderr.contains("conflicting symbol bindings"));
+    assert!(stderr.contains("binding_list_conflict.kasm:2:15"));
+    assert!(stderr.contains("1 | .local counter"));
+}
+
+#[test]
+fn weak_conflicts_with_extern() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/weak_extern.kasm",
+        "-o",
+        "tests/weak_extern.ko",
+    ]);
+
+    assert!(stderr.contains("conflicting symbol bindings"));
+    assert!(stderr.contains("1 | .weak counter"));
+}
+
+#[test]
+fn type_with_binding_conflicts_with_type() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/type_binding_conflict.kasm",
+        "-o",
+        "tests/type_binding_conflict.ko",
+    ]);
+
+    assert!(stderr.contains("conflicting symbol types"));
+    assert!(stderr.contains("type_binding_conflict.kasm:2:21"));
+    assert!(stderr.contains("1 | .type .func counter"));
+    assert!(stderr.contains("first declared here"));
+}
+
+// Assembles a file that breaks every rule about external symbols, and returns the diagnostics
+fn extern_invariants_stderr() -> String {
+    run_kasm_failing(&[
+        "tests/sources/extern_invariants.kasm",
+        "-o",
+        "tests/extern_invariants.ko",
+    ])
+}
+
+#[test]
+fn extern_requires_type() {
+    let stderr = extern_invariants_stderr();
+
+    assert!(stderr.contains("error: external symbol `untyped` must have its type specified"));
+    assert!(stderr.contains("extern_invariants.kasm:1:8"));
+}
+
+#[test]
+fn extern_data_entry_given_value() {
+    let stderr = extern_invariants_stderr();
+
+    assert!(stderr.contains("error: external symbol `counter` cannot be given a value"));
+    assert!(stderr.contains("extern_invariants.kasm:11:0"));
+    assert!(stderr.contains("2 | .extern .value counter"));
+}
+
+#[test]
+fn extern_function_defined() {
+    let stderr = extern_invariants_stderr();
+
+    assert!(stderr.contains("error: external symbol `helper` cannot be given a value"));
+    assert!(stderr.contains("extern_invariants.kasm:7:0"));
+    assert!(stderr.contains("3 | .extern .func helper"));
+}
+
+#[test]
+fn extern_declared_after_value() {
+    let stderr = extern_invariants_stderr();
+
+    assert!(stderr.contains("error: external symbol `late` cannot be given a value"));
+    assert!(stderr.contains("extern_invariants.kasm:12:0"));
+    assert!(stderr.contains("15 | .extern .value late"));
+}
+
+#[test]
+fn declared_symbol_never_defined() {
+    let stderr = extern_invariants_stderr();
+
+    assert!(stderr.contains("error: symbol `missing` declared but never given a value"));
+    assert!(stderr.contains("extern_invariants.kasm:4:8"));
+
+    // Every broken symbol is reported, not only the first
+    assert_eq!(stderr.matches("error:").count(), 5);
+}
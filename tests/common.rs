@@ -1,3 +1,6 @@
+// Each test binary that includes this module only uses some of its helpers
+#![allow(dead_code)]
+
 use kasm::{assemble_path, AssemblyOutput, Config};
 use kerbalobjects::ko::symbols::{SymBind, SymType};
 use kerbalobjects::ko::KOFile;
@@ -12,21 +15,38 @@ pub struct AssemblyTestInput {
     pub expected_code: Vec<(String, Vec<Opcode>)>,
 }
 
-pub fn run_assembly_test(input: AssemblyTestInput) {
-    let config = Config {
-        emit_errors: true,
-        emit_warnings: true,
+pub fn test_config() -> Config {
+    Config {
         root_dir: PathBuf::from("./tests/"),
-        run_preprocessor: false,
-        preprocess_only: false,
-        include_path: None,
-        file_sym_name: None,
         comment: String::from("KASM test"),
-    };
+        ..Config::default()
+    }
+}
+
+/// Assembles a test source file and returns the bytes of the resulting object file
+pub fn assemble_to_bytes(file_name_base: &str) -> Vec<u8> {
+    let output = assemble_path(
+        &PathBuf::from(format!("./tests/sources/{}.kasm", file_name_base)),
+        test_config(),
+    )
+    .unwrap();
+
+    match output {
+        AssemblyOutput::Object(ko) => {
+            let mut file_buffer = Vec::with_capacity(2048);
+
+            ko.write(&mut file_buffer);
+
+            file_buffer
+        }
+        _ => panic!(),
+    }
+}
 
+pub fn run_assembly_test(input: AssemblyTestInput) {
     let output = assemble_path(
         &PathBuf::from(format!("./tests/sources/{}.kasm", &input.file_name_base)),
-        config,
+        test_config(),
     )
     .unwrap();
 
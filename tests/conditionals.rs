@@ -0,0 +1,413 @@
+use kasm::{assemble_string, check_string, AssemblyOutput, Config};
+
+mod common;
+
+// Creates a Config suitable for only running the preprocessor
+fn conditionals_config() -> Config {
+    Config {
+        emit_errors: false,
+        emit_warnings: false,
+        preprocess_only: true,
+        ..common::test_config()
+    }
+}
+
+// Preprocesses the source with the provided command line definitions, and returns the operand of
+// every push that is left
+fn pushed(source: &str, defines: &[&str]) -> Vec<String> {
+    let mut config = conditionals_config();
+    config.defines = defines.iter().map(|define| define.to_string()).collect();
+
+    let output = match assemble_string(source.to_string(), config).expect("Preprocessing failed") {
+        AssemblyOutput::Source(output) => output,
+        AssemblyOutput::Object(_) => panic!("Expected preprocessed source"),
+    };
+
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("push "))
+        .map(str::to_string)
+        .collect()
+}
+
+fn fails(source: &str) -> bool {
+    assemble_string(source.to_string(), conditionals_config()).is_err()
+}
+
+#[test]
+fn elif_chain_selects_first_true_clause() {
+    let source = ".if LEVEL == 1\npush 1\n.elif LEVEL == 2\npush 2\n.elif LEVEL >= 2\npush 3\n.else\npush 4\n.endif\n";
+
+    assert_eq!(pushed(source, &["LEVEL=1"]), ["1"]);
+    assert_eq!(pushed(source, &["LEVEL=2"]), ["2"]);
+    assert_eq!(pushed(source, &["LEVEL=3"]), ["3"]);
+    assert_eq!(pushed(source, &["LEVEL=0"]), ["4"]);
+}
+
+#[test]
+fn elif_chain_without_else() {
+    let source = ".if LEVEL == 1\npush 1\n.elif LEVEL == 2\npush 2\n.endif\npush 0\n";
+
+    assert_eq!(pushed(source, &["LEVEL=2"]), ["2", "0"]);
+    assert_eq!(pushed(source, &["LEVEL=5"]), ["0"]);
+}
+
+#[test]
+fn empty_true_clause_stops_chain() {
+    let source = ".if 1\n.elif 1\npush 2\n.else\npush 3\n.endif\n";
+
+    assert!(pushed(source, &[]).is_empty());
+}
+
+#[test]
+fn inverted_clauses() {
+    let source = ".ifn LEVEL\npush 0\n.elifn LEVEL - 1\npush 1\n.else\npush 2\n.endif\n";
+
+    assert_eq!(pushed(source, &["LEVEL=0"]), ["0"]);
+    assert_eq!(pushed(source, &["LEVEL=1"]), ["1"]);
+    assert_eq!(pushed(source, &["LEVEL=2"]), ["2"]);
+}
+
+#[test]
+fn ifdef_combined_with_expressions() {
+    let source = ".ifdef DEBUG\npush 1\n.elif LEVEL > 1\npush 2\n.elifndef FAST\npush 3\n.elifdef SMALL\npush 4\n.else\npush 5\n.endif\n";
+
+    assert_eq!(pushed(source, &["DEBUG", "LEVEL=5"]), ["1"]);
+    assert_eq!(pushed(source, &["LEVEL=5"]), ["2"]);
+    assert_eq!(pushed(source, &["LEVEL=0"]), ["3"]);
+    assert_eq!(pushed(source, &["LEVEL=0", "FAST", "SMALL"]), ["4"]);
+    assert_eq!(pushed(source, &["LEVEL=0", "FAST"]), ["5"]);
+}
+
+#[test]
+fn expression_after_ifdef_not_evaluated() {
+    // LEVEL isn't defined, which would be an error if the .elif were evaluated
+    let source = ".ifdef DEBUG\npush 1\n.elif LEVEL > 1\npush 2\n.endif\n";
+
+    assert_eq!(pushed(source, &["DEBUG"]), ["1"]);
+}
+
+#[test]
+fn nested_conditionals() {
+    let source = ".if OUTER\n.if INNER\npush 1\n.else\npush 2\n.endif\npush 3\n.elif INNER\n.ifdef DEBUG\npush 4\n.endif\npush 5\n.else\npush 6\n.endif\n";
+
+    assert_eq!(pushed(source, &["OUTER=1", "INNER=1"]), ["1", "3"]);
+    assert_eq!(pushed(source, &["OUTER=1", "INNER=0"]), ["2", "3"]);
+    assert_eq!(pushed(source, &["OUTER=0", "INNER=1", "DEBUG"]), ["4", "5"]);
+    assert_eq!(pushed(source, &["OUTER=0", "INNER=1"]), ["5"]);
+    assert_eq!(pushed(source, &["OUTER=0", "INNER=0"]), ["6"]);
+}
+
+#[test]
+fn nested_else_belongs_to_inner_conditional() {
+    // The inner .else and .endif close the inner .if, so the outer one continues afterwards
+    let source = ".if 0\n.if 1\npush 1\n.else\npush 2\n.endif\n.else\npush 3\n.endif\n";
+
+    assert_eq!(pushed(source, &[]), ["3"]);
+}
+
+#[test]
+fn nested_conditionals_in_macro() {
+    let source = ".macro PICK 1\n.if &1 == 1\npush 1\n.elif &1 == 2\n.ifdef DEBUG\npush 2\n.else\npush 3\n.endif\n.endif\n.endmacro\nPICK(1)\nPICK(2)\nPICK(3)\n";
+
+    assert_eq!(pushed(source, &[]), ["1", "3"]);
+    assert_eq!(pushed(source, &["DEBUG"]), ["1", "2"]);
+}
+
+#[test]
+fn clause_after_else() {
+    let invalid = [
+        ".if 1\n.else\n.elif 1\n.endif\n",
+        ".if 1\n.else\n.elifdef DEBUG\n.endif\n",
+        ".if 1\n.else\n.else\n.endif\n",
+        ".if 1\n.if 1\n.endif\n.else\n.elifn 0\n.endif\n",
+    ];
+
+    for source in invalid {
+        assert!(fails(source), "{:?} was accepted", source);
+    }
+}
+
+#[test]
+fn unterminated_conditionals() {
+    let invalid = [
+        ".if 1\npush 1\n",
+        ".if 1\n.elif 1\n",
+        ".if 1\n.else\n",
+        ".if 1\n.if 1\n.endif\n",
+        ".elif 1\n.endif\n",
+        ".else\n",
+        ".endif\n",
+    ];
+
+    for source in invalid {
+        assert!(fails(source), "{:?} was accepted", source);
+    }
+}
+
+#[test]
+fn directives_in_nested_conditional_in_macro() {
+    // Nesting a conditional in a macro doesn't allow directives that the macro itself doesn't
+    let invalid = [
+        ".macro M 0\n.if 1\n.define X 1\n.endif\n.endmacro\n",
+        ".macro M 0\n.if 1\n.if 1\n.define X 1\n.endif\n.endif\n.endmacro\n",
+        ".macro M 0\n.if 1\n.ifdef Y\n.include \"file.kasm\"\n.endif\n.endif\n.endmacro\n",
+    ];
+
+    for source in invalid {
+        assert!(fails(source), "{:?} was accepted", source);
+    }
+}
+
+// Checks each condition against the definitions, returning which of them were true
+fn defined(definitions: &str, conditions: &[&str]) -> Vec<bool> {
+    conditions
+        .iter()
+        .map(|condition| {
+            let source = format!(
+                "{}{}\npush 1\n.else\npush 0\n.endif\n",
+                definitions, condition
+            );
+
+            pushed(&source, &[]) == ["1"]
+        })
+        .collect()
+}
+
+#[test]
+fn ifdef_macro_arity() {
+    let definitions = ".macro FOO 2\npush &1\npush &2\n.endmacro\n";
+
+    assert_eq!(
+        defined(
+            definitions,
+            &[".ifdef FOO 2", ".ifdef FOO 1-3", ".ifndef FOO 3"]
+        ),
+        [true, true, true]
+    );
+    assert_eq!(
+        defined(
+            definitions,
+            &[
+                ".ifdef FOO 1",
+                ".ifdef FOO 3-4",
+                ".ifdef FOO",
+                ".ifndef FOO 2"
+            ]
+        ),
+        [false, false, false, false]
+    );
+}
+
+#[test]
+fn ifdef_single_line_macro_arity() {
+    let definitions = ".define BAR(a, b) a\n";
+
+    assert_eq!(
+        defined(definitions, &[".ifdef BAR 2", ".ifdef BAR 1-2"]),
+        [true, true]
+    );
+    assert_eq!(
+        defined(
+            definitions,
+            &[".ifdef BAR 1", ".ifdef BAR 3-4", ".ifdef BAR"]
+        ),
+        [false, false, false]
+    );
+}
+
+#[test]
+fn ifdef_arity_selects_overload() {
+    let source = ".macro PUT 1\npush &1\n.endmacro\n.macro PUT 2\npush &1\npush &2\n.endmacro\n.ifdef PUT 2\nPUT(1,2)\n.elifdef PUT 1\nPUT(1)\n.endif\n";
+
+    assert_eq!(pushed(source, &[]), ["1", "2"]);
+}
+
+#[test]
+fn defined_in_expression() {
+    let source = ".if defined(DEBUG) && LEVEL > 1\npush 1\n.elif !defined( DEBUG )\npush 2\n.else\npush 3\n.endif\n";
+
+    assert_eq!(pushed(source, &["DEBUG", "LEVEL=2"]), ["1"]);
+    assert_eq!(pushed(source, &["DEBUG", "LEVEL=1"]), ["3"]);
+    assert_eq!(pushed(source, &["LEVEL=2"]), ["2"]);
+}
+
+#[test]
+fn defined_checks_multi_line_macros() {
+    let source = ".macro PUT 0\npush 1\n.endmacro\n.if defined(PUT) && defined(PUT) == !defined(OTHER)\nPUT\n.endif\n";
+
+    assert_eq!(pushed(source, &[]), ["1"]);
+}
+
+#[test]
+fn defined_missing_paren() {
+    assert!(fails(".if defined(DEBUG\npush 1\n.endif\n"));
+    assert!(fails(".if defined(1)\npush 1\n.endif\n"));
+}
+
+#[test]
+fn macro_adapts_to_section() {
+    let source = "
+.macro CONSTANT 2
+.ifsection data
+&1 .i32 &2
+.elifsection .rodata
+&1 .i32 -&2
+.else
+push &2
+.endif
+.endmacro
+
+.func
+_start:
+CONSTANT(first,1)
+.section .data
+CONSTANT(second,2)
+.section .text
+CONSTANT(third,3)
+.section .rodata
+CONSTANT(fourth,4)
+";
+
+    let output = match assemble_string(source.to_string(), conditionals_config()).unwrap() {
+        AssemblyOutput::Source(output) => output,
+        AssemblyOutput::Object(_) => panic!("Expected preprocessed source"),
+    };
+
+    let lines: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("push") || line.contains(".i32"))
+        .collect();
+
+    assert_eq!(
+        lines,
+        ["push 1", "second .i32 2", "push 3", "fourth .i32 -4"]
+    );
+}
+
+#[test]
+fn ifnsection() {
+    let source = ".ifnsection text\npush 1\n.endif\n.section .data\n.ifnsection .text\npush 2\n.elifnsection data\npush 3\n.endif\n";
+
+    assert_eq!(pushed(source, &[]), ["2"]);
+    assert!(fails(".ifsection\npush 1\n.endif\n"));
+    assert!(fails(".ifsection 1\npush 1\n.endif\n"));
+}
+
+// Every way of opening or continuing a conditional, along with a condition that is true and one
+// that is false. The expressions and definitions are checked against X, which is defined as 1
+const CLAUSE_VARIANTS: [(&str, &str, &str); 6] = [
+    ("if", "X", "X - 1"),
+    ("ifn", "X - 1", "X"),
+    ("ifdef", "X", "Y"),
+    ("ifndef", "Y", "X"),
+    ("ifsection", "text", "data"),
+    ("ifnsection", "data", "text"),
+];
+
+#[test]
+fn opening_clause_matrix() {
+    for (directive, truthy, falsy) in CLAUSE_VARIANTS {
+        let source = |condition| {
+            format!(
+                ".{} {}\npush 1\n.else\npush 2\n.endif\n",
+                directive, condition
+            )
+        };
+
+        assert_eq!(
+            pushed(&source(truthy), &["X=1"]),
+            ["1"],
+            ".{} {}",
+            directive,
+            truthy
+        );
+        assert_eq!(
+            pushed(&source(falsy), &["X=1"]),
+            ["2"],
+            ".{} {}",
+            directive,
+            falsy
+        );
+    }
+}
+
+#[test]
+fn continuing_clause_matrix() {
+    for (directive, truthy, falsy) in CLAUSE_VARIANTS {
+        let directive = directive.replacen("if", "elif", 1);
+        let source = |condition| {
+            format!(
+                ".if 0\npush 0\n.{} {}\npush 1\n.else\npush 2\n.endif\n",
+                directive, condition
+            )
+        };
+
+        assert_eq!(
+            pushed(&source(truthy), &["X=1"]),
+            ["1"],
+            ".{} {}",
+            directive,
+            truthy
+        );
+        assert_eq!(
+            pushed(&source(falsy), &["X=1"]),
+            ["2"],
+            ".{} {}",
+            directive,
+            falsy
+        );
+
+        // A clause that comes after a true one is never taken, whatever its condition is
+        let source = format!(
+            ".if 1\npush 0\n.{} {}\npush 1\n.else\npush 2\n.endif\n",
+            directive, truthy
+        );
+
+        assert_eq!(
+            pushed(&source, &["X=1"]),
+            ["0"],
+            ".{} after a true clause",
+            directive
+        );
+    }
+}
+
+#[test]
+fn integer_conditions() {
+    let source = ".if 0\npush 0\n.endif\n.if 1\npush 1\n.endif\n.if FLAG\npush 2\n.endif\n.if FLAG - 1\npush 3\n.else\npush 4\n.endif\n.if true\npush 5\n.endif\n";
+
+    assert_eq!(pushed(source, &["FLAG=1"]), ["1", "2", "4", "5"]);
+    assert_eq!(pushed(source, &["FLAG=0"]), ["1", "3", "5"]);
+    assert_eq!(pushed(source, &["FLAG=-2"]), ["1", "2", "3", "5"]);
+}
+
+#[test]
+fn chained_comparison() {
+    for source in [
+        ".if 1 < LEVEL < 3\n.endif\n",
+        ".if LEVEL == 1 != false\n.endif\n",
+    ] {
+        let mut config = conditionals_config();
+        config.defines = vec![String::from("LEVEL=2")];
+
+        let (success, diagnostics) = check_string(source.to_string(), config);
+
+        assert!(!success);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "chained comparisons are not allowed; use `&&`"
+        );
+    }
+
+    // Parenthesized comparisons, or comparisons of different precedence, aren't chained
+    assert_eq!(
+        pushed(
+            ".if (1 < LEVEL) == true\npush 1\n.endif\n.if 1 < LEVEL == LEVEL < 3\npush 2\n.endif\n",
+            &["LEVEL=2"]
+        ),
+        ["1", "2"]
+    );
+}
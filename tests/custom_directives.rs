@@ -0,0 +1,71 @@
+use kasm::preprocessor::directives::CustomDirectives;
+use kasm::{assemble_string, check_string, Config};
+
+mod common;
+use common::test_config;
+
+// A directive that pushes its argument twice, and errors if it isn't given one
+fn twice_config() -> Config {
+    let mut directives = CustomDirectives::new();
+
+    directives.register(".twice", |call, session| {
+        let argument: String = call
+            .arguments
+            .iter()
+            .map(|token| token.resolve(session))
+            .collect();
+
+        if argument.trim().is_empty() {
+            session
+                .struct_span_error(call.span, format!("`{}` needs a value", call.name))
+                .emit();
+
+            return Err(());
+        }
+
+        Ok(format!("push {0}\npush {0}", argument.trim()))
+    });
+
+    let mut config = test_config();
+    config.directives = directives;
+
+    config
+}
+
+fn object_bytes(source: &str, config: Config) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    assemble_string(source.to_string(), config)
+        .expect("Assembly failed")
+        .write(&mut bytes);
+
+    bytes
+}
+
+#[test]
+fn custom_directive_expands() {
+    let source = ".define VALUE 5\n.func\n_start:\n    .twice VALUE\n    add\n";
+    let expected = ".func\n_start:\n    push 5\n    push 5\n    add\n";
+
+    assert_eq!(
+        object_bytes(source, twice_config()),
+        object_bytes(expected, test_config())
+    );
+}
+
+#[test]
+fn custom_directive_errors() {
+    let (success, diagnostics) =
+        check_string(".func\n_start:\n    .twice\n".to_string(), twice_config());
+
+    assert!(!success);
+    assert_eq!(diagnostics[0].message, "`.twice` needs a value");
+
+    let (success, diagnostics) = check_string(
+        ".func\n_start:\n    .thrice 1\n".to_string(),
+        twice_config(),
+    );
+
+    assert!(!success);
+    assert_eq!(diagnostics[0].message, "unknown directive `.thrice`");
+}
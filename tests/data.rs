@@ -0,0 +1,462 @@
+use kasm::{assemble_string, check_string, AssemblyOutput, Config};
+use kerbalobjects::ko::KOFile;
+use kerbalobjects::KOSValue;
+
+fn test_config() -> Config {
+    Config {
+        emit_errors: false,
+        emit_warnings: false,
+        ..Config::default()
+    }
+}
+
+// Assembles a data section containing the given entries, which are each used once so that
+// they are kept
+fn assemble_data(entries: &[(&str, &str)]) -> Result<KOFile, ()> {
+    let mut source = String::from(".section .data\n");
+
+    for (name, entry) in entries {
+        source.push_str(&format!("{} {}\n", name, entry));
+    }
+
+    source.push_str(".section .text\n.func\n_start:\n");
+
+    for (name, entry) in entries {
+        // Only pushv can push the value types, such as .bv and .i32v
+        let instruction = if entry.split(' ').next().is_some_and(|ty| ty.ends_with('v')) {
+            "pushv"
+        } else {
+            "push"
+        };
+
+        source.push_str(&format!("    {} {}\n", instruction, name));
+    }
+
+    match assemble_string(source, test_config())? {
+        AssemblyOutput::Object(ko) => Ok(ko.get()),
+        AssemblyOutput::Source(_) => panic!("Expected object file"),
+    }
+}
+
+// Finds the value of the data symbol with the given name
+fn symbol_value(ko: &KOFile, name: &str) -> KOSValue {
+    let symtab = ko.sym_tab_by_name(".symtab").unwrap();
+    let symstrtab = ko.str_tab_by_name(".symstrtab").unwrap();
+    let data = ko.data_section_by_name(".data").unwrap();
+
+    let name_index = symstrtab.position(name).unwrap();
+    let symbol = symtab.find_by_name(name_index).unwrap();
+
+    data.get(symbol.value_idx).unwrap().clone()
+}
+
+#[test]
+fn boolean_from_comparison() {
+    let ko = assemble_data(&[
+        ("greater", ".b 3 > 2"),
+        ("equal", ".b 1 + 1 == 3"),
+        ("less_equal", ".bv 2.5 <= 2"),
+        ("mixed_greater", ".b 2.5 > 2"),
+        ("mixed_less", ".bv true < 2"),
+    ])
+    .expect("Assembly failed");
+
+    assert_eq!(symbol_value(&ko, "greater"), KOSValue::Bool(true));
+    assert_eq!(symbol_value(&ko, "equal"), KOSValue::Bool(false));
+    assert_eq!(symbol_value(&ko, "less_equal"), KOSValue::BoolValue(false));
+    assert_eq!(symbol_value(&ko, "mixed_greater"), KOSValue::Bool(true));
+    assert_eq!(symbol_value(&ko, "mixed_less"), KOSValue::BoolValue(true));
+}
+
+#[test]
+fn boolean_from_logical_expression() {
+    let ko = assemble_data(&[
+        ("either", ".b 1 == 2 || true"),
+        ("both", ".bv !(1 < 0) && 2 >= 2"),
+        ("neither", ".b false || !true"),
+    ])
+    .expect("Assembly failed");
+
+    assert_eq!(symbol_value(&ko, "either"), KOSValue::Bool(true));
+    assert_eq!(symbol_value(&ko, "both"), KOSValue::BoolValue(true));
+    assert_eq!(symbol_value(&ko, "neither"), KOSValue::Bool(false));
+}
+
+#[test]
+fn boolean_from_macro_comparison() {
+    let source = ".define LEVEL 3\n.section .data\nflag .b LEVEL >= 2\n.section .text\n.func\n_start:\n    push flag\n";
+
+    let ko = match assemble_string(source.to_string(), test_config()).expect("Assembly failed") {
+        AssemblyOutput::Object(ko) => ko.get(),
+        AssemblyOutput::Source(_) => panic!("Expected object file"),
+    };
+
+    assert_eq!(symbol_value(&ko, "flag"), KOSValue::Bool(true));
+}
+
+#[test]
+fn number_where_boolean_required() {
+    assert!(assemble_data(&[("number", ".b 3")]).is_err());
+    assert!(assemble_data(&[("sum", ".bv (3 > 2) + 1")]).is_err());
+}
+
+// Assembles the source, with or without strict symbol declarations
+fn assemble_strict(source: &str, strict: bool) -> Result<(), ()> {
+    let mut config = test_config();
+    config.strict = strict;
+
+    assemble_string(source.to_string(), config).map(|_| ())
+}
+
+#[test]
+fn strict_rejects_implicit_declaration() {
+    let source = ".section .data\ncount .i32v 3\n.section .text\n.func\n_start:\n    pushv count\n";
+
+    assert!(assemble_strict(source, false).is_ok());
+    assert!(assemble_strict(source, true).is_err());
+}
+
+#[test]
+fn strict_rejects_type_only_declaration() {
+    let source = ".type .value count\n.section .data\ncount .i32v 3\n.section .text\n.func\n_start:\n    pushv count\n";
+
+    assert!(assemble_strict(source, false).is_ok());
+    assert!(assemble_strict(source, true).is_err());
+}
+
+#[test]
+fn strict_accepts_declared_symbols() {
+    let source = ".local count\n.global name\n.section .data\ncount .i32v 3\nname .s \"kasm\"\n.section .text\n.func\n_start:\n    pushv count\n    push name\n";
+
+    assert!(assemble_strict(source, true).is_ok());
+}
+
+// Assembles source code into an object file
+fn assemble(source: &str) -> Result<KOFile, ()> {
+    match assemble_string(source.to_string(), test_config())? {
+        AssemblyOutput::Object(ko) => Ok(ko.get()),
+        AssemblyOutput::Source(_) => panic!("Expected object file"),
+    }
+}
+
+#[test]
+fn label_address_table() {
+    let source = ".section .data\n\
+                  table0 .i32 first\n\
+                  table1 .i32v second\n\
+                  .section .text\n\
+                  .func\n\
+                  first:\n\
+                  \tpush table0\n\
+                  \tpushv table1\n\
+                  \tret 0\n\
+                  .func\n\
+                  second:\n\
+                  \tnop\n";
+
+    let ko = assemble(source).expect("Assembly failed");
+
+    assert_eq!(symbol_value(&ko, "table0"), KOSValue::Int32(0));
+    assert_eq!(symbol_value(&ko, "table1"), KOSValue::ScalarInt(3));
+}
+
+#[test]
+fn label_address_invalid() {
+    let invalid = [
+        // Not a label at all
+        ".section .data\nentry .i32 missing\n.section .text\n.func\n_start:\n\tpush entry\n",
+        // A value symbol given later rather than a label
+        ".section .data\nentry .i32 value\nvalue .i32 1\n.section .text\n.func\n_start:\n\tpush entry\n",
+        // Object files can't relocate data
+        ".extern .func other\n.section .data\nentry .i32 other\n.section .text\n.func\n_start:\n\tpush entry\n",
+    ];
+
+    for source in invalid {
+        assert!(assemble(source).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn integer_type_aliases() {
+    let ko = assemble_data(&[
+        ("byte", ".byte 5"),
+        ("i8", ".i8 5"),
+        ("half", ".half 5"),
+        ("i16", ".i16 5"),
+        ("word", ".word 5"),
+        ("long", ".long 5"),
+        ("i32", ".i32 5"),
+    ])
+    .expect("Assembly failed");
+
+    assert_eq!(symbol_value(&ko, "byte"), symbol_value(&ko, "i8"));
+    assert_eq!(symbol_value(&ko, "half"), symbol_value(&ko, "i16"));
+    assert_eq!(symbol_value(&ko, "word"), symbol_value(&ko, "i32"));
+    assert_eq!(symbol_value(&ko, "long"), symbol_value(&ko, "i32"));
+    assert_eq!(symbol_value(&ko, "word"), KOSValue::Int32(5));
+}
+
+#[test]
+fn custom_section() {
+    let ko = assemble(
+        ".func\n_start:\n    push first\n    push second\n.section rodata\nfirst .i32 5\n.section .data\nsecond .i32 6\n",
+    )
+    .expect("Assembly failed");
+
+    let symtab = ko.sym_tab_by_name(".symtab").unwrap();
+    let symstrtab = ko.str_tab_by_name(".symstrtab").unwrap();
+    let rodata = ko.data_section_by_name("rodata").unwrap();
+
+    let name_index = symstrtab.position("first").unwrap();
+    let symbol = symtab.find_by_name(name_index).unwrap();
+
+    assert_eq!(symbol.sh_idx, rodata.section_index());
+    assert_eq!(rodata.get(symbol.value_idx), Some(&KOSValue::Int32(5)));
+    assert_eq!(symbol_value(&ko, "second"), KOSValue::Int32(6));
+}
+
+#[test]
+fn custom_section_invalid_contents() {
+    let invalid = [
+        ".section .rodata\n.func\n_start:\n    nop\n",
+        ".section .rodata\n.incbin \"tests/sources/incbin/empty.bin\"\n",
+        ".section .symtab\nvalue .i32 1\n",
+        ".func\nrodata:\n    push value\n.section rodata\nvalue .i32 1\n",
+    ];
+
+    for source in invalid {
+        assert!(assemble(source).is_err());
+    }
+}
+
+// Checks a single data entry that is out of range, and returns the error message along with the
+// range that it shows
+fn out_of_range(entry: &str) -> (String, String) {
+    let source = format!(".section .data\nvalue {}\n", entry);
+    let (success, diagnostics) = check_string(source, test_config());
+
+    assert!(!success, "{}", entry);
+    assert_eq!(diagnostics.len(), 1, "{}", entry);
+
+    let diagnostic = &diagnostics[0];
+
+    (diagnostic.message.clone(), diagnostic.spans[0].1.clone())
+}
+
+#[test]
+fn integer_overflow_shows_range() {
+    let cases = [
+        (".i8 128", "`128` does not fit into `.i8`", "-128..=127"),
+        (".i8 -129", "`-129` does not fit into `.i8`", "-128..=127"),
+        (".i8 0x100", "`256` does not fit into `.i8`", "-128..=127"),
+        (
+            ".i16 40000",
+            "`40000` does not fit into `.i16`",
+            "-32768..=32767",
+        ),
+        (
+            ".i32 3000000000",
+            "`3000000000` does not fit into `.i32`",
+            "-2147483648..=2147483647",
+        ),
+        (
+            ".i32v 2147483647 + 1",
+            "`2147483647 + 1` does not fit into `.i32v`",
+            "-2147483648..=2147483647",
+        ),
+        (
+            ".i8 3000000000",
+            "`3000000000` does not fit into `.i8`",
+            "-128..=127",
+        ),
+    ];
+
+    for (entry, message, range) in cases {
+        let (found_message, found_label) = out_of_range(entry);
+
+        assert_eq!(found_message, format!("value {}", message));
+        assert_eq!(found_label, format!("expected {}", range));
+    }
+}
+
+#[test]
+fn integer_limits_fit() {
+    let ko = assemble_data(&[
+        ("small", ".i8 -128"),
+        ("large", ".i16 32767"),
+        ("max", ".i32 2147483647"),
+    ])
+    .expect("Assembly failed");
+
+    assert_eq!(symbol_value(&ko, "small"), KOSValue::Byte(-128));
+    assert_eq!(symbol_value(&ko, "large"), KOSValue::Int16(32767));
+    assert_eq!(symbol_value(&ko, "max"), KOSValue::Int32(2147483647));
+}
+
+// Assembles the source and returns the size that ended up in the symbol table for each name
+fn symbol_sizes(source: &str, names: &[&str]) -> Vec<u16> {
+    let ko = assemble(source).unwrap();
+
+    let symtab = ko.sym_tab_by_name(".symtab").unwrap();
+    let symstrtab = ko.str_tab_by_name(".symstrtab").unwrap();
+
+    names
+        .iter()
+        .map(|name| {
+            let position = symstrtab.position(name).unwrap();
+
+            symtab.find_by_name(position).unwrap().size
+        })
+        .collect()
+}
+
+#[test]
+fn size_directive_recorded() {
+    let source = ".extern .func other\n.section .data\ntable .i32 5\nplain .i32 5\n.size table, 4 * 4\n.size other, 4\n.section .text\n.func\n_start:\n    push table\n    push plain\n.size _start, 2\n.size _start, 2\n";
+
+    let sizes = symbol_sizes(source, &["table", "plain", "other", "_start"]);
+
+    // A symbol without a .size keeps the size of its value
+    assert_eq!(sizes, vec![16, 5, 4, 2]);
+}
+
+#[test]
+fn size_of_unknown_symbol() {
+    let source = ".size missing, 4\n.func\n_start:\n    nop\n";
+    let (success, diagnostics) = check_string(String::from(source), test_config());
+
+    assert!(!success);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "cannot give a size to undeclared symbol `missing`"
+    );
+}
+
+#[test]
+fn conflicting_sizes_show_prior_span() {
+    let source = ".section .data\ntable .i32 5\n.size table, 16\n.size table, 8\n";
+    let (success, diagnostics) = check_string(String::from(source), test_config());
+
+    assert!(!success);
+    assert_eq!(diagnostics.len(), 1);
+
+    let diagnostic = &diagnostics[0];
+
+    assert_eq!(diagnostic.message, "conflicting symbol sizes");
+
+    // The label points at the symbol in the first .size
+    let (span, _) = diagnostic
+        .spans
+        .iter()
+        .find(|(_, label)| label == "size first given as 16 here")
+        .unwrap();
+
+    assert_eq!(&source[span.start..span.end], "table");
+    assert_eq!(span.start, source.find(".size").unwrap() + 6);
+}
+
+#[test]
+fn integer_from_earlier_symbols() {
+    let ko = assemble_data(&[
+        ("base", ".i32 40"),
+        ("count", ".i32 base + 2"),
+        ("small", ".i8 count * 2 - base"),
+        ("copy", ".i16 count"),
+        ("copy32", ".i32 count"),
+    ])
+    .expect("Assembly failed");
+
+    assert_eq!(symbol_value(&ko, "count"), KOSValue::Int32(42));
+    assert_eq!(symbol_value(&ko, "small"), KOSValue::Byte(44));
+    assert_eq!(symbol_value(&ko, "copy"), KOSValue::Int16(42));
+    assert_eq!(symbol_value(&ko, "copy32"), KOSValue::Int32(42));
+}
+
+// Checks a data section that uses a symbol in an expression that it can't, and returns the error
+fn symbol_error(entries: &str) -> String {
+    let source = format!(".section .data\n{}", entries);
+    let (success, diagnostics) = check_string(source, test_config());
+
+    assert!(!success, "{}", entries);
+
+    diagnostics[0].message.clone()
+}
+
+#[test]
+fn integer_from_invalid_symbols() {
+    assert_eq!(
+        symbol_error("count .i32 base + 2\nbase .i32 40\n"),
+        "symbol `base` is used before it is given a value"
+    );
+    assert_eq!(
+        symbol_error("count .i32 missing * 2\n"),
+        "symbol `missing` is used before it is given a value"
+    );
+    assert_eq!(
+        symbol_error("ratio .f64 1.5\ncount .i32 ratio + 1\n"),
+        "symbol `ratio` does not have an integer value"
+    );
+    assert_eq!(
+        symbol_error("name .s \"kasm\"\ncount .i32 -name\n"),
+        "symbol `name` does not have an integer value"
+    );
+}
+
+#[test]
+fn symbols_only_in_data_expressions() {
+    let source =
+        ".section .data\nbase .i32 40\n.section .text\n.func\n_start:\n    push 1 + base\n";
+    let (success, diagnostics) = check_string(source.to_string(), test_config());
+
+    assert!(!success);
+    assert_eq!(
+        diagnostics[0].message,
+        "expected parenthesis, constant, or operator"
+    );
+}
+
+#[test]
+fn negative_values() {
+    let ko = assemble_data(&[
+        ("byte", ".i8 -1"),
+        ("half", ".i16 -300"),
+        ("word", ".i32 -5"),
+        ("word_value", ".i32v -7"),
+        ("grouped", ".i32 -(2 + 3)"),
+        ("minimum", ".i32 -2147483648"),
+        ("double", ".f64 -2.5"),
+        ("double_value", ".f64v -1.5"),
+    ])
+    .expect("Assembly failed");
+
+    assert_eq!(symbol_value(&ko, "byte"), KOSValue::Byte(-1));
+    assert_eq!(symbol_value(&ko, "half"), KOSValue::Int16(-300));
+    assert_eq!(symbol_value(&ko, "word"), KOSValue::Int32(-5));
+    assert_eq!(symbol_value(&ko, "word_value"), KOSValue::ScalarInt(-7));
+    assert_eq!(symbol_value(&ko, "grouped"), KOSValue::Int32(-5));
+    assert_eq!(symbol_value(&ko, "minimum"), KOSValue::Int32(i32::MIN));
+    assert_eq!(symbol_value(&ko, "double"), KOSValue::Double(-2.5));
+    assert_eq!(
+        symbol_value(&ko, "double_value"),
+        KOSValue::ScalarDouble(-1.5)
+    );
+}
+
+#[test]
+fn negative_boolean() {
+    for entry in [".b -true", ".i32 -false", ".bv -(1 == 1)"] {
+        let source = format!(".section .data\nvalue {}\n", entry);
+        let (success, diagnostics) = check_string(source, test_config());
+
+        assert!(!success, "{}", entry);
+
+        let diagnostic = &diagnostics[0];
+
+        assert_eq!(diagnostic.message, "`-` operator invalid for booleans");
+        assert_eq!(
+            diagnostic.children[0].message,
+            "use `!` to invert a boolean"
+        );
+    }
+}
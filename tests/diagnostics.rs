@@ -0,0 +1,457 @@
+use std::process::Command;
+
+use kasm::errors::{SourceFile, Span};
+
+// Runs the kasm binary with the given arguments, expecting it to fail, and returns everything it
+// printed to stderr
+fn run_kasm_failing(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_kasm"))
+        .args(args)
+        .output()
+        .expect("Failed to run kasm");
+
+    assert!(!output.status.success());
+
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn multi_line_span() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/multi_line_span.kasm",
+        "-o",
+        "tests/multi_line_span.ko",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().map(str::trim_end).collect();
+
+    assert_eq!(
+        lines[3..7],
+        [
+            "1 | .rep 2 ==\\",
+            "  |      ^^^^^",
+            "2 |     2",
+            "  |     ^",
+        ]
+    );
+}
+
+#[test]
+fn long_line_trimmed() {
+    let stderr = run_kasm_failing(&["tests/sources/long_line.kasm", "-o", "tests/long_line.ko"]);
+
+    let lines: Vec<&str> = stderr.lines().map(str::trim_end).collect();
+
+    // The 500 column line is trimmed down to 120 columns, plus the markers on either side
+    let line = lines[3].strip_prefix("3 | ").unwrap();
+
+    assert!(line.starts_with("... "));
+    assert!(line.ends_with("..."));
+    assert_eq!(line.len(), 3 + 120 + 3);
+
+    let underline = lines[4].strip_prefix("  | ").unwrap();
+
+    assert_eq!(underline.trim_start(), "^^^^^^^^^^^");
+    assert_eq!(
+        line.find("NOT_DEFINED"),
+        Some(underline.len() - underline.trim_start().len())
+    );
+}
+
+#[test]
+fn long_line_not_trimmed() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/long_line.kasm",
+        "-o",
+        "tests/long_line.ko",
+        "--max-line-width",
+        "0",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(lines[3].len(), "3 | ".len() + 500);
+}
+
+// Returns the location, source line, and underline of the first diagnostic when tabs are shown
+// as the given number of columns
+fn tab_width_diagnostic(tab_width: &str) -> Vec<String> {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/tab_width.kasm",
+        "-o",
+        "tests/tab_width.ko",
+        "--tab-width",
+        tab_width,
+    ]);
+
+    let lines: Vec<String> = stderr
+        .lines()
+        .map(|line| line.trim_end().to_owned())
+        .collect();
+
+    vec![lines[1].clone(), lines[3].clone(), lines[4].clone()]
+}
+
+#[test]
+fn tab_width_2() {
+    assert_eq!(
+        tab_width_diagnostic("2"),
+        [
+            " -->  tests/sources/tab_width.kasm:3:8",
+            "3 |   push  NOT_DEFINED(12)",
+            "  |         ^^^^^^^^^^^",
+        ]
+    );
+}
+
+#[test]
+fn tab_width_8() {
+    assert_eq!(
+        tab_width_diagnostic("8"),
+        [
+            " -->  tests/sources/tab_width.kasm:3:20",
+            "3 |         push        NOT_DEFINED(12)",
+            "  |                     ^^^^^^^^^^^",
+        ]
+    );
+}
+
+#[test]
+fn func_followed_by_end_of_file() {
+    let stderr = run_kasm_failing(&["tests/sources/func_eof.kasm", "-o", "tests/func_eof.ko"]);
+
+    let lines: Vec<&str> = stderr.lines().map(str::trim_end).collect();
+
+    assert_eq!(
+        lines[0],
+        "error: `.func` must be followed by a function label"
+    );
+    assert_eq!(lines[3..5], ["1 | .func", "  | ^^^^^"]);
+    assert_eq!(lines[6], " = note: found end of file");
+    assert!(lines[7].starts_with(" = help: "));
+}
+
+#[test]
+fn directive_at_end_of_file() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/directive_eof.kasm",
+        "-o",
+        "tests/directive_eof.ko",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().map(str::trim_end).collect();
+
+    assert_eq!(lines[0], "error: expected identifier");
+    assert_eq!(lines[3..5], ["2 | .macro", "  | ^^^^^^ found end of file"]);
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn snippet_at_end_of_file() {
+    let file =
+        |source: &str| SourceFile::new(String::from("<input>"), None, None, source.to_string(), 0);
+
+    // Without a newline at the end, the marker goes just past the last line
+    let source = "push 1\n.macro";
+    let snippet = file(source).span_to_snippet(&Span::new(source.len(), source.len(), 0), 4);
+
+    assert_eq!(snippet.line, ".macro ");
+    assert_eq!((snippet.start_col, snippet.end_col), (6, 7));
+
+    // With one, it is on the empty line after it
+    let source = "push 1\n";
+    let snippet = file(source).span_to_snippet(&Span::new(source.len(), source.len(), 0), 4);
+
+    assert_eq!(snippet.line, " ");
+    assert_eq!((snippet.start_col, snippet.end_col), (0, 1));
+
+    // A newline is shown at the end of its own line, not along with the next one
+    let snippet = file("push 1\npop\n").span_to_snippet(&Span::new(6, 7, 0), 4);
+
+    assert_eq!(snippet.line, "push 1 ");
+    assert_eq!((snippet.start_col, snippet.end_col), (6, 7));
+}
+
+#[test]
+fn func_followed_by_instruction() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/func_no_label.kasm",
+        "-o",
+        "tests/func_no_label.ko",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().map(str::trim_end).collect();
+
+    assert_eq!(
+        lines[0],
+        "error: `.func` must be followed by a function label"
+    );
+    assert_eq!(lines[3..5], ["1 | .func", "  | ^^^^^"]);
+    assert_eq!(lines[7..9], ["2 | push 1", "  | ^^^^ found `push`"]);
+}
+
+#[test]
+fn missing_comma_between_operands() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/missing_comma.kasm",
+        "-o",
+        "tests/missing_comma.ko",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().map(str::trim_end).collect();
+
+    assert_eq!(lines[0], "error: expected `,` between operands");
+    assert_eq!(lines[3..5], ["3 |     bscp 1  2", "  |           ^^"]);
+}
+
+#[test]
+fn span_join() {
+    let first = Span::new(4, 8, 1);
+    let second = Span::new(10, 12, 1);
+
+    assert_eq!(first.join(second), Span::new(4, 12, 1));
+    assert_eq!(second.join(first), Span::new(4, 12, 1));
+    assert_eq!(first.join(Span::new(5, 6, 1)), first);
+}
+
+#[test]
+fn if_condition_span() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/if_string_condition.kasm",
+        "-p",
+        "-o",
+        "tests/if_string_condition.kasm",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().map(str::trim_end).collect();
+
+    // This used to start at the beginning of the file
+    assert_eq!(lines[1], " -->  tests/sources/if_string_condition.kasm:2:4");
+    assert_eq!(lines[3..5], ["2 | .if \"a\"", "  |     ^^^"]);
+}
+
+#[test]
+fn color_never() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/func_eof.kasm",
+        "-o",
+        "tests/func_eof.ko",
+        "--color=never",
+    ]);
+
+    assert!(stderr.contains("must be followed by a function label"));
+    assert!(!stderr.contains('\x1b'));
+}
+
+#[test]
+fn color_always() {
+    // stderr isn't a terminal here, so this would otherwise not be colored
+    let stderr = run_kasm_failing(&[
+        "tests/sources/func_eof.kasm",
+        "-o",
+        "tests/func_eof.ko",
+        "--color=always",
+    ]);
+
+    assert!(stderr.contains("\x1b["));
+}
+
+#[test]
+fn expansion_depth_exceeded() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/recursive_macros.kasm",
+        "-o",
+        "tests/recursive_macros.ko",
+        "--max-expansion-depth=3",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(
+        lines[0],
+        "error: expansion of macro `B` exceeded the maximum depth of 3"
+    );
+    assert_eq!(lines[1], " -->  tests/sources/recursive_macros.kasm:1:10");
+    assert!(
+        stderr.contains(" = note: expanded from `A` at tests/sources/recursive_macros.kasm:2:10\n")
+    );
+    assert!(
+        stderr.contains(" = note: expanded from `B` at tests/sources/recursive_macros.kasm:1:10\n")
+    );
+    assert!(
+        stderr.contains(" = note: expanded from `A` at tests/sources/recursive_macros.kasm:3:5\n")
+    );
+}
+
+#[test]
+fn unknown_tokens_coalesced() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/unknown_tokens.kasm",
+        "-o",
+        "tests/unknown_tokens.ko",
+    ]);
+
+    let errors: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with("error"))
+        .collect();
+
+    assert_eq!(
+        errors,
+        vec!["error: unknown tokens", "error: unknown token"]
+    );
+
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(lines[1], " -->  tests/sources/unknown_tokens.kasm:3:11");
+    assert_eq!(lines[4].trim_end(), "  |            ^^^");
+}
+
+#[test]
+fn misspelled_section_suggestion() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/misspelled_section.kasm",
+        "-o",
+        "tests/misspelled_section.ko",
+    ]);
+
+    assert!(stderr.contains(" = note: the current section is `.tex`\n"));
+    assert!(stderr.contains(" = help: did you mean `.section .text`?\n"));
+}
+
+#[test]
+fn bad_section_type_suggestion() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/bad_section.kasm",
+        "-o",
+        "tests/bad_section.ko",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(lines[0], "error: expected section type, found `.text:`");
+    assert!(stderr.contains(" = help: did you mean `.text`?\n"));
+    assert!(stderr.contains(
+        " = help: sections are `.text`, `.data`, or the name of a custom data section\n"
+    ));
+}
+
+#[test]
+fn macro_expansion_backtrace() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/macro_backtrace.kasm",
+        "-o",
+        "tests/macro_backtrace.ko",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(lines[0], "error: expected instruction, found `psh`");
+    assert_eq!(lines[1], " -->  tests/sources/macro_backtrace.kasm:3:4");
+    assert_eq!(lines[7], "8 |     PUSH_TWICE(1)");
+    assert_eq!(
+        lines[8],
+        "  |     ^^^^^^^^^^ in expansion of macro `PUSH_TWICE` invoked here"
+    );
+}
+
+#[test]
+fn nested_macro_expansion_backtrace() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/nested_macro_backtrace.kasm",
+        "-o",
+        "tests/nested_macro_backtrace.ko",
+    ]);
+
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(
+        lines[1],
+        " -->  tests/sources/nested_macro_backtrace.kasm:1:15"
+    );
+
+    // The innermost expansion comes first
+    assert_eq!(
+        &lines[5..],
+        [
+            "...",
+            "  |",
+            "2 | .define PUSH(x) OPCODE x",
+            "  |                 ^^^^^^ in expansion of macro `OPCODE` invoked here",
+            "...",
+            "  |",
+            "6 |     PUSH(1)",
+            "  |     ^^^^ in expansion of macro `PUSH` invoked here",
+            "  |",
+        ]
+    );
+}
+
+#[test]
+fn repeated_diagnostics_coalesced() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/repeated_diagnostics.kasm",
+        "-o",
+        "tests/repeated_diagnostics.ko",
+    ]);
+
+    // Every expansion after the first redeclares the symbol in the same place
+    let warnings: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with("warning"))
+        .collect();
+
+    assert_eq!(
+        warnings,
+        vec!["warning: redundant declaration of symbol binding"]
+    );
+
+    // Only the first expansion is shown
+    assert!(stderr.contains("6 | DECLARE_COUNTER\n"));
+    assert!(!stderr.contains("7 | DECLARE_COUNTER\n"));
+    assert!(stderr.contains(" = note: (and 2 more identical)\n"));
+
+    // A different diagnostic afterwards is still reported
+    assert!(stderr.contains("error: symbol `counter` declared but never given a value"));
+}
+
+#[test]
+fn not_utf8_input() {
+    let stderr = run_kasm_failing(&["tests/sources/not_utf8.kasm", "-o", "tests/not_utf8.ko"]);
+
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(
+        lines,
+        [
+            "error: file `tests/sources/not_utf8.kasm` is not valid UTF-8 text",
+            " = note: the first invalid byte is on line 3",
+            " = help: source files must be saved with the UTF-8 encoding",
+        ]
+    );
+}
+
+#[test]
+fn not_utf8_include() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/include_not_utf8.kasm",
+        "-o",
+        "tests/include_not_utf8.ko",
+    ]);
+
+    assert!(stderr.starts_with("error: file `"));
+    assert!(stderr.contains("not_utf8.kasm` is not valid UTF-8 text\n"));
+    assert!(stderr.contains("^^^^^^^^^^^^^^^ included here"));
+}
+
+#[test]
+fn misspelled_directive_suggestion() {
+    let stderr = run_kasm_failing(&[
+        "tests/sources/misspelled_directive.kasm",
+        "-o",
+        "tests/misspelled_directive.ko",
+    ]);
+
+    assert!(stderr.starts_with("error: unknown directive `.defien`\n"));
+    assert!(stderr.contains(" = help: did you mean `.define`?"));
+}
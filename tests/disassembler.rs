@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use kasm::{assemble_path, assemble_string, disassemble_path, AssemblyOutput, Config};
+
+fn test_config() -> Config {
+    Config {
+        emit_warnings: false,
+        root_dir: PathBuf::from("./tests/"),
+        run_preprocessor: false,
+        comment: String::from("KASM test"),
+        ..Config::default()
+    }
+}
+
+// Writes an assembled object file to the given path, but can panic
+fn write_object(output: AssemblyOutput, path: &Path) {
+    match output {
+        AssemblyOutput::Object(ko) => {
+            let mut file_buffer = Vec::with_capacity(2048);
+
+            ko.write(&mut file_buffer);
+
+            fs::write(path, &file_buffer).unwrap();
+        }
+        AssemblyOutput::Source(_) => panic!("Expected object file"),
+    }
+}
+
+// Assembles a test source, disassembles it, then assembles and disassembles the result again.
+// Returns both disassemblies, which should be identical if the round trip is lossless
+fn round_trip(file_name_base: &str) -> (String, String) {
+    let first_path = PathBuf::from(format!("tests/{}.ko", file_name_base));
+    let second_path = PathBuf::from(format!("tests/{}_round_trip.ko", file_name_base));
+
+    let output = assemble_path(
+        &PathBuf::from(format!("./tests/sources/{}.kasm", file_name_base)),
+        test_config(),
+    )
+    .unwrap();
+
+    write_object(output, &first_path);
+
+    let first = disassemble_path(&first_path, test_config()).unwrap();
+
+    let output = assemble_string(first.clone(), test_config()).unwrap();
+
+    write_object(output, &second_path);
+
+    let second = disassemble_path(&second_path, test_config()).unwrap();
+
+    (first, second)
+}
+
+#[test]
+fn round_trip_add_numbers() {
+    let (first, second) = round_trip("add_numbers");
+
+    assert_eq!(first, second);
+    assert!(first.contains("call #, \"print()\""));
+}
+
+#[test]
+fn round_trip_externs() {
+    let (first, second) = round_trip("externs");
+
+    assert_eq!(first, second);
+    assert!(first.contains(".extern .func other"));
+    assert!(first.contains(".extern .value data"));
+    assert!(first.contains("global_value .i32 2"));
+}
+
+#[test]
+fn round_trip_labels_and_values() {
+    let (first, second) = round_trip("disassembly");
+
+    assert_eq!(first, second);
+    assert!(first.contains("btr .L0"));
+    assert!(first.contains("jmp .L1"));
+    assert!(first.contains("push -5"));
+    assert!(first.contains("pushv true"));
+    assert!(first.contains("neg .f64v -1.0"));
+}
+
+#[test]
+fn disassemble_invalid_file() {
+    let mut config = test_config();
+    config.emit_errors = false;
+
+    assert!(disassemble_path(&PathBuf::from("tests/sources/externs.kasm"), config).is_err());
+}
+
+#[test]
+fn round_trip_custom_section() {
+    let (first, second) = round_trip("custom_section");
+
+    assert_eq!(first, second);
+    assert!(first.contains(".section .data\ncounter .i32 0\n"));
+    assert!(first.contains(".section .rodata\ngreeting .s \"hi\"\ntable_size .i32 3\n"));
+}
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use kasm::{assemble_string, AssemblyOutput, Config};
+use kerbalobjects::ko::KOFile;
+use kerbalobjects::KOSValue;
+
+fn test_config() -> Config {
+    Config {
+        emit_errors: false,
+        emit_warnings: false,
+        root_dir: PathBuf::new(),
+        run_preprocessor: false,
+        include_paths: vec![PathBuf::from("tests/sources/incbin")],
+        comment: String::from("KASM test"),
+        ..Config::default()
+    }
+}
+
+// Assembles the source, but can panic
+fn assemble(source: &str) -> KOFile {
+    match assemble_string(source.to_string(), test_config()).expect("Assembly failed") {
+        AssemblyOutput::Object(ko) => ko.get(),
+        AssemblyOutput::Source(_) => panic!("Expected object file"),
+    }
+}
+
+fn data_bytes(ko: &KOFile) -> Vec<i8> {
+    ko.data_section_by_name(".data")
+        .unwrap()
+        .data()
+        .filter_map(|value| match value {
+            KOSValue::Byte(b) => Some(*b),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn incbin_bytes() {
+    let ko = assemble(
+        ".section .data\n.incbin \"table.bin\"\n.section .text\n.func\n_start:\n    eop\n",
+    );
+
+    assert_eq!(data_bytes(&ko), vec![1, 2, 3, -1]);
+}
+
+#[test]
+fn incbin_empty_file() {
+    let with = assemble(
+        ".section .data\n.incbin \"empty.bin\"\n.section .text\n.func\n_start:\n    eop\n",
+    );
+    let without = assemble(".func\n_start:\n    eop\n");
+
+    assert_eq!(
+        with.data_section_by_name(".data").unwrap().data().count(),
+        without
+            .data_section_by_name(".data")
+            .unwrap()
+            .data()
+            .count()
+    );
+}
+
+#[test]
+fn incbin_missing_file() {
+    let source = ".section .data\n.incbin \"missing.bin\"\n";
+
+    assert!(assemble_string(source.to_string(), test_config()).is_err());
+}
+
+#[test]
+fn incbin_in_text_section() {
+    let source = ".func\n_start:\n    eop\n.incbin \"table.bin\"\n";
+
+    assert!(assemble_string(source.to_string(), test_config()).is_err());
+}
+
+#[test]
+fn ascii_bytes() {
+    let ascii =
+        assemble(".section .data\n.ascii \"hi\\n\"\n.section .text\n.func\n_start:\n    eop\n");
+    let asciz =
+        assemble(".section .data\n.asciz \"hi\\n\"\n.section .text\n.func\n_start:\n    eop\n");
+
+    assert_eq!(
+        data_bytes(&ascii),
+        vec![b'h' as i8, b'i' as i8, b'\n' as i8]
+    );
+    assert_eq!(
+        data_bytes(&asciz),
+        vec![b'h' as i8, b'i' as i8, b'\n' as i8, 0]
+    );
+    assert_eq!(data_bytes(&asciz).len(), data_bytes(&ascii).len() + 1);
+}
+
+#[test]
+fn ascii_invalid() {
+    let invalid = [
+        ".func\n_start:\n    eop\n.ascii \"hi\"\n",
+        ".section .data\n.asciz 5\n",
+        ".section .data\n.ascii \"a\" \"b\"\n",
+        ".section .data\n.ascii \"\\q\"\n",
+    ];
+
+    for source in invalid {
+        assert!(assemble_string(source.to_string(), test_config()).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use kasm::parser::parse::{InstructionOperand, Parser};
+use kasm::{preprocess_string, Config};
+
+mod common;
+
+fn test_config() -> Config {
+    Config {
+        emit_warnings: false,
+        ..common::test_config()
+    }
+}
+
+// Parses the source and renders every instruction of every function
+fn render_instructions(source: &str) -> Vec<String> {
+    let (tokens, session) =
+        preprocess_string(source.to_string(), test_config()).expect("Preprocessing failed");
+
+    let (functions, _, _, _) = Parser::new(tokens, &session)
+        .parse()
+        .expect("Parsing failed");
+
+    functions
+        .iter()
+        .flat_map(|function| function.instructions.iter())
+        .map(|instruction| instruction.to_string())
+        .collect()
+}
+
+#[test]
+fn operand_display() {
+    let operands = [
+        (InstructionOperand::Integer(-12), "-12"),
+        (
+            InstructionOperand::String(String::from("a \"b\"\n")),
+            "\"a \\\"b\\\"\\n\"",
+        ),
+        (InstructionOperand::Float(2.0), "2.0"),
+        (InstructionOperand::Float(-0.25), "-0.25"),
+        (
+            InstructionOperand::Label(String::from("_start.loop")),
+            ".loop",
+        ),
+        (InstructionOperand::Bool(true), "true"),
+        (InstructionOperand::Symbol(String::from("value")), "value"),
+        (InstructionOperand::ArgMarker, "@"),
+        (InstructionOperand::Null, "#"),
+    ];
+
+    for (operand, expected) in operands {
+        assert_eq!(operand.to_string(), expected);
+    }
+}
+
+#[test]
+fn instruction_display() {
+    let source = ".func\n_start:\n    nop\n.loop:\n    push 1 + 2\n    bscp 1, 2\n    btr .loop\n    call #, \"print()\"\n    push 1.5e1\n";
+
+    assert_eq!(
+        render_instructions(source),
+        vec![
+            "nop",
+            "push 3",
+            "bscp 1, 2",
+            "btr .loop",
+            "call #, \"print()\"",
+            "push 15.0",
+        ]
+    );
+}
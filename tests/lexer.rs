@@ -1,31 +1,27 @@
-use std::path::PathBuf;
-
 use kasm::{
     errors::SourceFile,
-    lexer::{Lexer, Token, TokenKind},
+    lex,
+    lexer::{Lexer, Token, TokenKind, TokenTexts, DIRECTIVE_SPELLINGS},
     session::Session,
-    Config,
+    session_from_string, Config,
 };
 
-// Lexes a source string to a vector, but can panic
-fn lex_from_text(source: &str) -> Vec<Token> {
-    let config = Config {
-        emit_errors: true,
+fn lexer_config() -> Config {
+    Config {
         emit_warnings: false,
-        root_dir: PathBuf::new(),
         run_preprocessor: false,
-        preprocess_only: false,
-        include_path: None,
-        file_sym_name: None,
-        comment: String::new(),
-    };
+        ..Config::default()
+    }
+}
 
-    let mut session = Session::new(config);
+// Lexes a source string to a vector, but can panic
+fn lex_from_text(source: &str) -> Vec<Token> {
+    let mut session = Session::new(lexer_config());
 
     // Create a SourceFile but with some dummy values
     let source_file = SourceFile::new("<input>".to_owned(), None, None, source.to_string(), 0);
 
-    session.add_file(source_file);
+    session.add_file(source_file).unwrap();
 
     let primary_file = session.get_file(0).unwrap();
 
@@ -109,12 +105,17 @@ fn lex_directives() {
         TokenKind::DirectiveRepeat,
         TokenKind::DirectiveEndRepeat,
         TokenKind::DirectiveInclude,
+        TokenKind::DirectiveIncludeDecls,
+        TokenKind::DirectiveIncbin,
+        TokenKind::DirectiveAscii,
+        TokenKind::DirectiveAsciz,
         TokenKind::DirectiveExtern,
         TokenKind::DirectiveGlobal,
         TokenKind::DirectiveLocal,
         TokenKind::DirectiveLine,
         TokenKind::DirectiveType,
         TokenKind::DirectiveValue,
+        TokenKind::DirectiveSize,
         TokenKind::DirectiveUndef,
         TokenKind::DirectiveUnmacro,
         TokenKind::DirectiveFunc,
@@ -126,6 +127,10 @@ fn lex_directives() {
         TokenKind::DirectiveElseIfNot,
         TokenKind::DirectiveElseIfDef,
         TokenKind::DirectiveElseIfNotDef,
+        TokenKind::DirectiveIfSection,
+        TokenKind::DirectiveIfNotSection,
+        TokenKind::DirectiveElseIfSection,
+        TokenKind::DirectiveElseIfNotSection,
         TokenKind::DirectiveElse,
         TokenKind::DirectiveEndIf,
     ];
@@ -139,12 +144,17 @@ fn lex_directives() {
 .rep
 .endrep
 .include
+.include_decls
+.incbin
+.ascii
+.asciz
 .extern
 .global
 .local
 .line
 .type
 .value
+.size
 .undef
 .unmacro
 .func
@@ -156,6 +166,10 @@ fn lex_directives() {
 .elifn
 .elifdef
 .elifndef
+.ifsection
+.ifnsection
+.elifsection
+.elifnsection
 .else
 .endif";
 
@@ -173,6 +187,54 @@ fn lex_directives() {
     }
 }
 
+#[test]
+fn directive_spellings_round_trip() {
+    for (spelling, kind) in DIRECTIVE_SPELLINGS {
+        let tokens = lex_from_text(spelling);
+
+        assert_eq!(tokens.len(), 1, "{} lexed to {:?}", spelling, tokens);
+        assert_eq!(tokens[0].kind, kind, "{} lexed to the wrong kind", spelling);
+        assert_eq!(spelling.parse::<TokenKind>(), Ok(kind));
+
+        // Printing the kind gives its first spelling, which is lexed back to the same kind
+        let printed = kind.directive_str().unwrap();
+
+        assert_eq!(lex_from_text(printed)[0].kind, kind);
+        assert_eq!(
+            DIRECTIVE_SPELLINGS
+                .iter()
+                .find(|(_, other)| *other == kind)
+                .unwrap()
+                .0,
+            printed
+        );
+    }
+}
+
+#[test]
+fn directive_prefixes_are_inner_labels() {
+    let source = ".macros .i32vv .defined .func:";
+
+    let kinds: Vec<TokenKind> = lex_from_text(source)
+        .iter()
+        .map(|token| token.kind)
+        .filter(|&kind| kind != TokenKind::Whitespace)
+        .collect();
+
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::InnerLabelReference,
+            TokenKind::InnerLabelReference,
+            TokenKind::InnerLabelReference,
+            TokenKind::InnerLabel,
+        ]
+    );
+
+    assert!(".macros".parse::<TokenKind>().is_err());
+    assert_eq!(TokenKind::Identifier.directive_str(), None);
+}
+
 #[test]
 fn lex_labels() {
     let correct_kinds = vec![
@@ -251,6 +313,25 @@ false
     }
 }
 
+#[test]
+fn lex_float_literals() {
+    let tokens = lex_from_text("1e10 .5 1.5e-3 2E10 6.02e+23");
+
+    let kinds: Vec<TokenKind> = tokens
+        .iter()
+        .map(|token| token.kind)
+        .filter(|kind| *kind != TokenKind::Whitespace)
+        .collect();
+
+    assert_eq!(kinds, vec![TokenKind::LiteralFloat; 5]);
+}
+
+#[test]
+#[should_panic(expected = "Lexing failed")]
+fn lex_malformed_float() {
+    lex_from_text("1.2.3");
+}
+
 #[test]
 fn lex_delimiters() {
     let correct_kinds = vec![
@@ -282,13 +363,15 @@ fn lex_symbols() {
         TokenKind::SymbolHash,
         TokenKind::SymbolAt,
         TokenKind::SymbolAnd,
+        TokenKind::SymbolLeftBrace,
+        TokenKind::SymbolRightBrace,
         TokenKind::SymbolRightParen,
         TokenKind::Comment,
     ];
 
     let mut correct_iter = correct_kinds.iter();
 
-    let source = " ( , # @ & ) ; This is a comment";
+    let source = " ( , # @ & { } ) ; This is a comment";
 
     let tokens = lex_from_text(source);
 
@@ -303,3 +386,38 @@ fn lex_symbols() {
         assert_eq!(token.kind, correct);
     }
 }
+
+#[test]
+fn resolve_token_text() {
+    let session = session_from_string("push \"hi\"  0x1F\tlabel:".to_string(), lexer_config());
+
+    let tokens = lex(&session).expect("Lexing failed");
+
+    let text: Vec<String> = tokens
+        .iter()
+        .filter(|token| token.kind != TokenKind::Whitespace)
+        .map(|token| token.resolve(&session))
+        .collect();
+
+    assert_eq!(text, ["push", "\"hi\"", "0x1F", "label:"]);
+}
+
+#[test]
+fn token_texts() {
+    let session = session_from_string(
+        ".func\n_start:\n    push \"hi\"\n".to_string(),
+        lexer_config(),
+    );
+    let tokens = lex(&session).expect("Lexing failed");
+    let files = session.source_files();
+
+    let texts: Vec<&str> = TokenTexts::new(&tokens, &files)
+        .filter(|(token, _)| token.kind != TokenKind::Whitespace)
+        .map(|(_, text)| text)
+        .collect();
+
+    assert_eq!(
+        texts,
+        [".func", "\n", "_start:", "\n", "push", "\"hi\"", "\n"]
+    );
+}
@@ -0,0 +1,22 @@
+use std::process::Command;
+
+// Runs the kasm binary with the given arguments, expecting it to fail, and returns everything it
+// printed to stderr
+fn run_kasm_failing(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_kasm"))
+        .args(args)
+        .output()
+        .expect("Failed to run kasm");
+
+    assert!(!output.status.success());
+
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn line_directive_in_diagnostics() {
+    let stderr = run_kasm_failing(&["tests/sources/line.kasm", "-o", "tests/line.ko"]);
+
+    assert!(stderr.contains("orig.src:101:4"));
+    assert!(!stderr.contains("line.kasm"));
+}
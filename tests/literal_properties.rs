@@ -0,0 +1,139 @@
+use kasm::preprocessor::parser::{
+    parse_binary_literal, parse_float_literal, parse_hexadecimal_literal, parse_integer_literal,
+};
+use proptest::prelude::*;
+
+// Inserts a `_` after each character that is chosen, as long as it falls between two digits. Only
+// decimal digits count for floats, so that the `e` of an exponent doesn't get a separator
+fn with_separators(literal: &str, separators: &[bool], is_digit: fn(&char) -> bool) -> String {
+    let chars: Vec<char> = literal.chars().collect();
+    let mut separated = String::with_capacity(literal.len() * 2);
+
+    for (index, c) in chars.iter().enumerate() {
+        separated.push(*c);
+
+        let between_digits = is_digit(c) && chars.get(index + 1).is_some_and(is_digit);
+
+        if between_digits && separators[index] {
+            separated.push('_');
+        }
+    }
+
+    separated
+}
+
+fn separators() -> impl Strategy<Value = Vec<bool>> {
+    prop::collection::vec(any::<bool>(), 1100)
+}
+
+proptest! {
+    #[test]
+    fn integer_round_trip(value in 0..=i32::MAX, separators in separators()) {
+        let literal = with_separators(&value.to_string(), &separators, char::is_ascii_hexdigit);
+
+        prop_assert_eq!(parse_integer_literal(&literal), Ok(value));
+    }
+
+    #[test]
+    fn integer_out_of_range(
+        value in (i32::MAX as u64 + 1)..=u64::MAX,
+        separators in separators(),
+    ) {
+        let literal = with_separators(&value.to_string(), &separators, char::is_ascii_hexdigit);
+
+        prop_assert_eq!(parse_integer_literal(&literal), Err(()));
+    }
+
+    #[test]
+    fn hexadecimal_round_trip(
+        value in 0..=i32::MAX,
+        upper in any::<bool>(),
+        separators in separators(),
+    ) {
+        let digits = if upper {
+            format!("{:X}", value)
+        } else {
+            format!("{:x}", value)
+        };
+        let separated = with_separators(&digits, &separators, char::is_ascii_hexdigit);
+        let literal = format!("0x{}", separated);
+
+        prop_assert_eq!(parse_hexadecimal_literal(&literal), Ok(value));
+    }
+
+    #[test]
+    fn hexadecimal_out_of_range(
+        value in (i32::MAX as u64 + 1)..=u64::MAX,
+        separators in separators(),
+    ) {
+        let digits = with_separators(&format!("{:x}", value), &separators, char::is_ascii_hexdigit);
+        let literal = format!("0x{}", digits);
+
+        prop_assert_eq!(parse_hexadecimal_literal(&literal), Err(()));
+    }
+
+    #[test]
+    fn binary_round_trip(value in 0..=i32::MAX, separators in separators()) {
+        let digits = with_separators(&format!("{:b}", value), &separators, char::is_ascii_hexdigit);
+        let literal = format!("0b{}", digits);
+
+        prop_assert_eq!(parse_binary_literal(&literal), Ok(value));
+    }
+
+    #[test]
+    fn binary_out_of_range(
+        value in (i32::MAX as u64 + 1)..=u64::MAX,
+        separators in separators(),
+    ) {
+        let digits = with_separators(&format!("{:b}", value), &separators, char::is_ascii_hexdigit);
+        let literal = format!("0b{}", digits);
+
+        prop_assert_eq!(parse_binary_literal(&literal), Err(()));
+    }
+
+    #[test]
+    fn float_round_trip(
+        value in prop::num::f64::POSITIVE | prop::num::f64::ZERO,
+        scientific in any::<bool>(),
+        separators in separators(),
+    ) {
+        let literal = if scientific {
+            format!("{:e}", value)
+        } else {
+            format!("{}", value)
+        };
+        let literal = with_separators(&literal, &separators, char::is_ascii_digit);
+
+        prop_assert_eq!(parse_float_literal(&literal), Ok(value));
+    }
+
+    #[test]
+    fn float_out_of_range(mantissa in 1..10u8, exponent in 309..100_000u32) {
+        let literal = format!("{}e{}", mantissa, exponent);
+
+        prop_assert_eq!(parse_float_literal(&literal), Err(()));
+    }
+
+    #[test]
+    fn float_separator_next_to_non_digit(
+        literal in "[0-9]{1,4}(\\.[0-9]{1,4})?[eE][+-]?[0-9]{1,3}",
+    ) {
+        for (index, c) in literal.char_indices() {
+            if !c.is_ascii_digit() {
+                let before = format!("{}_{}", &literal[..index], &literal[index..]);
+                let after = format!("{}_{}", &literal[..index + 1], &literal[index + 1..]);
+
+                prop_assert_eq!(parse_float_literal(&before), Err(()), "{}", before);
+                prop_assert_eq!(parse_float_literal(&after), Err(()), "{}", after);
+            }
+        }
+    }
+
+    #[test]
+    fn arbitrary_strings_do_not_panic(string in "\\PC*") {
+        let _ = parse_integer_literal(&string);
+        let _ = parse_hexadecimal_literal(&string);
+        let _ = parse_binary_literal(&string);
+        let _ = parse_float_literal(&string);
+    }
+}
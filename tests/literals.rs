@@ -0,0 +1,92 @@
+use kasm::preprocessor::parser::{
+    parse_binary_literal, parse_float_literal, parse_hexadecimal_literal, parse_integer_literal,
+};
+use kasm::{assemble_string, Config};
+
+#[test]
+fn integer_boundaries() {
+    assert_eq!(parse_integer_literal("0"), Ok(0));
+    assert_eq!(parse_integer_literal("2147483647"), Ok(i32::MAX));
+    assert_eq!(parse_integer_literal("2_147_483_647"), Ok(i32::MAX));
+    assert_eq!(parse_integer_literal("2147483648"), Err(()));
+    assert_eq!(parse_integer_literal("99999999999999999999"), Err(()));
+    assert_eq!(parse_integer_literal(""), Err(()));
+    assert_eq!(parse_integer_literal("_"), Err(()));
+    assert_eq!(parse_integer_literal("12a"), Err(()));
+}
+
+#[test]
+fn hexadecimal_boundaries() {
+    assert_eq!(parse_hexadecimal_literal("0x0"), Ok(0));
+    assert_eq!(parse_hexadecimal_literal("0x7FFFFFFF"), Ok(i32::MAX));
+    assert_eq!(parse_hexadecimal_literal("0x7fff_ffff"), Ok(i32::MAX));
+    assert_eq!(parse_hexadecimal_literal("0x80000000"), Err(()));
+    assert_eq!(parse_hexadecimal_literal("0x"), Err(()));
+    assert_eq!(parse_hexadecimal_literal("0x_"), Err(()));
+    assert_eq!(parse_hexadecimal_literal("0"), Err(()));
+    assert_eq!(parse_hexadecimal_literal(""), Err(()));
+}
+
+#[test]
+fn binary_boundaries() {
+    assert_eq!(parse_binary_literal("0b0"), Ok(0));
+    assert_eq!(parse_binary_literal("0b0110_0001"), Ok(0x61));
+    assert_eq!(
+        parse_binary_literal("0b1111111111111111111111111111111"),
+        Ok(i32::MAX)
+    );
+    assert_eq!(
+        parse_binary_literal("0b10000000000000000000000000000000"),
+        Err(())
+    );
+    assert_eq!(parse_binary_literal("0b"), Err(()));
+    assert_eq!(parse_binary_literal("0b2"), Err(()));
+    assert_eq!(parse_binary_literal("b"), Err(()));
+}
+
+#[test]
+fn float_boundaries() {
+    assert_eq!(parse_float_literal("1.5"), Ok(1.5));
+    assert_eq!(parse_float_literal("1e308"), Ok(1e308));
+    assert_eq!(parse_float_literal("1e309"), Err(()));
+    assert_eq!(parse_float_literal("inf"), Err(()));
+    assert_eq!(parse_float_literal("NaN"), Err(()));
+    assert_eq!(parse_float_literal("1.2.3"), Err(()));
+    assert_eq!(parse_float_literal(""), Err(()));
+}
+
+#[test]
+fn float_separators() {
+    assert_eq!(parse_float_literal("1_000.000_5"), Ok(1000.0005));
+    assert_eq!(parse_float_literal("1_0.5e1_0"), Ok(10.5e10));
+    assert_eq!(parse_float_literal(".2_5"), Ok(0.25));
+
+    for malformed in [
+        "1_.5", "1._5", "1.5_", "_1.5", "1__0.5", "1.5_e3", "1.5e_3", "1.5e+_3",
+    ] {
+        assert_eq!(parse_float_literal(malformed), Err(()), "{}", malformed);
+    }
+}
+
+#[test]
+fn oversized_literals_are_errors() {
+    let config = Config {
+        emit_errors: false,
+        emit_warnings: false,
+        ..Config::default()
+    };
+
+    for literal in [
+        "99999999999",
+        "0xFFFFFFFFF",
+        "0b111111111111111111111111111111111",
+    ] {
+        let source = format!(".func\n_start:\n    push {}\n", literal);
+
+        assert!(assemble_string(source, config.clone()).is_err());
+
+        let source = format!(".rep {}\nnop\n.endrep\n", literal);
+
+        assert!(assemble_string(source, config.clone()).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use std::process::Command;
+
+use kasm::session::OpcodeTable;
+use kasm::{assemble_string, Config};
+use kerbalobjects::Opcode;
+
+// Every opcode that can be written in KASM, along with operands that it accepts. This also serves
+// to document how many operands each instruction takes
+const INSTRUCTIONS: &[(&str, &[&str])] = &[
+    ("eof", &[]),
+    ("eop", &[]),
+    ("nop", &[]),
+    ("sto", &["\"$x\""]),
+    ("uns", &[]),
+    ("gmb", &["\"length\""]),
+    ("smb", &["\"length\""]),
+    ("gidx", &[]),
+    ("sidx", &[]),
+    ("bfa", &["1"]),
+    ("jmp", &["1"]),
+    ("add", &[]),
+    ("sub", &[]),
+    ("mul", &[]),
+    ("div", &[]),
+    ("pow", &[]),
+    ("cgt", &[]),
+    ("clt", &[]),
+    ("cge", &[]),
+    ("cle", &[]),
+    ("ceq", &[]),
+    ("cne", &[]),
+    ("neg", &[]),
+    ("bool", &[]),
+    ("not", &[]),
+    ("and", &[]),
+    ("or", &[]),
+    ("call", &["#", "\"print()\""]),
+    ("ret", &["0"]),
+    ("push", &["1"]),
+    ("pop", &[]),
+    ("dup", &[]),
+    ("swap", &[]),
+    ("eval", &[]),
+    ("addt", &["true", "0"]),
+    ("rmvt", &[]),
+    ("wait", &[]),
+    ("gmet", &["\"add\""]),
+    ("stol", &["\"$x\""]),
+    ("stog", &["\"$x\""]),
+    ("bscp", &["1", "0"]),
+    ("escp", &["1"]),
+    ("stoe", &["\"$x\""]),
+    ("phdl", &["1", "false"]),
+    ("btr", &["1"]),
+    ("exst", &[]),
+    ("argb", &[]),
+    ("targ", &[]),
+    ("tcan", &[]),
+    ("prl", &["\"_start\""]),
+    ("pdrl", &["\"_start\"", "true"]),
+    ("lbrt", &["\"@0001\""]),
+    ("pushv", &["1"]),
+];
+
+fn opcodes_config() -> Config {
+    Config {
+        emit_errors: false,
+        emit_warnings: false,
+        run_preprocessor: false,
+        ..Config::default()
+    }
+}
+
+// Creates the source of a function containing just this instruction
+fn instruction_source(mnemonic: &str, operands: &[&str]) -> String {
+    format!(".func\n_start:\n    {} {}\n", mnemonic, operands.join(", "))
+}
+
+// Assembles the source with the kasm binary, expecting it to fail, and returns everything it
+// printed to stderr
+fn run_kasm_failing(name: &str, source: &str) -> String {
+    let path = std::env::temp_dir().join(format!("kasm_opcodes_{}.kasm", name));
+
+    std::fs::write(&path, source).expect("Failed to write source");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_kasm"))
+        .arg(&path)
+        .arg("-o")
+        .arg(path.with_extension("ko"))
+        .output()
+        .expect("Failed to run kasm");
+
+    std::fs::remove_file(&path).ok();
+
+    assert!(!output.status.success(), "{} was accepted", name);
+
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn every_opcode_covered() {
+    let mut expected: Vec<&str> = (0..=u8::MAX)
+        .map(Opcode::from)
+        .filter(|&opcode| opcode != Opcode::Bogus)
+        .map(<&str>::from)
+        .collect();
+    expected.sort_unstable();
+
+    let mut covered: Vec<&str> = INSTRUCTIONS.iter().map(|(mnemonic, _)| *mnemonic).collect();
+    covered.sort_unstable();
+
+    assert_eq!(covered, expected);
+}
+
+#[test]
+fn every_opcode_arity() {
+    for (mnemonic, operands) in INSTRUCTIONS {
+        assert_eq!(
+            Opcode::from(*mnemonic).num_operands(),
+            operands.len(),
+            "{}",
+            mnemonic
+        );
+    }
+}
+
+#[test]
+fn every_opcode_assembles() {
+    for (mnemonic, operands) in INSTRUCTIONS {
+        let source = instruction_source(mnemonic, operands);
+
+        assert!(
+            assemble_string(source, opcodes_config()).is_ok(),
+            "{} failed to assemble",
+            mnemonic
+        );
+    }
+}
+
+#[test]
+fn every_opcode_wrong_operand_count() {
+    for (mnemonic, operands) in INSTRUCTIONS {
+        let wanted = operands.len();
+
+        // One too many, and if possible, one too few
+        let mut wrong = vec![[*operands, &["1"]].concat()];
+
+        if let Some((_, fewer)) = operands.split_last() {
+            wrong.push(fewer.to_vec());
+        }
+
+        for wrong_operands in wrong {
+            let stderr = run_kasm_failing(mnemonic, &instruction_source(mnemonic, &wrong_operands));
+
+            let message = format!(
+                "error: `{}` requires {} operand{}, {} provided",
+                mnemonic,
+                wanted,
+                if wanted == 1 { "" } else { "s" },
+                wrong_operands.len()
+            );
+
+            assert!(stderr.starts_with(&message), "{}", stderr);
+        }
+    }
+}
+
+#[test]
+fn opcode_table_matches_from() {
+    let table = OpcodeTable::new();
+
+    let others = [
+        "", "PUSH", "pus", "pushh", "_start", "MY_MACRO", "label", "bogus", "push1",
+    ];
+
+    for identifier in INSTRUCTIONS
+        .iter()
+        .map(|(mnemonic, _)| *mnemonic)
+        .chain(others)
+    {
+        assert_eq!(
+            table.get(identifier),
+            Opcode::from(identifier),
+            "{}",
+            identifier
+        );
+        assert_eq!(
+            table.contains(identifier),
+            Opcode::from(identifier) != Opcode::Bogus,
+            "{}",
+            identifier
+        );
+    }
+}
+
+#[test]
+fn opcode_table_lookups() {
+    // The kinds of identifiers in a large file: mostly instructions, with macros and labels
+    let mut identifiers: Vec<String> = INSTRUCTIONS
+        .iter()
+        .map(|(mnemonic, _)| mnemonic.to_string())
+        .collect();
+    identifiers.extend((0..50).map(|i| format!("SOME_LONGER_MACRO_{}", i)));
+    identifiers.extend((0..50).map(|i| format!("loop_{}", i)));
+
+    let table = OpcodeTable::new();
+
+    for identifier in &identifiers {
+        let opcode = Opcode::from(identifier.as_str());
+
+        assert_eq!(table.get(identifier), opcode, "{}", identifier);
+        assert_eq!(
+            table.contains(identifier),
+            opcode != Opcode::Bogus,
+            "{}",
+            identifier
+        );
+    }
+}
+
+#[test]
+fn list_opcodes() {
+    let output = Command::new(env!("CARGO_BIN_EXE_kasm"))
+        .arg("--list-opcodes")
+        .output()
+        .expect("Failed to run kasm");
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let listed: Vec<(&str, usize)> = stdout
+        .lines()
+        .map(|line| {
+            let (mnemonic, num_operands) = line.split_once(' ').unwrap();
+
+            (mnemonic, num_operands.trim().parse().unwrap())
+        })
+        .collect();
+
+    assert!(listed.contains(&("push", 1)));
+    assert_eq!(listed.len(), INSTRUCTIONS.len());
+
+    for (mnemonic, operands) in INSTRUCTIONS {
+        assert!(
+            listed.contains(&(mnemonic, operands.len())),
+            "{} is not listed with {} operand(s)",
+            mnemonic,
+            operands.len()
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use kasm::lexer::{Token, TokenKind};
+use kasm::parser::parse::OperandCache;
+use kasm::preprocessor::expressions::Value;
+use kasm::{preprocess_string, Config};
+
+// Evaluates each line of the source as an operand, and returns the cache that was used
+fn evaluate_lines(source: &str) -> (OperandCache, Vec<Value>) {
+    let config = Config {
+        emit_warnings: false,
+        ..Config::default()
+    };
+
+    let (tokens, session) =
+        preprocess_string(source.to_string(), config).expect("Preprocessing failed");
+
+    let mut cache = OperandCache::new();
+
+    let values = tokens
+        .split(|token| token.kind == TokenKind::Newline)
+        .map(|line| {
+            line.iter()
+                .filter(|token| token.kind != TokenKind::Whitespace)
+                .copied()
+                .collect::<Vec<Token>>()
+        })
+        .filter(|operand| !operand.is_empty())
+        .map(|operand| {
+            cache
+                .evaluate(&operand, &session)
+                .expect("Evaluation failed")
+        })
+        .collect();
+
+    (cache, values)
+}
+
+#[test]
+fn repeated_constants_evaluated_once() {
+    let source = "1 + 2 * 3\n0x10\n2.5\n-1\n".repeat(250);
+
+    let (cache, values) = evaluate_lines(&source);
+
+    assert_eq!(values.len(), 1000);
+    assert_eq!(values[996], Value::Int(7));
+    assert_eq!(values[999], Value::Int(-1));
+
+    assert_eq!(cache.evaluated(), 4);
+    assert_eq!(cache.reused(), 996);
+}
+
+#[test]
+fn equivalent_constants_share_cache() {
+    // Whitespace doesn't change what an expression evaluates to
+    let (cache, values) = evaluate_lines("1+2\n1 + 2\n3\n");
+
+    assert_eq!(values, [Value::Int(3), Value::Int(3), Value::Int(3)]);
+
+    assert_eq!(cache.evaluated(), 2);
+    assert_eq!(cache.reused(), 1);
+}
@@ -0,0 +1,89 @@
+use kasm::parser::parse::ParsedFunction;
+use kasm::{optimize, parse_tokens, preprocess, session_from_string, verify};
+
+mod common;
+use common::test_config;
+
+// Parses and optimizes the source, returning the number of instructions that were removed and
+// what is left of each function
+fn optimized(source: &str) -> (usize, Vec<Vec<String>>) {
+    let mut session = session_from_string(source.to_string(), test_config());
+
+    let tokens = preprocess(&mut session).expect("Preprocessing failed");
+
+    let (mut functions, mut label_manager, symbol_manager, _) =
+        parse_tokens(tokens, &session).expect("Parsing failed");
+
+    let removed = optimize(&mut functions, &mut label_manager);
+
+    let instructions = functions.iter().map(instruction_text).collect();
+
+    // Whatever is left must still refer to labels that exist
+    verify(functions, &label_manager, &symbol_manager, &session).expect("Verification failed");
+
+    (removed, instructions)
+}
+
+fn instruction_text(function: &ParsedFunction) -> Vec<String> {
+    function
+        .instructions
+        .iter()
+        .map(|instruction| instruction.to_string())
+        .collect()
+}
+
+#[test]
+fn push_then_pop_removed() {
+    let (removed, functions) = optimized(
+        ".func\n_start:\n    push 1\n    push 2\n    pop\n    pop\n    push 3\n    ret 0\n",
+    );
+
+    assert_eq!(removed, 4);
+    assert_eq!(functions, [["push 3", "ret 0"]]);
+}
+
+#[test]
+fn jump_to_next_instruction_removed() {
+    let (removed, functions) =
+        optimized(".func\n_start:\n    push 1\n    jmp .next\n.next:\n    ret 0\n");
+
+    assert_eq!(removed, 1);
+    assert_eq!(functions, [["push 1", "ret 0"]]);
+}
+
+#[test]
+fn label_blocks_push_pop() {
+    let source = ".func\n_start:\n    push 1\n.skip:\n    pop\n    jmp .skip\n";
+
+    let (removed, functions) = optimized(source);
+
+    assert_eq!(removed, 0);
+    assert_eq!(functions, [["push 1", "pop", "jmp .skip"]]);
+}
+
+#[test]
+fn labels_after_removal_move_back() {
+    let source = ".func\n_start:\n    push 1\n    pop\n    jmp .end\n    push 2\n.end:\n    ret 0\n.func\nother:\n    push 3\n    ret 0\n";
+
+    let mut session = session_from_string(source.to_string(), test_config());
+    let tokens = preprocess(&mut session).expect("Preprocessing failed");
+
+    let (mut functions, mut label_manager, _, _) =
+        parse_tokens(tokens, &session).expect("Parsing failed");
+
+    assert_eq!(optimize(&mut functions, &mut label_manager), 2);
+
+    let address = |name: &str| label_manager.get(&name.to_string()).unwrap().value;
+
+    assert_eq!(address("_start.end"), 2);
+    assert_eq!(address("other"), 3);
+}
+
+#[test]
+fn relative_branch_disables_optimization() {
+    let (removed, functions) =
+        optimized(".func\n_start:\n    push 1\n    pop\n    jmp 1\n    ret 0\n");
+
+    assert_eq!(removed, 0);
+    assert_eq!(functions[0].len(), 4);
+}
@@ -1,11 +1,20 @@
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
 
 use kasm::{
-    errors::SourceFile,
+    errors::{SourceFile, Span},
     lexer::{Lexer, Token, TokenKind},
+    parse_past_string,
     preprocessor::parser::parse_binary_literal,
-    preprocessor::past::PASTNode,
-    preprocessor::{expressions::ExpressionParser, parser::parse_hexadecimal_literal},
+    preprocessor::past::{
+        BenignTokens, Ident, IfClause, IfClauseBegin, IfCondition, IfExpCondition, IfStatement,
+        MLMacroArgs, MLMacroDef, PASTNode,
+    },
+    preprocessor::{
+        evaluator::{EvalErrorKind, ExpressionEvaluator},
+        expressions::ExpressionParser,
+        parser::parse_hexadecimal_literal,
+    },
     preprocessor::{
         expressions::{BinOp, ExpNode, UnOp, Value},
         parser::parse_integer_literal,
@@ -16,25 +25,22 @@ use kasm::{
 
 use kasm::preprocessor::parser::Parser;
 
-// Lexes a source string to a vector, but can panic
-fn lex_from_text(source: &str) -> (Vec<Token>, Session) {
-    let config = Config {
-        emit_errors: true,
+fn parser_config() -> Config {
+    Config {
         emit_warnings: false,
-        root_dir: PathBuf::new(),
         run_preprocessor: false,
-        preprocess_only: false,
-        include_path: None,
-        file_sym_name: None,
-        comment: String::new(),
-    };
+        ..Config::default()
+    }
+}
 
-    let mut session = Session::new(config);
+// Lexes a source string to a vector, but can panic
+fn lex_from_text(source: &str) -> (Vec<Token>, Session) {
+    let mut session = Session::new(parser_config());
 
     // Create a SourceFile but with some dummy values
     let source_file = SourceFile::new("<input>".to_owned(), None, None, source.to_string(), 0);
 
-    session.add_file(source_file);
+    session.add_file(source_file).unwrap();
 
     let primary_file = session.get_file(0).unwrap();
 
@@ -198,15 +204,22 @@ fn parse_expression() {
                 Some(expression) => {
                     let correct = ExpNode::UnOp(
                         UnOp::Not,
+                        Span::new(0, 1, 0),
                         Box::new(ExpNode::BinOp(
-                            Box::new(ExpNode::Constant(Value::Int(2))),
+                            Box::new(ExpNode::Constant(Value::Int(2), Span::new(2, 3, 0))),
                             BinOp::Eq,
+                            Span::new(4, 6, 0),
                             Box::new(ExpNode::UnOp(
                                 UnOp::Negate,
+                                Span::new(7, 8, 0),
                                 Box::new(ExpNode::BinOp(
-                                    Box::new(ExpNode::Constant(Value::Int(4))),
+                                    Box::new(ExpNode::Constant(Value::Int(4), Span::new(9, 10, 0))),
                                     BinOp::Mult,
-                                    Box::new(ExpNode::Constant(Value::Int(4))),
+                                    Span::new(11, 12, 0),
+                                    Box::new(ExpNode::Constant(
+                                        Value::Int(4),
+                                        Span::new(13, 14, 0),
+                                    )),
                                 )),
                             )),
                         )),
@@ -229,3 +242,187 @@ fn parse_expression() {
         panic!("PASTNode was not BenignTokens");
     }
 }
+
+// Parses and evaluates an expression, returning the error that evaluation failed with
+fn evaluation_error(source: &str) -> (EvalErrorKind, Span) {
+    let (nodes, session) = parse_source(source);
+
+    if let PASTNode::BenignTokens(benign_tokens) = nodes.first().unwrap() {
+        let mut tokens = benign_tokens.tokens.iter().peekable();
+
+        let expression = ExpressionParser::parse_expression(&mut tokens, &session, false)
+            .unwrap_or_else(|_| panic!("Failed to parse expression"))
+            .expect("No expression parsed");
+
+        match ExpressionEvaluator::evaluate(&expression) {
+            Ok(value) => panic!("Expression evaluated to {:?}", value),
+            Err(e) => (e.kind, e.span),
+        }
+    } else {
+        panic!("PASTNode was not BenignTokens");
+    }
+}
+
+#[test]
+fn zero_divide_span() {
+    // The span should be only the / operator, not the whole expression
+    let (kind, span) = evaluation_error("1 + (4 / (2 - 2)) * 3");
+
+    assert_eq!(kind, EvalErrorKind::ZeroDivide);
+    assert_eq!(span, Span::new(7, 8, 0));
+}
+
+#[test]
+fn invalid_operand_span() {
+    // The span should be the operand of the -, which is the parenthesized expression
+    let (kind, span) = evaluation_error("2 * -(true || false)");
+
+    assert_eq!(kind, EvalErrorKind::NegateBool);
+    assert_eq!(span, Span::new(6, 19, 0));
+}
+
+#[test]
+fn addition_overflow() {
+    let (kind, span) = evaluation_error("2147483647 + 1");
+
+    assert_eq!(kind, EvalErrorKind::Overflow);
+    assert_eq!(span, Span::new(11, 12, 0));
+}
+
+#[test]
+fn multiplication_overflow() {
+    let (kind, span) = evaluation_error("3 + 100000 * 100000");
+
+    assert_eq!(kind, EvalErrorKind::Overflow);
+    assert_eq!(span, Span::new(11, 12, 0));
+}
+
+#[test]
+fn negation_overflow() {
+    let (kind, span) = evaluation_error("-(-2147483647 - 1)");
+
+    assert_eq!(kind, EvalErrorKind::Overflow);
+    assert_eq!(span, Span::new(0, 1, 0));
+}
+
+// Parses a source string into PAST nodes, but can panic
+fn parse_past(source: &str) -> Vec<PASTNode> {
+    let (nodes, _) = parse_past_string(source.to_string(), parser_config())
+        .unwrap_or_else(|_| panic!("Failed to parse {:?}", source));
+
+    nodes
+}
+
+// The span doesn't take part in comparisons, so any will do
+fn dummy_span() -> Span {
+    Span::new(0, 0, 0)
+}
+
+fn ident(name: &str) -> Ident {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(name.as_bytes());
+
+    Ident::new(dummy_span(), hasher.finish())
+}
+
+fn benign(kinds: &[TokenKind]) -> PASTNode {
+    let tokens = kinds
+        .iter()
+        .map(|&kind| Token {
+            kind,
+            file_id: 0,
+            source_index: 0,
+            len: 0,
+            expansion: 0,
+        })
+        .collect();
+
+    PASTNode::BenignTokens(BenignTokens::from_vec(tokens))
+}
+
+fn clause(inverse: bool, condition: IfCondition, contents: Vec<PASTNode>) -> IfClause {
+    IfClause::new(
+        dummy_span(),
+        IfClauseBegin::new(dummy_span(), inverse),
+        condition,
+        contents,
+    )
+}
+
+#[test]
+fn past_macro_with_nested_if() {
+    let source = ".macro PICK 1\n.if &1 == 1\npush 1\n.else\npush 2\n.endif\n.endmacro\n";
+
+    let push = || {
+        benign(&[
+            TokenKind::Identifier,
+            TokenKind::Whitespace,
+            TokenKind::LiteralInteger,
+            TokenKind::Newline,
+        ])
+    };
+
+    let condition = IfCondition::Exp(IfExpCondition::new(
+        dummy_span(),
+        vec![benign(&[
+            TokenKind::SymbolAnd,
+            TokenKind::LiteralInteger,
+            TokenKind::Whitespace,
+            TokenKind::OperatorEquals,
+            TokenKind::Whitespace,
+            TokenKind::LiteralInteger,
+        ])],
+    ));
+
+    let if_statement = IfStatement::new(
+        dummy_span(),
+        vec![
+            clause(false, condition, vec![push()]),
+            clause(false, IfCondition::Else, vec![push()]),
+        ],
+    );
+
+    let expected = vec![
+        PASTNode::MLMacroDef(MLMacroDef::new(
+            dummy_span(),
+            ident("PICK"),
+            Some(MLMacroArgs::new(dummy_span(), 1, None)),
+            None,
+            vec![
+                PASTNode::IfStatement(if_statement),
+                benign(&[TokenKind::Newline]),
+            ],
+        )),
+        benign(&[TokenKind::Newline]),
+    ];
+
+    assert_eq!(parse_past(source), expected);
+}
+
+#[test]
+fn past_equality_ignores_spans() {
+    let body = ".macro M 0-1 5\n.ifdef DEBUG\npush &1\n.endif\n.endmacro\n.rep 2\nM(3)\n.endrep\n";
+
+    let first = parse_past(&format!(".define A 1\n{}", body));
+    let second = parse_past(&format!(".define B 22\n{}", body));
+
+    // The definitions differ in their identifier and contents
+    assert_ne!(first[0], second[0]);
+    assert_eq!(first[1..], second[1..]);
+
+    // Renaming the macro changes the tree, even though the tokens are all of the same kinds
+    let renamed = parse_past(&body.replace(".macro M", ".macro N"));
+    assert_ne!(first[1..], renamed[..]);
+}
+
+#[test]
+fn past_debug_leaves_out_spans() {
+    let nodes = parse_past(".ifn 1\n.endif");
+
+    assert_eq!(
+        format!("{:?}", nodes),
+        "[IfStatement(IfStatement { clauses: [IfClause { begin: IfClauseBegin { inverse: true }, \
+         condition: Exp(IfExpCondition { expression: [BenignTokens(BenignTokens([LiteralInteger]))] }), \
+         contents: [] }] })]"
+    );
+}
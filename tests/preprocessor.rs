@@ -0,0 +1,580 @@
+use std::path::PathBuf;
+
+use kasm::lexer::TokenKind;
+use kasm::{assemble_string, check_string, preprocess_string, AssemblyOutput, Config};
+
+// Creates a Config suitable for only running the preprocessor
+fn preprocess_config() -> Config {
+    Config {
+        emit_warnings: false,
+        preprocess_only: true,
+        ..Config::default()
+    }
+}
+
+// Preprocesses a source string and returns the resulting source, but can panic
+fn preprocess_with(source: &str, config: Config) -> String {
+    match assemble_string(source.to_string(), config).expect("Preprocessing failed") {
+        AssemblyOutput::Source(output) => output,
+        AssemblyOutput::Object(_) => panic!("Expected preprocessed source"),
+    }
+}
+
+// Preprocesses a source string with the provided command line definitions
+fn preprocess(source: &str, defines: &[&str]) -> String {
+    let mut config = preprocess_config();
+    config.defines = defines.iter().map(|define| define.to_string()).collect();
+
+    preprocess_with(source, config)
+}
+
+#[test]
+fn ifdef_command_line_define() {
+    let source = ".ifdef DEBUG\npush 1\n.else\npush 2\n.endif\n";
+
+    let output = preprocess(source, &["DEBUG"]);
+
+    assert!(output.contains("push 1"));
+    assert!(!output.contains("push 2"));
+
+    let output = preprocess(source, &[]);
+
+    assert!(!output.contains("push 1"));
+    assert!(output.contains("push 2"));
+}
+
+#[test]
+fn command_line_define_value() {
+    let output = preprocess("push LEVEL\n", &["LEVEL=3"]);
+
+    assert_eq!(output.trim(), "push 3");
+}
+
+#[test]
+fn if_command_line_define_comparison() {
+    let source = ".if LEVEL >= 2\npush 1\n.else\npush 2\n.endif\n";
+
+    let output = preprocess(source, &["LEVEL=3"]);
+
+    assert_eq!(output.trim(), "push 1");
+
+    let output = preprocess(source, &["LEVEL=1"]);
+
+    assert_eq!(output.trim(), "push 2");
+}
+
+#[test]
+fn if_undefined_in_expression() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    let source = ".if LEVEL >= 2\npush 1\n.endif\n";
+
+    assert!(assemble_string(source.to_string(), config).is_err());
+}
+
+#[test]
+fn include_from_second_search_dir() {
+    let mut config = preprocess_config();
+    config.include_paths = vec![
+        PathBuf::from("tests/sources/include/first"),
+        PathBuf::from("tests/sources/include/second"),
+    ];
+
+    let output = preprocess_with(".include \"header.kasm\"\npush FROM_SECOND\n", config);
+
+    assert_eq!(output.trim(), "push 2");
+}
+
+#[test]
+fn include_not_found() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+    config.include_paths = vec![PathBuf::from("tests/sources/include/first")];
+
+    assert!(assemble_string(".include \"header.kasm\"\n".to_string(), config).is_err());
+}
+
+#[test]
+fn include_line_range() {
+    let output = preprocess(
+        ".include \"tests/sources/include/table.kasm\" lines 3-5\n",
+        &[],
+    );
+
+    assert_eq!(
+        output.lines().map(str::trim).collect::<Vec<_>>(),
+        vec!["push 3", "push 4", "push 5"]
+    );
+
+    // Spans still point into the whole file
+    let locations =
+        identifier_locations(".include \"tests/sources/include/table.kasm\" lines 4-4\n");
+
+    assert_eq!(locations.len(), 1);
+    assert!(locations[0].0.ends_with("table.kasm"));
+    assert_eq!((locations[0].1, locations[0].2), (4, 0));
+}
+
+#[test]
+fn include_line_range_invalid() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    let invalid = [
+        ".include \"tests/sources/include/table.kasm\" lines 3-9\n",
+        ".include \"tests/sources/include/table.kasm\" lines 7-7\n",
+        ".include \"tests/sources/include/table.kasm\" lines 0-2\n",
+        ".include \"tests/sources/include/table.kasm\" lines 5-3\n",
+        ".include \"tests/sources/include/table.kasm\" lines 3\n",
+        ".include \"tests/sources/include/table.kasm\" lines\n",
+    ];
+
+    for source in invalid {
+        assert!(
+            assemble_string(source.to_string(), config.clone()).is_err(),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn preprocessed_tokens() {
+    let (tokens, session) = preprocess_string(
+        ".define VALUE 3\npush VALUE\n".to_string(),
+        preprocess_config(),
+    )
+    .expect("Preprocessing failed");
+
+    let tokens: Vec<_> = tokens
+        .iter()
+        .filter(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Newline))
+        .map(|token| (token.kind, session.span_to_source(&token.as_span())))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            (TokenKind::Identifier, String::from("push")),
+            (TokenKind::LiteralInteger, String::from("3")),
+        ]
+    );
+}
+
+#[test]
+fn preprocessed_output_preserves_source() {
+    let source = "; A comment\n.func\n_start:\n    push   0x1F ; trailing\n\tpush 0b101,\\\n  1.50\n    push \"a  b\"\n    cgt\n";
+
+    let output = preprocess(source, &[]);
+
+    assert_eq!(output, source);
+}
+
+#[test]
+fn emit_tokens() {
+    let mut config = preprocess_config();
+    config.preprocess_only = false;
+    config.emit_tokens = true;
+
+    // This would fail to parse, but tokens are emitted before parsing
+    let output = preprocess_with(".define VALUE 3\npush VALUE,\n", config);
+
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        vec![
+            "<input>:2:0 Identifier \"push\"",
+            "<input>:2:4 Whitespace \" \"",
+            "<input>:1:14 LiteralInteger \"3\"",
+            "<input>:2:10 SymbolComma \",\"",
+            "<input>:2:11 Newline \"\\n\"",
+        ]
+    );
+}
+
+// Returns the reported location of each token that is an identifier
+fn identifier_locations(source: &str) -> Vec<(String, usize, usize)> {
+    let (tokens, session) =
+        preprocess_string(source.to_string(), preprocess_config()).expect("Preprocessing failed");
+
+    tokens
+        .iter()
+        .filter(|token| token.kind == TokenKind::Identifier)
+        .map(|token| session.get_source_location(&token.as_span()))
+        .collect()
+}
+
+#[test]
+fn line_directive() {
+    let locations = identifier_locations("nop\n.line 100 \"orig.src\"\nnop\n\nnop\n");
+
+    assert_eq!(
+        locations,
+        vec![
+            (String::from("<input>"), 1, 0),
+            (String::from("orig.src"), 100, 0),
+            (String::from("orig.src"), 102, 0),
+        ]
+    );
+}
+
+#[test]
+fn line_directive_keeps_file_name() {
+    let locations = identifier_locations(".line 10 \"orig.src\"\nnop\n.line 50\nnop\n");
+
+    assert_eq!(
+        locations,
+        vec![
+            (String::from("orig.src"), 10, 0),
+            (String::from("orig.src"), 50, 0),
+        ]
+    );
+}
+
+#[test]
+fn line_directive_invalid() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    assert!(assemble_string(".line 0\n".to_string(), config.clone()).is_err());
+    assert!(assemble_string(".line LINE\n".to_string(), config.clone()).is_err());
+    assert!(assemble_string(".line 5 \"a\" nop\n".to_string(), config).is_err());
+}
+
+#[test]
+fn macro_default_references_earlier_argument() {
+    let source = ".macro PAIR 1-2 &1\npush &1\npush &2\n.endmacro\nPAIR(5)\nPAIR(5,6)\n";
+
+    let output = preprocess(source, &[]);
+
+    assert_eq!(
+        output.split_whitespace().collect::<Vec<_>>(),
+        vec!["push", "5", "push", "5", "push", "5", "push", "6"]
+    );
+}
+
+#[test]
+fn macro_default_references_earlier_default() {
+    let source = ".macro TRIPLE 1-3 &1, &2\npush &1, &2, &3\n.endmacro\nTRIPLE(7)\n";
+
+    let output = preprocess(source, &[]);
+
+    assert_eq!(output.trim(), "push 7, 7, 7");
+}
+
+#[test]
+fn macro_default_references_later_argument() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    let source = ".macro PAIR 1-2 &2\npush &1\n.endmacro\n";
+
+    assert!(assemble_string(source.to_string(), config).is_err());
+}
+
+#[test]
+fn if_string_define_comparison() {
+    let source = ".if TARGET == \"release\"\npush 1\n.else\npush 2\n.endif\n";
+
+    let output = preprocess(&format!(".define TARGET \"release\"\n{}", source), &[]);
+
+    assert_eq!(output.trim(), "push 1");
+
+    let output = preprocess(source, &["TARGET=\"debug\""]);
+
+    assert_eq!(output.trim(), "push 2");
+
+    let output = preprocess(".if \"a\\n\" != \"a\"\npush 1\n.endif\n", &[]);
+
+    assert_eq!(output.trim(), "push 1");
+}
+
+#[test]
+fn if_string_invalid_operator() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    let invalid = [
+        ".if \"b\" > \"a\"\n.endif\n",
+        ".if \"a\" + \"b\" == \"ab\"\n.endif\n",
+        ".if \"1\" == 1\n.endif\n",
+        ".if -\"a\"\n.endif\n",
+        ".if \"a\"\n.endif\n",
+    ];
+
+    for source in invalid {
+        assert!(assemble_string(source.to_string(), config.clone()).is_err());
+    }
+}
+
+#[test]
+fn rep_named_index() {
+    let output = preprocess(".rep 3 as i\npush i\n.endrep\n", &[]);
+
+    assert_eq!(
+        output.split_whitespace().collect::<Vec<_>>(),
+        vec!["push", "0", "push", "1", "push", "2"]
+    );
+}
+
+#[test]
+fn rep_named_index_nested() {
+    let source = ".rep 2 as row\n.rep row + 1 as col\npush row, col\n.endrep\n.endrep\n";
+
+    let output = preprocess(source, &[]);
+
+    assert_eq!(
+        output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>(),
+        vec!["push 0, 0", "push 1, 0", "push 1, 1"]
+    );
+}
+
+#[test]
+fn rep_arithmetic_count() {
+    let output = preprocess(
+        ".define BASE 2\n.rep (BASE + 3) * 1\npush 1\n.endrep\n",
+        &[],
+    );
+
+    assert_eq!(output.matches("push 1").count(), 5);
+}
+
+#[test]
+fn rep_hex_count() {
+    let output = preprocess(".rep 0x4\npush 1\n.endrep\n.rep 0\npush 2\n.endrep\n", &[]);
+
+    assert_eq!(output.matches("push 1").count(), 4);
+    assert!(!output.contains("push 2"));
+}
+
+#[test]
+fn rep_negative_count() {
+    let (success, diagnostics) = check_string(
+        ".rep 1 - 3\npush 1\n.endrep\n".to_string(),
+        preprocess_config(),
+    );
+
+    assert!(!success);
+    assert_eq!(diagnostics[0].message, "repeat count cannot be negative");
+}
+
+#[test]
+fn rep_non_integer_count() {
+    let counts = [
+        ("2.5", "expression resulted in double value"),
+        ("1 == 1", "expression resulted in boolean value"),
+    ];
+
+    for (count, message) in counts {
+        let source = format!(".rep {}\npush 1\n.endrep\n", count);
+        let (success, diagnostics) = check_string(source, preprocess_config());
+
+        assert!(!success);
+        assert_eq!(diagnostics[0].message, message);
+    }
+}
+
+#[test]
+fn macro_trailing_comma() {
+    let output = preprocess(
+        ".define PAIR(a, b,) push a, b\nPAIR(1, 2,)\nPAIR(3,4 , )\n",
+        &[],
+    );
+
+    assert_eq!(
+        output.split_whitespace().collect::<Vec<_>>(),
+        vec!["push", "1,", "2", "push", "3,", "4"]
+    );
+}
+
+#[test]
+fn macro_empty_argument() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    let invalid = [
+        ".define PAIR(a, b) push a, b\nPAIR(1,,2)\n",
+        ".define PAIR(a, b) push a, b\nPAIR(1, , 2)\n",
+        ".define PAIR(a, b) push a, b\nPAIR(,)\n",
+        ".define PAIR(a,,b) push a, b\n",
+    ];
+
+    for source in invalid {
+        assert!(assemble_string(source.to_string(), config.clone()).is_err());
+    }
+}
+
+#[test]
+fn macro_braced_argument() {
+    let output = preprocess(
+        ".macro PAIR 2\npush &1\npush &2\n.endmacro\nPAIR({1, 2}, 3)\n.define ONE(a) push a\nONE({(4), {5}})\n",
+        &[],
+    );
+
+    let lines: Vec<String> = output
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    assert_eq!(lines, ["push 1, 2", "push 3", "push (4), {5}"]);
+}
+
+#[test]
+fn macro_unbalanced_braces() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    let invalid = [
+        ".define ONE(a) push a\nONE({1, 2)\n",
+        ".define ONE(a) push a\nONE({1, 2)",
+        ".define ONE(a) push a\nONE(1})\n",
+        ".define ONE(a) push a\nONE({})\n",
+    ];
+
+    for source in invalid {
+        assert!(
+            assemble_string(source.to_string(), config.clone()).is_err(),
+            "{:?} was accepted",
+            source
+        );
+    }
+}
+
+#[test]
+fn globl_spelling_preserved() {
+    let source = ".globl _start\n.global helper\n";
+
+    assert_eq!(preprocess(source, &[]), source);
+}
+
+#[test]
+fn mutually_recursive_macros() {
+    let mut config = preprocess_config();
+    config.emit_errors = false;
+
+    let sources = [
+        ".define A B\n.define B A\npush A\n",
+        ".define PING(x) PONG(x)\n.define PONG(x) PING(x)\npush PING(1)\n",
+    ];
+
+    for source in sources {
+        assert!(assemble_string(source.to_string(), config.clone()).is_err());
+    }
+
+    config.max_expansion_depth = 2;
+
+    let output = preprocess_with(".define A B\n.define B 1\npush A\n", config.clone());
+
+    assert_eq!(output.trim(), "push 1");
+
+    let source = ".define A B\n.define B C\n.define C 1\npush A\n";
+
+    assert!(assemble_string(source.to_string(), config).is_err());
+}
+
+#[test]
+fn data_type_alias_spelling_preserved() {
+    let source = ".section .data\nfirst .byte 1\nsecond .word 2\nthird .i32 3\n";
+
+    assert_eq!(preprocess(source, &[]), source);
+}
+
+// Returns the source of every token after preprocessing, other than whitespace
+fn preprocessed_token_sources(source: &str) -> Vec<String> {
+    let (tokens, session) =
+        preprocess_string(source.to_string(), preprocess_config()).expect("Preprocessing failed");
+
+    tokens
+        .iter()
+        .filter(|token| token.kind != TokenKind::Whitespace)
+        .map(|token| session.span_to_source(&token.as_span()))
+        .collect()
+}
+
+#[test]
+fn macro_arguments_continued_across_lines() {
+    let definitions =
+        ".define SUM(a, b, c) push a + b + c\n.macro TRIPLE 3\npush &1, &2, &3\n.endmacro\n";
+
+    let continued = preprocessed_token_sources(&format!(
+        "{}SUM(1, \\\n    2, \\\n    3)\nTRIPLE(\\\n  4, 5 \\\n  , 6 \\\n)\n",
+        definitions
+    ));
+    let single =
+        preprocessed_token_sources(&format!("{}SUM(1, 2, 3)\nTRIPLE(4, 5, 6)\n", definitions));
+
+    assert_eq!(continued, single);
+}
+
+#[test]
+fn line_markers_around_include() {
+    let mut config = preprocess_config();
+    config.line_markers = true;
+
+    let output = preprocess_with(
+        "push 1\n.include \"tests/sources/include/table.kasm\" lines 3-5\npush 2\n",
+        config,
+    );
+
+    let lines: Vec<&str> = output.lines().collect();
+
+    assert_eq!(lines.len(), 8, "{}", output);
+    assert!(lines[0].starts_with(".line 1 "));
+    assert_eq!(lines[1], "push 1");
+    assert!(lines[2].starts_with(".line 3 ") && lines[2].ends_with("table.kasm\""));
+    assert_eq!(&lines[3..6], ["push 3", "push 4", "push 5"]);
+    assert_eq!(lines[6], lines[0].replace(".line 1 ", ".line 3 "));
+    assert_eq!(lines[7], "push 2");
+}
+
+#[test]
+fn include_declarations_only() {
+    let output = preprocess(
+        ".include_decls \"tests/sources/include/api.kasm\"\n.ifsection text\n.func\n_start:\n    TWICE(ANSWER)\n.endif\n",
+        &[],
+    );
+
+    let lines: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    assert_eq!(
+        lines,
+        [
+            ".extern print_answer",
+            ".type .func print_answer",
+            ".extern .value answer",
+            ".func",
+            "_start:",
+            "push 42",
+            "push 42",
+        ]
+    );
+}
+
+#[test]
+fn include_declarations_assemble() {
+    let source = ".include_decls \"tests/sources/include/api.kasm\"\n.func\n_start:\n    TWICE(ANSWER)\n    push answer\n    call print_answer, 1\n";
+
+    let (success, diagnostics) = check_string(source.to_string(), Config::default());
+
+    assert!(success);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+}
+
+#[test]
+fn rep_named_index_many_runs() {
+    // Each run of the inner .rep needs indices, which shouldn't use up a file each time
+    let source = ".rep 300 as j\n.rep 1 as i\npush i\n.endrep\n.endrep\n";
+
+    let output = preprocess(source, &[]);
+
+    assert_eq!(output.matches("push 0").count(), 300);
+}
@@ -0,0 +1,210 @@
+use clap::Parser;
+use kasm::lexer::TokenKind;
+use kasm::preprocessor::past::PASTNode;
+use kasm::{
+    assemble_full, assemble_string, assemble_string_into, generate, lex, parse_past, parse_tokens,
+    preprocess, session_from_path, session_from_string, verify, AssemblyOutput, Config,
+};
+use std::path::Path;
+
+mod common;
+use common::test_config;
+
+const SOURCE: &str = ".define TWO 2\n.global _start\n.func\n_start:\n    push TWO\n    push TWO\n    add\n    pop\n.func\nother:\n    push 1\n    pop\n";
+
+#[test]
+fn stages_match_assemble() {
+    let mut session = session_from_string(SOURCE.to_string(), test_config());
+
+    let tokens = preprocess(&mut session).expect("Preprocessing failed");
+
+    let (functions, label_manager, symbol_manager, binaries) =
+        parse_tokens(tokens, &session).expect("Parsing failed");
+
+    let verified =
+        verify(functions, &label_manager, &symbol_manager, &session).expect("Verification failed");
+
+    let kofile = generate(
+        verified,
+        binaries,
+        None,
+        &label_manager,
+        &symbol_manager,
+        &session,
+    )
+    .expect("Generation failed");
+
+    let mut staged = Vec::new();
+    kofile.write(&mut staged);
+
+    let mut assembled = Vec::new();
+    match assemble_string(SOURCE.to_string(), test_config()).expect("Assembly failed") {
+        AssemblyOutput::Object(kofile) => kofile.write(&mut assembled),
+        AssemblyOutput::Source(_) => panic!("Expected an object file"),
+    }
+
+    assert_eq!(staged, assembled);
+}
+
+#[test]
+fn preprocess_once_parse_twice() {
+    let mut session = session_from_string(SOURCE.to_string(), test_config());
+
+    let tokens = preprocess(&mut session).expect("Preprocessing failed");
+
+    let (first, _, _, _) = parse_tokens(tokens.clone(), &session).expect("Parsing failed");
+    let (second, _, _, _) = parse_tokens(tokens, &session).expect("Parsing failed");
+
+    let names = |functions: &[kasm::parser::parse::ParsedFunction]| {
+        functions
+            .iter()
+            .map(|function| function.name.clone())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(names(&first), ["_start", "other"]);
+    assert_eq!(names(&first), names(&second));
+}
+
+#[test]
+fn lex_without_preprocessing() {
+    let session = session_from_string(".define TWO 2\npush TWO\n".to_string(), test_config());
+
+    let tokens = lex(&session).expect("Lexing failed");
+
+    // Nothing has been expanded, so the definition is still there
+    assert_eq!(tokens.first().unwrap().kind, TokenKind::DirectiveDefine);
+
+    let nodes = parse_past(tokens, &session).expect("Parsing failed");
+
+    assert!(matches!(nodes.first(), Some(PASTNode::SLMacroDef(_))));
+    assert!(nodes
+        .iter()
+        .any(|node| matches!(node, PASTNode::MacroInvok(_))));
+}
+
+#[test]
+fn verify_stage_in_isolation() {
+    let source = ".func\n_start:\n    call missing, \"#\"\n";
+
+    let mut session = session_from_string(source.to_string(), test_config());
+
+    let tokens = preprocess(&mut session).expect("Preprocessing failed");
+
+    let (functions, label_manager, symbol_manager, _) =
+        parse_tokens(tokens, &session).expect("Parsing failed");
+
+    assert_eq!(functions.len(), 1);
+
+    assert!(verify(functions, &label_manager, &symbol_manager, &session).is_err());
+}
+
+#[test]
+fn full_artifacts_populated() {
+    let artifacts = assemble_full(SOURCE.to_string(), test_config()).expect("Assembly failed");
+
+    assert!(artifacts
+        .tokens
+        .iter()
+        .any(|token| token.resolve(&artifacts.session) == "_start"));
+
+    let names: Vec<&str> = artifacts
+        .functions
+        .iter()
+        .map(|function| function.name.as_str())
+        .collect();
+
+    assert_eq!(names, ["_start", "other"]);
+    assert_eq!(artifacts.functions[0].instructions.len(), 4);
+
+    assert_eq!(
+        artifacts
+            .label_manager
+            .get(&String::from("other"))
+            .unwrap()
+            .value,
+        4
+    );
+    assert!(artifacts.symbol_manager.contains(&String::from("_start")));
+    assert!(artifacts.diagnostics.is_empty());
+
+    // The object file is the same as one assembled the usual way
+    let mut full = Vec::new();
+    artifacts.object.write(&mut full);
+
+    let mut assembled = Vec::new();
+    match assemble_string(SOURCE.to_string(), test_config()).expect("Assembly failed") {
+        AssemblyOutput::Object(kofile) => kofile.write(&mut assembled),
+        AssemblyOutput::Source(_) => panic!("Expected an object file"),
+    }
+
+    assert_eq!(full, assembled);
+}
+
+#[test]
+fn full_artifacts_failure_returns_diagnostics() {
+    let diagnostics =
+        match assemble_full(".func\n_start:\n    bogus 1\n".to_string(), test_config()) {
+            Ok(_) => panic!("Assembly should have failed"),
+            Err(diagnostics) => diagnostics,
+        };
+
+    assert!(!diagnostics.is_empty());
+}
+
+#[test]
+fn assemble_into_reused_buffer() {
+    let other_source = ".func\n_start:\n    push 5\n";
+    let mut expected = Vec::new();
+
+    for source in [SOURCE, other_source] {
+        let mut owned = Vec::new();
+        assemble_string(source.to_string(), test_config())
+            .expect("Assembly failed")
+            .write(&mut owned);
+
+        expected.push(owned);
+    }
+
+    let mut buffer = Vec::new();
+
+    assemble_string_into(SOURCE.to_string(), test_config(), &mut buffer).expect("Assembly failed");
+    assert_eq!(buffer, expected[0]);
+
+    let capacity = buffer.capacity();
+
+    assemble_string_into(other_source.to_string(), test_config(), &mut buffer)
+        .expect("Assembly failed");
+    assert_eq!(buffer, expected[1]);
+
+    // The second file is smaller, so it should have fit without growing the buffer
+    assert!(expected[1].len() < expected[0].len());
+    assert_eq!(buffer.capacity(), capacity);
+
+    assert!(assemble_string_into("push".to_string(), test_config(), &mut buffer).is_err());
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn file_without_relative_path_to_root() {
+    // There is no relative path from a root directory that steps out of the file's directory, in
+    // the same way as there is none between different drives on Windows
+    let root_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+
+    let mut config = test_config();
+    config.root_dir = root_dir;
+
+    let path = Path::new("./tests/sources/add_numbers.kasm");
+    let session = session_from_path(path, config).expect("Reading the file failed");
+
+    let file = session.get_file(0).unwrap();
+
+    assert_eq!(file.rel_path, Some(std::fs::canonicalize(path).unwrap()));
+}
+
+#[test]
+fn default_config_matches_command_line() {
+    let parsed = Config::parse_from(["kasm"]);
+
+    assert_eq!(format!("{:?}", Config::default()), format!("{:?}", parsed));
+}
@@ -0,0 +1,101 @@
+use kasm::{assemble_string, AssemblyOutput, Config};
+use kerbalobjects::ko::KOFile;
+use kerbalobjects::KOSValue;
+
+fn test_config() -> Config {
+    Config {
+        emit_errors: false,
+        emit_warnings: false,
+        run_preprocessor: false,
+        ..Config::default()
+    }
+}
+
+// Assembles the source, but can panic
+fn assemble(source: &str) -> KOFile {
+    match assemble_string(source.to_string(), test_config()).expect("Assembly failed") {
+        AssemblyOutput::Object(ko) => ko.get(),
+        AssemblyOutput::Source(_) => panic!("Expected object file"),
+    }
+}
+
+// Finds the value of the data symbol with the given name
+fn symbol_value(ko: &KOFile, name: &str) -> KOSValue {
+    let symtab = ko.sym_tab_by_name(".symtab").unwrap();
+    let symstrtab = ko.str_tab_by_name(".symstrtab").unwrap();
+    let data = ko.data_section_by_name(".data").unwrap();
+
+    let name_index = symstrtab.position(name).unwrap();
+    let symbol = symtab.find_by_name(name_index).unwrap();
+
+    data.get(symbol.value_idx).unwrap().clone()
+}
+
+#[test]
+fn data_string_escapes() {
+    let ko = assemble(
+        ".section .data\ntext .s \"line1\\nline2\"\nvalue .sv \"say \\\"hi\\\"\\t\\\\\"\n.section .text\n.func\n_start:\n    push text\n    pushv value\n",
+    );
+
+    match symbol_value(&ko, "text") {
+        KOSValue::String(s) => assert_eq!(s, "line1\nline2"),
+        other => panic!("Expected string, found {:?}", other),
+    }
+
+    match symbol_value(&ko, "value") {
+        KOSValue::StringValue(s) => assert_eq!(s, "say \"hi\"\t\\"),
+        other => panic!("Expected string value, found {:?}", other),
+    }
+}
+
+#[test]
+fn operand_string_escapes() {
+    let ko = assemble(".func\n_start:\n    push \"a\\nb\"\n");
+
+    let data = ko.data_section_by_name(".data").unwrap();
+
+    assert!(data
+        .data()
+        .any(|value| matches!(value, KOSValue::String(s) if s == "a\nb")));
+}
+
+#[test]
+fn unknown_escape() {
+    let source = ".section .data\ntext .s \"bad\\q\"\n";
+
+    assert!(assemble_string(source.to_string(), test_config()).is_err());
+}
+
+#[test]
+fn repeated_operands_shared() {
+    let ko = assemble(
+        ".func\n_start:\n    push \"print\"\n    push 1\n    push \"print\"\n    push 1\n",
+    );
+
+    let data = ko.data_section_by_name(".data").unwrap();
+
+    let strings = data
+        .data()
+        .filter(|value| matches!(value, KOSValue::String(s) if s == "print"))
+        .count();
+    let ints = data
+        .data()
+        .filter(|value| matches!(value, KOSValue::Int16(1) | KOSValue::Byte(1)))
+        .count();
+
+    assert_eq!(strings, 1);
+    assert_eq!(ints, 1);
+
+    // Both uses of each operand should refer to the same entry
+    let func = ko.func_sections().next().unwrap();
+    let ops: Vec<_> = func
+        .instructions()
+        .map(|instr| match instr {
+            kerbalobjects::ko::Instr::OneOp(_, op) => *op,
+            _ => panic!("Expected one operand instruction"),
+        })
+        .collect();
+
+    assert_eq!(ops[0], ops[2]);
+    assert_eq!(ops[1], ops[3]);
+}
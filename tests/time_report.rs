@@ -0,0 +1,55 @@
+use std::process::Command;
+
+// Assembles the file with a time report, and returns the name of each phase in the report
+fn reported_phases(args: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_kasm"))
+        .args(args)
+        .arg("--time-report")
+        .output()
+        .expect("Failed to run kasm");
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(stderr.contains("note: assembly took "), "{}", stderr);
+
+    stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix(" = note: "))
+        .map(|line| line.split_whitespace().next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn time_report_lists_phases() {
+    let phases = reported_phases(&[
+        "tests/sources/add_numbers.kasm",
+        "-o",
+        "tests/time_report.ko",
+    ]);
+
+    assert_eq!(
+        phases,
+        [
+            "lexing",
+            "phase0",
+            "preprocessing",
+            "parsing",
+            "verification",
+            "generation"
+        ]
+    );
+}
+
+#[test]
+fn time_report_without_other_notes() {
+    // The report is shown even when warnings aren't, and includes the phase that failed
+    let phases = reported_phases(&[
+        "tests/sources/undeclared.kasm",
+        "-o",
+        "tests/time_report_failed.ko",
+        "-q",
+        "-a",
+    ]);
+
+    assert_eq!(phases, ["lexing", "phase0", "parsing", "verification"]);
+}
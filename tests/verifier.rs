@@ -0,0 +1,259 @@
+use kasm::errors::Level;
+use kasm::{assemble_string, check_string, Config};
+
+fn test_config() -> Config {
+    Config {
+        emit_errors: false,
+        emit_warnings: false,
+        run_preprocessor: false,
+        ..Config::default()
+    }
+}
+
+fn assembles(source: &str) -> bool {
+    assemble_string(source.to_string(), test_config()).is_ok()
+}
+
+#[test]
+fn valid_operands() {
+    assert!(assembles(
+        ".func\n_start:\n    push 1\n.loop:\n    bscp 1, 2\n    btr .loop\n    call #, \"print()\"\n"
+    ));
+}
+
+// Checks the source, and returns the part of it that the first error points at
+fn error_text(source: &str, config: Config) -> String {
+    let (success, diagnostics) = check_string(source.to_string(), config);
+
+    assert!(!success, "{}", source);
+
+    let span = diagnostics[0].spans[0].0;
+
+    source[span.start..span.end].to_string()
+}
+
+#[test]
+fn label_where_value_required() {
+    assert_eq!(
+        error_text(
+            ".func\n_start:\n    nop\n.loop:\n    push .loop\n",
+            test_config()
+        ),
+        ".loop"
+    );
+}
+
+#[test]
+fn string_where_integer_required() {
+    assert_eq!(
+        error_text(".func\n_start:\n    bscp 1, \"two\"\n", test_config()),
+        "\"two\""
+    );
+}
+
+#[test]
+fn integer_too_large_for_operand() {
+    assert_eq!(
+        error_text(".func\n_start:\n    ret 100000\n", test_config()),
+        "100000"
+    );
+}
+
+#[test]
+fn negative_operand_span() {
+    // The `-` and the literal are separate tokens, but the error covers both
+    assert_eq!(
+        error_text(".func\n_start:\n    bscp 1, -1.5\n", test_config()),
+        "-1.5"
+    );
+    assert_eq!(
+        error_text(".func\n_start:\n    bscp 1, 2 * -1.5\n", test_config()),
+        "2 * -1.5"
+    );
+}
+
+#[test]
+fn undeclared_label() {
+    assert!(!assembles(".func\n_start:\n    nop\n    jmp .missing\n"));
+}
+
+#[test]
+fn inner_label_from_other_function() {
+    assert!(!assembles(
+        ".func\n_start:\n    nop\n.loop:\n    jmp .loop\n.func\nother:\n    jmp .loop\n"
+    ));
+}
+
+#[test]
+fn undeclared_symbol() {
+    assert!(!assembles(".func\n_start:\n    call missing, #\n"));
+    assert!(!assembles(".func\n_start:\n    push missing\n"));
+}
+
+#[test]
+fn forward_label_reference() {
+    assert!(assembles(
+        ".func\n_start:\n    jmp .end\n    nop\n.end:\n    call later, #\n.func\nlater:\n    ret 0\n"
+    ));
+}
+
+#[test]
+fn extern_symbol_reference() {
+    assert!(assembles(
+        ".extern .func other\n.func\n_start:\n    call other, #\n"
+    ));
+}
+
+#[test]
+fn symbol_type_matches_value() {
+    assert!(assembles(
+        ".type .value count\n.type .func helper\n.section .data\ncount .i32 1\n.section .text\n.func\nhelper:\n    push count\n"
+    ));
+}
+
+#[test]
+fn function_symbol_given_value() {
+    assert!(!assembles(
+        ".type .func count\n.section .data\ncount .i32 1\n.section .text\n.func\n_start:\n    push count\n"
+    ));
+}
+
+#[test]
+fn float_literal_forms() {
+    assert!(assembles(
+        ".section .data\nbig .f64 1e10\nhalf .f64v .5\n.section .text\n.func\n_start:\n    push 1.5e-3\n    push big\n    pushv half\n"
+    ));
+    assert!(!assembles(".func\n_start:\n    push 1.2.3\n"));
+}
+
+#[test]
+fn float_literal_separators() {
+    assert!(assembles(
+        ".section .data\nbig .f64 1_000.000_5\n.section .text\n.func\n_start:\n    push 2_5.0e1_0\n"
+    ));
+    assert!(!assembles(".func\n_start:\n    push 1_.5\n"));
+    assert!(!assembles(".func\n_start:\n    push 1.5_\n"));
+}
+
+#[test]
+fn operand_expressions_without_commas() {
+    assert!(assembles(
+        ".func\n_start:\n    push 1 + 2\n    bscp 1 * 2 - 1, -3\n"
+    ));
+    assert!(!assembles(".func\n_start:\n    push 1 2\n"));
+    assert!(!assembles(".func\n_start:\n    bscp 1, 2 (3)\n"));
+}
+
+#[test]
+fn check_reports_verifier_error() {
+    let (success, diagnostics) = check_string(
+        ".func\n_start:\n    bscp 1, \"two\"\n".to_string(),
+        test_config(),
+    );
+
+    assert!(!success);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].level, Level::Error);
+    assert!(diagnostics[0].message.contains("operand 2"));
+
+    let (success, diagnostics) =
+        check_string(".func\n_start:\n    bscp 1, 2\n".to_string(), test_config());
+
+    assert!(success);
+    assert!(diagnostics.is_empty());
+}
+
+// Checks the source, and returns the message of the error that it fails with
+fn label_error(source: &str) -> String {
+    let (success, diagnostics) = check_string(source.to_string(), test_config());
+
+    assert!(!success, "{}", source);
+
+    diagnostics[0].message.clone()
+}
+
+#[test]
+fn valid_label_names() {
+    assert!(assembles(
+        ".func\n_start:\n    nop\n.__inner:\n    jmp .__inner\n.func\nloop_2:\n    nop\n"
+    ));
+}
+
+#[test]
+fn reserved_label_prefix() {
+    assert_eq!(
+        label_error(".func\n__start:\n    nop\n"),
+        "label `__start` starts with the reserved prefix `__`"
+    );
+    assert_eq!(
+        label_error(".func\n_start:\n    nop\n__WRITE_0:\n    nop\n"),
+        "label `__WRITE_0` starts with the reserved prefix `__`"
+    );
+}
+
+#[test]
+fn invalid_label_characters() {
+    assert_eq!(
+        label_error(".func\nmy-func:\n    nop\n"),
+        "invalid label name `my-func`"
+    );
+    assert_eq!(
+        label_error(".func\n_start:\n    nop\nend-loop:\n    nop\n"),
+        "invalid label name `end-loop`"
+    );
+    assert_eq!(
+        label_error(".func\n_start:\n    nop\n2nd:\n    nop\n"),
+        "invalid label name `2nd`"
+    );
+}
+
+#[test]
+fn macro_supplied_operand_span() {
+    // Part of each operand comes from a macro, so its tokens aren't next to each other in one file
+    // and the error only points at the first of them
+    let sources = [
+        (
+            ".define X 2.0\n.func\n_start:\n    bscp 1, 1 + X\n",
+            vec![],
+            "1",
+        ),
+        (
+            ".func\n_start:\n    bscp 1, 1 + X\n",
+            vec![String::from("X=2.0")],
+            "1",
+        ),
+        (
+            ".define X 2.0\n.func\n_start:\n    bscp 1, X + 1\n",
+            vec![],
+            "2.0",
+        ),
+    ];
+
+    for (source, defines, operand) in sources {
+        let mut config = test_config();
+        config.run_preprocessor = true;
+        config.defines = defines;
+
+        assert_eq!(error_text(source, config.clone()), operand, "{}", source);
+
+        // Rendering the diagnostic used to panic
+        config.emit_errors = true;
+        assert!(assemble_string(source.to_string(), config).is_err());
+    }
+}
+
+#[test]
+fn inner_label_declared_in_several_functions() {
+    let source = ".func\nfirst:\n    nop\n.loop:\n    jmp .loop\n.func\nsecond:\n    nop\n.loop:\n    jmp .loop\n.func\nthird:\n    nop\n.loop:\n    jmp .loop\n.func\nfourth:\n    jmp .loop\n";
+
+    // Each run hashes the labels differently, so the function named has to be picked by order
+    for _ in 0..20 {
+        let (success, diagnostics) = check_string(source.to_string(), test_config());
+
+        assert!(!success);
+        assert_eq!(
+            diagnostics[0].children[0].message,
+            "a label `.loop` is declared in function `first`, but inner labels can only be used within their own function"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::process::Command;
+
+// Runs the kasm binary with the given arguments, and returns everything it printed to stderr
+fn run_kasm(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_kasm"))
+        .args(args)
+        .output()
+        .expect("Failed to run kasm");
+
+    assert!(output.status.success());
+
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn warn_unused() {
+    let stderr = run_kasm(&[
+        "tests/sources/unused.kasm",
+        "-o",
+        "tests/unused.ko",
+        "--warn-unused",
+        "-D",
+        "FROM_COMMAND_LINE",
+    ]);
+
+    assert!(stderr.contains("macro `UNUSED` is defined but never used"));
+    assert!(stderr.contains("macro `NEVER_EXPANDED` is defined but never used"));
+    assert!(stderr.contains("label `.dead` is never referenced"));
+
+    // Used macros, referenced labels, and functions are never warned about
+    assert!(!stderr.contains("`USED`"));
+    assert!(!stderr.contains("`FLAG`"));
+    assert!(!stderr.contains("`FROM_COMMAND_LINE`"));
+    assert!(!stderr.contains("`PUSH_TWO`"));
+    assert!(!stderr.contains("`.loop`"));
+    assert!(!stderr.contains("`_start`"));
+}
+
+#[test]
+fn warn_unused_off_by_default() {
+    let stderr = run_kasm(&["tests/sources/unused.kasm", "-o", "tests/unused.ko"]);
+
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn verbose_notes() {
+    let stderr = run_kasm(&[
+        "tests/sources/single_instruction.kasm",
+        "-o",
+        "tests/verbose.ko",
+        "--verbose",
+    ]);
+
+    assert!(stderr.contains("note: read file `tests/sources/single_instruction.kasm`"));
+    assert!(stderr.contains("note: parsed 1 function(s)"));
+    assert!(stderr.contains("bytes to `tests/verbose.ko`"));
+}
+
+#[test]
+fn quiet_suppresses_warnings() {
+    let stderr = run_kasm(&[
+        "tests/sources/unused.kasm",
+        "-o",
+        "tests/unused.ko",
+        "--warn-unused",
+        "--quiet",
+    ]);
+
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn weak_and_strong_declarations() {
+    let stderr = run_kasm(&["tests/sources/weak.kasm", "-o", "tests/weak.ko"]);
+
+    // The strong declaration silently takes the place of the weak one
+    assert!(!stderr.contains("redundant declaration of symbol binding"));
+    assert!(!stderr.contains("conflicting symbol bindings"));
+}
+
+#[test]
+fn redefined_macro() {
+    let stderr = run_kasm(&[
+        "tests/sources/redefined_macro.kasm",
+        "-o",
+        "tests/redefined_macro.ko",
+    ]);
+
+    assert_eq!(stderr.matches("macro `PUSH_ONE` redefined").count(), 1);
+    assert!(stderr.contains("redefined_macro.kasm:4:7"));
+    assert!(stderr.contains("previously defined here"));
+}
+
+#[test]
+fn single_macro_definitions() {
+    let stderr = run_kasm(&[
+        "tests/sources/single_macro.kasm",
+        "-o",
+        "tests/single_macro.ko",
+    ]);
+
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn warn_unreachable() {
+    let stderr = run_kasm(&[
+        "tests/sources/unreachable.kasm",
+        "-o",
+        "tests/unreachable.ko",
+        "--warn-unreachable",
+    ]);
+
+    let warnings: Vec<&str> = stderr
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| line.starts_with("-->"))
+        .collect();
+
+    // Only the first instruction after each jmp or ret is warned about, and never one that
+    // follows a label
+    assert_eq!(
+        warnings,
+        [
+            "-->  tests/sources/unreachable.kasm:5:4",
+            "-->  tests/sources/unreachable.kasm:12:4",
+        ]
+    );
+    assert_eq!(
+        stderr.matches("warning: unreachable instruction").count(),
+        2
+    );
+}
+
+#[test]
+fn warn_unreachable_off_by_default() {
+    let stderr = run_kasm(&[
+        "tests/sources/unreachable.kasm",
+        "-o",
+        "tests/unreachable.ko",
+    ]);
+
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn empty_macro_body() {
+    let stderr = run_kasm(&[
+        "tests/sources/empty_macro.kasm",
+        "-o",
+        "tests/empty_macro.ko",
+    ]);
+
+    assert!(stderr.contains("warning: macro `NOTHING` has an empty body"));
+    assert!(stderr.contains("empty_macro.kasm:1:0"));
+    assert!(stderr.contains("warning: macro `SPACES` has an empty body"));
+    assert!(stderr.contains("empty_macro.kasm:4:0"));
+
+    // Macros that expand to something, even if only conditionally, are fine, and so are
+    // definitions used as flags
+    assert!(!stderr.contains("`PUSH_ONE`"));
+    assert!(!stderr.contains("`MAYBE`"));
+    assert!(!stderr.contains("`FLAG`"));
+}
+
+#[test]
+fn empty_macro_body_no_warn() {
+    let stderr = run_kasm(&[
+        "tests/sources/empty_macro.kasm",
+        "-o",
+        "tests/empty_macro.ko",
+        "--no-warn",
+    ]);
+
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn warn_discarded() {
+    let stderr = run_kasm(&[
+        "tests/sources/discarded.kasm",
+        "-o",
+        "tests/discarded.ko",
+        "--warn-discarded",
+    ]);
+
+    let warnings: Vec<&str> = stderr
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| line.starts_with("-->"))
+        .collect();
+
+    // Empty contents, and counts or conditions that use a macro or macro argument, are never warned
+    // about
+    assert_eq!(
+        warnings,
+        [
+            "-->  tests/sources/discarded.kasm:4:5",
+            "-->  tests/sources/discarded.kasm:9:4",
+            "-->  tests/sources/discarded.kasm:18:4",
+            "-->  tests/sources/discarded.kasm:23:5",
+        ]
+    );
+    assert_eq!(
+        stderr
+            .matches("warning: contents are always discarded")
+            .count(),
+        4
+    );
+}
+
+#[test]
+fn warn_discarded_off_by_default() {
+    let stderr = run_kasm(&["tests/sources/discarded.kasm", "-o", "tests/discarded.ko"]);
+
+    assert!(stderr.is_empty());
+}